@@ -6,11 +6,19 @@ logoutLink.addEventListener("click", (e) => {
     e.preventDefault();
 
     let url = logoutLink.href;
+    // The auth service only accepts the session cookie with its CSRF token,
+    // which this page cannot read from the cookie itself
+    let csrfTokenUrl = new URL('csrf-token', url);
 
-    fetch(url, {
+    fetch(csrfTokenUrl, {
+        credentials: 'include',
+    }).then(response => response.json()).then(({ csrfToken }) => fetch(url, {
         method: 'POST',
         credentials: 'include', // This will include cookies in the request
-    }).then(response => {
+        headers: {
+            'X-CSRF-Token': csrfToken,
+        },
+    })).then(response => {
         if (response.ok) {
             loginLink.style.display = "block";
             logoutLink.style.display = "none";
@@ -18,11 +26,24 @@ logoutLink.addEventListener("click", (e) => {
         } else {
             alert("Failed to logout");
         }
-    });
+    }).catch(() => alert("Failed to logout"));
+});
+
+// The session may have expired while the auth service still remembers this
+// device, in which case it signs the browser back in once
+const fetchProtected = () => fetch('/protected').then(response => {
+    if (response.status !== 401) {
+        return response;
+    }
+    return fetch(new URL('refresh-token', logoutLink.href), {
+        method: 'POST',
+        credentials: 'include',
+    }).then(refreshed => refreshed.ok ? fetch('/protected') : response)
+        .catch(() => response);
 });
 
 (() => {
-    fetch('/protected').then(response => {
+    fetchProtected().then(response => {
         if (response.ok) {
             loginLink.style.display = "none";
             logoutLink.style.display = "block";
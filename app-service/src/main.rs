@@ -3,20 +3,28 @@ use std::env;
 use askama::Template;
 use axum::{
     Json, Router,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse},
     routing::get,
 };
 use axum_extra::extract::CookieJar;
 use serde::Serialize;
-use tower_http::services::ServeDir;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+};
+
+// Tracing headers forwarded to auth-service so its logs can be correlated with ours
+const PROPAGATED_HEADERS: [&str; 3] = ["x-request-id", "traceparent", "tracestate"];
 
 #[tokio::main]
 async fn main() {
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
-        .route("/protected", get(protected));
+        .route("/protected", get(protected))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
@@ -46,8 +54,26 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
-    let jwt_cookie = match jar.get("jwt") {
+// The name auth-service sets its cookie under, from the same AUTH_COOKIE_* settings
+fn auth_cookie_name() -> String {
+    let name = env::var("AUTH_COOKIE_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("jwt".to_owned());
+    let prefix = match env::var("AUTH_COOKIE_PREFIX")
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "host" => "__Host-",
+        "secure" => "__Secure-",
+        _ => "",
+    };
+    format!("{}{}", prefix, name)
+}
+
+async fn protected(headers: HeaderMap, jar: CookieJar) -> impl IntoResponse {
+    let jwt_cookie = match jar.get(&auth_cookie_name()) {
         Some(cookie) => cookie,
         None => {
             return StatusCode::UNAUTHORIZED.into_response();
@@ -63,7 +89,14 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
+    let mut request = api_client.post(&url).json(&verify_token_body);
+    for name in PROPAGATED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            request = request.header(name, value);
+        }
+    }
+
+    let response = match request.send().await {
         Ok(response) => response,
         Err(_) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
@@ -5,8 +5,13 @@ const signupSection = document.getElementById("signup-section");
 
 const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
+const twoFAChannelHint = document.getElementById("2fa-channel-hint");
 const signupLoginLink = document.getElementById("signup-login-link");
 
+// Pages served under /realms/{tenant}/ call that tenant's endpoints
+const realmMatch = window.location.pathname.match(/^\/realms\/[^/]+/);
+const apiBase = realmMatch ? realmMatch[0] : "";
+
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
 
@@ -42,22 +47,26 @@ loginButton.addEventListener("click", (e) => {
 
     const email = loginForm.email.value;
     const password = loginForm.password.value;
+    const rememberMe = loginForm.rememberMe.checked;
 
-    fetch('/login', {
+    fetch(apiBase + '/login', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
         },
-        body: JSON.stringify({ email, password }),
+        body: JSON.stringify({ email, password, rememberMe }),
     }).then(response => {
         if (response.status === 206) {
-            TwoFAForm.email.value = email;
+            TwoFAForm.remember_me.value = rememberMe ? "true" : "";
             response.json().then(data => {
                 TwoFAForm.login_attempt_id.value = data.loginAttemptId;
+                showTwoFAChannel(data.channel);
+                startResendCountdown(data.nextResendAt);
             });
 
             loginForm.email.value = "";
             loginForm.password.value = "";
+            loginForm.rememberMe.checked = false;
 
             loginSection.style.display = "none";
             twoFASection.style.display = "block";
@@ -66,6 +75,7 @@ loginButton.addEventListener("click", (e) => {
         } else if (response.status === 200) {
             loginForm.email.value = "";
             loginForm.password.value = "";
+            loginForm.rememberMe.checked = false;
             loginErrAlter.style.display = "none";
             alert("You have successfully logged in! Redirecting in 1 second...");
             // Redirect back to app-service - Force redirect v2
@@ -88,6 +98,37 @@ loginButton.addEventListener("click", (e) => {
     });
 });
 
+const magicLinkLink = document.getElementById("magic-link-link");
+
+magicLinkLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+
+    fetch(apiBase + '/login/magic-link', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        if (response.status === 202) {
+            loginErrAlter.style.display = "none";
+            alert("Check your email for a sign-in link. Open it in this browser.");
+        } else {
+            response.json().then(data => {
+                let error_msg = data.error;
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    loginErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    loginErrAlter.style.display = "block";
+                } else {
+                    loginErrAlter.style.display = "none";
+                }
+            });
+        }
+    });
+});
+
 const signupForm = document.getElementById("signup-form");
 const signupButton = document.getElementById("signup-form-submit");
 const signupErrAlter = document.getElementById("signup-err-alert");
@@ -99,7 +140,7 @@ signupButton.addEventListener("click", (e) => {
     const password = signupForm.password.value;
     const requires2FA = signupForm.twoFA.checked;
 
-    fetch('/signup', {
+    fetch(apiBase + '/signup', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
@@ -111,7 +152,15 @@ signupButton.addEventListener("click", (e) => {
             signupForm.password.value = "";
             signupForm.twoFA.checked = false;
             signupErrAlter.style.display = "none";
-            alert("You have successfully created a user.");
+            response.json().then(data => {
+                // Recovery codes are only returned once, for 2FA accounts
+                let message = "You have successfully created a user.";
+                if (data.recoveryCodes) {
+                    message += "\n\nSave these recovery codes somewhere safe. Each one can be used once "
+                        + "to sign in if you cannot receive a 2FA code:\n\n" + data.recoveryCodes.join("\n");
+                }
+                alert(message);
+            });
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
@@ -136,21 +185,24 @@ const TwoFAErrAlter = document.getElementById("2fa-err-alert");
 TwoFAButton.addEventListener("click", (e) => {
     e.preventDefault();
 
-    const email = TwoFAForm.email.value;
     const loginAttemptId = TwoFAForm.login_attempt_id.value;
     const TwoFACode = TwoFAForm.email_code.value;
+    const rememberMe = TwoFAForm.remember_me.value === "true";
+    const trustDevice = TwoFAForm.trustDevice.checked;
 
-    fetch('/verify-2fa', {
+    fetch(apiBase + '/verify-2fa', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
         },
-        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode }),
+        body: JSON.stringify({ loginAttemptId, "2FACode": TwoFACode, rememberMe, trustDevice }),
     }).then(response => {
         if (response.ok) {
-            TwoFAForm.email.value = "";
+            startResendCountdown(null);
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
+            TwoFAForm.remember_me.value = "";
+            TwoFAForm.trustDevice.checked = false;
             TwoFAErrAlter.style.display = "none";
             alert("You have successfully logged in! Redirecting in 1 second...");
             // Redirect back to app-service - Force redirect v2
@@ -171,4 +223,71 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+
+function showTwoFAChannel(channel) {
+    twoFAChannelHint.textContent = channel === "sms"
+        ? "Enter the code we sent to your phone."
+        : "Enter the code we sent to your email.";
+}
+
+// The code can be sent again after a cooldown, a limited number of times per login
+const TwoFAResendButton = document.getElementById("2fa-resend");
+let resendTimer = null;
+
+// Count down to `nextResendAt`; null once no more codes can be sent for the login
+function startResendCountdown(nextResendAt) {
+    clearInterval(resendTimer);
+    resendTimer = null;
+    if (nextResendAt === null || nextResendAt === undefined) {
+        TwoFAResendButton.disabled = true;
+        TwoFAResendButton.textContent = "Resend code";
+        return;
+    }
+
+    const resendAt = new Date(nextResendAt).getTime();
+    const tick = () => {
+        const seconds = Math.ceil((resendAt - Date.now()) / 1000);
+        if (seconds > 0) {
+            TwoFAResendButton.disabled = true;
+            TwoFAResendButton.textContent = `Resend code in ${seconds}s`;
+        } else {
+            clearInterval(resendTimer);
+            resendTimer = null;
+            TwoFAResendButton.disabled = false;
+            TwoFAResendButton.textContent = "Resend code";
+        }
+    };
+    tick();
+    if (resendTimer === null && TwoFAResendButton.disabled) {
+        resendTimer = setInterval(tick, 1000);
+    }
+}
+
+TwoFAResendButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const loginAttemptId = TwoFAForm.login_attempt_id.value;
+    TwoFAResendButton.disabled = true;
+
+    fetch(apiBase + '/resend-2fa', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ loginAttemptId }),
+    }).then(response => {
+        response.json().then(data => {
+            if (response.ok) {
+                TwoFAErrAlter.style.display = "none";
+                showTwoFAChannel(data.channel);
+                startResendCountdown(data.nextResendAt);
+            } else {
+                TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                TwoFAErrAlter.style.display = "block";
+                // Only server errors are worth retrying; otherwise the login has to be started over
+                TwoFAResendButton.disabled = response.status < 500;
+            }
+        });
+    });
+});
@@ -0,0 +1,153 @@
+// Load benchmark comparing the old `Arc<RwLock<Box<dyn UserStore>>>` signup path with the
+// lock-free `Arc<dyn UserStore>` path used by `AppState`.
+//
+// Requires the same PostgreSQL instance as the integration tests (`DATABASE_URL`).
+// Run with: cargo bench --bench store_concurrency
+//
+// Password hashing is CPU bound, so the speedup grows with the number of cores available.
+use auth_service::domain::{Email, Password, TenantId, User};
+use auth_service::get_postgres_pool;
+use auth_service::services::data_stores::{PostgresUserStore, UserStore};
+use auth_service::utils::constants::DATABASE_URL;
+use secrecy::SecretBox;
+use sqlx::{Executor, PgPool, postgres::PgPoolOptions};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+const SIGNUPS: usize = 64;
+const TENANT: &str = "default";
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!(
+        "store_concurrency: {} signups, {} worker threads",
+        SIGNUPS, workers
+    );
+
+    let (pool, db_name) = configure_database().await;
+    let store = Arc::new(PostgresUserStore::new(pool.clone()));
+
+    // Warm up the connection pool and the blocking thread pool used for hashing
+    run_signups("warm-up", |user| {
+        let store = store.clone();
+        async move {
+            store
+                .add_user(&TenantId::parse(TENANT).unwrap(), user)
+                .await
+        }
+    })
+    .await;
+
+    // Old behaviour: every signup holds the store's write lock across hashing and the insert
+    let locked: Arc<RwLock<Box<dyn UserStore>>> =
+        Arc::new(RwLock::new(Box::new(PostgresUserStore::new(pool.clone()))));
+    let serialized = run_signups("locked", |user| {
+        let store = locked.clone();
+        async move {
+            store
+                .write()
+                .await
+                .add_user(&TenantId::parse(TENANT).unwrap(), user)
+                .await
+        }
+    })
+    .await;
+
+    // New behaviour: the store is shared without a lock
+    let concurrent = run_signups("lock-free", |user| {
+        let store = store.clone();
+        async move {
+            store
+                .add_user(&TenantId::parse(TENANT).unwrap(), user)
+                .await
+        }
+    })
+    .await;
+
+    println!(
+        "speedup: {:.2}x",
+        serialized.as_secs_f64() / concurrent.as_secs_f64()
+    );
+
+    pool.close().await;
+    drop_database(&db_name).await;
+}
+
+async fn run_signups<F, Fut>(label: &str, signup: F) -> Duration
+where
+    F: Fn(User) -> Fut,
+    Fut: Future<Output = Result<(), auth_service::services::UserStoreError>> + Send + 'static,
+{
+    let start = Instant::now();
+    let handles: Vec<_> = (0..SIGNUPS)
+        .map(|_| tokio::spawn(signup(random_user())))
+        .collect();
+    for handle in handles {
+        handle
+            .await
+            .expect("signup task panicked")
+            .expect("signup failed");
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:>10}: {:>8.1?} total, {:>7.1} signups/s",
+        label,
+        elapsed,
+        SIGNUPS as f64 / elapsed.as_secs_f64()
+    );
+    elapsed
+}
+
+fn random_user() -> User {
+    let email = Email::parse(SecretBox::new(Box::new(format!(
+        "{}@example.com",
+        Uuid::new_v4()
+    ))))
+    .expect("valid email");
+    let password = Password::parse(SecretBox::new(Box::new("password123".to_owned())))
+        .expect("valid password");
+    User::new(email, password, false)
+}
+
+async fn configure_database() -> (PgPool, String) {
+    let db_name = format!("bench_{}", Uuid::new_v4().simple());
+    let connection = PgPoolOptions::new()
+        .connect(&DATABASE_URL)
+        .await
+        .expect("Failed to connect to Postgres.");
+    connection
+        .execute(sqlx::raw_sql(sqlx::AssertSqlSafe(format!(
+            r#"CREATE DATABASE "{}";"#,
+            db_name
+        ))))
+        .await
+        .expect("Failed to create database.");
+
+    let pool = get_postgres_pool(&format!("{}/{}", *DATABASE_URL, db_name))
+        .await
+        .expect("Failed to create Postgres connection pool!");
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .expect("Failed to migrate the database");
+
+    (pool, db_name)
+}
+
+async fn drop_database(db_name: &str) {
+    let connection = PgPoolOptions::new()
+        .connect(&DATABASE_URL)
+        .await
+        .expect("Failed to connect to Postgres.");
+    connection
+        .execute(sqlx::raw_sql(sqlx::AssertSqlSafe(format!(
+            r#"DROP DATABASE "{}" WITH (FORCE);"#,
+            db_name
+        ))))
+        .await
+        .expect("Failed to drop the database.");
+}
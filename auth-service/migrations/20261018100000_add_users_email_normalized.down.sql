@@ -0,0 +1,4 @@
+-- Down migration script for the normalized email column
+DROP INDEX IF EXISTS users_email_normalized_key;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_email_normalized_lowercase;
+ALTER TABLE users DROP COLUMN IF EXISTS email_normalized;
@@ -0,0 +1,28 @@
+-- Store a normalized (lowercase) email so that `Bob@x.com` and `bob@x.com` resolve to the same account
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_normalized TEXT;
+
+UPDATE users SET email_normalized = lower(email) WHERE email_normalized IS NULL;
+
+-- Refuse to migrate if existing accounts only differ by case; they must be merged by hand first
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(email_normalized, ', ')
+    INTO duplicates
+    FROM (
+        SELECT email_normalized
+        FROM users
+        GROUP BY email_normalized
+        HAVING count(*) > 1
+    ) AS duplicate_emails;
+
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'users contains accounts whose emails only differ by case: %', duplicates
+            USING HINT = 'Merge or delete the duplicate accounts, then re-run the migration.';
+    END IF;
+END $$;
+
+ALTER TABLE users ALTER COLUMN email_normalized SET NOT NULL;
+ALTER TABLE users ADD CONSTRAINT users_email_normalized_lowercase CHECK (email_normalized = lower(email_normalized));
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_normalized_key ON users (email_normalized);
@@ -0,0 +1,2 @@
+-- Down migration script for the email outbox
+DROP TABLE IF EXISTS email_outbox;
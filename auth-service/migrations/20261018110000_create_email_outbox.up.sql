@@ -0,0 +1,21 @@
+-- Transactional emails waiting to be delivered by the outbox worker.
+-- Rows are claimed with FOR UPDATE SKIP LOCKED, so several auth-service instances can share the table.
+CREATE TABLE IF NOT EXISTS email_outbox(
+   id UUID NOT NULL PRIMARY KEY,
+   -- Enqueuing the same key twice keeps the first message, so a retried request does not send twice
+   idempotency_key TEXT NOT NULL UNIQUE,
+   template_id TEXT NOT NULL,
+   recipient TEXT NOT NULL,
+   -- The serialized EmailMessage. Cleared once the message is sent or expires, since it can hold one-time codes.
+   message JSONB,
+   status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sending', 'sent', 'dead', 'expired')),
+   attempts INTEGER NOT NULL DEFAULT 0,
+   next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   -- A `sending` row whose lease has run out belonged to a worker that stopped; it is claimed again
+   locked_until TIMESTAMPTZ,
+   last_error TEXT,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx ON email_outbox (next_attempt_at) WHERE status IN ('pending', 'sending');
@@ -0,0 +1,6 @@
+-- Down migration script for phone numbers
+DROP TABLE IF EXISTS phone_verifications;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_sms_requires_phone_number;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_two_fa_channel_valid;
+ALTER TABLE users DROP COLUMN IF EXISTS two_fa_channel;
+ALTER TABLE users DROP COLUMN IF EXISTS phone_number;
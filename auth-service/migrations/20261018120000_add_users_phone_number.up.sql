@@ -0,0 +1,14 @@
+-- A verified phone number lets users receive 2FA codes by SMS instead of email
+ALTER TABLE users ADD COLUMN IF NOT EXISTS phone_number TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS two_fa_channel TEXT NOT NULL DEFAULT 'email';
+ALTER TABLE users ADD CONSTRAINT users_two_fa_channel_valid CHECK (two_fa_channel IN ('email', 'sms'));
+ALTER TABLE users ADD CONSTRAINT users_sms_requires_phone_number CHECK (two_fa_channel <> 'sms' OR phone_number IS NOT NULL);
+
+-- A number waiting to be confirmed with a code sent to it. At most one per user; starting over replaces it.
+CREATE TABLE IF NOT EXISTS phone_verifications(
+   email_normalized TEXT NOT NULL PRIMARY KEY REFERENCES users (email_normalized) ON DELETE CASCADE,
+   phone_number TEXT NOT NULL,
+   code TEXT NOT NULL,
+   attempts INTEGER NOT NULL DEFAULT 0,
+   expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Down migration script for recovery codes
+DROP TABLE IF EXISTS recovery_codes;
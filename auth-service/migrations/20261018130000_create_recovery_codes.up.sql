@@ -0,0 +1,14 @@
+-- One-time codes that stand in for a 2FA code when the user cannot receive one.
+-- Only SHA-256 digests are stored; the codes themselves are shown to the user once.
+CREATE TABLE IF NOT EXISTS recovery_codes(
+   id UUID NOT NULL PRIMARY KEY,
+   email_normalized TEXT NOT NULL REFERENCES users (email_normalized) ON DELETE CASCADE,
+   code_hash TEXT NOT NULL,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   -- Audit trail for a used code
+   used_at TIMESTAMPTZ,
+   used_ip TEXT,
+   used_user_agent TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS recovery_codes_email_code_hash_idx ON recovery_codes (email_normalized, code_hash);
@@ -0,0 +1,24 @@
+-- Down migration script for tenants. Fails if an email is registered in more than one tenant.
+ALTER TABLE email_outbox DROP CONSTRAINT IF EXISTS email_outbox_tenant_idempotency_key_key;
+ALTER TABLE email_outbox ADD CONSTRAINT email_outbox_idempotency_key_key UNIQUE (idempotency_key);
+
+ALTER TABLE recovery_codes DROP CONSTRAINT IF EXISTS recovery_codes_user_fkey;
+DROP INDEX IF EXISTS recovery_codes_tenant_email_code_hash_idx;
+ALTER TABLE phone_verifications DROP CONSTRAINT IF EXISTS phone_verifications_user_fkey;
+ALTER TABLE phone_verifications DROP CONSTRAINT IF EXISTS phone_verifications_pkey;
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_pkey;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (email);
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_normalized_key ON users (email_normalized);
+
+ALTER TABLE phone_verifications ADD CONSTRAINT phone_verifications_pkey PRIMARY KEY (email_normalized);
+ALTER TABLE phone_verifications ADD CONSTRAINT phone_verifications_email_normalized_fkey
+    FOREIGN KEY (email_normalized) REFERENCES users (email_normalized) ON DELETE CASCADE;
+CREATE UNIQUE INDEX IF NOT EXISTS recovery_codes_email_code_hash_idx ON recovery_codes (email_normalized, code_hash);
+ALTER TABLE recovery_codes ADD CONSTRAINT recovery_codes_email_normalized_fkey
+    FOREIGN KEY (email_normalized) REFERENCES users (email_normalized) ON DELETE CASCADE;
+
+ALTER TABLE email_outbox DROP COLUMN IF EXISTS tenant_id;
+ALTER TABLE recovery_codes DROP COLUMN IF EXISTS tenant_id;
+ALTER TABLE phone_verifications DROP COLUMN IF EXISTS tenant_id;
+ALTER TABLE users DROP COLUMN IF EXISTS tenant_id;
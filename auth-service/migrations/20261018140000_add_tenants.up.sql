@@ -0,0 +1,34 @@
+-- Tenants (realms) have separate user bases, so an email is unique per tenant rather than globally.
+-- Existing rows belong to the `default` tenant.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE phone_verifications ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE recovery_codes ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE email_outbox ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default';
+
+-- The foreign keys depend on the unique email index, so they go first
+ALTER TABLE phone_verifications DROP CONSTRAINT IF EXISTS phone_verifications_email_normalized_fkey;
+ALTER TABLE recovery_codes DROP CONSTRAINT IF EXISTS recovery_codes_email_normalized_fkey;
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_pkey;
+DROP INDEX IF EXISTS users_email_normalized_key;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (tenant_id, email_normalized);
+
+ALTER TABLE phone_verifications DROP CONSTRAINT IF EXISTS phone_verifications_pkey;
+ALTER TABLE phone_verifications ADD CONSTRAINT phone_verifications_pkey PRIMARY KEY (tenant_id, email_normalized);
+ALTER TABLE phone_verifications ADD CONSTRAINT phone_verifications_user_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE;
+
+DROP INDEX IF EXISTS recovery_codes_email_code_hash_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS recovery_codes_tenant_email_code_hash_idx ON recovery_codes (tenant_id, email_normalized, code_hash);
+ALTER TABLE recovery_codes ADD CONSTRAINT recovery_codes_user_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE;
+
+-- Idempotency keys only need to be unique within a tenant
+ALTER TABLE email_outbox DROP CONSTRAINT IF EXISTS email_outbox_idempotency_key_key;
+ALTER TABLE email_outbox ADD CONSTRAINT email_outbox_tenant_idempotency_key_key UNIQUE (tenant_id, idempotency_key);
+
+-- From now on every row names its tenant explicitly
+ALTER TABLE users ALTER COLUMN tenant_id DROP DEFAULT;
+ALTER TABLE phone_verifications ALTER COLUMN tenant_id DROP DEFAULT;
+ALTER TABLE recovery_codes ALTER COLUMN tenant_id DROP DEFAULT;
+ALTER TABLE email_outbox ALTER COLUMN tenant_id DROP DEFAULT;
@@ -0,0 +1,2 @@
+-- Down migration script for remember me tokens
+DROP TABLE IF EXISTS remember_me_tokens;
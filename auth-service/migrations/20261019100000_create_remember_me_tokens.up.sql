@@ -0,0 +1,20 @@
+-- Long-lived "remember me" credentials. Browsers keep `<id>.<secret>` in a cookie; only a SHA-256
+-- digest of the secret is stored, and the secret is replaced every time the token is used.
+CREATE TABLE IF NOT EXISTS remember_me_tokens(
+   id UUID NOT NULL PRIMARY KEY,
+   tenant_id TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   secret_hash TEXT NOT NULL,
+   -- The secret replaced last; presenting it again means the cookie was copied
+   previous_secret_hash TEXT,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   last_used_at TIMESTAMPTZ,
+   -- Fixed when the token is issued; renewals do not extend it
+   expires_at TIMESTAMPTZ NOT NULL,
+   -- The device the token was issued to, for the account owner's reference
+   ip_address TEXT,
+   user_agent TEXT,
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS remember_me_tokens_tenant_email_idx ON remember_me_tokens (tenant_id, email_normalized);
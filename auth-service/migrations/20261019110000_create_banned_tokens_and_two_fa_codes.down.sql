@@ -0,0 +1,3 @@
+-- Down migration script for banned tokens and 2FA codes
+DROP TABLE IF EXISTS two_fa_codes;
+DROP TABLE IF EXISTS banned_tokens;
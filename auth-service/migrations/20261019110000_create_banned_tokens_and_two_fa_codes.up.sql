@@ -0,0 +1,25 @@
+-- Postgres stand-ins for the Redis keys of the same names, used with STORE_BACKEND=postgres.
+-- Rows past `expires_at` are ignored, and deleted by the expired row sweeper.
+
+-- Revoked JWTs, by their `jti`
+CREATE TABLE IF NOT EXISTS banned_tokens(
+   tenant_id TEXT NOT NULL,
+   jti TEXT NOT NULL,
+   expires_at TIMESTAMPTZ NOT NULL,
+   PRIMARY KEY (tenant_id, jti)
+);
+
+CREATE INDEX IF NOT EXISTS banned_tokens_expires_at_idx ON banned_tokens (expires_at);
+
+-- The pending 2FA login of each account
+CREATE TABLE IF NOT EXISTS two_fa_codes(
+   tenant_id TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   login_attempt_id UUID NOT NULL,
+   code TEXT NOT NULL,
+   expires_at TIMESTAMPTZ NOT NULL,
+   PRIMARY KEY (tenant_id, email_normalized),
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS two_fa_codes_expires_at_idx ON two_fa_codes (expires_at);
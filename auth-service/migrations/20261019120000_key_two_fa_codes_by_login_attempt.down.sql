@@ -0,0 +1,8 @@
+-- Down migration script for keying 2FA codes by login attempt
+DELETE FROM two_fa_codes;
+
+DROP INDEX IF EXISTS two_fa_codes_account_idx;
+ALTER TABLE two_fa_codes DROP COLUMN IF EXISTS created_at;
+ALTER TABLE two_fa_codes DROP COLUMN IF EXISTS client_binding;
+ALTER TABLE two_fa_codes DROP CONSTRAINT two_fa_codes_pkey;
+ALTER TABLE two_fa_codes ADD PRIMARY KEY (tenant_id, email_normalized);
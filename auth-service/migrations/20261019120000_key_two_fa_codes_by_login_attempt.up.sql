@@ -0,0 +1,10 @@
+-- Key pending 2FA logins by login attempt, so an account can have several at once,
+-- and bind each to the client that started it. Pending logins are not carried over.
+DELETE FROM two_fa_codes;
+
+ALTER TABLE two_fa_codes DROP CONSTRAINT two_fa_codes_pkey;
+ALTER TABLE two_fa_codes ADD PRIMARY KEY (tenant_id, login_attempt_id);
+ALTER TABLE two_fa_codes ADD COLUMN client_binding TEXT NOT NULL;
+ALTER TABLE two_fa_codes ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX IF NOT EXISTS two_fa_codes_account_idx ON two_fa_codes (tenant_id, email_normalized);
@@ -0,0 +1,3 @@
+-- Down migration script for 2FA code resends
+ALTER TABLE two_fa_codes DROP COLUMN IF EXISTS resends;
+ALTER TABLE two_fa_codes DROP COLUMN IF EXISTS sent_at;
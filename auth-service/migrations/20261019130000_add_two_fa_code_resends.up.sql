@@ -0,0 +1,3 @@
+-- When the current code of a pending 2FA login was sent, and how many times it was sent again
+ALTER TABLE two_fa_codes ADD COLUMN IF NOT EXISTS sent_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE two_fa_codes ADD COLUMN IF NOT EXISTS resends INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Down migration script for trusted devices
+DROP TABLE IF EXISTS trusted_devices;
@@ -0,0 +1,16 @@
+-- Devices that passed 2FA and skip it on later logins until `expires_at`. Browsers keep a token
+-- signed for the device's ID and account in a cookie; deleting the row revokes it.
+CREATE TABLE IF NOT EXISTS trusted_devices(
+   id UUID NOT NULL PRIMARY KEY,
+   tenant_id TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   last_used_at TIMESTAMPTZ,
+   expires_at TIMESTAMPTZ NOT NULL,
+   -- The device that was trusted, so the account owner can tell their devices apart
+   ip_address TEXT,
+   user_agent TEXT,
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS trusted_devices_tenant_email_idx ON trusted_devices (tenant_id, email_normalized);
@@ -0,0 +1,2 @@
+-- Down migration script for known devices
+DROP TABLE IF EXISTS known_devices;
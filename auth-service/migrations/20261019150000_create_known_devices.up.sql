@@ -0,0 +1,11 @@
+-- The devices each account has signed in from, so that sign-ins from a new one can be reported
+-- to the account owner. A device is the fingerprint of its user agent and network, see ClientInfo.
+CREATE TABLE IF NOT EXISTS known_devices(
+   tenant_id TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   fingerprint TEXT NOT NULL,
+   first_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   PRIMARY KEY (tenant_id, email_normalized, fingerprint),
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE
+);
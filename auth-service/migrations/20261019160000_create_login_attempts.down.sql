@@ -0,0 +1,2 @@
+-- Down migration script for login attempts
+DROP TABLE IF EXISTS login_attempts;
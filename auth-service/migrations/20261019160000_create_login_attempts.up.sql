@@ -0,0 +1,22 @@
+-- Password logins to existing accounts with the risk decision made for them, see RiskEngine.
+-- They are the history that impossible travel and recent failures are judged from.
+CREATE TABLE IF NOT EXISTS login_attempts(
+   id UUID NOT NULL PRIMARY KEY,
+   tenant_id TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   attempted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   expires_at TIMESTAMPTZ NOT NULL,
+   ip_address TEXT,
+   user_agent TEXT,
+   -- Where the GeoIP database placed the client, if configured
+   country TEXT,
+   latitude DOUBLE PRECISION,
+   longitude DOUBLE PRECISION,
+   -- NULL when the password was wrong, so the login was not scored
+   decision TEXT CHECK (decision IN ('allow', 'challenge', 'deny')),
+   score INTEGER NOT NULL DEFAULT 0,
+   reasons TEXT[] NOT NULL DEFAULT '{}',
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS login_attempts_tenant_email_idx ON login_attempts (tenant_id, email_normalized, attempted_at DESC);
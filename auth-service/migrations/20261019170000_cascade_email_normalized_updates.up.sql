@@ -0,0 +1,36 @@
+-- Let a change to a user's canonical email follow into the rows that belong to them, so that
+-- `email_normalized` can be recomputed when the canonical form changes, see EmailRenormalizer
+ALTER TABLE phone_verifications DROP CONSTRAINT phone_verifications_user_fkey;
+ALTER TABLE phone_verifications ADD CONSTRAINT phone_verifications_user_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE recovery_codes DROP CONSTRAINT recovery_codes_user_fkey;
+ALTER TABLE recovery_codes ADD CONSTRAINT recovery_codes_user_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE remember_me_tokens DROP CONSTRAINT remember_me_tokens_tenant_id_email_normalized_fkey;
+ALTER TABLE remember_me_tokens ADD CONSTRAINT remember_me_tokens_tenant_id_email_normalized_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE two_fa_codes DROP CONSTRAINT two_fa_codes_tenant_id_email_normalized_fkey;
+ALTER TABLE two_fa_codes ADD CONSTRAINT two_fa_codes_tenant_id_email_normalized_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE trusted_devices DROP CONSTRAINT trusted_devices_tenant_id_email_normalized_fkey;
+ALTER TABLE trusted_devices ADD CONSTRAINT trusted_devices_tenant_id_email_normalized_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE known_devices DROP CONSTRAINT known_devices_tenant_id_email_normalized_fkey;
+ALTER TABLE known_devices ADD CONSTRAINT known_devices_tenant_id_email_normalized_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE login_attempts DROP CONSTRAINT login_attempts_tenant_id_email_normalized_fkey;
+ALTER TABLE login_attempts ADD CONSTRAINT login_attempts_tenant_id_email_normalized_fkey
+    FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+    ON DELETE CASCADE ON UPDATE CASCADE;
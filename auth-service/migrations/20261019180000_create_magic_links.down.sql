@@ -0,0 +1,2 @@
+-- Down migration script for magic links
+DROP TABLE IF EXISTS magic_links;
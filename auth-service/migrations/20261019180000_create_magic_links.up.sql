@@ -0,0 +1,20 @@
+-- Pending magic link sign-ins, for deployments without Redis. Rows past `expires_at` are ignored,
+-- and deleted by the expired row sweeper.
+CREATE TABLE IF NOT EXISTS magic_links(
+   tenant_id TEXT NOT NULL,
+   id TEXT NOT NULL,
+   -- The address as entered, and its canonical form for the account
+   email TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   browser_binding TEXT NOT NULL,
+   confirmation_code_hash TEXT NOT NULL,
+   requested_at TIMESTAMPTZ NOT NULL,
+   expires_at TIMESTAMPTZ NOT NULL,
+   ip_address TEXT,
+   user_agent TEXT,
+   PRIMARY KEY (tenant_id, id),
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+      ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS magic_links_expires_at_idx ON magic_links (expires_at);
@@ -0,0 +1,3 @@
+-- Down migration script for the network of login attempts
+DROP INDEX IF EXISTS login_attempts_tenant_email_prefix_idx;
+ALTER TABLE login_attempts DROP COLUMN IF EXISTS ip_prefix;
@@ -0,0 +1,6 @@
+-- The network of the client, see ClientInfo::ip_prefix. Wrong passwords only count towards the risk of
+-- later logins from the same network, so that strangers cannot push an account towards being denied.
+ALTER TABLE login_attempts ADD COLUMN IF NOT EXISTS ip_prefix TEXT;
+
+CREATE INDEX IF NOT EXISTS login_attempts_tenant_email_prefix_idx
+    ON login_attempts (tenant_id, email_normalized, ip_prefix, attempted_at DESC);
@@ -0,0 +1,2 @@
+-- Down migration script for 2FA code attempts
+ALTER TABLE two_fa_codes DROP COLUMN IF EXISTS attempts;
@@ -0,0 +1,2 @@
+-- How many wrong codes were entered for a pending 2FA login
+ALTER TABLE two_fa_codes ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Down migration script for used sign-in alerts
+DROP TABLE IF EXISTS used_sign_in_alerts;
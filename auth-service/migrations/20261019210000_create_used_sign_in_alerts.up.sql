@@ -0,0 +1,13 @@
+-- The "this wasn't me" links that were confirmed, so that each works once. Keyed by the session the
+-- alert was about, since every new device sign-in has its own session and its own alert.
+CREATE TABLE IF NOT EXISTS used_sign_in_alerts(
+   tenant_id TEXT NOT NULL,
+   session_id TEXT NOT NULL,
+   email_normalized TEXT NOT NULL,
+   used_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   -- When the link would have expired anyway; the row can go after that
+   expires_at TIMESTAMPTZ NOT NULL,
+   PRIMARY KEY (tenant_id, session_id),
+   FOREIGN KEY (tenant_id, email_normalized) REFERENCES users (tenant_id, email_normalized)
+      ON DELETE CASCADE ON UPDATE CASCADE
+);
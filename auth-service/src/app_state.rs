@@ -1,13 +1,30 @@
-use crate::services::data_stores::{BannedTokenStore, TwoFACodeStore, UserStore};
-use crate::services::postmark_email_client::PostmarkEmailClient;
+use crate::domain::{
+    AuthCookieSettings, CorsPolicy, EmailClient, RiskEngine, SmsClient, StepUpMaxAges,
+    TrustedProxies, TwoFAResendPolicy,
+};
+use crate::services::data_stores::{
+    BannedTokenStore, EmailOutbox, KnownDeviceStore, LoginAttemptStore, MagicLinkStore,
+    RecoveryCodeStore, RememberMeStore, TrustedDeviceStore, TwoFACodeStore, UserStore,
+};
+use crate::services::{DevMailbox, ReloadableCorsPolicy, TenantRegistry};
+use secrecy::SecretBox;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 // Using a type alias to improve readability!
-pub type UserStoreType = Arc<RwLock<Box<dyn UserStore>>>;
-pub type BannedTokenStoreType = Arc<RwLock<Box<dyn BannedTokenStore>>>;
-pub type TwoFACodeStoreType = Arc<RwLock<Box<dyn TwoFACodeStore>>>;
-pub type EmailClientType = Arc<RwLock<Box<PostmarkEmailClient>>>;
+// The stores take `&self` and handle concurrency internally (connection pools, DashMap),
+// so they are shared without a global lock.
+pub type UserStoreType = Arc<dyn UserStore>;
+pub type BannedTokenStoreType = Arc<dyn BannedTokenStore>;
+pub type TwoFACodeStoreType = Arc<dyn TwoFACodeStore>;
+pub type EmailClientType = Arc<dyn EmailClient>;
+pub type EmailOutboxType = Arc<dyn EmailOutbox>;
+pub type SmsClientType = Arc<dyn SmsClient>;
+pub type RecoveryCodeStoreType = Arc<dyn RecoveryCodeStore>;
+pub type MagicLinkStoreType = Arc<dyn MagicLinkStore>;
+pub type RememberMeStoreType = Arc<dyn RememberMeStore>;
+pub type TrustedDeviceStoreType = Arc<dyn TrustedDeviceStore>;
+pub type KnownDeviceStoreType = Arc<dyn KnownDeviceStore>;
+pub type LoginAttemptStoreType = Arc<dyn LoginAttemptStore>;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -15,20 +32,119 @@ pub struct AppState {
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
     pub email_client: EmailClientType,
+    pub email_outbox: EmailOutboxType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub magic_link_store: MagicLinkStoreType,
+    pub remember_me_store: RememberMeStoreType,
+    pub trusted_device_store: TrustedDeviceStoreType,
+    pub known_device_store: KnownDeviceStoreType,
+    pub login_attempt_store: LoginAttemptStoreType,
+    // Every request is served on behalf of one of these tenants, see utils::tenant
+    pub tenants: Arc<TenantRegistry>,
+    // Which browser origins may call the API; no cross-origin access unless configured
+    pub cors_policy: Arc<ReloadableCorsPolicy>,
+    // Name and attributes of the auth cookie, and of the CSRF cookie issued with it
+    pub auth_cookie: Arc<AuthCookieSettings>,
+    // Bearer token for the /admin endpoints; they respond with 404 when this is None
+    pub admin_api_token: Option<Arc<SecretBox<String>>>,
+    // Set when the dev mailbox is the email client, which enables the /dev/mailbox page
+    pub dev_mailbox: Option<Arc<DevMailbox>>,
+    // SMS is optional; without a client, phone verification is unavailable and 2FA codes go by email
+    pub sms_client: Option<SmsClientType>,
+    // How often users can ask for the code of a pending 2FA login again
+    pub two_fa_resend: TwoFAResendPolicy,
+    // Scores logins, which can then need a code or be refused, see utils::login_risk
+    pub risk_engine: Arc<RiskEngine>,
+    // The proxies whose forwarded client addresses are believed; none unless configured
+    pub trusted_proxies: Arc<TrustedProxies>,
+    // How recently sessions must have authenticated to use each sensitive route
+    pub step_up_max_ages: StepUpMaxAges,
 }
 
 impl AppState {
+    // Every store is required; optional services are added with the `with_*` methods below
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
         email_client: EmailClientType,
+        email_outbox: EmailOutboxType,
+        recovery_code_store: RecoveryCodeStoreType,
+        magic_link_store: MagicLinkStoreType,
+        remember_me_store: RememberMeStoreType,
+        trusted_device_store: TrustedDeviceStoreType,
+        known_device_store: KnownDeviceStoreType,
+        login_attempt_store: LoginAttemptStoreType,
+        tenants: Arc<TenantRegistry>,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
             two_fa_code_store,
             email_client,
+            email_outbox,
+            recovery_code_store,
+            magic_link_store,
+            remember_me_store,
+            trusted_device_store,
+            known_device_store,
+            login_attempt_store,
+            tenants,
+            cors_policy: Arc::new(ReloadableCorsPolicy::fixed(CorsPolicy::default())),
+            auth_cookie: Arc::new(AuthCookieSettings::default()),
+            admin_api_token: None,
+            dev_mailbox: None,
+            sms_client: None,
+            two_fa_resend: TwoFAResendPolicy::default(),
+            risk_engine: Arc::new(RiskEngine::default()),
+            trusted_proxies: Arc::new(TrustedProxies::default()),
+            step_up_max_ages: StepUpMaxAges::default(),
         }
     }
+
+    pub fn with_cors_policy(mut self, cors_policy: Arc<ReloadableCorsPolicy>) -> Self {
+        self.cors_policy = cors_policy;
+        self
+    }
+
+    pub fn with_auth_cookie(mut self, auth_cookie: AuthCookieSettings) -> Self {
+        self.auth_cookie = Arc::new(auth_cookie);
+        self
+    }
+
+    pub fn with_admin_api_token(mut self, token: Option<SecretBox<String>>) -> Self {
+        self.admin_api_token = token.map(Arc::new);
+        self
+    }
+
+    pub fn with_dev_mailbox(mut self, mailbox: Option<Arc<DevMailbox>>) -> Self {
+        self.dev_mailbox = mailbox;
+        self
+    }
+
+    pub fn with_sms_client(mut self, sms_client: Option<SmsClientType>) -> Self {
+        self.sms_client = sms_client;
+        self
+    }
+
+    pub fn with_two_fa_resend(mut self, two_fa_resend: TwoFAResendPolicy) -> Self {
+        self.two_fa_resend = two_fa_resend;
+        self
+    }
+
+    pub fn with_risk_engine(mut self, risk_engine: RiskEngine) -> Self {
+        self.risk_engine = Arc::new(risk_engine);
+        self
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Arc::new(trusted_proxies);
+        self
+    }
+
+    pub fn with_step_up_max_ages(mut self, step_up_max_ages: StepUpMaxAges) -> Self {
+        self.step_up_max_ages = step_up_max_ages;
+        self
+    }
 }
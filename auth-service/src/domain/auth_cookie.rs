@@ -0,0 +1,304 @@
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use color_eyre::eyre::{Report, Result, eyre};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::services::{MAGIC_LINK_TTL_SECONDS, TWO_FA_CODE_TTL_SECONDS};
+use crate::utils::{
+    auth::TOKEN_TTL_SECONDS,
+    constants::{
+        DEFAULT_REMEMBER_ME_MAX_AGE_SECONDS, DEFAULT_TRUSTED_DEVICE_MAX_AGE_SECONDS,
+        JWT_COOKIE_NAME, MAGIC_LINK_COOKIE_NAME, REMEMBER_ME_COOKIE_NAME,
+        TRUSTED_DEVICE_COOKIE_NAME, TWO_FA_COOKIE_NAME,
+    },
+};
+
+// Cookie name prefixes that browsers enforce: `__Secure-` cookies must be Secure, and `__Host-`
+// cookies must also have Path=/ and no Domain, so no other host can set or overwrite them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookiePrefix {
+    None,
+    Secure,
+    Host,
+}
+
+impl CookiePrefix {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CookiePrefix::None => "",
+            CookiePrefix::Secure => "__Secure-",
+            CookiePrefix::Host => "__Host-",
+        }
+    }
+}
+
+impl FromStr for CookiePrefix {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" | "" => Ok(Self::None),
+            "secure" => Ok(Self::Secure),
+            "host" => Ok(Self::Host),
+            _ => Err(eyre!("Unknown cookie prefix: {}", s)),
+        }
+    }
+}
+
+// How the auth cookie is set. The CSRF cookie shares its attributes, except that scripts can
+// read it, so the two always reach the same hosts and expire together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthCookieSettings {
+    // The name without its prefix
+    pub name: String,
+    pub prefix: CookiePrefix,
+    pub secure: bool,
+    // Share the cookie with the subdomains of this domain, e.g. `example.com`; host-only when None
+    pub domain: Option<String>,
+    pub same_site: SameSite,
+    // How long browsers keep the cookie; a session cookie when None
+    pub max_age: Option<Duration>,
+    // The longest a "remember me" login lasts before the password is needed again
+    pub remember_me_max_age: Duration,
+    // How long a device trusted at 2FA skips it on later logins
+    pub trusted_device_max_age: Duration,
+}
+
+impl Default for AuthCookieSettings {
+    // A host-only cookie that expires with the token
+    fn default() -> Self {
+        Self {
+            name: JWT_COOKIE_NAME.to_owned(),
+            prefix: CookiePrefix::None,
+            secure: false,
+            domain: None,
+            same_site: SameSite::Lax,
+            max_age: Some(Duration::from_secs(TOKEN_TTL_SECONDS as u64)),
+            remember_me_max_age: Duration::from_secs(DEFAULT_REMEMBER_ME_MAX_AGE_SECONDS),
+            trusted_device_max_age: Duration::from_secs(DEFAULT_TRUSTED_DEVICE_MAX_AGE_SECONDS),
+        }
+    }
+}
+
+impl AuthCookieSettings {
+    // Rejects combinations that browsers would silently drop
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.starts_with("__") {
+            return Err(eyre!(
+                "Invalid auth cookie name, set the prefix separately: {}",
+                self.name
+            ));
+        }
+        if self.prefix != CookiePrefix::None && !self.secure {
+            return Err(eyre!(
+                "{} cookies must be Secure",
+                self.prefix.as_str().trim_end_matches('-')
+            ));
+        }
+        if self.prefix == CookiePrefix::Host && self.domain.is_some() {
+            return Err(eyre!("__Host cookies cannot have a Domain"));
+        }
+        if self.same_site == SameSite::None && !self.secure {
+            return Err(eyre!("SameSite=None cookies must be Secure"));
+        }
+        Ok(())
+    }
+
+    pub fn parse_same_site(s: &str) -> Result<SameSite> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(eyre!("Unknown SameSite value: {}", s)),
+        }
+    }
+
+    // The name the cookie is sent under, e.g. `__Host-jwt`
+    pub fn cookie_name(&self) -> String {
+        format!("{}{}", self.prefix.as_str(), self.name)
+    }
+
+    pub fn auth_cookie(&self, token: String) -> Cookie<'static> {
+        let mut cookie = self.build(self.cookie_name(), token);
+        cookie.set_http_only(true); // prevent JavaScript from accessing the cookie
+        cookie
+    }
+
+    // The "remember me" token's cookie, prefixed like the auth cookie, e.g. `__Host-remember_me`
+    pub fn remember_me_cookie_name(&self) -> String {
+        format!("{}{}", self.prefix.as_str(), REMEMBER_ME_COOKIE_NAME)
+    }
+
+    // Kept until the token expires, however short-lived the auth cookie is
+    pub fn remember_me_cookie(&self, token: String, max_age: Duration) -> Cookie<'static> {
+        let mut cookie = self.build(self.remember_me_cookie_name(), token);
+        cookie.set_http_only(true);
+        cookie.set_max_age(time::Duration::try_from(max_age).unwrap_or(time::Duration::MAX));
+        cookie
+    }
+
+    // The trusted device's cookie, prefixed like the auth cookie, e.g. `__Host-trusted_device`
+    pub fn trusted_device_cookie_name(&self) -> String {
+        format!("{}{}", self.prefix.as_str(), TRUSTED_DEVICE_COOKIE_NAME)
+    }
+
+    // Kept for as long as the device is trusted
+    pub fn trusted_device_cookie(&self, token: String) -> Cookie<'static> {
+        let mut cookie = self.build(self.trusted_device_cookie_name(), token);
+        cookie.set_http_only(true);
+        cookie.set_max_age(
+            time::Duration::try_from(self.trusted_device_max_age).unwrap_or(time::Duration::MAX),
+        );
+        cookie
+    }
+
+    // The cookie that binds pending 2FA logins to the browser, e.g. `__Host-two_fa_browser`
+    pub fn two_fa_cookie_name(&self) -> String {
+        format!("{}{}", self.prefix.as_str(), TWO_FA_COOKIE_NAME)
+    }
+
+    // Kept as long as a 2FA code stays valid
+    pub fn two_fa_cookie(&self, nonce: String) -> Cookie<'static> {
+        let mut cookie = self.build(self.two_fa_cookie_name(), nonce);
+        cookie.set_http_only(true);
+        cookie.set_max_age(time::Duration::seconds(TWO_FA_CODE_TTL_SECONDS as i64));
+        cookie
+    }
+
+    // The cookie that binds magic links to the browser that asked for them. It is scoped to the
+    // magic link endpoints under `path`, which `__Host-` cookies cannot be, so those get `__Secure-`
+    // instead, e.g. `__Secure-magic_link_browser`.
+    pub fn magic_link_cookie_name(&self) -> String {
+        let prefix = match self.prefix {
+            CookiePrefix::Host => CookiePrefix::Secure,
+            prefix => prefix,
+        };
+        format!("{}{}", prefix.as_str(), MAGIC_LINK_COOKIE_NAME)
+    }
+
+    // Kept as long as a magic link stays valid. Always Lax, so that it is sent when the link is
+    // opened from an email.
+    pub fn magic_link_cookie(&self, nonce: String, path: String) -> Cookie<'static> {
+        let mut cookie = self.build(self.magic_link_cookie_name(), nonce);
+        cookie.set_path(path);
+        cookie.set_http_only(true);
+        cookie.set_same_site(SameSite::Lax);
+        cookie.set_max_age(time::Duration::seconds(MAGIC_LINK_TTL_SECONDS as i64));
+        cookie
+    }
+
+    // Scripts read this one, so it is not HttpOnly
+    pub fn csrf_cookie(&self, name: &str, token: String) -> Cookie<'static> {
+        self.build(name.to_owned(), token)
+    }
+
+    // A cookie with the attributes of the one set under `name`. Browsers only replace a cookie
+    // with the same name, Domain and Path, so removals must be built from this.
+    pub fn removal(&self, name: String) -> Cookie<'static> {
+        self.build(name, String::new())
+    }
+
+    fn build(&self, name: String, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::build((name, value))
+            .path("/") // apply cookie to all URLs on the server
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .build();
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain.clone());
+        }
+        if let Some(max_age) = self.max_age {
+            cookie.set_max_age(time::Duration::try_from(max_age).unwrap_or(time::Duration::MAX));
+        }
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_cookie_attributes() {
+        let settings = AuthCookieSettings {
+            prefix: CookiePrefix::Secure,
+            secure: true,
+            domain: Some("example.com".to_owned()),
+            same_site: SameSite::Strict,
+            max_age: Some(Duration::from_secs(600)),
+            ..AuthCookieSettings::default()
+        };
+        settings.validate().unwrap();
+
+        let cookie = settings.auth_cookie("token".to_owned());
+        assert_eq!(cookie.name(), "__Secure-jwt");
+        assert_eq!(cookie.value(), "token");
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert_eq!(cookie.max_age().map(|age| age.whole_seconds()), Some(600));
+
+        let removal = settings.removal(settings.cookie_name());
+        assert_eq!(removal.name(), cookie.name());
+        assert_eq!(removal.domain(), cookie.domain());
+        assert_eq!(removal.path(), cookie.path());
+    }
+
+    #[test]
+    fn test_magic_link_cookie_attributes() {
+        let settings = AuthCookieSettings {
+            prefix: CookiePrefix::Host,
+            secure: true,
+            same_site: SameSite::Strict,
+            ..AuthCookieSettings::default()
+        };
+        settings.validate().unwrap();
+
+        let cookie = settings.magic_link_cookie("nonce".to_owned(), "/login/magic-link".to_owned());
+        assert_eq!(cookie.name(), "__Secure-magic_link_browser");
+        assert_eq!(cookie.path(), Some("/login/magic-link"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(
+            cookie.max_age().map(|age| age.whole_seconds()),
+            Some(MAGIC_LINK_TTL_SECONDS as i64)
+        );
+
+        let shared = AuthCookieSettings {
+            domain: Some("example.com".to_owned()),
+            ..AuthCookieSettings::default()
+        };
+        let cookie = shared.magic_link_cookie("nonce".to_owned(), "/login/magic-link".to_owned());
+        assert_eq!(cookie.name(), "magic_link_browser");
+        assert_eq!(cookie.domain(), Some("example.com"));
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        let insecure = AuthCookieSettings {
+            prefix: CookiePrefix::Host,
+            ..AuthCookieSettings::default()
+        };
+        assert!(insecure.validate().is_err());
+
+        let with_domain = AuthCookieSettings {
+            prefix: CookiePrefix::Host,
+            secure: true,
+            domain: Some("example.com".to_owned()),
+            ..AuthCookieSettings::default()
+        };
+        assert!(with_domain.validate().is_err());
+
+        let cross_site = AuthCookieSettings {
+            same_site: SameSite::None,
+            ..AuthCookieSettings::default()
+        };
+        assert!(cross_site.validate().is_err());
+
+        assert!(AuthCookieSettings::parse_same_site("sometimes").is_err());
+        assert!("subdomain".parse::<CookiePrefix>().is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::utils::constants::DEFAULT_STEP_UP_MAX_AGE_SECONDS;
+
+// How the user proved who they are, as the `amr` values of RFC 8176
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    // The account password
+    Pwd,
+    // A code sent by email or SMS, or a recovery code
+    Otp,
+    // A security key or passkey
+    Webauthn,
+}
+
+// The `acr` of a session, after the authenticator assurance levels of NIST SP 800-63B
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthLevel {
+    // One factor
+    Aal1,
+    // Two factors
+    Aal2,
+}
+
+// When and how a session was authenticated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authentication {
+    pub time: DateTime<Utc>,
+    pub methods: Vec<AuthMethod>,
+}
+
+impl Authentication {
+    pub fn now(methods: Vec<AuthMethod>) -> Self {
+        Self {
+            time: Utc::now(),
+            methods,
+        }
+    }
+
+    pub fn level(&self) -> AuthLevel {
+        if self.methods.len() > 1 {
+            AuthLevel::Aal2
+        } else {
+            AuthLevel::Aal1
+        }
+    }
+}
+
+// How recent and strong the authentication of a session must be for a route, see
+// utils::step_up::require_step_up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepUpPolicy {
+    // The oldest `auth_time` the route accepts
+    pub max_age: Duration,
+    // Accounts with 2FA must have used their second factor
+    pub second_factor: bool,
+}
+
+impl Default for StepUpPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(DEFAULT_STEP_UP_MAX_AGE_SECONDS),
+            second_factor: true,
+        }
+    }
+}
+
+// The max_age of each guarded route. They all default to DEFAULT_STEP_UP_MAX_AGE_SECONDS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepUpMaxAges {
+    // POST /change-password
+    pub change_password: Duration,
+    // PUT /2fa-channel
+    pub two_fa_channel: Duration,
+    // POST /recovery-codes
+    pub recovery_codes: Duration,
+    // The other guarded routes, e.g. adding a phone number
+    pub default: Duration,
+}
+
+impl Default for StepUpMaxAges {
+    fn default() -> Self {
+        let max_age = Duration::from_secs(DEFAULT_STEP_UP_MAX_AGE_SECONDS);
+        Self {
+            change_password: max_age,
+            two_fa_channel: max_age,
+            recovery_codes: max_age,
+            default: max_age,
+        }
+    }
+}
+
+impl StepUpPolicy {
+    // Whether a session authenticated as given may use the route. Sessions that do not say how
+    // they were authenticated, e.g. refreshed with a "remember me" cookie, never may.
+    pub fn allows(
+        &self,
+        authentication: Option<&Authentication>,
+        uses_2fa: bool,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let Some(authentication) = authentication else {
+            return false;
+        };
+        let max_age = TimeDelta::from_std(self.max_age).unwrap_or(TimeDelta::MAX);
+        let recent = now - authentication.time <= max_age;
+        let strong_enough =
+            !(self.second_factor && uses_2fa) || authentication.level() >= AuthLevel::Aal2;
+        recent && strong_enough
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticated(minutes_ago: i64, methods: Vec<AuthMethod>) -> Authentication {
+        Authentication {
+            time: Utc::now() - TimeDelta::minutes(minutes_ago),
+            methods,
+        }
+    }
+
+    #[test]
+    fn test_a_second_factor_raises_the_level() {
+        assert_eq!(
+            authenticated(0, vec![AuthMethod::Pwd]).level(),
+            AuthLevel::Aal1
+        );
+        assert_eq!(
+            authenticated(0, vec![AuthMethod::Pwd, AuthMethod::Otp]).level(),
+            AuthLevel::Aal2
+        );
+    }
+
+    #[test]
+    fn test_step_up_requires_recent_authentication() {
+        let policy = StepUpPolicy {
+            max_age: Duration::from_secs(300),
+            second_factor: false,
+        };
+        let now = Utc::now();
+        assert!(policy.allows(Some(&authenticated(4, vec![AuthMethod::Pwd])), false, now));
+        assert!(!policy.allows(Some(&authenticated(6, vec![AuthMethod::Pwd])), false, now));
+        assert!(!policy.allows(None, false, now));
+    }
+
+    #[test]
+    fn test_step_up_requires_the_second_factor_of_accounts_with_2fa() {
+        let policy = StepUpPolicy::default();
+        let now = Utc::now();
+        let password = authenticated(0, vec![AuthMethod::Pwd]);
+        let two_factors = authenticated(0, vec![AuthMethod::Pwd, AuthMethod::Otp]);
+        assert!(policy.allows(Some(&password), false, now));
+        assert!(!policy.allows(Some(&password), true, now));
+        assert!(policy.allows(Some(&two_factors), true, now));
+
+        let relaxed = StepUpPolicy {
+            second_factor: false,
+            ..policy
+        };
+        assert!(relaxed.allows(Some(&password), true, now));
+    }
+}
@@ -0,0 +1,203 @@
+use axum::http::{HeaderName, Method};
+use color_eyre::eyre::{Report, Result, eyre};
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+// An allowed browser origin: either exact, e.g. `https://app.example.com`, or every subdomain
+// of a domain, e.g. `https://*.example.com`. The scheme and port always have to match.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum OriginPattern {
+    Exact(String),
+    // `scheme://` and `.domain[:port]` of a `scheme://*.domain[:port]` pattern
+    Subdomains { scheme: String, suffix: String },
+}
+
+impl OriginPattern {
+    pub fn parse(s: &str) -> Result<Self> {
+        let pattern = s.trim().trim_end_matches('/').to_ascii_lowercase();
+        let Some((scheme, host)) = pattern.split_once("://") else {
+            return Err(eyre!("Origin patterns need a scheme: {}", s));
+        };
+        if scheme != "http" && scheme != "https" {
+            return Err(eyre!("Origin patterns must use http or https: {}", s));
+        }
+        if host.is_empty() || host.contains('/') {
+            return Err(eyre!("Origin patterns cannot have a path: {}", s));
+        }
+
+        match host.strip_prefix("*.") {
+            Some(domain) if !domain.is_empty() && !domain.contains('*') => {
+                Ok(OriginPattern::Subdomains {
+                    scheme: format!("{}://", scheme),
+                    suffix: format!(".{}", domain),
+                })
+            }
+            None if !host.contains('*') => Ok(OriginPattern::Exact(pattern)),
+            // Credentials are allowed, so `*` or a partial wildcard would trust too much
+            _ => Err(eyre!(
+                "Wildcards are only allowed as `*.` in front of a domain: {}",
+                s
+            )),
+        }
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            OriginPattern::Exact(allowed) => *allowed == origin,
+            OriginPattern::Subdomains { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && subdomain.split('.').all(|label| {
+                            !label.is_empty()
+                                && label
+                                    .bytes()
+                                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                        })
+                }),
+        }
+    }
+}
+
+impl TryFrom<String> for OriginPattern {
+    type Error = Report;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::parse(&s)
+    }
+}
+
+impl fmt::Display for OriginPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OriginPattern::Exact(origin) => f.write_str(origin),
+            OriginPattern::Subdomains { scheme, suffix } => write!(f, "{}*{}", scheme, suffix),
+        }
+    }
+}
+
+// Which browser origins may call the API with credentials, and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsPolicy {
+    pub allowed_origins: Vec<OriginPattern>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    // How long browsers may cache a preflight response
+    pub max_age: Duration,
+}
+
+impl Default for CorsPolicy {
+    // No cross-origin access
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![Method::GET, Method::POST],
+            allowed_headers: Vec::new(),
+            max_age: Duration::ZERO,
+        }
+    }
+}
+
+impl CorsPolicy {
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|pattern| pattern.matches(origin))
+    }
+
+    pub fn parse_origins(list: &str) -> Result<Vec<OriginPattern>> {
+        split_list(list).map(OriginPattern::parse).collect()
+    }
+
+    pub fn parse_methods(list: &str) -> Result<Vec<Method>> {
+        split_list(list)
+            .map(|method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .map_err(|_| eyre!("Invalid HTTP method: {}", method))
+            })
+            .collect()
+    }
+
+    pub fn parse_headers(list: &str) -> Result<Vec<HeaderName>> {
+        split_list(list)
+            .map(|header| {
+                HeaderName::from_bytes(header.to_ascii_lowercase().as_bytes())
+                    .map_err(|_| eyre!("Invalid HTTP header name: {}", header))
+            })
+            .collect()
+    }
+}
+
+// Comma-separated settings, e.g. `GET, POST`
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_origins() {
+        let pattern = OriginPattern::parse("https://App.Example.com/").unwrap();
+        assert!(pattern.matches("https://app.example.com"));
+        assert!(!pattern.matches("http://app.example.com"));
+        assert!(!pattern.matches("https://app.example.com:8443"));
+        assert!(!pattern.matches("https://evil-app.example.com"));
+    }
+
+    #[test]
+    fn test_subdomain_patterns() {
+        let pattern = OriginPattern::parse("https://*.example.com").unwrap();
+        assert!(pattern.matches("https://app.example.com"));
+        assert!(pattern.matches("https://eu.app.example.com"));
+        assert!(!pattern.matches("https://example.com"));
+        assert!(!pattern.matches("http://app.example.com"));
+        assert!(!pattern.matches("https://app.example.com.evil.com"));
+        assert!(!pattern.matches("https://evilexample.com"));
+        assert!(!pattern.matches("https://app.example.com:8443"));
+        assert_eq!(pattern.to_string(), "https://*.example.com");
+
+        let pattern = OriginPattern::parse("http://*.localhost:8000").unwrap();
+        assert!(pattern.matches("http://app.localhost:8000"));
+        assert!(!pattern.matches("http://app.localhost"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in [
+            "*",
+            "app.example.com",
+            "ftp://app.example.com",
+            "https://*",
+            "https://*.",
+            "https://app.*.com",
+            "https://*example.com",
+            "https://app.example.com/path",
+        ] {
+            assert!(OriginPattern::parse(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_parse_lists() {
+        let origins =
+            CorsPolicy::parse_origins("http://localhost:8000, https://*.example.com,").unwrap();
+        assert_eq!(origins.len(), 2);
+        assert_eq!(
+            CorsPolicy::parse_methods("get, POST,delete").unwrap(),
+            vec![Method::GET, Method::POST, Method::DELETE]
+        );
+        assert_eq!(
+            CorsPolicy::parse_headers("Content-Type").unwrap(),
+            vec![HeaderName::from_static("content-type")]
+        );
+        assert!(CorsPolicy::parse_headers("bad header").is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use color_eyre::eyre::{Result, bail, eyre};
+use secrecy::{ExposeSecret, SecretBox};
+use std::hash::{Hash, Hasher};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::utils::constants::EMAIL_PLUS_TAG_DEDUPLICATION;
+
+// Size limits from RFC 5321 section 4.5.3.1, in octets.
+// The path limit of 256 octets includes the surrounding angle brackets.
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_EMAIL_LENGTH: usize = 254;
+
+// Characters allowed in an unquoted local part besides letters and digits (RFC 5322 `atext`)
+const ATEXT_SPECIALS: &str = "!#$%&'*+-/=?^_`{|}~";
+
+// Whether `user+tag@example.com` should be treated as the same account as `user@example.com`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlusTagPolicy {
+    Keep,
+    Strip,
+}
+
+impl PlusTagPolicy {
+    // The policy set by EMAIL_PLUS_TAG_DEDUPLICATION
+    pub fn configured() -> Self {
+        if *EMAIL_PLUS_TAG_DEDUPLICATION {
+            PlusTagPolicy::Strip
+        } else {
+            PlusTagPolicy::Keep
+        }
+    }
+}
+
+// An email address as entered by the user, together with its canonical form.
+// The canonical form is used for storage keys and lookups, and two emails are equal
+// if their canonical forms match, e.g. `Bob@Bücher.de` and `bob@xn--bcher-kva.de`.
+#[derive(Debug)]
+pub struct Email {
+    email: SecretBox<String>,
+    normalized: SecretBox<String>,
+}
+
+impl Clone for Email {
+    fn clone(&self) -> Self {
+        Self {
+            email: SecretBox::new(Box::new(self.email.expose_secret().clone())),
+            normalized: SecretBox::new(Box::new(self.normalized.expose_secret().clone())),
+        }
+    }
+}
+
+impl Email {
+    // Parse an address using the plus-tag policy configured for this deployment
+    pub fn parse(email: SecretBox<String>) -> Result<Self> {
+        Self::parse_with_policy(email, PlusTagPolicy::configured())
+    }
+
+    // Parse a mailbox per RFC 5321 (SMTP) and RFC 6531 (internationalized addresses).
+    // Address literals such as `user@[127.0.0.1]` are rejected, as are domains without a TLD.
+    pub fn parse_with_policy(email: SecretBox<String>, policy: PlusTagPolicy) -> Result<Self> {
+        let normalized = canonicalize(email.expose_secret(), policy)?;
+        Ok(Email {
+            email,
+            normalized: SecretBox::new(Box::new(normalized)),
+        })
+    }
+
+    pub fn to_str(&self) -> &str {
+        self.email.expose_secret()
+    }
+
+    // The canonical form used for storage keys and lookups: NFC-normalized, lowercase,
+    // with a punycode (ASCII) domain and without unnecessary quoting.
+    pub fn normalized(&self) -> &str {
+        self.normalized.expose_secret()
+    }
+}
+
+impl Eq for Email {}
+
+impl PartialEq for Email {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized.expose_secret() == other.normalized.expose_secret()
+    }
+}
+
+impl Hash for Email {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized.expose_secret().hash(state);
+    }
+}
+
+// The AsRef trait is used to convert a reference of one type to a reference of another type.
+// In this case, we're implementing the AsRef trait for the Email type to allow us to convert a &Email to a &str.
+// This is useful when we want to expose the inner email string in a read-only manner.
+impl AsRef<str> for Email {
+    fn as_ref(&self) -> &str {
+        self.email.expose_secret().as_str()
+    }
+}
+
+fn canonicalize(address: &str, policy: PlusTagPolicy) -> Result<String> {
+    // Compose Unicode characters so that visually identical addresses compare equal
+    let address: String = address.nfc().collect();
+
+    // The domain cannot contain '@', so the last one separates it from a (possibly quoted) local part
+    let (local_part, domain) = address
+        .rsplit_once('@')
+        .ok_or_else(|| eyre!("Email address must contain '@'"))?;
+
+    let local_part = canonical_local_part(local_part, policy)?;
+    let domain = canonical_domain(domain)?;
+
+    let canonical = format!("{}@{}", local_part, domain);
+    if canonical.len() > MAX_EMAIL_LENGTH {
+        bail!("Email address must be at most {} octets", MAX_EMAIL_LENGTH);
+    }
+    Ok(canonical)
+}
+
+fn canonical_local_part(local_part: &str, policy: PlusTagPolicy) -> Result<String> {
+    if local_part.is_empty() {
+        bail!("Email local part must not be empty");
+    }
+    if local_part.len() > MAX_LOCAL_PART_LENGTH {
+        bail!(
+            "Email local part must be at most {} octets",
+            MAX_LOCAL_PART_LENGTH
+        );
+    }
+
+    let content = if local_part.starts_with('"') {
+        unquote(local_part)?
+    } else if is_dot_atom(local_part) {
+        local_part.to_owned()
+    } else {
+        bail!("Email local part contains invalid characters or misplaced dots");
+    };
+
+    // Mailbox names are treated case-insensitively, as virtually every provider does.
+    // Lowercasing can decompose some characters, so compose them again afterwards.
+    let mut content: String = content.to_lowercase().nfc().collect();
+    if policy == PlusTagPolicy::Strip
+        && let Some((mailbox, _tag)) = content.split_once('+')
+        && !mailbox.is_empty()
+    {
+        content = mailbox.to_owned();
+    }
+
+    // Only keep the quotes if the content cannot be written as a dot-atom
+    if is_dot_atom(&content) {
+        Ok(content)
+    } else {
+        Ok(quote(&content))
+    }
+}
+
+fn canonical_domain(domain: &str) -> Result<String> {
+    if domain.starts_with('[') {
+        bail!("Email address literals are not supported");
+    }
+    if domain.is_empty() || domain.ends_with('.') {
+        bail!("Email domain is invalid");
+    }
+
+    // Maps internationalized domains to punycode and lowercases them (UTS #46, STD3 rules)
+    let ascii = idna::domain_to_ascii_strict(domain)
+        .map_err(|e| eyre!("Email domain is invalid: {}", e))?;
+    if ascii.len() > MAX_DOMAIN_LENGTH {
+        bail!("Email domain must be at most {} octets", MAX_DOMAIN_LENGTH);
+    }
+
+    let labels: Vec<&str> = ascii.split('.').collect();
+    if labels.len() < 2 {
+        bail!("Email domain must include a top-level domain");
+    }
+    if labels
+        .iter()
+        .any(|label| label.is_empty() || label.len() > MAX_LABEL_LENGTH)
+    {
+        bail!("Email domain contains an invalid label");
+    }
+    // An all-numeric TLD means this is an IP address rather than a domain name
+    if labels
+        .last()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+    {
+        bail!("Email domain must not be an IP address");
+    }
+
+    Ok(ascii)
+}
+
+// RFC 5322 `atext`, extended with non-ASCII characters by RFC 6531
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ATEXT_SPECIALS.contains(c) || (!c.is_ascii() && !c.is_control())
+}
+
+// One or more runs of `atext` separated by single dots, with no leading or trailing dot
+fn is_dot_atom(s: &str) -> bool {
+    !s.is_empty()
+        && s.split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+// Returns the content of a quoted local part with quoted-pairs resolved
+fn unquote(quoted: &str) -> Result<String> {
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| eyre!("Email local part has an invalid quoted string"))?;
+
+    let mut content = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // quoted-pair: a backslash followed by any printable ASCII character or space
+            '\\' => match chars.next() {
+                Some(escaped) if (' '..='~').contains(&escaped) => content.push(escaped),
+                _ => bail!("Email local part has an invalid escape sequence"),
+            },
+            '"' => bail!("Email local part has an unescaped quote"),
+            // qtext (RFC 5321) extended with non-ASCII characters (RFC 6531)
+            c if (' '..='~').contains(&c) || (!c.is_ascii() && !c.is_control()) => content.push(c),
+            _ => bail!("Email local part contains invalid characters"),
+        }
+    }
+    Ok(content)
+}
+
+fn quote(content: &str) -> String {
+    let mut quoted = String::with_capacity(content.len() + 2);
+    quoted.push('"');
+    for c in content.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{Fake, faker::internet::en::SafeEmail};
+
+    fn parse(s: &str) -> Result<Email> {
+        Email::parse_with_policy(SecretBox::new(Box::new(s.to_string())), PlusTagPolicy::Keep)
+    }
+
+    fn canonical(s: &str) -> String {
+        parse(s).unwrap().normalized().to_owned()
+    }
+
+    #[test]
+    fn test_email_parse() {
+        let email_str: String = SafeEmail().fake();
+        let email = Email::parse(SecretBox::new(Box::new(email_str.clone())));
+        assert!(matches!(email, Ok(e) if e.email.expose_secret() == &email_str));
+    }
+
+    #[test]
+    fn test_email_parse_empty() {
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_email_parse_invalid() {
+        assert!(parse("test").is_err());
+    }
+
+    #[test]
+    fn test_email_parse_normalizes_case() {
+        let email = parse("Bob@Example.com").unwrap();
+        assert_eq!(email.as_ref(), "Bob@Example.com");
+        assert_eq!(email.normalized(), "bob@example.com");
+        assert_eq!(email, parse("bob@example.com").unwrap());
+    }
+
+    #[test]
+    fn test_email_parse_accepts_valid_addresses() {
+        let valid = [
+            "simple@example.com",
+            "very.common@example.photography",
+            "disposable.style.email.with+symbol@example.com",
+            "other.email-with-hyphen@sub.example.co.uk",
+            "x@example.com",
+            "user%example.com@example.org",
+            "!#$%&'*+-/=?^_`{|}~@example.com",
+            "\"john doe\"@example.com",
+            "\"very.(),:;<>[]\\\".VERY.\\\"very@\\\\ \\\"very\\\".unusual\"@strange.example.com",
+            "用户@例子.广告",
+            "josé@bücher.de",
+        ];
+        for address in valid {
+            assert!(parse(address).is_ok(), "{address}");
+        }
+    }
+
+    #[test]
+    fn test_email_parse_rejects_invalid_addresses() {
+        let invalid = [
+            "plainaddress",
+            "@example.com",
+            "user@",
+            "user@example",
+            "user@example.",
+            "user@.example.com",
+            "user@exa..mple.com",
+            "user@-example.com",
+            "user@exa_mple.com",
+            "user@[127.0.0.1]",
+            "user@127.0.0.1",
+            ".user@example.com",
+            "user.@example.com",
+            "us..er@example.com",
+            "us er@example.com",
+            "us\"er@example.com",
+            "\"unterminated@example.com",
+            "\"\"@example.com",
+            "a\"b(c)d,e:f;g<h>i[j\\k]l@example.com",
+        ];
+        for address in invalid {
+            assert!(parse(address).is_err(), "{address}");
+        }
+    }
+
+    #[test]
+    fn test_email_parse_enforces_length_limits() {
+        let local_part = "a".repeat(MAX_LOCAL_PART_LENGTH);
+        assert!(parse(&format!("{local_part}@example.com")).is_ok());
+        assert!(parse(&format!("{local_part}a@example.com")).is_err());
+
+        let label = "a".repeat(MAX_LABEL_LENGTH);
+        assert!(parse(&format!("user@{label}.com")).is_ok());
+        assert!(parse(&format!("user@{label}a.com")).is_err());
+
+        // 64 + 1 + 3 * 64 = 257 octets
+        let domain = format!("{label}.{label}.{label}.com");
+        assert!(parse(&format!("{local_part}@{domain}")).is_err());
+    }
+
+    #[test]
+    fn test_email_canonical_form() {
+        // Internationalized domains are stored as punycode
+        assert_eq!(canonical("user@Bücher.de"), "user@xn--bcher-kva.de");
+        assert_eq!(
+            parse("user@bücher.de").unwrap(),
+            parse("user@xn--bcher-kva.de").unwrap()
+        );
+        // Unicode local parts are composed and lowercased
+        assert_eq!(canonical("JOSE\u{301}@example.com"), "josé@example.com");
+        // Unnecessary quoting is removed, necessary quoting is kept
+        assert_eq!(canonical("\"John\"@example.com"), "john@example.com");
+        assert_eq!(
+            canonical("\"John Doe\"@example.com"),
+            "\"john doe\"@example.com"
+        );
+        assert_eq!(canonical("\"a\\b\"@example.com"), "ab@example.com");
+    }
+
+    #[test]
+    fn test_email_plus_tag_policy() {
+        let tagged = "Bob+Newsletter@example.com";
+        assert_eq!(canonical(tagged), "bob+newsletter@example.com");
+
+        let stripped = |s: &str| {
+            Email::parse_with_policy(
+                SecretBox::new(Box::new(s.to_string())),
+                PlusTagPolicy::Strip,
+            )
+            .unwrap()
+        };
+        assert_eq!(stripped(tagged).normalized(), "bob@example.com");
+        assert_eq!(stripped(tagged), stripped("bob@example.com"));
+        // A local part that is only a tag is left alone
+        assert_eq!(
+            stripped("+tag@example.com").normalized(),
+            "+tag@example.com"
+        );
+    }
+}
@@ -1,10 +1,36 @@
-use super::Email;
-use color_eyre::eyre::Result;
-// This trait represents the interface all concrete email clients should implement
+use super::{Email, EmailMessage};
+use color_eyre::eyre::{Report, Result, eyre};
+use std::str::FromStr;
+// This trait represents the interface all concrete email clients should implement.
+// The sender is passed per message, since each tenant sends from its own address.
 #[async_trait::async_trait]
-pub trait EmailClient {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()>;
+pub trait EmailClient: Send + Sync {
+    async fn send_email(
+        &self,
+        sender: &Email,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<()>;
 }
 
-#[derive(Clone)]
-pub struct MockEmailClient;
+// The service used to deliver email, selected with the EMAIL_PROVIDER environment variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailProvider {
+    Postmark,
+    Smtp,
+    // Keeps mail in memory or a directory for local development, see DevMailbox
+    DevMailbox,
+}
+
+impl FromStr for EmailProvider {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "postmark" => Ok(Self::Postmark),
+            "smtp" => Ok(Self::Smtp),
+            "dev-mailbox" => Ok(Self::DevMailbox),
+            _ => Err(eyre!("Unknown email provider: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use askama::Template;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::constants::{EMAIL_BRAND_NAME, EMAIL_BRAND_URL};
+
+// Product details shown in every email
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branding {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+impl Default for Branding {
+    // The branding configured for this deployment
+    fn default() -> Self {
+        Self {
+            name: EMAIL_BRAND_NAME.to_owned(),
+            url: EMAIL_BRAND_URL.to_owned(),
+        }
+    }
+}
+
+// A transactional email. Each variant identifies a template and carries the context it needs.
+// Messages are serialized into the email outbox, so renaming a variant or field needs a data migration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "template", rename_all = "kebab-case")]
+pub enum EmailMessage {
+    #[serde(rename = "two-fa-code")]
+    TwoFACode {
+        code: String,
+        expires_at: DateTime<Utc>,
+    },
+    MagicLink {
+        url: String,
+        expires_at: DateTime<Utc>,
+    },
+    // Sent whenever a recovery code is used, so the owner notices if it was not them
+    RecoveryCodeUsed {
+        used_at: DateTime<Utc>,
+        ip_address: Option<String>,
+        remaining: i64,
+    },
+    // Sent when an account is signed in to from a device it was not used from before
+    NewDeviceSignIn {
+        signed_in_at: DateTime<Utc>,
+        ip_address: Option<String>,
+        location: Option<String>,
+        user_agent: Option<String>,
+        // The "this wasn't me" link
+        url: String,
+    },
+}
+
+// The subject and bodies produced from an EmailMessage, ready to hand to a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl EmailMessage {
+    // A stable identifier for the template, used for logging and provider-side tagging
+    pub fn template_id(&self) -> &'static str {
+        match self {
+            EmailMessage::TwoFACode { .. } => "two-fa-code",
+            EmailMessage::MagicLink { .. } => "magic-link",
+            EmailMessage::RecoveryCodeUsed { .. } => "recovery-code-used",
+            EmailMessage::NewDeviceSignIn { .. } => "new-device-sign-in",
+        }
+    }
+
+    // After this time the message is useless to the recipient and should not be delivered
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            EmailMessage::TwoFACode { expires_at, .. } => Some(*expires_at),
+            EmailMessage::MagicLink { expires_at, .. } => Some(*expires_at),
+            EmailMessage::RecoveryCodeUsed { .. } | EmailMessage::NewDeviceSignIn { .. } => None,
+        }
+    }
+
+    pub fn render(&self) -> Result<RenderedEmail> {
+        self.render_with_branding(&Branding::default())
+    }
+
+    pub fn render_with_branding(&self, branding: &Branding) -> Result<RenderedEmail> {
+        match self {
+            EmailMessage::TwoFACode { code, expires_at } => {
+                let context = TwoFACodeContext {
+                    branding,
+                    code,
+                    expires_in_minutes: minutes_until(expires_at),
+                    expires_at: expires_at.format("%H:%M UTC").to_string(),
+                };
+                Ok(RenderedEmail {
+                    subject: format!("Your {} verification code", branding.name),
+                    html_body: TwoFACodeHtml { ctx: &context }.render()?,
+                    text_body: TwoFACodeText { ctx: &context }.render()?,
+                })
+            }
+            EmailMessage::MagicLink { url, expires_at } => {
+                let context = MagicLinkContext {
+                    branding,
+                    url,
+                    expires_in_minutes: minutes_until(expires_at),
+                };
+                Ok(RenderedEmail {
+                    subject: format!("Your {} sign-in link", branding.name),
+                    html_body: MagicLinkHtml { ctx: &context }.render()?,
+                    text_body: MagicLinkText { ctx: &context }.render()?,
+                })
+            }
+            EmailMessage::RecoveryCodeUsed {
+                used_at,
+                ip_address,
+                remaining,
+            } => {
+                let context = RecoveryCodeUsedContext {
+                    branding,
+                    used_at: used_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                    ip_address: ip_address.as_deref(),
+                    remaining: *remaining,
+                };
+                Ok(RenderedEmail {
+                    subject: format!("A {} recovery code was used", branding.name),
+                    html_body: RecoveryCodeUsedHtml { ctx: &context }.render()?,
+                    text_body: RecoveryCodeUsedText { ctx: &context }.render()?,
+                })
+            }
+            EmailMessage::NewDeviceSignIn {
+                signed_in_at,
+                ip_address,
+                location,
+                user_agent,
+                url,
+            } => {
+                let context = NewDeviceSignInContext {
+                    branding,
+                    signed_in_at: signed_in_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                    ip_address: ip_address.as_deref(),
+                    location: location.as_deref(),
+                    user_agent: user_agent.as_deref(),
+                    url,
+                };
+                Ok(RenderedEmail {
+                    subject: format!("New sign-in to your {} account", branding.name),
+                    html_body: NewDeviceSignInHtml { ctx: &context }.render()?,
+                    text_body: NewDeviceSignInText { ctx: &context }.render()?,
+                })
+            }
+        }
+    }
+}
+
+// Rounded up, so a code with 9m59s left reads as 10 minutes
+pub(crate) fn minutes_until(time: &DateTime<Utc>) -> i64 {
+    let seconds = (*time - Utc::now()).num_seconds().max(0);
+    (seconds + 59) / 60
+}
+
+// Templates share the base layout, which expects every context to have a `branding` field
+struct TwoFACodeContext<'a> {
+    branding: &'a Branding,
+    code: &'a str,
+    expires_in_minutes: i64,
+    expires_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "email/two_fa_code.html")]
+struct TwoFACodeHtml<'a> {
+    ctx: &'a TwoFACodeContext<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "email/two_fa_code.txt")]
+struct TwoFACodeText<'a> {
+    ctx: &'a TwoFACodeContext<'a>,
+}
+
+struct MagicLinkContext<'a> {
+    branding: &'a Branding,
+    url: &'a str,
+    expires_in_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "email/magic_link.html")]
+struct MagicLinkHtml<'a> {
+    ctx: &'a MagicLinkContext<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "email/magic_link.txt")]
+struct MagicLinkText<'a> {
+    ctx: &'a MagicLinkContext<'a>,
+}
+
+struct RecoveryCodeUsedContext<'a> {
+    branding: &'a Branding,
+    used_at: String,
+    ip_address: Option<&'a str>,
+    remaining: i64,
+}
+
+#[derive(Template)]
+#[template(path = "email/recovery_code_used.html")]
+struct RecoveryCodeUsedHtml<'a> {
+    ctx: &'a RecoveryCodeUsedContext<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "email/recovery_code_used.txt")]
+struct RecoveryCodeUsedText<'a> {
+    ctx: &'a RecoveryCodeUsedContext<'a>,
+}
+
+struct NewDeviceSignInContext<'a> {
+    branding: &'a Branding,
+    signed_in_at: String,
+    ip_address: Option<&'a str>,
+    location: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    url: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "email/new_device_sign_in.html")]
+struct NewDeviceSignInHtml<'a> {
+    ctx: &'a NewDeviceSignInContext<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "email/new_device_sign_in.txt")]
+struct NewDeviceSignInText<'a> {
+    ctx: &'a NewDeviceSignInContext<'a>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn branding() -> Branding {
+        Branding {
+            name: "Acme & Co".to_owned(),
+            url: Some("https://acme.example.com".to_owned()),
+        }
+    }
+
+    fn two_fa_code() -> EmailMessage {
+        EmailMessage::TwoFACode {
+            code: "123456".to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        }
+    }
+
+    #[test]
+    fn test_two_fa_code_renders_html_and_text_bodies() {
+        let rendered = two_fa_code().render_with_branding(&branding()).unwrap();
+
+        assert_eq!(rendered.subject, "Your Acme & Co verification code");
+
+        assert!(rendered.html_body.contains("123456"));
+        assert!(rendered.html_body.contains("expires in 10 minutes"));
+        // Branding is escaped in the HTML body but not in the plain-text body
+        assert!(rendered.html_body.contains("Acme &#38; Co"));
+        assert!(
+            rendered
+                .html_body
+                .contains(r#"href="https://acme.example.com""#)
+        );
+
+        assert!(rendered.text_body.contains("123456"));
+        assert!(rendered.text_body.contains("expires in 10 minutes"));
+        assert!(rendered.text_body.contains("Acme & Co"));
+        assert!(rendered.text_body.contains("https://acme.example.com"));
+        assert!(!rendered.text_body.contains('<'));
+    }
+
+    #[test]
+    fn test_two_fa_code_renders_without_brand_url() {
+        let branding = Branding {
+            url: None,
+            ..branding()
+        };
+        let rendered = two_fa_code().render_with_branding(&branding).unwrap();
+        assert!(!rendered.html_body.contains("href"));
+        assert!(!rendered.text_body.contains("https://"));
+    }
+
+    #[test]
+    fn test_serialized_template_matches_template_id() {
+        let message = two_fa_code();
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["template"], message.template_id());
+        assert_eq!(
+            serde_json::from_value::<EmailMessage>(json).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_magic_link_renders_an_escaped_link() {
+        let message = EmailMessage::MagicLink {
+            url: "https://auth.example.com/login/magic-link/verify?token=a.b&x=1".to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        };
+        let rendered = message.render_with_branding(&branding()).unwrap();
+
+        assert_eq!(rendered.subject, "Your Acme & Co sign-in link");
+        assert!(rendered.html_body.contains(
+            r#"href="https://auth.example.com/login/magic-link/verify?token=a.b&#38;x=1""#
+        ));
+        assert!(
+            rendered
+                .text_body
+                .contains("https://auth.example.com/login/magic-link/verify?token=a.b&x=1")
+        );
+        assert!(rendered.text_body.contains("expires in 10 minutes"));
+    }
+
+    #[test]
+    fn test_recovery_code_used_renders_the_audit_details() {
+        let message = EmailMessage::RecoveryCodeUsed {
+            used_at: "2026-10-18T12:30:00Z".parse().unwrap(),
+            ip_address: Some("203.0.113.7".to_owned()),
+            remaining: 9,
+        };
+        let rendered = message.render_with_branding(&branding()).unwrap();
+
+        assert_eq!(rendered.subject, "A Acme & Co recovery code was used");
+        for body in [&rendered.html_body, &rendered.text_body] {
+            assert!(body.contains("2026-10-18 12:30 UTC"));
+            assert!(body.contains("203.0.113.7"));
+            assert!(body.contains("9 unused recovery codes"));
+        }
+        assert_eq!(message.expires_at(), None);
+
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["template"], message.template_id());
+    }
+
+    #[test]
+    fn test_new_device_sign_in_renders_the_device_and_link() {
+        let message = EmailMessage::NewDeviceSignIn {
+            signed_in_at: "2026-10-19T08:15:00Z".parse().unwrap(),
+            ip_address: Some("203.0.113.7".to_owned()),
+            location: Some("Berlin, Germany".to_owned()),
+            user_agent: None,
+            url: "https://auth.example.com/sign-in-alert?token=a.b&x=1".to_owned(),
+        };
+        let rendered = message.render_with_branding(&branding()).unwrap();
+
+        assert_eq!(rendered.subject, "New sign-in to your Acme & Co account");
+        for body in [&rendered.html_body, &rendered.text_body] {
+            assert!(body.contains("2026-10-19 08:15 UTC"));
+            assert!(body.contains("Berlin, Germany"));
+            assert!(body.contains("203.0.113.7"));
+            assert!(!body.contains("Browser"));
+        }
+        assert!(
+            rendered
+                .html_body
+                .contains(r#"href="https://auth.example.com/sign-in-alert?token=a.b&#38;x=1""#)
+        );
+        assert!(
+            rendered
+                .text_body
+                .contains("https://auth.example.com/sign-in-alert?token=a.b&x=1")
+        );
+        assert_eq!(message.expires_at(), None);
+    }
+
+    #[test]
+    fn test_expired_code_renders_zero_minutes() {
+        let message = EmailMessage::TwoFACode {
+            code: "123456".to_owned(),
+            expires_at: Utc::now() - TimeDelta::minutes(1),
+        };
+        let rendered = message.render_with_branding(&branding()).unwrap();
+        assert!(rendered.text_body.contains("expires in 0 minutes"));
+    }
+}
@@ -19,6 +19,30 @@ pub enum AuthAPIError {
     MissingToken,
     #[error("Invalid token")]
     InvalidToken,
+    #[error("Invalid phone number")]
+    InvalidPhoneNumber,
+    #[error("Invalid verification code")]
+    InvalidVerificationCode,
+    #[error("Phone number not verified")]
+    PhoneNumberNotVerified,
+    #[error("Two-factor authentication not enabled")]
+    TwoFactorNotEnabled,
+    #[error("SMS unavailable")]
+    SmsUnavailable,
+    #[error("Unknown tenant")]
+    UnknownTenant,
+    #[error("CSRF check failed")]
+    CsrfCheckFailed,
+    #[error("2FA code resent too soon")]
+    ResendTooSoon,
+    #[error("2FA code resend limit reached")]
+    ResendLimitReached,
+    #[error("Trusted device not found")]
+    TrustedDeviceNotFound,
+    #[error("Login denied")]
+    LoginDenied,
+    #[error("Reauthentication required")]
+    ReauthenticationRequired,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
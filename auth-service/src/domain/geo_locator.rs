@@ -0,0 +1,55 @@
+use std::net::IpAddr;
+
+// Where an IP address is, as far as a GeoIP database can tell
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GeoLocation {
+    // ISO 3166-1 alpha-2 code, e.g. "DE"
+    pub country: Option<String>,
+    // Country databases have no coordinates
+    pub coordinates: Option<Coordinates>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    // Great-circle distance, with the haversine formula
+    pub fn distance_km(&self, other: &Coordinates) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+// This trait represents the interface all GeoIP lookups should implement
+pub trait GeoLocator: Send + Sync {
+    // None when the address is not in the database, e.g. a private address
+    fn locate(&self, ip: IpAddr) -> Option<GeoLocation>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_km() {
+        let berlin = Coordinates {
+            latitude: 52.52,
+            longitude: 13.405,
+        };
+        let sydney = Coordinates {
+            latitude: -33.8688,
+            longitude: 151.2093,
+        };
+        assert_eq!(berlin.distance_km(&berlin), 0.0);
+        let distance = berlin.distance_km(&sydney);
+        assert!((16_000.0..16_200.0).contains(&distance), "{}", distance);
+        assert_eq!(distance, sydney.distance_km(&berlin));
+    }
+}
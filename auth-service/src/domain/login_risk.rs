@@ -0,0 +1,264 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Result};
+use ipnet::IpNet;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use super::{Coordinates, GeoLocator};
+use crate::utils::constants::{DEFAULT_RISK_CHALLENGE_THRESHOLD, DEFAULT_RISK_DENY_THRESHOLD};
+
+// Points each signal adds to the risk score of a login
+const BLOCKLISTED_IP_SCORE: u32 = 100;
+// Without an address, neither the blocklist nor the location can be checked
+const UNKNOWN_IP_SCORE: u32 = 50;
+const IMPOSSIBLE_TRAVEL_SCORE: u32 = 60;
+const NEW_DEVICE_SCORE: u32 = 30;
+const NEW_COUNTRY_SCORE: u32 = 25;
+const RECENT_FAILURE_SCORE: u32 = 10;
+const MAX_RECENT_FAILURES_SCORE: u32 = 50;
+
+// Faster than a plane between two logins. Short hops are ignored, since GeoIP locations of
+// nearby addresses can be far apart.
+const IMPOSSIBLE_TRAVEL_KMH: f64 = 1_000.0;
+const MIN_TRAVEL_KM: f64 = 500.0;
+
+// Something about a login that makes it more likely not to be the account owner
+#[derive(Debug, Clone, PartialEq)]
+pub enum RiskSignal {
+    BlocklistedIp,
+    // No client address, or one that does not parse, see TrustedProxies
+    UnknownIp,
+    // The last sign-in was too far away to have travelled since
+    ImpossibleTravel { speed_kmh: u32 },
+    // Another country than the last sign-in
+    NewCountry { country: String },
+    // A device the account has not signed in from, see ClientInfo::fingerprint
+    NewDevice,
+    // Wrong passwords for the account from the client's network in the last few minutes
+    RecentFailures { count: u32 },
+}
+
+impl RiskSignal {
+    pub fn score(&self) -> u32 {
+        match self {
+            RiskSignal::BlocklistedIp => BLOCKLISTED_IP_SCORE,
+            RiskSignal::UnknownIp => UNKNOWN_IP_SCORE,
+            RiskSignal::ImpossibleTravel { .. } => IMPOSSIBLE_TRAVEL_SCORE,
+            RiskSignal::NewCountry { .. } => NEW_COUNTRY_SCORE,
+            RiskSignal::NewDevice => NEW_DEVICE_SCORE,
+            RiskSignal::RecentFailures { count } => {
+                (count * RECENT_FAILURE_SCORE).min(MAX_RECENT_FAILURES_SCORE)
+            }
+        }
+    }
+
+    // The impossible travel signal, if getting from the last sign-in to this one was too fast
+    pub fn travel(
+        from: (DateTime<Utc>, &Coordinates),
+        to: (DateTime<Utc>, &Coordinates),
+    ) -> Option<RiskSignal> {
+        let distance_km = from.1.distance_km(to.1);
+        if distance_km < MIN_TRAVEL_KM {
+            return None;
+        }
+        // At least a minute, so logins in the same instant do not divide by zero
+        let hours = ((to.0 - from.0).num_seconds().max(60) as f64) / 3600.0;
+        let speed_kmh = distance_km / hours;
+        (speed_kmh > IMPOSSIBLE_TRAVEL_KMH).then(|| RiskSignal::ImpossibleTravel {
+            speed_kmh: speed_kmh.round() as u32,
+        })
+    }
+}
+
+// The reasons recorded with a decision
+impl fmt::Display for RiskSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskSignal::BlocklistedIp => write!(f, "blocklisted-ip"),
+            RiskSignal::UnknownIp => write!(f, "unknown-ip"),
+            RiskSignal::ImpossibleTravel { speed_kmh } => {
+                write!(f, "impossible-travel:{}km/h", speed_kmh)
+            }
+            RiskSignal::NewCountry { country } => write!(f, "new-country:{}", country),
+            RiskSignal::NewDevice => write!(f, "new-device"),
+            RiskSignal::RecentFailures { count } => write!(f, "recent-failures:{}", count),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskDecision {
+    // Log in as the account's settings say
+    Allow,
+    // Ask for a 2FA code, by email for accounts without 2FA, even on a trusted device
+    Challenge,
+    // Refuse the login
+    Deny,
+}
+
+impl RiskDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskDecision::Allow => "allow",
+            RiskDecision::Challenge => "challenge",
+            RiskDecision::Deny => "deny",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskAssessment {
+    pub score: u32,
+    pub decision: RiskDecision,
+    pub signals: Vec<RiskSignal>,
+}
+
+impl RiskAssessment {
+    pub fn reasons(&self) -> Vec<String> {
+        self.signals.iter().map(ToString::to_string).collect()
+    }
+}
+
+// The scores at which a login needs a code or is refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RiskPolicy {
+    pub challenge_threshold: u32,
+    pub deny_threshold: u32,
+}
+
+impl Default for RiskPolicy {
+    fn default() -> Self {
+        Self {
+            challenge_threshold: DEFAULT_RISK_CHALLENGE_THRESHOLD,
+            deny_threshold: DEFAULT_RISK_DENY_THRESHOLD,
+        }
+    }
+}
+
+impl RiskPolicy {
+    pub fn assess(&self, signals: Vec<RiskSignal>) -> RiskAssessment {
+        let score = signals.iter().map(RiskSignal::score).sum();
+        let decision = if score >= self.deny_threshold {
+            RiskDecision::Deny
+        } else if score >= self.challenge_threshold {
+            RiskDecision::Challenge
+        } else {
+            RiskDecision::Allow
+        };
+        RiskAssessment {
+            score,
+            decision,
+            signals,
+        }
+    }
+}
+
+// Addresses and networks that logins are not accepted from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IpBlocklist {
+    networks: Vec<IpNet>,
+}
+
+impl IpBlocklist {
+    // One address or CIDR network per line. Blank lines and `#` comments are ignored.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let networks = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.parse::<IpNet>()
+                    .or_else(|_| line.parse::<IpAddr>().map(IpNet::from))
+                    .wrap_err_with(|| format!("invalid address or network: {}", line))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { networks })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+}
+
+// What logins are scored with. Without a GeoIP database, the location signals are left out.
+#[derive(Clone, Default)]
+pub struct RiskEngine {
+    pub policy: RiskPolicy,
+    pub blocklist: IpBlocklist,
+    pub geo_locator: Option<Arc<dyn GeoLocator>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_assess() {
+        let policy = RiskPolicy::default();
+        assert_eq!(policy.assess(Vec::new()).decision, RiskDecision::Allow);
+
+        let assessment = policy.assess(vec![
+            RiskSignal::NewDevice,
+            RiskSignal::RecentFailures { count: 2 },
+        ]);
+        assert_eq!(assessment.score, 50);
+        assert_eq!(assessment.decision, RiskDecision::Challenge);
+        assert_eq!(assessment.reasons(), ["new-device", "recent-failures:2"]);
+
+        let assessment = policy.assess(vec![RiskSignal::BlocklistedIp]);
+        assert_eq!(assessment.decision, RiskDecision::Deny);
+    }
+
+    #[test]
+    fn test_recent_failures_score_is_capped() {
+        assert_eq!(RiskSignal::RecentFailures { count: 1 }.score(), 10);
+        assert_eq!(RiskSignal::RecentFailures { count: 100 }.score(), 50);
+    }
+
+    #[test]
+    fn test_travel() {
+        let berlin = Coordinates {
+            latitude: 52.52,
+            longitude: 13.405,
+        };
+        let paris = Coordinates {
+            latitude: 48.8566,
+            longitude: 2.3522,
+        };
+        let potsdam = Coordinates {
+            latitude: 52.39,
+            longitude: 13.06,
+        };
+        let now = Utc::now();
+
+        // About 880 km in half an hour
+        assert!(matches!(
+            RiskSignal::travel((now - TimeDelta::minutes(30), &berlin), (now, &paris)),
+            Some(RiskSignal::ImpossibleTravel { speed_kmh }) if speed_kmh > 1_700
+        ));
+        // The same trip in two hours is a flight
+        assert_eq!(
+            RiskSignal::travel((now - TimeDelta::hours(2), &berlin), (now, &paris)),
+            None
+        );
+        // Nearby locations are not compared
+        assert_eq!(RiskSignal::travel((now, &berlin), (now, &potsdam)), None);
+    }
+
+    #[test]
+    fn test_ip_blocklist() {
+        let blocklist = IpBlocklist::parse(
+            "# Known bad\n203.0.113.0/24\n\n198.51.100.7 # one address\n2001:db8::/32\n",
+        )
+        .unwrap();
+        assert!(blocklist.contains("203.0.113.99".parse().unwrap()));
+        assert!(blocklist.contains("198.51.100.7".parse().unwrap()));
+        assert!(!blocklist.contains("198.51.100.8".parse().unwrap()));
+        assert!(blocklist.contains("2001:db8::1".parse().unwrap()));
+        assert!(!IpBlocklist::default().contains("203.0.113.99".parse().unwrap()));
+
+        assert!(IpBlocklist::parse("not-an-address").is_err());
+    }
+}
@@ -1,17 +1,24 @@
-use crate::domain::{Email, EmailClient};
+use crate::domain::{Email, EmailClient, EmailMessage};
 use color_eyre::eyre::Result;
 #[derive(Clone)]
 pub struct MockEmailClient;
 
 #[async_trait::async_trait]
 impl EmailClient for MockEmailClient {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
-        // Our mock email client will simply log the recipient, subject, and content to standard output
+    async fn send_email(
+        &self,
+        sender: &Email,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<()> {
+        // Our mock email client will simply log the sender, recipient, subject, and plain-text body to standard output
+        let rendered = message.render()?;
         tracing::debug!(
-            "Sending email to {} with subject: {} and content: {}",
+            "Sending email from {} to {} with subject: {} and content: {}",
+            sender.as_ref(),
             recipient.as_ref(),
-            subject,
-            content
+            rendered.subject,
+            rendered.text_body
         );
 
         Ok(())
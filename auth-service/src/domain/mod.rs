@@ -1,10 +1,42 @@
+pub mod auth_cookie;
+pub mod authentication;
+pub mod cors_policy;
+pub mod email;
 pub mod email_client;
+pub mod email_message;
 pub mod error;
+pub mod geo_locator;
+pub mod login_risk;
 pub mod mock_email_client;
+pub mod phone_number;
+pub mod recovery_code;
+pub mod remember_me;
+pub mod sms_client;
+pub mod sms_message;
+pub mod tenant;
+pub mod trusted_proxies;
+pub mod two_fa_resend_policy;
 pub mod user;
 
 // re-export items from sub-modules
+pub use auth_cookie::{AuthCookieSettings, CookiePrefix};
+pub use authentication::{AuthLevel, AuthMethod, Authentication, StepUpMaxAges, StepUpPolicy};
+pub use cors_policy::{CorsPolicy, OriginPattern};
+pub use email::{Email, PlusTagPolicy};
 pub use email_client::*;
+pub use email_message::{Branding, EmailMessage, RenderedEmail};
 pub use error::{AuthAPIError, AuthAPIError::*};
+pub use geo_locator::{Coordinates, GeoLocation, GeoLocator};
+pub use login_risk::{
+    IpBlocklist, RiskAssessment, RiskDecision, RiskEngine, RiskPolicy, RiskSignal,
+};
 pub use mock_email_client::MockEmailClient;
-pub use user::{Email, Password, User};
+pub use phone_number::PhoneNumber;
+pub use recovery_code::{RECOVERY_CODE_COUNT, RecoveryCode};
+pub use remember_me::RememberMeToken;
+pub use sms_client::SmsClient;
+pub use sms_message::SmsMessage;
+pub use tenant::{SigningKey, Tenant, TenantId, TwoFAPolicy};
+pub use trusted_proxies::TrustedProxies;
+pub use two_fa_resend_policy::TwoFAResendPolicy;
+pub use user::{Password, TwoFAChannel, User};
@@ -0,0 +1,112 @@
+use color_eyre::eyre::{Result, eyre};
+
+// E.164 allows at most 15 digits, country code included
+const MAX_DIGITS: usize = 15;
+// The shortest national numbers in use are around 4 digits, plus a 1-3 digit country code
+const MIN_DIGITS: usize = 7;
+
+// A phone number in E.164 form, e.g. `+14155552671`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhoneNumber(String);
+
+impl PhoneNumber {
+    // Accepts the common ways people type numbers (`+1 (415) 555-2671`, `+44 20 7946 0958`)
+    // and stores the E.164 form. The country code is required; national formats like
+    // `(415) 555-2671` are ambiguous without one and are rejected.
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let rest = s
+            .strip_prefix('+')
+            .ok_or_else(|| eyre!("Phone number must start with + and a country code"))?;
+
+        let mut digits = String::with_capacity(MAX_DIGITS + 1);
+        digits.push('+');
+        for c in rest.chars() {
+            match c {
+                '0'..='9' => digits.push(c),
+                ' ' | '-' | '.' | '(' | ')' => {}
+                _ => return Err(eyre!("Phone number contains an invalid character")),
+            }
+        }
+
+        let count = digits.len() - 1;
+        if !(MIN_DIGITS..=MAX_DIGITS).contains(&count) {
+            return Err(eyre!(
+                "Phone number must have between {} and {} digits",
+                MIN_DIGITS,
+                MAX_DIGITS
+            ));
+        }
+        if digits.as_bytes()[1] == b'0' {
+            return Err(eyre!("Country codes do not start with 0"));
+        }
+
+        Ok(Self(digits))
+    }
+
+    // Only the last few digits, for showing the user where a code was sent without revealing the number
+    pub fn masked(&self) -> String {
+        let visible = &self.0[self.0.len() - 4..];
+        format!("+{}{}", "*".repeat(self.0.len() - 5), visible)
+    }
+}
+
+impl AsRef<str> for PhoneNumber {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accepts_e164() {
+        let phone = PhoneNumber::parse("+14155552671").unwrap();
+        assert_eq!(phone.as_ref(), "+14155552671");
+    }
+
+    #[test]
+    fn test_parse_strips_formatting() {
+        for input in ["+1 (415) 555-2671", "+1.415.555.2671", " +1 415 555 2671 "] {
+            assert_eq!(PhoneNumber::parse(input).unwrap().as_ref(), "+14155552671");
+        }
+        assert_eq!(
+            PhoneNumber::parse("+44 20 7946 0958").unwrap().as_ref(),
+            "+442079460958"
+        );
+    }
+
+    #[test]
+    fn test_parse_requires_a_country_code() {
+        assert!(PhoneNumber::parse("(415) 555-2671").is_err());
+        assert!(PhoneNumber::parse("004155552671").is_err());
+        assert!(PhoneNumber::parse("+04155552671").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_numbers() {
+        for input in [
+            "",
+            "+",
+            "+123456",
+            "+1234567890123456",
+            "+1 415 555 267a",
+            "+1 415 555 2671 ext 2",
+            "++14155552671",
+        ] {
+            assert!(
+                PhoneNumber::parse(input).is_err(),
+                "{} should be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_masked_shows_only_the_last_four_digits() {
+        let phone = PhoneNumber::parse("+14155552671").unwrap();
+        assert_eq!(phone.masked(), "+*******2671");
+    }
+}
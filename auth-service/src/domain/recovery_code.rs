@@ -0,0 +1,100 @@
+use color_eyre::eyre::{Result, eyre};
+use rand::RngExt;
+use sha2::{Digest, Sha256};
+
+// Lowercase letters and digits, without the look-alikes 0/o and 1/i/l
+const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+// 10 characters from a 31-character alphabet is about 50 bits of entropy
+const CODE_LENGTH: usize = 10;
+// How many codes make up a set
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// A one-time code that stands in for a 2FA code when the user cannot receive one.
+// Shown as `abcde-fghjk`; input is accepted with or without the dash and in any case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    pub fn generate() -> Self {
+        let mut rng = rand::rng();
+        let code = (0..CODE_LENGTH)
+            .map(|_| ALPHABET[rng.random_range(0..ALPHABET.len())] as char)
+            .collect();
+        Self(code)
+    }
+
+    // A fresh set of codes
+    pub fn generate_set() -> Vec<Self> {
+        (0..RECOVERY_CODE_COUNT).map(|_| Self::generate()).collect()
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let code: String = s
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if code.len() != CODE_LENGTH || !code.bytes().all(|b| ALPHABET.contains(&b)) {
+            return Err(eyre!("Invalid recovery code"));
+        }
+        Ok(Self(code))
+    }
+
+    // The form shown to users
+    pub fn formatted(&self) -> String {
+        let (first, second) = self.0.split_at(CODE_LENGTH / 2);
+        format!("{}-{}", first, second)
+    }
+
+    // Codes are stored as SHA-256 digests. They are random and long enough that a slow password
+    // hash is not needed, and a plain digest can be looked up directly.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_codes_round_trip_through_their_formatted_form() {
+        for code in RecoveryCode::generate_set() {
+            let formatted = code.formatted();
+            assert_eq!(formatted.len(), CODE_LENGTH + 1);
+            assert_eq!(RecoveryCode::parse(&formatted).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_parse_ignores_case_dashes_and_spaces() {
+        let code = RecoveryCode::parse("abcde-fghjk").unwrap();
+        assert_eq!(RecoveryCode::parse("ABCDE FGHJK").unwrap(), code);
+        assert_eq!(RecoveryCode::parse(" abcdefghjk ").unwrap(), code);
+        assert_eq!(
+            code.hash(),
+            RecoveryCode::parse("AbCdEfGhJk").unwrap().hash()
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_codes() {
+        for input in [
+            "",
+            "abcde-fghj",
+            "abcde-fghjkm",
+            "abcde-fgh1k",
+            "abcde_fghjk",
+        ] {
+            assert!(RecoveryCode::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_generated_sets_do_not_repeat_codes() {
+        let set = RecoveryCode::generate_set();
+        assert_eq!(set.len(), RECOVERY_CODE_COUNT);
+        let hashes: std::collections::HashSet<_> = set.iter().map(RecoveryCode::hash).collect();
+        assert_eq!(hashes.len(), RECOVERY_CODE_COUNT);
+    }
+}
@@ -0,0 +1,87 @@
+use color_eyre::eyre::{Result, eyre};
+use rand::RngExt;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// The long-lived credential behind "remember me", kept by the browser as `<id>.<secret>`.
+// The ID names the stored token; the secret changes every time the token is exchanged
+// for a new session, so a copy of the cookie stops working once the original is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RememberMeToken {
+    id: Uuid,
+    secret: String,
+}
+
+impl RememberMeToken {
+    pub fn generate() -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            secret: new_secret(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let (id, secret) = s
+            .split_once('.')
+            .ok_or_else(|| eyre!("Invalid remember me token"))?;
+        let id = Uuid::parse_str(id).map_err(|_| eyre!("Invalid remember me token"))?;
+        if secret.len() != 64 || !secret.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(eyre!("Invalid remember me token"));
+        }
+        Ok(Self {
+            id,
+            secret: secret.to_ascii_lowercase(),
+        })
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    // The same token with a new secret
+    pub fn rotated(&self) -> Self {
+        Self {
+            id: self.id,
+            secret: new_secret(),
+        }
+    }
+
+    // The cookie value
+    pub fn value(&self) -> String {
+        format!("{}.{}", self.id, self.secret)
+    }
+
+    // Secrets are stored as SHA-256 digests, like recovery codes
+    pub fn secret_hash(&self) -> String {
+        hex::encode(Sha256::digest(self.secret.as_bytes()))
+    }
+}
+
+fn new_secret() -> String {
+    hex::encode(rand::rng().random::<[u8; 32]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_rotation() {
+        let token = RememberMeToken::generate();
+        let parsed = RememberMeToken::parse(&token.value()).unwrap();
+        assert_eq!(parsed, token);
+
+        let rotated = token.rotated();
+        assert_eq!(rotated.id(), token.id());
+        assert_ne!(rotated.secret_hash(), token.secret_hash());
+
+        for invalid in [
+            "",
+            "no-dot",
+            "not-a-uuid.00",
+            &format!("{}.short", token.id()),
+        ] {
+            assert!(RememberMeToken::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Utc};
+
+use super::email_message::{Branding, minutes_until};
+
+// A text message. Each variant carries what its body needs; bodies are kept short enough for a single SMS segment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmsMessage {
+    TwoFACode {
+        code: String,
+        expires_at: DateTime<Utc>,
+    },
+    PhoneVerificationCode {
+        code: String,
+        expires_at: DateTime<Utc>,
+    },
+}
+
+impl SmsMessage {
+    pub fn body(&self) -> String {
+        self.body_with_branding(&Branding::default())
+    }
+
+    // The code leads the message, so it shows up in lock-screen previews and autofill suggestions
+    pub fn body_with_branding(&self, branding: &Branding) -> String {
+        match self {
+            SmsMessage::TwoFACode { code, expires_at } => format!(
+                "{} is your {} verification code. It expires in {} minutes.",
+                code,
+                branding.name,
+                minutes_until(expires_at)
+            ),
+            SmsMessage::PhoneVerificationCode { code, expires_at } => format!(
+                "{} is your code to verify this phone number for {}. It expires in {} minutes.",
+                code,
+                branding.name,
+                minutes_until(expires_at)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn branding() -> Branding {
+        Branding {
+            name: "Acme".to_owned(),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn test_two_fa_code_body() {
+        let message = SmsMessage::TwoFACode {
+            code: "123456".to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        };
+        assert_eq!(
+            message.body_with_branding(&branding()),
+            "123456 is your Acme verification code. It expires in 10 minutes."
+        );
+    }
+
+    #[test]
+    fn test_bodies_fit_in_one_segment() {
+        let expires_at = Utc::now() + TimeDelta::minutes(10);
+        for message in [
+            SmsMessage::TwoFACode {
+                code: "123456".to_owned(),
+                expires_at,
+            },
+            SmsMessage::PhoneVerificationCode {
+                code: "123456".to_owned(),
+                expires_at,
+            },
+        ] {
+            assert!(message.body_with_branding(&branding()).len() <= 160);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use color_eyre::eyre::{Report, Result, eyre};
+use secrecy::SecretBox;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Email, OriginPattern};
+
+// Tenant IDs appear in URLs, storage keys and Redis keys, so they are kept to a small alphabet
+const MAX_TENANT_ID_LENGTH: usize = 63;
+
+// Identifies a tenant (realm). Each tenant has its own user base, keys and settings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TenantId(String);
+
+impl TenantId {
+    // Lowercase letters, digits and dashes, starting with a letter or digit
+    pub fn parse(s: &str) -> Result<Self> {
+        let valid = !s.is_empty()
+            && s.len() <= MAX_TENANT_ID_LENGTH
+            && !s.starts_with('-')
+            && s.bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+        if !valid {
+            return Err(eyre!(
+                "Tenant IDs must be 1-{} lowercase letters, digits or dashes: {}",
+                MAX_TENANT_ID_LENGTH,
+                s
+            ));
+        }
+        Ok(Self(s.to_owned()))
+    }
+}
+
+impl AsRef<str> for TenantId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for TenantId {
+    type Error = Report;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::parse(&s)
+    }
+}
+
+impl From<TenantId> for String {
+    fn from(id: TenantId) -> Self {
+        id.0
+    }
+}
+
+// Whether users of a tenant sign in with a second factor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAPolicy {
+    // Each user chooses at signup
+    #[default]
+    Optional,
+    // Every user, whatever they chose at signup
+    Required,
+    // No user, whatever they chose at signup
+    Disabled,
+}
+
+impl TwoFAPolicy {
+    // Whether a user who chose `requested` signs in with 2FA under this policy
+    pub fn applies(&self, requested: bool) -> bool {
+        match self {
+            TwoFAPolicy::Optional => requested,
+            TwoFAPolicy::Required => true,
+            TwoFAPolicy::Disabled => false,
+        }
+    }
+}
+
+impl FromStr for TwoFAPolicy {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "optional" => Ok(Self::Optional),
+            "required" => Ok(Self::Required),
+            "disabled" => Ok(Self::Disabled),
+            _ => Err(eyre!("Unknown 2FA policy: {}", s)),
+        }
+    }
+}
+
+// A key for signing and checking a tenant's JWTs. The `kid` goes in the token header,
+// so tokens signed with an older key keep working while keys are rotated.
+pub struct SigningKey {
+    pub kid: String,
+    pub secret: SecretBox<String>,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub struct Tenant {
+    pub id: TenantId,
+    pub name: String,
+    // Host names that resolve to this tenant, e.g. `auth.example.com`
+    pub hosts: Vec<String>,
+    // Where this tenant's users reach the service; links in emails point here
+    pub public_url: String,
+    // The `iss` claim of this tenant's tokens
+    pub jwt_issuer: String,
+    // New tokens are signed with the first key; any of them is accepted
+    pub signing_keys: Vec<SigningKey>,
+    // Browser origins allowed to call this tenant's endpoints with credentials, on top of the
+    // service-wide CORS policy
+    pub cors_origins: Vec<OriginPattern>,
+    pub two_fa_policy: TwoFAPolicy,
+    // The From address of this tenant's emails
+    pub email_sender: Email,
+}
+
+impl Tenant {
+    pub fn active_signing_key(&self) -> &SigningKey {
+        // A tenant cannot be configured without keys, see TenantRegistry
+        &self.signing_keys[0]
+    }
+
+    pub fn signing_key(&self, kid: &str) -> Option<&SigningKey> {
+        self.signing_keys.iter().find(|key| key.kid == kid)
+    }
+
+    // The path part of the public URL, e.g. `/realms/acme`, or an empty string
+    pub fn path_prefix(&self) -> &str {
+        let path = self
+            .public_url
+            .split_once("://")
+            .map_or(self.public_url.as_str(), |(_, rest)| rest);
+        path.find('/')
+            .map_or("", |start| &path[start..])
+            .trim_end_matches('/')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_id_accepts_slugs() {
+        for id in ["default", "acme", "product-2", "9lives"] {
+            assert_eq!(TenantId::parse(id).unwrap().as_ref(), id);
+        }
+    }
+
+    #[test]
+    fn test_tenant_id_rejects_other_strings() {
+        for id in [
+            "",
+            "Acme",
+            "-acme",
+            "acme corp",
+            "acme/x",
+            "acme:1",
+            &"a".repeat(64),
+        ] {
+            assert!(TenantId::parse(id).is_err(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_path_prefix() {
+        let mut tenant = Tenant {
+            id: TenantId::parse("acme").unwrap(),
+            name: "Acme".to_owned(),
+            hosts: Vec::new(),
+            public_url: "https://auth.example.com/realms/acme/".to_owned(),
+            jwt_issuer: "acme".to_owned(),
+            signing_keys: Vec::new(),
+            cors_origins: Vec::new(),
+            two_fa_policy: TwoFAPolicy::Optional,
+            email_sender: Email::parse(SecretBox::new(Box::new("no-reply@acme.com".to_owned())))
+                .unwrap(),
+        };
+        assert_eq!(tenant.path_prefix(), "/realms/acme");
+
+        tenant.public_url = "https://auth.acme.com".to_owned();
+        assert_eq!(tenant.path_prefix(), "");
+    }
+
+    #[test]
+    fn test_two_fa_policy_overrides_the_users_choice() {
+        assert!(TwoFAPolicy::Optional.applies(true));
+        assert!(!TwoFAPolicy::Optional.applies(false));
+        assert!(TwoFAPolicy::Required.applies(false));
+        assert!(!TwoFAPolicy::Disabled.applies(true));
+    }
+}
@@ -0,0 +1,154 @@
+use axum::http::HeaderMap;
+use color_eyre::eyre::{Context, Result};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+// The reverse proxies, e.g. nginx, whose X-Real-IP and X-Forwarded-For headers are believed.
+// Anyone else's are ignored, and the request is attributed to the address it came from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    // Comma-separated addresses or CIDR networks, e.g. `172.18.0.0/16, 127.0.0.1`
+    pub fn parse(list: &str) -> Result<Self> {
+        let networks = list
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse::<IpNet>()
+                    .or_else(|_| item.parse::<IpAddr>().map(IpNet::from))
+                    .wrap_err_with(|| format!("invalid address or network: {}", item))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { networks })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+
+    // The address of the client: the peer, unless it is a trusted proxy that names the client.
+    // None when the peer is unknown, or when a proxy sent an address that does not parse.
+    pub fn client_address(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = peer?.to_canonical();
+        if !self.contains(peer) {
+            return Some(peer);
+        }
+
+        // nginx sets X-Real-IP; other proxies append the address they saw to X-Forwarded-For,
+        // so the last hop that is not one of ours is the client
+        if let Some(real_ip) = header_value(headers, "x-real-ip") {
+            return parse_address(real_ip);
+        }
+        let Some(forwarded_for) = header_value(headers, "x-forwarded-for") else {
+            // The proxy made the request itself
+            return Some(peer);
+        };
+        let mut client = Some(peer);
+        for hop in forwarded_for.rsplit(',') {
+            client = parse_address(hop);
+            match client {
+                Some(ip) if self.contains(ip) => continue,
+                _ => break,
+            }
+        }
+        client
+    }
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_address(value: &str) -> Option<IpAddr> {
+    value
+        .trim()
+        .parse::<IpAddr>()
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_ignores_the_headers_of_untrusted_peers() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        let forged = headers(&[
+            ("x-real-ip", "198.51.100.1"),
+            ("x-forwarded-for", "198.51.100.2"),
+        ]);
+        assert_eq!(
+            proxies.client_address(ip("203.0.113.7"), &forged),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            TrustedProxies::default().client_address(ip("10.0.0.2"), &forged),
+            ip("10.0.0.2")
+        );
+        assert_eq!(proxies.client_address(None, &forged), None);
+    }
+
+    #[test]
+    fn test_believes_trusted_proxies() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8, 127.0.0.1").unwrap();
+        let peer = ip("10.0.0.2");
+        assert_eq!(
+            proxies.client_address(peer, &headers(&[("x-real-ip", "203.0.113.7")])),
+            ip("203.0.113.7")
+        );
+        // The first hop from the right that is not a proxy of ours
+        assert_eq!(
+            proxies.client_address(
+                peer,
+                &headers(&[("x-forwarded-for", "198.51.100.1, 203.0.113.7, 10.0.0.3")])
+            ),
+            ip("203.0.113.7")
+        );
+        assert_eq!(proxies.client_address(peer, &HeaderMap::new()), peer);
+        // IPv4 peers of an IPv6 listener
+        assert_eq!(
+            proxies.client_address(
+                ip("::ffff:127.0.0.1"),
+                &headers(&[("x-real-ip", "203.0.113.7")])
+            ),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn test_unparseable_forwarded_addresses_are_unknown() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        let peer = ip("10.0.0.2");
+        assert_eq!(
+            proxies.client_address(peer, &headers(&[("x-real-ip", "unknown")])),
+            None
+        );
+        assert_eq!(
+            proxies.client_address(peer, &headers(&[("x-forwarded-for", "203.0.113.7, junk")])),
+            None
+        );
+        assert!(TrustedProxies::parse("10.0.0.0/8, nginx").is_err());
+    }
+}
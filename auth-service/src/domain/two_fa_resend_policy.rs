@@ -0,0 +1,78 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use std::time::Duration;
+
+use crate::services::TwoFAChallenge;
+use crate::utils::constants::{DEFAULT_TWO_FA_MAX_RESENDS, DEFAULT_TWO_FA_RESEND_COOLDOWN_SECONDS};
+
+// How often the code of a pending 2FA login can be sent again, e.g. when the first one is lost
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwoFAResendPolicy {
+    // The least time between two codes of a login
+    pub cooldown: Duration,
+    // How many times a login's code can be sent again
+    pub max_resends: u32,
+}
+
+impl Default for TwoFAResendPolicy {
+    fn default() -> Self {
+        Self {
+            cooldown: Duration::from_secs(DEFAULT_TWO_FA_RESEND_COOLDOWN_SECONDS),
+            max_resends: DEFAULT_TWO_FA_MAX_RESENDS,
+        }
+    }
+}
+
+impl TwoFAResendPolicy {
+    // When the login's code can next be sent again; None once it has been resent too often
+    pub fn next_resend_at(&self, challenge: &TwoFAChallenge) -> Option<DateTime<Utc>> {
+        if challenge.resends >= self.max_resends {
+            return None;
+        }
+        let cooldown = TimeDelta::from_std(self.cooldown).unwrap_or(TimeDelta::MAX);
+        Some(
+            challenge
+                .sent_at
+                .checked_add_signed(cooldown)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        )
+    }
+
+    pub fn resends_remaining(&self, challenge: &TwoFAChallenge) -> u32 {
+        self.max_resends.saturating_sub(challenge.resends)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Email;
+    use crate::services::TwoFACode;
+    use secrecy::SecretBox;
+
+    #[test]
+    fn test_next_resend_at() {
+        let policy = TwoFAResendPolicy {
+            cooldown: Duration::from_secs(30),
+            max_resends: 2,
+        };
+        let sent_at = Utc::now();
+        let mut challenge = TwoFAChallenge {
+            email: Email::parse(SecretBox::new(Box::new("alice@example.com".to_owned()))).unwrap(),
+            code: TwoFACode::default(),
+            client_binding: "binding".to_owned(),
+            created_at: sent_at,
+            sent_at,
+            resends: 0,
+            attempts: 0,
+        };
+        assert_eq!(
+            policy.next_resend_at(&challenge),
+            Some(sent_at + TimeDelta::seconds(30))
+        );
+        assert_eq!(policy.resends_remaining(&challenge), 2);
+
+        challenge.resends = 2;
+        assert_eq!(policy.next_resend_at(&challenge), None);
+        assert_eq!(policy.resends_remaining(&challenge), 0);
+    }
+}
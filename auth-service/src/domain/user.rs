@@ -1,8 +1,10 @@
-use color_eyre::eyre::{Result, eyre};
-use regex::Regex;
+use color_eyre::eyre::{Report, Result, eyre};
 use secrecy::{ExposeSecret, SecretBox};
-use std::hash::{Hash, Hasher};
-use validator::ValidationError;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use super::PhoneNumber;
+pub use super::email::Email;
 
 #[derive(Debug)]
 pub struct Password(SecretBox<String>);
@@ -48,70 +50,16 @@ impl AsRef<SecretBox<String>> for Password {
     }
 }
 
-fn validate_email(email: &SecretBox<String>) -> Result<(), ValidationError> {
-    let email_regex = Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").unwrap();
-    if email_regex.is_match(email.expose_secret()) {
-        Ok(())
-    } else {
-        Err(ValidationError::new("invalid_email"))
-    }
-}
-
-#[derive(Debug)]
-pub struct Email {
-    email: SecretBox<String>,
-}
-
-impl Clone for Email {
-    fn clone(&self) -> Self {
-        Self {
-            email: SecretBox::new(Box::new(self.email.expose_secret().clone())),
-        }
-    }
-}
-
-impl Email {
-    pub fn parse(email: SecretBox<String>) -> Result<Self> {
-        let email = Email { email };
-        validate_email(&email.email)?;
-        Ok(email)
-    }
-
-    pub fn to_str(&self) -> &str {
-        self.email.expose_secret()
-    }
-}
-
-impl Eq for Email {}
-
-impl PartialEq for Email {
-    fn eq(&self, other: &Self) -> bool {
-        self.email.expose_secret() == other.email.expose_secret()
-    }
-}
-
-impl Hash for Email {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.email.expose_secret().hash(state);
-    }
-}
-
-// The AsRef trait is used to convert a reference of one type to a reference of another type.
-// In this case, we're implementing the AsRef trait for the Email type to allow us to convert a &Email to a &str.
-// This is useful when we want to expose the inner email string in a read-only manner.
-impl AsRef<str> for Email {
-    fn as_ref(&self) -> &str {
-        self.email.expose_secret().as_str()
-    }
-}
-
 // The User struct should contain 3 fields. email, which is a String;
 // password, which is also a String; and requires_2fa, which is a boolean.
+// A verified phone number and the 2FA channel are optional and start out unset (email).
 #[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
 pub struct User {
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    pub phone_number: Option<PhoneNumber>,
+    pub two_fa_channel: TwoFAChannel,
 }
 
 impl User {
@@ -120,11 +68,44 @@ impl User {
             email,
             password,
             requires_2fa,
+            phone_number: None,
+            two_fa_channel: TwoFAChannel::Email,
+        }
+    }
+}
+
+// Where 2FA codes are sent. SMS requires a verified phone number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAChannel {
+    #[default]
+    Email,
+    Sms,
+}
+
+impl TwoFAChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TwoFAChannel::Email => "email",
+            TwoFAChannel::Sms => "sms",
+        }
+    }
+}
+
+impl FromStr for TwoFAChannel {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "email" => Ok(Self::Email),
+            "sms" => Ok(Self::Sms),
+            _ => Err(eyre!("Unknown 2FA channel: {}", s)),
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_pattern_matching)]
 mod tests {
     use super::*;
     use fake::{
@@ -132,25 +113,6 @@ mod tests {
         faker::{internet::en::Password as FakerPassword, internet::en::SafeEmail},
     };
 
-    #[test]
-    fn test_email_parse() {
-        let email_str: String = SafeEmail().fake();
-        let email = Email::parse(SecretBox::new(Box::new(email_str.clone())));
-        assert!(matches!(email, Ok(e) if e.email.expose_secret() == &email_str));
-    }
-
-    #[test]
-    fn test_email_parse_empty() {
-        let email = Email::parse(SecretBox::new(Box::new("".to_string())));
-        assert!(matches!(email, Err(_)));
-    }
-
-    #[test]
-    fn test_email_parse_invalid() {
-        let email = Email::parse(SecretBox::new(Box::new("test".to_string())));
-        assert!(matches!(email, Err(_)));
-    }
-
     #[test]
     fn test_password_parse() {
         let password_secret: SecretBox<String> = SecretBox::new(Box::new(
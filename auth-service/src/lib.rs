@@ -1,18 +1,22 @@
 use app_state::AppState;
 use axum::{
     Json, Router,
-    http::Method,
+    extract::connect_info::{ConnectInfo, IntoMakeServiceWithConnectInfo},
     http::StatusCode,
+    middleware::AddExtension,
     response::{IntoResponse, Response},
     serve::Serve,
 };
 use domain::AuthAPIError;
-use redis::{Client, RedisResult};
+use redis::{
+    Client, RedisResult,
+    aio::{ConnectionManager, ConnectionManagerConfig},
+};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use std::error::Error;
-use tower_http::cors::CorsLayer;
-use utils::constants::APP_SERVICE_HOST;
+use std::net::SocketAddr;
+use utils::constants::{REDIS_CONNECTION_TIMEOUT, REDIS_NUMBER_OF_RETRIES, REDIS_RESPONSE_TIMEOUT};
 
 pub mod app_state;
 pub mod domain;
@@ -35,6 +39,37 @@ impl IntoResponse for AuthAPIError {
                 (StatusCode::UNAUTHORIZED, "Incorrect credentials")
             }
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing JWT Token"),
+            AuthAPIError::InvalidPhoneNumber => (StatusCode::BAD_REQUEST, "Invalid phone number"),
+            AuthAPIError::InvalidVerificationCode => {
+                (StatusCode::BAD_REQUEST, "Invalid verification code")
+            }
+            AuthAPIError::PhoneNumberNotVerified => {
+                (StatusCode::CONFLICT, "Phone number not verified")
+            }
+            AuthAPIError::TwoFactorNotEnabled => (
+                StatusCode::CONFLICT,
+                "Two-factor authentication is not enabled",
+            ),
+            AuthAPIError::SmsUnavailable => {
+                (StatusCode::SERVICE_UNAVAILABLE, "SMS is not available")
+            }
+            AuthAPIError::UnknownTenant => (StatusCode::NOT_FOUND, "Unknown tenant"),
+            AuthAPIError::CsrfCheckFailed => (StatusCode::FORBIDDEN, "CSRF check failed"),
+            AuthAPIError::ResendTooSoon => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Please wait before asking for another code",
+            ),
+            AuthAPIError::ResendLimitReached => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "No more codes can be sent for this login, please log in again",
+            ),
+            AuthAPIError::TrustedDeviceNotFound => {
+                (StatusCode::NOT_FOUND, "Trusted device not found")
+            }
+            AuthAPIError::LoginDenied => (StatusCode::FORBIDDEN, "Login denied"),
+            AuthAPIError::ReauthenticationRequired => {
+                (StatusCode::UNAUTHORIZED, "Reauthentication required")
+            }
             AuthAPIError::UnexpectedError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
@@ -62,7 +97,11 @@ fn log_error_chain(e: &(dyn Error + 'static)) {
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<tokio::net::TcpListener, Router, Router>,
+    server: Serve<
+        tokio::net::TcpListener,
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
@@ -70,33 +109,54 @@ pub struct Application {
 
 impl Application {
     pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
-        // Allow the app service(running on our local machine and in production) to call the auth service
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            format!("http://{}", *APP_SERVICE_HOST).parse()?,
-        ];
-
-        let cors = CorsLayer::new()
-            // Allow GET and POST requests
-            .allow_methods([Method::GET, Method::POST])
-            // Allow cookies to be included in requests
-            .allow_credentials(true)
-            .allow_origin(allowed_origins);
-
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, routes::get_routes(app_state, cors));
+        // The peer address is the client's, unless it is one of the trusted proxies
+        let server = axum::serve(
+            listener,
+            routes::get_routes(app_state).into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         // Create a new Application instance and return it
         Ok(Application { server, address })
     }
 
+    // Serve until Ctrl+C or SIGTERM, letting requests in flight finish
     pub async fn run(self) -> Result<(), std::io::Error> {
         tracing::info!("listening on {}", &self.address);
-        self.server.await
+        self.server.with_graceful_shutdown(shutdown_signal()).await
     }
 }
 
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for Ctrl+C: {:?}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("failed to listen for SIGTERM: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutting down");
+}
+
 pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
     // Create a new PostgreSQL connection pool
     PgPoolOptions::new().max_connections(5).connect(url).await
@@ -106,3 +166,18 @@ pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
     let redis_url = format!("redis://{}/", redis_hostname);
     redis::Client::open(redis_url)
 }
+
+// Create a multiplexed async Redis connection that is shared by all Redis-backed stores.
+// The connection manager transparently reconnects (with backoff) if the connection drops.
+pub async fn get_redis_connection_manager(
+    redis_hostname: String,
+) -> RedisResult<ConnectionManager> {
+    let config = ConnectionManagerConfig::new()
+        .set_connection_timeout(Some(*REDIS_CONNECTION_TIMEOUT))
+        .set_response_timeout(Some(*REDIS_RESPONSE_TIMEOUT))
+        .set_number_of_retries(*REDIS_NUMBER_OF_RETRIES);
+
+    get_redis_client(redis_hostname)?
+        .get_connection_manager_with_config(config)
+        .await
+}
@@ -1,52 +1,149 @@
-use auth_service::domain::Email;
-use auth_service::utils::constants::{prod, test};
+use auth_service::domain::{
+    AuthCookieSettings, CorsPolicy, Email, EmailProvider, GeoLocator, IpBlocklist, PhoneNumber,
+    PlusTagPolicy, RiskEngine, RiskPolicy, SigningKey, StepUpMaxAges, Tenant, TenantId,
+    TrustedProxies, TwoFAPolicy, TwoFAResendPolicy,
+};
+use auth_service::utils::constants::{
+    ADMIN_API_TOKEN, AUTH_COOKIE_DOMAIN, AUTH_COOKIE_MAX_AGE, AUTH_COOKIE_NAME, AUTH_COOKIE_PREFIX,
+    AUTH_COOKIE_SAME_SITE, AUTH_COOKIE_SECURE, AUTH_SERVICE_URL, CHANGE_PASSWORD_STEP_UP_MAX_AGE,
+    CORS_ALLOWED_HEADERS, CORS_ALLOWED_METHODS, CORS_ALLOWED_ORIGINS, CORS_MAX_AGE,
+    CORS_POLICY_FILE, CORS_RELOAD_INTERVAL, DEFAULT_DEV_MAILBOX_CAPACITY, DEFAULT_JWT_ISSUER,
+    DEFAULT_TENANT_ID, DEV_MAILBOX_DIR, DEV_MODE, EMAIL_PROVIDER, EXPIRED_ROW_SWEEP_INTERVAL,
+    GEOIP_DATABASE_FILE, IP_BLOCKLIST_FILE, JWT_SECRET, RECOVERY_CODES_STEP_UP_MAX_AGE,
+    REMEMBER_ME_MAX_AGE, RISK_CHALLENGE_THRESHOLD, RISK_DENY_THRESHOLD, STEP_UP_MAX_AGE,
+    STORE_BACKEND, TENANTS_FILE, TOKEN_REVOCATION_FAILURE_POLICY, TRUSTED_DEVICE_MAX_AGE,
+    TRUSTED_PROXIES, TWO_FA_CHANNEL_STEP_UP_MAX_AGE, TWO_FA_MAX_RESENDS, TWO_FA_RESEND_COOLDOWN,
+    prod, test,
+};
 use auth_service::utils::init_tracing;
 use auth_service::{
     Application,
     app_state::{
-        AppState, BannedTokenStoreType, EmailClientType, TwoFACodeStoreType, UserStoreType,
+        AppState, BannedTokenStoreType, EmailClientType, EmailOutboxType, KnownDeviceStoreType,
+        LoginAttemptStoreType, MagicLinkStoreType, RecoveryCodeStoreType, RememberMeStoreType,
+        SmsClientType, TrustedDeviceStoreType, TwoFACodeStoreType, UserStoreType,
+    },
+    get_postgres_pool, get_redis_client, get_redis_connection_manager,
+    services::data_stores::{
+        CachedBannedTokenStore, PostgresBannedTokenStore, PostgresEmailOutbox,
+        PostgresKnownDeviceStore, PostgresLoginAttemptStore, PostgresMagicLinkStore,
+        PostgresRecoveryCodeStore, PostgresRememberMeStore, PostgresTrustedDeviceStore,
+        PostgresTwoFACodeStore, PostgresUserStore, RedisMagicLinkStore, RedisTwoFACodeStore,
+        StoreBackend,
     },
-    get_postgres_pool, get_redis_client,
-    services::data_stores::{PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore},
+    services::dev_mailbox::DevMailbox,
+    services::email_outbox_worker::{EmailOutboxWorker, EmailOutboxWorkerConfig},
+    services::email_renormalizer::EmailRenormalizer,
+    services::expired_row_sweeper::ExpiredRowSweeper,
+    services::maxmind_geo_locator::MaxMindGeoLocator,
     services::postmark_email_client::PostmarkEmailClient,
+    services::reloadable_cors_policy::ReloadableCorsPolicy,
+    services::smtp_email_client::{SmtpCredentials, SmtpEmailClient, SmtpSettings},
+    services::tenant_registry::TenantRegistry,
+    services::twilio_sms_client::TwilioSmsClient,
     utils::{DATABASE_URL, REDIS_HOST_NAME},
 };
+use redis::aio::ConnectionManager;
 use reqwest::Client;
 use secrecy::SecretBox;
 use sqlx::PgPool;
 use std::env;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 #[tokio::main]
 async fn main() {
     color_eyre::install().expect("Failed to install color_eyre");
-    init_tracing().expect("Failed to initialize tracing");
+    let tracing_guard = init_tracing().expect("Failed to initialize tracing");
 
+    let tenants = Arc::new(configure_tenants());
+    let cors_policy = Arc::new(configure_cors_policy());
     let pg_pool = configure_postgresql().await;
-    let user_store: UserStoreType =
-        Arc::new(RwLock::new(Box::new(PostgresUserStore::new(pg_pool))));
-    let banned_token_store: BannedTokenStoreType = Arc::new(RwLock::new(Box::new(
-        RedisBannedTokenStore::new(Arc::new(RwLock::new(configure_redis()))),
-    )));
-    let two_fa_token_store: TwoFACodeStoreType = Arc::new(RwLock::new(Box::new(
-        RedisTwoFACodeStore::new(Arc::new(RwLock::new(configure_redis()))),
-    )));
-    let email_client: EmailClientType =
-        Arc::new(RwLock::new(Box::new(configure_postmark_email_client())));
+    let user_store: UserStoreType = Arc::new(PostgresUserStore::new(pg_pool.clone()));
+    let email_outbox: EmailOutboxType = Arc::new(PostgresEmailOutbox::new(pg_pool.clone()));
+    let recovery_code_store: RecoveryCodeStoreType =
+        Arc::new(PostgresRecoveryCodeStore::new(pg_pool.clone()));
+    let remember_me_store: RememberMeStoreType =
+        Arc::new(PostgresRememberMeStore::new(pg_pool.clone()));
+    let trusted_device_store: TrustedDeviceStoreType =
+        Arc::new(PostgresTrustedDeviceStore::new(pg_pool.clone()));
+    let known_device_store: KnownDeviceStoreType =
+        Arc::new(PostgresKnownDeviceStore::new(pg_pool.clone()));
+    let login_attempt_store: LoginAttemptStoreType =
+        Arc::new(PostgresLoginAttemptStore::new(pg_pool.clone()));
+    let (banned_token_store, two_fa_token_store, magic_link_store) =
+        configure_ephemeral_stores(&pg_pool, *STORE_BACKEND).await;
+    let (email_client, dev_mailbox): (EmailClientType, _) = match *EMAIL_PROVIDER {
+        EmailProvider::Postmark => (Arc::new(configure_postmark_email_client()), None),
+        EmailProvider::Smtp => (Arc::new(configure_smtp_email_client()), None),
+        EmailProvider::DevMailbox => {
+            let mailbox = Arc::new(configure_dev_mailbox());
+            (mailbox.clone(), Some(mailbox))
+        }
+    };
+
+    // Deliver queued emails in the background
+    tokio::spawn(
+        EmailOutboxWorker::new(
+            email_outbox.clone(),
+            email_client.clone(),
+            tenants.clone(),
+            EmailOutboxWorkerConfig::default(),
+        )
+        .run(),
+    );
+
+    // Delete expired tokens and codes in the background
+    tokio::spawn(ExpiredRowSweeper::new(pg_pool, *EXPIRED_ROW_SWEEP_INTERVAL).run());
+
+    // Pick up changes to CORS_POLICY_FILE
+    tokio::spawn(cors_policy.clone().watch(*CORS_RELOAD_INTERVAL));
 
     let app_state: AppState = AppState::new(
         user_store,
         banned_token_store,
         two_fa_token_store,
         email_client,
-    );
+        email_outbox,
+        recovery_code_store,
+        magic_link_store,
+        remember_me_store,
+        trusted_device_store,
+        known_device_store,
+        login_attempt_store,
+        tenants,
+    )
+    .with_cors_policy(cors_policy)
+    .with_auth_cookie(configure_auth_cookie())
+    .with_admin_api_token(
+        ADMIN_API_TOKEN
+            .to_owned()
+            .map(|token| SecretBox::new(Box::new(token))),
+    )
+    .with_dev_mailbox(dev_mailbox)
+    .with_sms_client(configure_sms_client())
+    .with_two_fa_resend(TwoFAResendPolicy {
+        cooldown: *TWO_FA_RESEND_COOLDOWN,
+        max_resends: *TWO_FA_MAX_RESENDS,
+    })
+    .with_risk_engine(configure_risk_engine())
+    .with_trusted_proxies(
+        TrustedProxies::parse(&TRUSTED_PROXIES)
+            .expect("TRUSTED_PROXIES must be a list of addresses or networks."),
+    )
+    .with_step_up_max_ages(StepUpMaxAges {
+        change_password: *CHANGE_PASSWORD_STEP_UP_MAX_AGE,
+        two_fa_channel: *TWO_FA_CHANNEL_STEP_UP_MAX_AGE,
+        recovery_codes: *RECOVERY_CODES_STEP_UP_MAX_AGE,
+        default: *STEP_UP_MAX_AGE,
+    });
 
     let app = Application::build(app_state, "0.0.0.0:3000")
         .await
         .expect("Failed to build app");
 
     app.run().await.expect("Failed to run app");
+    // Send the spans still waiting to be exported
+    tracing_guard.shutdown();
 }
 
 async fn configure_postgresql() -> PgPool {
@@ -62,14 +159,53 @@ async fn configure_postgresql() -> PgPool {
         .await
         .expect("Failed to run migrations");
 
+    // Bring canonical emails stored by earlier versions up to date
+    let renormalized = EmailRenormalizer::new(pg_pool.clone(), PlusTagPolicy::configured())
+        .run()
+        .await
+        .expect("Failed to renormalize user emails");
+    if renormalized > 0 {
+        tracing::info!(count = renormalized, "renormalized user emails");
+    }
+
     pg_pool
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+async fn configure_redis() -> ConnectionManager {
+    get_redis_connection_manager(REDIS_HOST_NAME.to_owned())
+        .await
+        .expect("Failed to get Redis connection manager")
+}
+
+// Revoked tokens, pending 2FA codes and magic links go to Redis, or to Postgres for deployments
+// without Redis, which then is not connected to at all
+async fn configure_ephemeral_stores(
+    pg_pool: &PgPool,
+    backend: StoreBackend,
+) -> (BannedTokenStoreType, TwoFACodeStoreType, MagicLinkStoreType) {
+    match backend {
+        StoreBackend::Redis => {
+            let redis_conn = configure_redis().await;
+            let revocation_cache = Arc::new(CachedBannedTokenStore::new(
+                redis_conn.clone(),
+                *TOKEN_REVOCATION_FAILURE_POLICY,
+            ));
+            // Keep the revocation cache in sync with the other instances
+            tokio::spawn(revocation_cache.clone().run(
+                get_redis_client(REDIS_HOST_NAME.to_owned()).expect("Failed to get Redis client"),
+            ));
+            (
+                revocation_cache,
+                Arc::new(RedisTwoFACodeStore::new(redis_conn.clone())),
+                Arc::new(RedisMagicLinkStore::new(redis_conn)),
+            )
+        }
+        StoreBackend::Postgres => (
+            Arc::new(PostgresBannedTokenStore::new(pg_pool.clone())),
+            Arc::new(PostgresTwoFACodeStore::new(pg_pool.clone())),
+            Arc::new(PostgresMagicLinkStore::new(pg_pool.clone())),
+        ),
+    }
 }
 
 fn configure_postmark_email_client() -> PostmarkEmailClient {
@@ -83,11 +219,159 @@ fn configure_postmark_email_client() -> PostmarkEmailClient {
 
     PostmarkEmailClient::new(
         prod::email_client::BASE_URL.to_owned(),
-        Email::parse(SecretBox::new(Box::new(
-            prod::email_client::SENDER.to_owned(),
-        )))
-        .unwrap(),
         SecretBox::new(Box::new(postmark_auth_token)),
         http_client,
     )
 }
+
+fn configure_smtp_email_client() -> SmtpEmailClient {
+    let credentials = match (
+        prod::smtp::USERNAME.to_owned(),
+        prod::smtp::PASSWORD.to_owned(),
+    ) {
+        (Some(username), Some(password)) => Some(SmtpCredentials {
+            username,
+            password: SecretBox::new(Box::new(password)),
+        }),
+        (None, None) => None,
+        _ => panic!("SMTP_USERNAME and SMTP_PASSWORD must be set together."),
+    };
+
+    let settings = SmtpSettings {
+        host: prod::smtp::HOST.to_owned(),
+        port: *prod::smtp::PORT,
+        tls: *prod::smtp::TLS,
+        credentials,
+        pool_max_size: *prod::smtp::POOL_MAX_SIZE,
+        timeout: *prod::smtp::TIMEOUT,
+    };
+
+    SmtpEmailClient::new(settings).expect("Failed to build SMTP email client")
+}
+
+fn configure_tenants() -> TenantRegistry {
+    if let Some(path) = TENANTS_FILE.as_ref() {
+        return TenantRegistry::from_file(path).expect("Failed to load TENANTS_FILE");
+    }
+
+    // Without a tenants file, every request belongs to one tenant configured from the environment
+    let tenant = Tenant {
+        id: TenantId::parse(DEFAULT_TENANT_ID).unwrap(),
+        name: DEFAULT_TENANT_ID.to_owned(),
+        hosts: Vec::new(),
+        public_url: AUTH_SERVICE_URL.to_owned(),
+        jwt_issuer: DEFAULT_JWT_ISSUER.to_owned(),
+        signing_keys: vec![SigningKey {
+            kid: DEFAULT_TENANT_ID.to_owned(),
+            secret: SecretBox::new(Box::new(JWT_SECRET.to_owned())),
+        }],
+        // The service-wide CORS policy applies
+        cors_origins: Vec::new(),
+        two_fa_policy: TwoFAPolicy::Optional,
+        email_sender: Email::parse(SecretBox::new(Box::new(
+            prod::email_client::SENDER.to_owned(),
+        )))
+        .expect("EMAIL_FROM_USER must be an email address."),
+    };
+    TenantRegistry::single(tenant).expect("Failed to configure the default tenant")
+}
+
+fn configure_cors_policy() -> ReloadableCorsPolicy {
+    let policy = CorsPolicy {
+        allowed_origins: CorsPolicy::parse_origins(&CORS_ALLOWED_ORIGINS)
+            .expect("CORS_ALLOWED_ORIGINS must be a list of origins."),
+        allowed_methods: CorsPolicy::parse_methods(&CORS_ALLOWED_METHODS)
+            .expect("CORS_ALLOWED_METHODS must be a list of HTTP methods."),
+        allowed_headers: CorsPolicy::parse_headers(&CORS_ALLOWED_HEADERS)
+            .expect("CORS_ALLOWED_HEADERS must be a list of header names."),
+        max_age: *CORS_MAX_AGE,
+    };
+    match CORS_POLICY_FILE.as_ref() {
+        Some(path) => {
+            ReloadableCorsPolicy::from_file(policy, path).expect("Failed to load CORS_POLICY_FILE")
+        }
+        None => ReloadableCorsPolicy::fixed(policy),
+    }
+}
+
+fn configure_auth_cookie() -> AuthCookieSettings {
+    let settings = AuthCookieSettings {
+        name: AUTH_COOKIE_NAME.to_owned(),
+        prefix: *AUTH_COOKIE_PREFIX,
+        secure: *AUTH_COOKIE_SECURE,
+        domain: AUTH_COOKIE_DOMAIN.to_owned(),
+        same_site: AuthCookieSettings::parse_same_site(&AUTH_COOKIE_SAME_SITE)
+            .expect("AUTH_COOKIE_SAME_SITE must be strict, lax or none."),
+        max_age: *AUTH_COOKIE_MAX_AGE,
+        remember_me_max_age: *REMEMBER_ME_MAX_AGE,
+        trusted_device_max_age: *TRUSTED_DEVICE_MAX_AGE,
+    };
+    settings.validate().expect("Invalid auth cookie settings");
+    settings
+}
+
+fn configure_risk_engine() -> RiskEngine {
+    let policy = RiskPolicy {
+        challenge_threshold: *RISK_CHALLENGE_THRESHOLD,
+        deny_threshold: *RISK_DENY_THRESHOLD,
+    };
+    let blocklist = match IP_BLOCKLIST_FILE.as_ref() {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path).expect("Failed to read IP_BLOCKLIST_FILE");
+            IpBlocklist::parse(&contents).expect("Invalid IP_BLOCKLIST_FILE")
+        }
+        None => IpBlocklist::default(),
+    };
+    let geo_locator: Option<Arc<dyn GeoLocator>> = GEOIP_DATABASE_FILE.as_ref().map(|path| {
+        Arc::new(MaxMindGeoLocator::open(path).expect("Failed to open GEOIP_DATABASE_FILE")) as _
+    });
+    if geo_locator.is_none() {
+        tracing::info!("GEOIP_DATABASE_FILE is not set: logins are scored without locations");
+    }
+    RiskEngine {
+        policy,
+        blocklist,
+        geo_locator,
+    }
+}
+
+fn configure_dev_mailbox() -> DevMailbox {
+    // Anyone who can reach /dev/mailbox can read every code and link the service sends
+    if !*DEV_MODE {
+        panic!("EMAIL_PROVIDER=dev-mailbox is for development only; set DEV_MODE=true to use it.");
+    }
+    tracing::warn!("EMAIL_PROVIDER=dev-mailbox: emails are not delivered, see /dev/mailbox");
+    match DEV_MAILBOX_DIR.as_ref() {
+        Some(dir) => DevMailbox::in_directory(dir).expect("Failed to create the dev mailbox"),
+        None => DevMailbox::in_memory(DEFAULT_DEV_MAILBOX_CAPACITY),
+    }
+}
+
+fn configure_sms_client() -> Option<SmsClientType> {
+    let Some(account_sid) = prod::twilio::ACCOUNT_SID.to_owned() else {
+        tracing::info!("TWILIO_ACCOUNT_SID is not set: SMS 2FA is disabled");
+        return None;
+    };
+    let auth_token = prod::twilio::AUTH_TOKEN
+        .to_owned()
+        .expect("TWILIO_AUTH_TOKEN must be set when TWILIO_ACCOUNT_SID is set.");
+    let sender = PhoneNumber::parse(
+        prod::twilio::FROM_NUMBER
+            .as_deref()
+            .expect("TWILIO_FROM_NUMBER must be set when TWILIO_ACCOUNT_SID is set."),
+    )
+    .expect("TWILIO_FROM_NUMBER must be an E.164 phone number.");
+
+    let http_client = Client::builder()
+        .timeout(*prod::twilio::TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    Some(Arc::new(TwilioSmsClient::new(
+        prod::twilio::BASE_URL.to_owned(),
+        account_sid,
+        SecretBox::new(Box::new(auth_token)),
+        sender,
+        http_client,
+    )))
+}
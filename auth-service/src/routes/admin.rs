@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use secrecy::{ExposeSecret, SecretBox};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{
+    app_state::AppState,
+    domain::AuthAPIError,
+    services::{
+        OUTBOX_METRICS,
+        data_stores::{OutboxEntry, OutboxStats},
+        email_outbox_worker::OutboxMetricsSnapshot,
+    },
+    utils::auth::{bearer_token, constant_time_eq},
+};
+
+const DEFAULT_STUCK_AFTER_SECONDS: u64 = 300;
+const DEFAULT_STUCK_LIMIT: u32 = 50;
+const MAX_STUCK_LIMIT: u32 = 500;
+
+// Queue depth, this instance's delivery counters, and messages that need attention
+#[tracing::instrument(skip_all)]
+pub async fn email_outbox_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<EmailOutboxStatusParams>,
+) -> Result<Response, AuthAPIError> {
+    // The admin endpoints are hidden unless ADMIN_API_TOKEN is configured
+    let Some(expected) = &state.admin_api_token else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    authorize_admin(expected, &headers)?;
+
+    let stats = state
+        .email_outbox
+        .stats()
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let older_than = Duration::from_secs(
+        params
+            .stuck_after_seconds
+            .unwrap_or(DEFAULT_STUCK_AFTER_SECONDS),
+    );
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_STUCK_LIMIT)
+        .min(MAX_STUCK_LIMIT);
+    let stuck = state
+        .email_outbox
+        .stuck_messages(older_than, limit)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(Json(EmailOutboxStatusResponse {
+        stats,
+        worker: OUTBOX_METRICS.snapshot(),
+        stuck,
+    })
+    .into_response())
+}
+
+fn authorize_admin(expected: &SecretBox<String>, headers: &HeaderMap) -> Result<(), AuthAPIError> {
+    let provided = bearer_token(headers).ok_or(AuthAPIError::MissingToken)?;
+
+    if constant_time_eq(provided.as_bytes(), expected.expose_secret().as_bytes()) {
+        Ok(())
+    } else {
+        Err(AuthAPIError::InvalidToken)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmailOutboxStatusParams {
+    // Undelivered messages older than this are reported as stuck
+    pub stuck_after_seconds: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailOutboxStatusResponse {
+    pub stats: OutboxStats,
+    pub worker: OutboxMetricsSnapshot,
+    pub stuck: Vec<OutboxEntry>,
+}
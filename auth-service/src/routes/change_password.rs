@@ -0,0 +1,79 @@
+use axum::{
+    Extension,
+    extract::{Json, State},
+    http::{HeaderMap, StatusCode},
+};
+use axum_extra::extract::CookieJar;
+use secrecy::SecretBox;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Password, Tenant},
+    utils::{
+        auth::authenticated_email, remember_me::forget_device,
+        trusted_device::forget_trusted_device,
+    },
+};
+
+// Change the signed-in user's password. Every "remember me" login of the account ends, and
+// its trusted devices need 2FA again, so a device that was signed in with the old password
+// has to go through the whole login.
+#[tracing::instrument(skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let email = match authenticated_email(&state, &tenant, &headers, &jar).await {
+        Ok(email) => email,
+        Err(e) => return (jar, Err(e)),
+    };
+    let (current_password, new_password) = match (
+        Password::parse(request.current_password),
+        Password::parse(request.new_password),
+    ) {
+        (Ok(current), Ok(new)) => (current, new),
+        _ => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    if state
+        .user_store
+        .validate_user(&tenant.id, &email, &current_password)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    }
+
+    if let Err(e) = state
+        .user_store
+        .update_password(&tenant.id, &email, new_password)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+    if let Err(e) = state.remember_me_store.revoke_all(&tenant.id, &email).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+    if let Err(e) = state
+        .trusted_device_store
+        .revoke_all(&tenant.id, &email)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    let jar = forget_trusted_device(&state, forget_device(&state, jar));
+    (jar, Ok(StatusCode::OK))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordRequest {
+    current_password: SecretBox<String>,
+    new_password: SecretBox<String>,
+}
@@ -0,0 +1,31 @@
+use crate::app_state::AppState;
+use crate::utils::{constants::CSRF_COOKIE_NAME, csrf::generate_csrf_cookie};
+use axum::Json;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+// Scripts on another origin cannot read the CSRF cookie, so they ask for its value here.
+// The CORS policy keeps the response away from origins it does not allow.
+#[tracing::instrument(skip_all)]
+pub async fn csrf_token(State(state): State<AppState>, jar: CookieJar) -> impl IntoResponse {
+    let (jar, token) = match jar.get(CSRF_COOKIE_NAME) {
+        Some(cookie) => {
+            let token = cookie.value().to_owned();
+            (jar, token)
+        }
+        None => {
+            let cookie = generate_csrf_cookie(&state.auth_cookie);
+            let token = cookie.value().to_owned();
+            (jar.add(cookie), token)
+        }
+    };
+    (jar, Json(CsrfTokenResponse { csrf_token: token }))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CsrfTokenResponse {
+    pub csrf_token: String,
+}
@@ -0,0 +1,33 @@
+use askama::Template;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::{app_state::AppState, domain::AuthAPIError, services::dev_mailbox::MailboxMessage};
+
+// Lists the emails captured by the dev mailbox, so flows like 2FA can be completed without a real provider
+#[tracing::instrument(skip_all)]
+pub async fn dev_mailbox(State(state): State<AppState>) -> Result<Response, AuthAPIError> {
+    // Only available when EMAIL_PROVIDER=dev-mailbox
+    let Some(mailbox) = &state.dev_mailbox else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let messages = mailbox
+        .messages()
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let page = DevMailboxPage { messages }
+        .render()
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(Html(page).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "dev/mailbox.html")]
+struct DevMailboxPage {
+    messages: Vec<MailboxMessage>,
+}
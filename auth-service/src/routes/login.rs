@@ -1,20 +1,42 @@
-use axum::{debug_handler, extract::Json, extract::State, http::StatusCode};
+use axum::{
+    Extension, debug_handler,
+    extract::Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
 use axum_extra::extract::CookieJar;
+use chrono::{DateTime, TimeDelta, Utc};
 use color_eyre::eyre::{Result, eyre};
 use secrecy::SecretBox;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, Password, email_client::EmailClient},
-    services::{LoginAttemptId, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        AuthAPIError, AuthMethod, Authentication, Email, EmailMessage, Password, RiskDecision,
+        SmsMessage, Tenant, TwoFAChannel, User,
+    },
+    services::{
+        LoginAttemptId, TWO_FA_CODE_TTL_SECONDS, TwoFAChallenge, TwoFACode, UserStoreError,
+    },
+    utils::{
+        auth::generate_auth_cookie,
+        csrf::generate_csrf_cookie,
+        login_risk::{assess_login, record_failed_login},
+        remember_me::remember_device,
+        sign_in_alert::notify_new_device,
+        trusted_device::is_trusted_device,
+        two_fa::bind_client,
+    },
 };
 
 #[debug_handler]
 #[tracing::instrument(skip_all)]
 pub async fn login(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> (
@@ -30,66 +52,113 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
-    let user_store = state.user_store.read().await;
-    match user_store.validate_user(&email, &password).await {
+    let user_store = &state.user_store;
+    match user_store
+        .validate_user(&tenant.id, &email, &password)
+        .await
+    {
         Ok(_) => (),
+        Err(UserStoreError::InvalidCredentials) => {
+            record_failed_login(&state, &tenant, &email, &headers).await;
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    let user = match user_store.get_user(&email).await {
+    let user = match user_store.get_user(&tenant.id, &email).await {
         Ok(user) => user,
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    // Handle request based on user's 2FA configuration
-    match user.requires_2fa {
-        true => handle_2fa(&email, &state, jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    let assessment = match assess_login(&state, &tenant, &user.email, &headers).await {
+        Ok(assessment) => assessment,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Handle request based on user's 2FA configuration, as overridden by the tenant's policy.
+    // A device that was trusted at an earlier 2FA login skips it. A risky login always needs a
+    // code, sent by email if the user has no 2FA set up. A denied one fails like a wrong password,
+    // so that a denied client cannot tell whether it guessed the password.
+    let (requires_2fa, user) = match assessment.decision {
+        RiskDecision::Deny => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        RiskDecision::Challenge if tenant.two_fa_policy.applies(user.requires_2fa) => (true, user),
+        RiskDecision::Challenge => (
+            true,
+            User {
+                two_fa_channel: TwoFAChannel::Email,
+                ..user
+            },
+        ),
+        RiskDecision::Allow => (
+            tenant.two_fa_policy.applies(user.requires_2fa)
+                && !is_trusted_device(&state, &tenant, &user.email, &jar).await,
+            user,
+        ),
+    };
+    match requires_2fa {
+        true => handle_2fa(&user, &state, &tenant, jar).await,
+        false => {
+            handle_no_2fa(
+                &state,
+                &tenant,
+                &user.email,
+                &headers,
+                request.remember_me,
+                jar,
+            )
+            .await
+        }
     }
 }
 
 #[tracing::instrument(skip_all)]
-async fn handle_2fa(
-    email: &Email,
+pub(crate) async fn handle_2fa(
+    user: &User,
     state: &AppState,
+    tenant: &Tenant,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
     // Return a TwoFactorAuthResponse. The message should be "2FA required".
-    // The login attempt ID should be "123456". We will replace this hard-coded login attempt ID soon!
     let login_attempt_id = LoginAttemptId::default();
 
     let message = "2FA required".to_string();
-    let tw_code = TwoFACode::default();
-    // Store the ID and code in our 2FA code store. Return `AuthAPIError::UnexpectedError` if the operation fails
-
+    // Store the challenge under the login attempt, bound to this client.
+    // Return `AuthAPIError::UnexpectedError` if the operation fails
+    let (jar, client_binding) = bind_client(state, jar);
+    let now = Utc::now();
+    let challenge = TwoFAChallenge {
+        email: user.email.clone(),
+        code: TwoFACode::default(),
+        client_binding,
+        created_at: now,
+        sent_at: now,
+        resends: 0,
+        attempts: 0,
+    };
     if let Err(e) = state
         .two_fa_code_store
-        .write()
-        .await
-        .add_code(email.clone(), login_attempt_id.clone(), tw_code.clone())
+        .add_code(&tenant.id, login_attempt_id.clone(), challenge.clone())
         .await
     {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
-    // send 2FA code via the email client. Return `AuthAPIError::UnexpectedError` if the operation fails.
-    if let Err(e) = state
-        .email_client
-        .write()
-        .await
-        .send_email(email, "2FA Code", tw_code.as_ref())
-        .await
-    {
-        return (jar, Err(AuthAPIError::UnexpectedError(eyre!(e))));
-    }
+    let idempotency_key = format!("two-fa-code:{}", login_attempt_id.as_ref());
+    let channel =
+        match send_two_fa_code(state, tenant, user, &challenge.code, &idempotency_key).await {
+            Ok(channel) => channel,
+            Err(e) => return (jar, Err(e)),
+        };
 
     // Return
     let response = TwoFactorAuthResponse {
         message,
         login_attempt_id: login_attempt_id.as_ref().to_string(),
+        channel,
+        next_resend_at: state.two_fa_resend.next_resend_at(&challenge),
     };
 
     (
@@ -101,19 +170,80 @@ async fn handle_2fa(
     )
 }
 
+// Send a 2FA code over the user's channel, falling back to email when SMS is not available.
+// Each email needs its own idempotency key, or the outbox treats it as already queued.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn send_two_fa_code(
+    state: &AppState,
+    tenant: &Tenant,
+    user: &User,
+    code: &TwoFACode,
+    idempotency_key: &str,
+) -> Result<TwoFAChannel, AuthAPIError> {
+    let expires_at = Utc::now() + TimeDelta::seconds(TWO_FA_CODE_TTL_SECONDS as i64);
+    match (&user.two_fa_channel, &user.phone_number, &state.sms_client) {
+        (TwoFAChannel::Sms, Some(phone_number), Some(sms_client)) => {
+            let sms_message = SmsMessage::TwoFACode {
+                code: code.as_ref().to_owned(),
+                expires_at,
+            };
+            sms_client
+                .send_sms(phone_number, &sms_message)
+                .await
+                .map_err(AuthAPIError::UnexpectedError)?;
+            Ok(TwoFAChannel::Sms)
+        }
+        (channel, _, _) => {
+            if *channel == TwoFAChannel::Sms {
+                tracing::warn!("SMS is not available, sending the 2FA code by email instead");
+            }
+
+            // Queue the 2FA code email; the outbox worker delivers it (with retries) after we respond.
+            // Return `AuthAPIError::UnexpectedError` if the message could not be queued.
+            let email_message = EmailMessage::TwoFACode {
+                code: code.as_ref().to_owned(),
+                expires_at,
+            };
+            state
+                .email_outbox
+                .enqueue(&tenant.id, idempotency_key, &user.email, &email_message)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
+            Ok(TwoFAChannel::Email)
+        }
+    }
+}
+
 #[tracing::instrument(skip_all)]
 async fn handle_no_2fa(
+    state: &AppState,
+    tenant: &Tenant,
     email: &Email,
+    headers: &HeaderMap,
+    // Whether the user asked to be remembered on this device
+    remember_me: bool,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
+    let authentication = Authentication::now(vec![AuthMethod::Pwd]);
+    let auth_cookie = match generate_auth_cookie(&state.auth_cookie, tenant, email, &authentication)
+    {
         Ok(cookie) => cookie,
         Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
     };
-    let updated_jar = jar.add(auth_cookie);
+    notify_new_device(state, tenant, email, headers, &auth_cookie).await;
+    let mut updated_jar = jar
+        .add(auth_cookie)
+        .add(generate_csrf_cookie(&state.auth_cookie));
+    if remember_me {
+        updated_jar =
+            match remember_device(state, tenant, email, headers, updated_jar.clone()).await {
+                Ok(jar) => jar,
+                Err(e) => return (updated_jar, Err(e)),
+            };
+    }
 
     // Return the updated cookie jar and a 200 status code
     (
@@ -126,6 +256,9 @@ async fn handle_no_2fa(
 pub struct LoginRequest {
     email: SecretBox<String>,
     password: SecretBox<String>,
+    // Stay signed in on this device; with 2FA, the flag is sent again with the code
+    #[serde(rename = "rememberMe", default)]
+    remember_me: bool,
 }
 
 impl LoginRequest {
@@ -133,6 +266,7 @@ impl LoginRequest {
         Self {
             email: SecretBox::new(Box::new(email)),
             password: SecretBox::new(Box::new(password)),
+            remember_me: false,
         }
     }
 }
@@ -152,4 +286,9 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    // Where the code was sent
+    pub channel: TwoFAChannel,
+    // When the code can be sent again through /resend-2fa; null once no more resends are left
+    #[serde(rename = "nextResendAt")]
+    pub next_resend_at: Option<DateTime<Utc>>,
 }
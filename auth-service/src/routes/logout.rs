@@ -1,46 +1,67 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum::{
+    Extension,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
 use axum_extra::extract::CookieJar;
-use axum_extra::extract::cookie::Cookie;
+use std::sync::Arc;
 
 use crate::{
     app_state::AppState,
-    domain::AuthAPIError,
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    domain::{AuthAPIError, Tenant},
+    utils::{
+        auth::{session_token, validate_token},
+        constants::CSRF_COOKIE_NAME,
+        remember_me::{forget_device, remembered_token},
+    },
 };
 
 #[tracing::instrument(skip_all)]
 pub async fn logout(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
     jar: CookieJar,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    // Retrieve JWT cookie from the `CookieJar`
-    // Return AuthAPIError::MissingToken is the cookie is not found
-    let cookie = match jar.get(JWT_COOKIE_NAME) {
-        Some(cookie) => cookie,
+    // End the "remember me" login of this device, if there is one,
+    // even when the session itself has already expired
+    if let Some(remembered) = remembered_token(&state, &jar)
+        && let Err(e) = state
+            .remember_me_store
+            .revoke_token(&tenant.id, &remembered)
+            .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+    let jar = forget_device(&state, jar);
+
+    // Retrieve the bearer token or the JWT cookie
+    // Return AuthAPIError::MissingToken if there is neither
+    let token = match session_token(&state.auth_cookie, &headers, &jar) {
+        Some(token) => token.to_owned(),
         None => return (jar, Err(AuthAPIError::MissingToken)),
     };
 
-    let token = cookie.value().to_owned();
-
     // Validate JWT token by calling `validate_token` from the auth service.
-    // If the token is valid you can ignore the returned claims for now.
     // Return AuthAPIError::InvalidToken is validation fails.
-    match validate_token(&token, state.banned_token_store.clone()).await {
-        Ok(_) => (),
+    let claims = match validate_token(&tenant, &token, state.banned_token_store.clone()).await {
+        Ok(claims) => claims,
         Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
     };
 
-    // Add the token to the banned token store
-    let mut banned_token_store = state.banned_token_store.write().await;
-    let _ = banned_token_store
-        .add_token(token.clone())
+    // Add the token's ID to the banned token store
+    let _ = state
+        .banned_token_store
+        .add_token(&tenant.id, claims.revocation_id(&token))
         .await
         .map_err(|_| AuthAPIError::UnexpectedError);
 
-    // Remove the JWT cookie from the `CookieJar`
-    let mut cookie_for_removal = Cookie::from(JWT_COOKIE_NAME);
-    cookie_for_removal.set_path("/"); // Needed for https context removal
-    let updated_jar = jar.remove(cookie_for_removal);
+    // Remove the JWT and CSRF cookies from the `CookieJar`. Browsers only clear a cookie
+    // when the removal has the same Domain and Path, so it is built from the same settings.
+    let updated_jar = jar
+        .remove(state.auth_cookie.removal(state.auth_cookie.cookie_name()))
+        .remove(state.auth_cookie.removal(CSRF_COOKIE_NAME.to_owned()));
     // Return the updated cookie jar and a 200 status code
     (updated_jar, Ok(StatusCode::OK))
 }
@@ -0,0 +1,358 @@
+use askama::Template;
+use axum::{
+    Extension, debug_handler,
+    extract::{Form, Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{CookieJar, cookie::Cookie};
+use chrono::{TimeDelta, Utc};
+use color_eyre::eyre::eyre;
+use rand::RngExt;
+use secrecy::SecretBox;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, AuthMethod, Authentication, Email, EmailMessage, RiskDecision, Tenant},
+    services::{MAGIC_LINK_TTL_SECONDS, MagicLink, MagicLinkStoreError, UserStoreError},
+    utils::{
+        auth::{
+            constant_time_eq, generate_auth_cookie, generate_magic_link_confirmation_token,
+            generate_magic_link_token, validate_magic_link_confirmation_token,
+            validate_magic_link_token,
+        },
+        client::ClientInfo,
+        constants::LOGIN_REDIRECT_URL,
+        csrf::generate_csrf_cookie,
+        login_risk::{assess_login, record_failed_login},
+    },
+};
+
+// Email a single-use sign-in link. The response is the same whether or not the account exists.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<MagicLinkRequest>,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<MagicLinkResponse>), AuthAPIError>,
+) {
+    let email = match Email::parse(request.email) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // Reuse the browser's binding so that every link it asked for stays usable
+    let browser_nonce = match jar.get(&state.auth_cookie.magic_link_cookie_name()) {
+        Some(cookie) if !cookie.value().is_empty() => cookie.value().to_owned(),
+        _ => hex::encode(rand::rng().random::<[u8; 32]>()),
+    };
+    let jar = jar.add(create_browser_cookie(
+        &state,
+        &tenant,
+        browser_nonce.clone(),
+    ));
+
+    // Shown by this browser only, so that a forwarded link cannot be confirmed elsewhere without it.
+    // Issued whether or not a link is sent, like the rest of the response.
+    let confirmation_code = format!("{:06}", rand::rng().random_range(0..1_000_000));
+    let accepted = Ok((
+        StatusCode::ACCEPTED,
+        Json(MagicLinkResponse {
+            message: "If an account exists for this email, a sign-in link is on its way".to_owned(),
+            confirmation_code: confirmation_code.clone(),
+        }),
+    ));
+
+    match state.user_store.get_user(&tenant.id, &email).await {
+        // A link only proves access to the mailbox, so accounts with 2FA keep signing in with
+        // their password and second factor
+        Ok(user) if tenant.two_fa_policy.applies(user.requires_2fa) => {
+            tracing::info!("Not sending a magic link to an account that requires 2FA");
+            return (jar, accepted);
+        }
+        Ok(_) => (),
+        Err(UserStoreError::UserNotFound) => return (jar, accepted),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let token = match generate_magic_link_token(&tenant, &id, MAGIC_LINK_TTL_SECONDS) {
+        Ok(token) => token,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+
+    let client = ClientInfo::from_headers(&headers);
+    let link = MagicLink {
+        email: email.clone(),
+        browser_binding: browser_binding(&browser_nonce),
+        confirmation_code_hash: confirmation_code_hash(&confirmation_code),
+        requested_at: Utc::now(),
+        ip_address: client.ip_address,
+        user_agent: client.user_agent,
+    };
+    if let Err(e) = state
+        .magic_link_store
+        .add_link(&tenant.id, &id, &link)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    let message = EmailMessage::MagicLink {
+        url: format!(
+            "{}/login/magic-link/verify?token={}",
+            tenant.public_url.trim_end_matches('/'),
+            token
+        ),
+        expires_at: link.requested_at + TimeDelta::seconds(MAGIC_LINK_TTL_SECONDS as i64),
+    };
+    let idempotency_key = format!("magic-link:{}", id);
+    if let Err(e) = state
+        .email_outbox
+        .enqueue(&tenant.id, &idempotency_key, &email, &message)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(eyre!(e))));
+    }
+
+    (jar, accepted)
+}
+
+// The link from the email. In the browser that asked for it, this signs the user in straight away.
+// Anywhere else it asks for the confirmation code shown by that browser, so a forwarded email is
+// not enough on its own.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn open_magic_link(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Query(query): Query<MagicLinkToken>,
+) -> Result<Response, AuthAPIError> {
+    let Ok(id) = validate_magic_link_token(&tenant, &query.token) else {
+        return invalid_link_page();
+    };
+    let link = match state.magic_link_store.get_link(&tenant.id, &id).await {
+        Ok(link) => link,
+        Err(MagicLinkStoreError::LinkNotFound) => return invalid_link_page(),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let browser_nonce = jar
+        .get(&state.auth_cookie.magic_link_cookie_name())
+        .map(|cookie| cookie.value().to_owned())
+        .filter(|nonce| !nonce.is_empty());
+    if let Some(nonce) = &browser_nonce
+        && browser_binding(nonce) == link.browser_binding
+    {
+        return sign_in(&state, &tenant, &id, &headers, jar).await;
+    }
+
+    // The confirmation form is bound to this browser, so give it a binding if it has none
+    let browser_nonce =
+        browser_nonce.unwrap_or_else(|| hex::encode(rand::rng().random::<[u8; 32]>()));
+    let csrf_token = generate_magic_link_confirmation_token(
+        &tenant,
+        &id,
+        &browser_binding(&browser_nonce),
+        MAGIC_LINK_TTL_SECONDS,
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+    let jar = jar.add(create_browser_cookie(&state, &tenant, browser_nonce));
+
+    let page = ConfirmMagicLinkPage {
+        path_prefix: tenant.path_prefix().to_owned(),
+        token: query.token,
+        csrf_token,
+        email: link.email.as_ref().to_owned(),
+        requested_at: link.requested_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        ip_address: link.ip_address,
+        user_agent: link.user_agent,
+    }
+    .render()
+    .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok((jar, Html(page)).into_response())
+}
+
+// Submitted from the confirmation page when the link is opened in a different browser
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn confirm_magic_link(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Form(form): Form<ConfirmMagicLinkForm>,
+) -> Result<Response, AuthAPIError> {
+    let Ok(id) = validate_magic_link_token(&tenant, &form.token) else {
+        return invalid_link_page();
+    };
+
+    // The form must have been rendered for this browser, or another site could submit it
+    let cookie_name = state.auth_cookie.magic_link_cookie_name();
+    let Some(browser_nonce) = jar.get(&cookie_name).map(|cookie| cookie.value()) else {
+        return Err(AuthAPIError::CsrfCheckFailed);
+    };
+    if validate_magic_link_confirmation_token(
+        &tenant,
+        &form.csrf_token,
+        &id,
+        &browser_binding(browser_nonce),
+    )
+    .is_err()
+    {
+        return Err(AuthAPIError::CsrfCheckFailed);
+    }
+
+    let code_hash = confirmation_code_hash(form.code.trim());
+    sign_in_with(&state, &tenant, &id, &headers, jar, |link| {
+        constant_time_eq(link.confirmation_code_hash.as_bytes(), code_hash.as_bytes())
+    })
+    .await
+}
+
+async fn sign_in(
+    state: &AppState,
+    tenant: &Tenant,
+    id: &str,
+    headers: &HeaderMap,
+    jar: CookieJar,
+) -> Result<Response, AuthAPIError> {
+    sign_in_with(state, tenant, id, headers, jar, |_| true).await
+}
+
+// Consume the link and sign in if `confirmed` accepts it. A link that is not accepted is used up
+// all the same, so the confirmation code cannot be guessed. Like a password login, the sign-in
+// is scored for risk, see utils::login_risk.
+async fn sign_in_with(
+    state: &AppState,
+    tenant: &Tenant,
+    id: &str,
+    headers: &HeaderMap,
+    jar: CookieJar,
+    confirmed: impl FnOnce(&MagicLink) -> bool,
+) -> Result<Response, AuthAPIError> {
+    let link = match state.magic_link_store.consume_link(&tenant.id, id).await {
+        Ok(link) => link,
+        Err(MagicLinkStoreError::LinkNotFound) => return invalid_link_page(),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    if !confirmed(&link) {
+        tracing::info!("Magic link confirmed with a wrong code");
+        record_failed_login(state, tenant, &link.email, headers).await;
+        return invalid_link_page();
+    }
+
+    // The link only proves access to the mailbox, which is what a challenge would ask for, so a
+    // risky sign-in needs the password as well
+    let assessment = assess_login(state, tenant, &link.email, headers).await?;
+    match assessment.decision {
+        RiskDecision::Allow => (),
+        RiskDecision::Challenge => return password_required_page(tenant),
+        RiskDecision::Deny => return Err(AuthAPIError::LoginDenied),
+    }
+
+    // The link is a single-use secret sent to the user, like an emailed code
+    let authentication = Authentication::now(vec![AuthMethod::Otp]);
+    let auth_cookie =
+        generate_auth_cookie(&state.auth_cookie, tenant, &link.email, &authentication)
+            .map_err(AuthAPIError::UnexpectedError)?;
+    tracing::info!("Signed in with a magic link");
+
+    Ok((
+        jar.add(auth_cookie)
+            .add(generate_csrf_cookie(&state.auth_cookie)),
+        Redirect::to(LOGIN_REDIRECT_URL.as_str()),
+    )
+        .into_response())
+}
+
+fn invalid_link_page() -> Result<Response, AuthAPIError> {
+    let page = InvalidMagicLinkPage
+        .render()
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    Ok((StatusCode::BAD_REQUEST, Html(page)).into_response())
+}
+
+fn password_required_page(tenant: &Tenant) -> Result<Response, AuthAPIError> {
+    let page = PasswordRequiredPage {
+        path_prefix: tenant.path_prefix().to_owned(),
+    }
+    .render()
+    .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    Ok((StatusCode::FORBIDDEN, Html(page)).into_response())
+}
+
+// Only the hash is stored, so the Redis data alone cannot be used to pass as the browser
+fn browser_binding(nonce: &str) -> String {
+    hex::encode(Sha256::digest(nonce.as_bytes()))
+}
+
+fn confirmation_code_hash(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+// Scoped to the tenant's magic link endpoints, where the emailed links point
+fn create_browser_cookie(state: &AppState, tenant: &Tenant, nonce: String) -> Cookie<'static> {
+    state
+        .auth_cookie
+        .magic_link_cookie(nonce, format!("{}/login/magic-link", tenant.path_prefix()))
+}
+
+#[derive(Template)]
+#[template(path = "magic_link/confirm.html")]
+struct ConfirmMagicLinkPage {
+    path_prefix: String,
+    token: String,
+    csrf_token: String,
+    email: String,
+    requested_at: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "magic_link/invalid.html")]
+struct InvalidMagicLinkPage;
+
+#[derive(Template)]
+#[template(path = "magic_link/password_required.html")]
+struct PasswordRequiredPage {
+    path_prefix: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MagicLinkRequest {
+    pub email: SecretBox<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MagicLinkResponse {
+    pub message: String,
+    // To show to the user, who enters it if they open the link in another browser
+    #[serde(rename = "confirmationCode")]
+    pub confirmation_code: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MagicLinkToken {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfirmMagicLinkForm {
+    pub token: String,
+    pub code: String,
+    pub csrf_token: String,
+}
@@ -1,32 +1,81 @@
 use crate::app_state::AppState;
+use crate::domain::StepUpPolicy;
+use crate::utils::client::resolve_client_address;
+use crate::utils::cors::cors;
+use crate::utils::csrf::csrf_protection;
+use crate::utils::step_up::require_step_up;
+use crate::utils::tenant::resolve_tenant;
 use crate::utils::tracing::{make_span_with_request_id, on_request, on_response};
 use axum::Router;
-use axum::routing::post;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{delete, get, post, put};
+use std::time::Duration;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
 
+mod admin;
+mod change_password;
+mod csrf_token;
+mod dev_mailbox;
 mod login;
 mod logout;
+mod magic_link;
+mod phone_number;
+mod reauthenticate;
+mod recovery_codes;
+mod refresh_token;
+mod resend_2fa;
+mod sign_in_alert;
 mod signup;
+mod trusted_devices;
 mod verify_2fa;
 mod verify_token;
 
 // re-export items from sub-modules
+pub use admin::*;
+pub use change_password::*;
+pub use csrf_token::*;
+pub use dev_mailbox::*;
 pub use login::*;
 pub use logout::*;
+pub use magic_link::*;
+pub use phone_number::*;
+pub use reauthenticate::*;
+pub use recovery_codes::*;
+pub use refresh_token::*;
+pub use resend_2fa::*;
+pub use sign_in_alert::*;
 pub use signup::*;
+pub use trusted_devices::*;
 pub use verify_2fa::*;
 pub use verify_token::*;
 
-pub fn get_routes(app_state: AppState, cors: CorsLayer) -> Router {
+pub fn get_routes(app_state: AppState) -> Router {
+    let cors_layer = from_fn_with_state(app_state.clone(), cors);
+    let tenant_layer = from_fn_with_state(app_state.clone(), resolve_tenant);
+    let client_address_layer = from_fn_with_state(app_state.clone(), resolve_client_address);
+    // Each tenant is served on the hosts it is configured with, and under /realms/{tenant}
+    // on any host. On a realm path even the static files belong to the tenant.
+    let realm_routes = tenant_routes(&app_state)
+        .fallback_service(ServeDir::new("assets"))
+        .layer(tenant_layer.clone())
+        .with_state(app_state.clone());
+
     Router::new()
-        .route("/signup", post(signup))
-        .route("/login", post(login))
-        .route("/logout", post(logout))
-        .route("/verify-2fa", post(verify_2fa))
-        .route("/verify-token", post(verify_token))
+        .merge(tenant_routes(&app_state).route_layer(tenant_layer))
+        // A nested service also matches `/realms/{tenant}/`, where the login page is served
+        .nest_service("/realms/{tenant}", realm_routes)
+        // Service-wide endpoints, not tied to a tenant
+        .route("/admin/email-outbox", get(email_outbox_status))
+        .route("/dev/mailbox", get(dev_mailbox))
         .fallback_service(ServeDir::new("assets"))
         .with_state(app_state)
-        .layer(cors)
+        .layer(cors_layer)
+        // Before anything reads the client address, e.g. the risk engine or device records
+        .layer(client_address_layer)
         .layer(
             // Add a TraceLayer for HTTP requests to enable detailed tracing
             // This layer will create spans for each request using the make_span_with_request_id function,
@@ -36,4 +85,76 @@ pub fn get_routes(app_state: AppState, cors: CorsLayer) -> Router {
                 .on_request(on_request)
                 .on_response(on_response),
         )
+        // Echo the request ID back to the caller so both sides can correlate logs
+        .layer(PropagateRequestIdLayer::x_request_id())
+        // Generate a request ID unless the caller (e.g. app-service or nginx) already sent one.
+        // This is the outermost layer so the ID is in place before the trace span is created.
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+// The endpoints served on behalf of a tenant, which handlers receive as an `Extension<Arc<Tenant>>`
+fn tenant_routes(app_state: &AppState) -> Router<AppState> {
+    // Sensitive endpoints need a sign-in within their max age, with the second factor of
+    // accounts with 2FA
+    let step_up = |max_age: Duration| {
+        from_fn_with_state(
+            (
+                app_state.clone(),
+                StepUpPolicy {
+                    max_age,
+                    second_factor: true,
+                },
+            ),
+            require_step_up,
+        )
+    };
+    let max_ages = app_state.step_up_max_ages;
+
+    // Endpoints that act on the session cookie; they run inside the tenant layer,
+    // so the CSRF check sees the tenant's allowed origins
+    let session_routes = Router::new()
+        .route("/logout", post(logout))
+        .route("/reauthenticate", post(reauthenticate))
+        .route(
+            "/phone-number",
+            post(add_phone_number).route_layer(step_up(max_ages.default)),
+        )
+        .route(
+            "/phone-number/verify",
+            post(verify_phone_number).route_layer(step_up(max_ages.default)),
+        )
+        .route(
+            "/2fa-channel",
+            put(set_two_fa_channel).route_layer(step_up(max_ages.two_fa_channel)),
+        )
+        .route(
+            "/recovery-codes",
+            post(regenerate_recovery_codes).route_layer(step_up(max_ages.recovery_codes)),
+        )
+        .route(
+            "/change-password",
+            post(change_password).route_layer(step_up(max_ages.change_password)),
+        )
+        .route("/trusted-devices", get(list_trusted_devices))
+        .route("/trusted-devices/{id}", delete(revoke_trusted_device))
+        .route_layer(from_fn_with_state(app_state.clone(), csrf_protection));
+
+    Router::new()
+        .route("/signup", post(signup))
+        .route("/login", post(login))
+        .route("/login/magic-link", post(request_magic_link))
+        .route("/login/magic-link/verify", get(open_magic_link))
+        .route("/login/magic-link/confirm", post(confirm_magic_link))
+        .route(
+            "/sign-in-alert",
+            get(open_sign_in_alert).post(report_sign_in),
+        )
+        .route("/reset-password", post(reset_password))
+        .route("/verify-2fa", post(verify_2fa))
+        .route("/resend-2fa", post(resend_2fa))
+        .route("/verify-recovery-code", post(verify_recovery_code))
+        .route("/verify-token", post(verify_token))
+        .route("/csrf-token", get(csrf_token))
+        .route("/refresh-token", post(refresh_token))
+        .merge(session_routes)
 }
@@ -0,0 +1,141 @@
+use axum::{
+    Extension,
+    extract::Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use axum_extra::extract::CookieJar;
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, PhoneNumber, SmsMessage, Tenant, TwoFAChannel},
+    services::{TWO_FA_CODE_TTL_SECONDS, TwoFACode, UserStoreError},
+    utils::auth::authenticated_email,
+};
+
+// Text a code to a new phone number. The number is only saved once the code is confirmed.
+#[tracing::instrument(skip_all)]
+pub async fn add_phone_number(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<AddPhoneNumberRequest>,
+) -> Result<(StatusCode, Json<AddPhoneNumberResponse>), AuthAPIError> {
+    let email = authenticated_email(&state, &tenant, &headers, &jar).await?;
+    let sms_client = state
+        .sms_client
+        .as_ref()
+        .ok_or(AuthAPIError::SmsUnavailable)?;
+    let phone_number =
+        PhoneNumber::parse(&request.phone_number).map_err(|_| AuthAPIError::InvalidPhoneNumber)?;
+
+    let code = TwoFACode::default();
+    let expires_at = Utc::now() + TimeDelta::seconds(TWO_FA_CODE_TTL_SECONDS as i64);
+    state
+        .user_store
+        .start_phone_verification(&tenant.id, &email, &phone_number, &code, expires_at)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let message = SmsMessage::PhoneVerificationCode {
+        code: code.as_ref().to_owned(),
+        expires_at,
+    };
+    sms_client
+        .send_sms(&phone_number, &message)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(AddPhoneNumberResponse {
+            message: "Verification code sent".to_owned(),
+            phone_number: phone_number.masked(),
+        }),
+    ))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn verify_phone_number(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<VerifyPhoneNumberRequest>,
+) -> Result<Json<PhoneNumberResponse>, AuthAPIError> {
+    let email = authenticated_email(&state, &tenant, &headers, &jar).await?;
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidVerificationCode)?;
+
+    let phone_number = state
+        .user_store
+        .confirm_phone_verification(&tenant.id, &email, &code)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::InvalidVerificationCode => AuthAPIError::InvalidVerificationCode,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    Ok(Json(PhoneNumberResponse {
+        phone_number: phone_number.as_ref().to_owned(),
+    }))
+}
+
+// Choose where 2FA codes are sent
+#[tracing::instrument(skip_all)]
+pub async fn set_two_fa_channel(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<TwoFAChannelRequest>,
+) -> Result<Json<TwoFAChannelRequest>, AuthAPIError> {
+    let email = authenticated_email(&state, &tenant, &headers, &jar).await?;
+    if request.channel == TwoFAChannel::Sms && state.sms_client.is_none() {
+        return Err(AuthAPIError::SmsUnavailable);
+    }
+
+    state
+        .user_store
+        .set_two_fa_channel(&tenant.id, &email, request.channel)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::PhoneNumberNotVerified => AuthAPIError::PhoneNumberNotVerified,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    Ok(Json(request))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AddPhoneNumberRequest {
+    pub phone_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AddPhoneNumberResponse {
+    pub message: String,
+    // Only the last digits, e.g. `+*******2671`
+    pub phone_number: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyPhoneNumberRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PhoneNumberResponse {
+    pub phone_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TwoFAChannelRequest {
+    pub channel: TwoFAChannel,
+}
@@ -0,0 +1,204 @@
+use axum::{
+    Extension, debug_handler,
+    extract::{Json, State},
+    http::{HeaderMap, StatusCode},
+};
+use axum_extra::extract::CookieJar;
+use secrecy::SecretBox;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::login::{LoginResponse, handle_2fa};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, AuthMethod, Authentication, Password, Tenant},
+    services::{LoginAttemptId, TwoFACode, UserStoreError},
+    utils::{
+        auth::{Claims, authenticated_claims, generate_reauthenticated_auth_cookie},
+        login_risk::record_failed_login,
+        two_fa::{check_code, complete_challenge, pending_challenge},
+    },
+};
+
+// Authenticate the signed-in user again, for routes that need a recent or stronger sign-in.
+// The password upgrades the session, unless the account uses 2FA: then a code is sent as at
+// /login, and the session is upgraded when it is posted back here with the login attempt ID.
+// The session keeps its ID, so logging out or reporting it still ends it.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn reauthenticate(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<ReauthenticateRequest>,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let claims = match authenticated_claims(&state, &tenant, &headers, &jar).await {
+        Ok(claims) => claims,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    match (
+        request.password,
+        request.login_attempt_id,
+        request.two_fa_code,
+    ) {
+        (None, Some(login_attempt_id), Some(two_fa_code)) => {
+            verify_second_factor(
+                &state,
+                &tenant,
+                &claims,
+                &headers,
+                login_attempt_id,
+                two_fa_code,
+                jar,
+            )
+            .await
+        }
+        (Some(password), None, None) => {
+            verify_password(&state, &tenant, &claims, &headers, password, jar).await
+        }
+        _ => (jar, Err(AuthAPIError::InvalidCredentials)),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn verify_password(
+    state: &AppState,
+    tenant: &Tenant,
+    claims: &Claims,
+    headers: &HeaderMap,
+    password: SecretBox<String>,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let email = match claims.email() {
+        Ok(email) => email,
+        Err(e) => return (jar, Err(e)),
+    };
+    let password = match Password::parse(password) {
+        Ok(password) => password,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+    match state
+        .user_store
+        .validate_user(&tenant.id, &email, &password)
+        .await
+    {
+        Ok(()) => (),
+        Err(UserStoreError::InvalidCredentials) => {
+            record_failed_login(state, tenant, &email, headers).await;
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    }
+
+    let user = match state.user_store.get_user(&tenant.id, &email).await {
+        Ok(user) => user,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+    // A trusted device does not stand in for the second factor here
+    if tenant.two_fa_policy.applies(user.requires_2fa) {
+        return handle_2fa(&user, state, tenant, jar).await;
+    }
+
+    upgrade_session(state, tenant, claims, vec![AuthMethod::Pwd], jar)
+}
+
+#[tracing::instrument(skip_all)]
+async fn verify_second_factor(
+    state: &AppState,
+    tenant: &Tenant,
+    claims: &Claims,
+    headers: &HeaderMap,
+    login_attempt_id: String,
+    two_fa_code: String,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let (login_attempt_id, two_fa_code) = match (
+        LoginAttemptId::parse(login_attempt_id),
+        TwoFACode::parse(two_fa_code),
+    ) {
+        (Ok(id), Ok(code)) => (id, code),
+        _ => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // The code must have been sent to this client, for the account of the session
+    let challenge = match pending_challenge(state, tenant, &jar, &login_attempt_id).await {
+        Ok(challenge) => challenge,
+        Err(e) => return (jar, Err(e)),
+    };
+    if challenge.email.normalized() != claims.sub {
+        return (jar, Err(AuthAPIError::InvalidToken));
+    }
+    if let Err(e) = check_code(
+        state,
+        tenant,
+        headers,
+        &login_attempt_id,
+        &challenge,
+        &two_fa_code,
+    )
+    .await
+    {
+        return (jar, Err(e));
+    }
+    if let Err(e) = complete_challenge(state, tenant, &login_attempt_id).await {
+        return (jar, Err(e));
+    }
+
+    upgrade_session(
+        state,
+        tenant,
+        claims,
+        vec![AuthMethod::Pwd, AuthMethod::Otp],
+        jar,
+    )
+}
+
+fn upgrade_session(
+    state: &AppState,
+    tenant: &Tenant,
+    claims: &Claims,
+    methods: Vec<AuthMethod>,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let authentication = Authentication::now(methods);
+    let auth_cookie = match generate_reauthenticated_auth_cookie(
+        &state.auth_cookie,
+        tenant,
+        claims,
+        &authentication,
+    ) {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+    tracing::info!("Reauthenticated");
+
+    (
+        jar.add(auth_cookie),
+        Ok((StatusCode::OK, Json(LoginResponse::RegularAuth))),
+    )
+}
+
+// Either the password, or the login attempt and code of the 206 response that asked for a code
+#[derive(Deserialize)]
+pub struct ReauthenticateRequest {
+    #[serde(default)]
+    password: Option<SecretBox<String>>,
+    #[serde(rename = "loginAttemptId", default)]
+    login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode", default)]
+    two_fa_code: Option<String>,
+}
@@ -0,0 +1,179 @@
+use axum::{
+    Extension, debug_handler,
+    extract::{Json, State},
+    http::HeaderMap,
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, AuthMethod, Authentication, Email, EmailMessage, RecoveryCode, Tenant},
+    services::{LoginAttemptId, RecoveryCodeStoreError, RecoveryCodeUsage},
+    utils::{
+        auth::{authenticated_email, generate_auth_cookie},
+        client::ClientInfo,
+        csrf::generate_csrf_cookie,
+        two_fa::{complete_challenge, pending_challenge},
+    },
+};
+
+// Create a fresh set of recovery codes and return them in the form shown to users.
+// The previous set stops working.
+pub(crate) async fn issue_recovery_codes(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+) -> Result<Vec<String>, AuthAPIError> {
+    let codes = RecoveryCode::generate_set();
+    state
+        .recovery_code_store
+        .replace_codes(&tenant.id, email, &codes)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    Ok(codes.iter().map(RecoveryCode::formatted).collect())
+}
+
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Result<Json<RecoveryCodesResponse>, AuthAPIError> {
+    let email = authenticated_email(&state, &tenant, &headers, &jar).await?;
+    let user = state
+        .user_store
+        .get_user(&tenant.id, &email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if !tenant.two_fa_policy.applies(user.requires_2fa) {
+        return Err(AuthAPIError::TwoFactorNotEnabled);
+    }
+
+    let recovery_codes = issue_recovery_codes(&state, &tenant, &email).await?;
+    tracing::info!("Recovery codes regenerated");
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+// Finish a 2FA login with a recovery code instead of the code that was sent to the user
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn verify_recovery_code(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<VerifyRecoveryCodeRequest>,
+) -> (
+    CookieJar,
+    Result<Json<VerifyRecoveryCodeResponse>, AuthAPIError>,
+) {
+    let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id) {
+        Ok(id) => id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+    let recovery_code = match RecoveryCode::parse(&request.recovery_code) {
+        Ok(code) => code,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // A recovery code only replaces the second factor; the password must have been checked
+    // by a login of this client that is still waiting for its 2FA code.
+    let email = match pending_challenge(&state, &tenant, &jar, &login_attempt_id).await {
+        Ok(challenge) => challenge.email,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let client = ClientInfo::from_headers(&headers);
+    let usage = RecoveryCodeUsage {
+        used_at: Utc::now(),
+        ip_address: client.ip_address,
+        user_agent: client.user_agent,
+    };
+    match state
+        .recovery_code_store
+        .use_code(&tenant.id, &email, &recovery_code, &usage)
+        .await
+    {
+        Ok(()) => (),
+        Err(RecoveryCodeStoreError::InvalidCode) => {
+            tracing::warn!("Rejected an invalid or already used recovery code");
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+    tracing::info!(
+        ip_address = usage.ip_address.as_deref(),
+        user_agent = usage.user_agent.as_deref(),
+        "Recovery code used"
+    );
+
+    if let Err(e) = complete_challenge(&state, &tenant, &login_attempt_id).await {
+        return (jar, Err(e));
+    }
+
+    let remaining = match state
+        .recovery_code_store
+        .remaining_codes(&tenant.id, &email)
+        .await
+    {
+        Ok(remaining) => remaining,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // Let the owner know, in case someone else got hold of their codes
+    let message = EmailMessage::RecoveryCodeUsed {
+        used_at: usage.used_at,
+        ip_address: usage.ip_address,
+        remaining,
+    };
+    let idempotency_key = format!("recovery-code-used:{}", login_attempt_id.as_ref());
+    if let Err(e) = state
+        .email_outbox
+        .enqueue(&tenant.id, &idempotency_key, &email, &message)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(eyre!(e))));
+    }
+
+    // The recovery code stands in for the 2FA code
+    let authentication = Authentication::now(vec![AuthMethod::Pwd, AuthMethod::Otp]);
+    let auth_cookie =
+        match generate_auth_cookie(&state.auth_cookie, &tenant, &email, &authentication) {
+            Ok(cookie) => cookie,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        };
+
+    (
+        jar.add(auth_cookie)
+            .add(generate_csrf_cookie(&state.auth_cookie)),
+        Ok(Json(VerifyRecoveryCodeResponse {
+            remaining_recovery_codes: remaining,
+        })),
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyRecoveryCodeRequest {
+    pub login_attempt_id: String,
+    pub recovery_code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyRecoveryCodeResponse {
+    pub remaining_recovery_codes: i64,
+}
@@ -0,0 +1,70 @@
+use axum::{
+    Extension,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Tenant},
+    services::RememberMeStoreError,
+    utils::{
+        auth::generate_resumed_auth_cookie,
+        client::ClientInfo,
+        csrf::generate_csrf_cookie,
+        remember_me::{forget_device, remembered_token},
+    },
+};
+
+// Exchange the "remember me" cookie for a new auth cookie. Browsers call this when the
+// short-lived JWT has expired; the token gets a new secret on every exchange. The user did not
+// authenticate, so sensitive routes need /reauthenticate first.
+#[tracing::instrument(skip_all)]
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let Some(token) = remembered_token(&state, &jar) else {
+        return (jar, Err(AuthAPIError::MissingToken));
+    };
+
+    let rotated = token.rotated();
+    let (email, expires_at) = match state
+        .remember_me_store
+        .rotate_token(
+            &tenant.id,
+            &token,
+            &rotated,
+            &ClientInfo::from_headers(&headers),
+        )
+        .await
+    {
+        Ok(remembered) => remembered,
+        Err(RememberMeStoreError::InvalidToken) => {
+            return (forget_device(&state, jar), Err(AuthAPIError::InvalidToken));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    let auth_cookie = match generate_resumed_auth_cookie(&state.auth_cookie, &tenant, &email) {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+    // The cookie lasts as long as the token has left
+    let remaining = (expires_at - Utc::now()).to_std().unwrap_or_default();
+    let updated_jar = jar
+        .add(auth_cookie)
+        .add(generate_csrf_cookie(&state.auth_cookie))
+        .add(
+            state
+                .auth_cookie
+                .remember_me_cookie(rotated.value(), remaining),
+        );
+
+    (updated_jar, Ok(StatusCode::OK))
+}
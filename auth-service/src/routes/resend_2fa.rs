@@ -0,0 +1,100 @@
+use axum::{Extension, debug_handler, extract::Json, extract::State};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::login::send_two_fa_code;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Tenant, TwoFAChannel},
+    services::{LoginAttemptId, TwoFAChallenge, TwoFACode, TwoFACodeStoreError},
+    utils::two_fa::pending_challenge,
+};
+
+// Send a pending 2FA login a new code, e.g. when the first one never arrived. The code is
+// rotated, so a code that turns up late no longer works.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn resend_2fa(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    jar: CookieJar,
+    Json(request): Json<Resend2FARequest>,
+) -> Result<Json<Resend2FAResponse>, AuthAPIError> {
+    let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Only the client that started the login can ask for its code again
+    let challenge = pending_challenge(&state, &tenant, &jar, &login_attempt_id).await?;
+    let policy = &state.two_fa_resend;
+    let next_resend_at = policy
+        .next_resend_at(&challenge)
+        .ok_or(AuthAPIError::ResendLimitReached)?;
+    if Utc::now() < next_resend_at {
+        return Err(AuthAPIError::ResendTooSoon);
+    }
+
+    let user = state
+        .user_store
+        .get_user(&tenant.id, &challenge.email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let previous_sent_at = challenge.sent_at;
+    let challenge = TwoFAChallenge {
+        code: TwoFACode::default(),
+        sent_at: Utc::now(),
+        resends: challenge.resends + 1,
+        ..challenge
+    };
+    match state
+        .two_fa_code_store
+        .update_code(
+            &tenant.id,
+            &login_attempt_id,
+            previous_sent_at,
+            challenge.clone(),
+        )
+        .await
+    {
+        Ok(()) => (),
+        // Finished or expired while we were looking
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => return Err(AuthAPIError::InvalidToken),
+        // A concurrent resend went first, and the cooldown starts over from it
+        Err(TwoFACodeStoreError::ChallengeChanged) => return Err(AuthAPIError::ResendTooSoon),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let idempotency_key = format!(
+        "two-fa-code:{}:{}",
+        login_attempt_id.as_ref(),
+        challenge.resends
+    );
+    let channel =
+        send_two_fa_code(&state, &tenant, &user, &challenge.code, &idempotency_key).await?;
+
+    Ok(Json(Resend2FAResponse {
+        message: "2FA code sent".to_owned(),
+        channel,
+        next_resend_at: policy.next_resend_at(&challenge),
+        resends_remaining: policy.resends_remaining(&challenge),
+    }))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Resend2FARequest {
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resend2FAResponse {
+    pub message: String,
+    pub channel: TwoFAChannel,
+    // When the code can be sent again; null once no more resends are left
+    #[serde(rename = "nextResendAt")]
+    pub next_resend_at: Option<DateTime<Utc>>,
+    #[serde(rename = "resendsRemaining")]
+    pub resends_remaining: u32,
+}
@@ -0,0 +1,233 @@
+use askama::Template;
+use axum::{
+    Extension, debug_handler,
+    extract::{Form, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{TimeDelta, Utc};
+use secrecy::SecretBox;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Password, Tenant},
+    services::{BannedTokenStoreError, UserStoreError},
+    utils::{
+        auth::{
+            generate_password_reset_token, validate_password_reset_token,
+            validate_sign_in_alert_token,
+        },
+        constants::{PASSWORD_RESET_TTL_SECONDS, SIGN_IN_ALERT_TTL_SECONDS},
+    },
+};
+
+// The "this wasn't me" link from a new device email. Opening it changes nothing, since mail
+// scanners follow links; the page asks the owner to confirm.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn open_sign_in_alert(
+    Extension(tenant): Extension<Arc<Tenant>>,
+    Query(query): Query<SignInAlertToken>,
+) -> Result<Response, AuthAPIError> {
+    let Ok(claims) = validate_sign_in_alert_token(&tenant, &query.token) else {
+        return invalid_link_page(&tenant);
+    };
+    render(
+        StatusCode::OK,
+        ConfirmSignInAlertPage {
+            path_prefix: tenant.path_prefix(),
+            email: &claims.sub,
+            token: &query.token,
+        },
+    )
+}
+
+// Submitted from the confirmation page: sign the device out, end the account's remembered
+// sign-ins, and ask for a new password. The link works once, so it cannot be used to reset the
+// password again later.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn report_sign_in(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    Form(form): Form<SignInAlertToken>,
+) -> Result<Response, AuthAPIError> {
+    let Ok(claims) = validate_sign_in_alert_token(&tenant, &form.token) else {
+        return invalid_link_page(&tenant);
+    };
+    let email = parse_email(&claims.sub)?;
+    let user = match state.user_store.get_user(&tenant.id, &email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return invalid_link_page(&tenant),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    let expires_at = Utc::now() + TimeDelta::seconds(SIGN_IN_ALERT_TTL_SECONDS as i64);
+    let unused = state
+        .known_device_store
+        .use_sign_in_alert(&tenant.id, &email, &claims.jti, expires_at)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if !unused {
+        return invalid_link_page(&tenant);
+    }
+
+    // The session may have been banned already, e.g. by logging out
+    match state
+        .banned_token_store
+        .add_token(&tenant.id, claims.jti)
+        .await
+    {
+        Ok(()) | Err(BannedTokenStoreError::TokenAlreadyExists) => {}
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+    sign_out_everywhere(&state, &tenant, &email).await?;
+    state
+        .known_device_store
+        .forget_device(&tenant.id, &email, &claims.dev)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    tracing::warn!("New device sign-in reported by the account owner");
+
+    let token =
+        generate_password_reset_token(&tenant, &email, &user.password, PASSWORD_RESET_TTL_SECONDS)
+            .map_err(AuthAPIError::UnexpectedError)?;
+    render(
+        StatusCode::OK,
+        ResetPasswordPage {
+            path_prefix: tenant.path_prefix(),
+            email: user.email.as_ref(),
+            token: &token,
+            error: None,
+        },
+    )
+}
+
+// Submitted from the reset password page. The token works until the password changes.
+#[debug_handler]
+#[tracing::instrument(skip_all)]
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    Form(form): Form<ResetPasswordForm>,
+) -> Result<Response, AuthAPIError> {
+    let Ok(claims) = validate_password_reset_token(&tenant, &form.token) else {
+        return invalid_link_page(&tenant);
+    };
+    let email = parse_email(&claims.sub)?;
+    let user = match state.user_store.get_user(&tenant.id, &email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return invalid_link_page(&tenant),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    if !claims.is_current(&user.password) {
+        return invalid_link_page(&tenant);
+    }
+
+    let Ok(new_password) = Password::parse(form.new_password) else {
+        return render(
+            StatusCode::BAD_REQUEST,
+            ResetPasswordPage {
+                path_prefix: tenant.path_prefix(),
+                email: user.email.as_ref(),
+                token: &form.token,
+                error: Some("Passwords must be at least 8 characters long."),
+            },
+        );
+    };
+    state
+        .user_store
+        .update_password(&tenant.id, &email, new_password)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    // Anything remembered in the meantime goes too
+    sign_out_everywhere(&state, &tenant, &email).await?;
+    tracing::info!("Password reset after a reported sign-in");
+
+    render(
+        StatusCode::OK,
+        PasswordResetPage {
+            path_prefix: tenant.path_prefix(),
+        },
+    )
+}
+
+// End the account's "remember me" logins and make its trusted devices go through 2FA again
+async fn sign_out_everywhere(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    state
+        .remember_me_store
+        .revoke_all(&tenant.id, email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .trusted_device_store
+        .revoke_all(&tenant.id, email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+fn parse_email(normalized: &str) -> Result<Email, AuthAPIError> {
+    Email::parse(SecretBox::new(Box::new(normalized.to_owned())))
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+fn invalid_link_page(tenant: &Tenant) -> Result<Response, AuthAPIError> {
+    render(
+        StatusCode::BAD_REQUEST,
+        InvalidSignInAlertPage {
+            path_prefix: tenant.path_prefix(),
+        },
+    )
+}
+
+fn render(status: StatusCode, page: impl Template) -> Result<Response, AuthAPIError> {
+    let page = page
+        .render()
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    Ok((status, Html(page)).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "sign_in_alert/confirm.html")]
+struct ConfirmSignInAlertPage<'a> {
+    path_prefix: &'a str,
+    email: &'a str,
+    token: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "sign_in_alert/reset_password.html")]
+struct ResetPasswordPage<'a> {
+    path_prefix: &'a str,
+    email: &'a str,
+    token: &'a str,
+    error: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "sign_in_alert/done.html")]
+struct PasswordResetPage<'a> {
+    path_prefix: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "sign_in_alert/invalid.html")]
+struct InvalidSignInAlertPage<'a> {
+    path_prefix: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SignInAlertToken {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordForm {
+    pub token: String,
+    pub new_password: SecretBox<String>,
+}
@@ -1,35 +1,60 @@
 use crate::app_state::AppState;
-use crate::domain::{AuthAPIError, Email, Password, User};
+use crate::domain::{AuthAPIError, Email, Password, Tenant, User};
+use crate::routes::recovery_codes::issue_recovery_codes;
+use crate::services::UserStoreError;
 use axum::{
-    debug_handler, extract::Json, extract::State, http::StatusCode, response::IntoResponse,
+    Extension, debug_handler, extract::Json, extract::State, http::StatusCode,
+    response::IntoResponse,
 };
 use secrecy::SecretBox;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 #[debug_handler]
 #[tracing::instrument(name = "Signup", skip_all)]
 pub async fn signup(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     Json(request): Json<SignupRequest>,
 ) -> impl IntoResponse {
     let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
     let password =
         Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let mut user_store = state.user_store.write().await;
+    // The tenant's policy can override what the user asked for
+    let requires_2fa = tenant.two_fa_policy.applies(request.requires_2fa);
+    let user = User::new(email.clone(), password, requires_2fa);
 
-    if user_store.get_user(&email).await.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
+    // `add_user` checks for an existing account atomically, so concurrent signups
+    // for the same email cannot both succeed.
+    match state.user_store.add_user(&tenant.id, user).await {
+        Ok(()) => (),
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     }
 
-    let user = User::new(email, password, request.requires_2fa);
-
-    if let Err(e) = user_store.add_user(user).await {
-        return Err(AuthAPIError::UnexpectedError(e.into()));
-    }
+    // 2FA accounts get recovery codes up front, so losing the mailbox does not lock the user out.
+    // This is the only time they are shown. Without them the signup is undone, so that it can be
+    // retried.
+    let recovery_codes = match requires_2fa {
+        true => match issue_recovery_codes(&state, &tenant, &email).await {
+            Ok(codes) => Some(codes),
+            Err(e) => {
+                if let Err(delete_error) = state.user_store.delete_user(&tenant.id, &email).await {
+                    tracing::error!(
+                        "failed to undo a signup without recovery codes: {:?}",
+                        delete_error
+                    );
+                }
+                return Err(e);
+            }
+        },
+        false => None,
+    };
 
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
+        recovery_codes,
     });
 
     Ok((StatusCode::CREATED, response))
@@ -56,4 +81,10 @@ impl SignupRequest {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recovery_codes: Option<Vec<String>>,
 }
@@ -0,0 +1,111 @@
+use axum::{
+    Extension,
+    extract::{Json, Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Tenant},
+    services::{TrustedDevice, TrustedDeviceStoreError},
+    utils::{
+        auth::authenticated_email,
+        trusted_device::{forget_trusted_device, trusted_device_id},
+    },
+};
+
+// The devices that skip 2FA for the signed-in user
+#[tracing::instrument(skip_all)]
+pub async fn list_trusted_devices(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Result<Json<TrustedDevicesResponse>, AuthAPIError> {
+    let email = authenticated_email(&state, &tenant, &headers, &jar).await?;
+    let devices = state
+        .trusted_device_store
+        .list_devices(&tenant.id, &email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let current = trusted_device_id(&state, &tenant, &email, &jar);
+    Ok(Json(TrustedDevicesResponse {
+        devices: devices
+            .into_iter()
+            .map(|device| TrustedDeviceResponse::new(device, current))
+            .collect(),
+    }))
+}
+
+// Stop trusting one of the signed-in user's devices; its next login asks for a 2FA code again
+#[tracing::instrument(skip_all)]
+pub async fn revoke_trusted_device(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let email = match authenticated_email(&state, &tenant, &headers, &jar).await {
+        Ok(email) => email,
+        Err(e) => return (jar, Err(e)),
+    };
+    match state
+        .trusted_device_store
+        .revoke_device(&tenant.id, &email, id)
+        .await
+    {
+        Ok(()) => (),
+        Err(TrustedDeviceStoreError::DeviceNotFound) => {
+            return (jar, Err(AuthAPIError::TrustedDeviceNotFound));
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // The browser's own cookie is of no further use
+    if trusted_device_id(&state, &tenant, &email, &jar) == Some(id) {
+        return (
+            forget_trusted_device(&state, jar),
+            Ok(StatusCode::NO_CONTENT),
+        );
+    }
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustedDevicesResponse {
+    pub devices: Vec<TrustedDeviceResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedDeviceResponse {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    // Whether this is the browser that sent the request
+    pub current: bool,
+}
+
+impl TrustedDeviceResponse {
+    fn new(device: TrustedDevice, current: Option<Uuid>) -> Self {
+        Self {
+            id: device.id,
+            created_at: device.created_at,
+            last_used_at: device.last_used_at,
+            expires_at: device.expires_at,
+            ip_address: device.client.ip_address,
+            user_agent: device.client.user_agent,
+            current: current == Some(device.id),
+        }
+    }
+}
@@ -1,28 +1,33 @@
-use axum::http::StatusCode;
-use secrecy::SecretBox;
+use axum::http::{HeaderMap, StatusCode};
 use serde::Deserialize;
+use std::sync::Arc;
 
-use axum::{debug_handler, extract::Json, extract::State};
+use axum::{Extension, debug_handler, extract::Json, extract::State};
 use axum_extra::extract::CookieJar;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email},
+    domain::{AuthAPIError, AuthMethod, Authentication, Tenant},
     services::{LoginAttemptId, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    utils::{
+        auth::generate_auth_cookie,
+        csrf::generate_csrf_cookie,
+        remember_me::remember_device,
+        sign_in_alert::notify_new_device,
+        trusted_device::trust_device,
+        two_fa::{check_code, complete_challenge, pending_challenge},
+    },
 };
 
 #[debug_handler]
 #[tracing::instrument(skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
     jar: CookieJar,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
-    let email = match Email::parse(request.email) {
-        Ok(email) => email,
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-    };
     let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id) {
         Ok(id) => id,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
@@ -32,34 +37,53 @@ pub async fn verify_2fa(
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
+    // The login attempt identifies the account, and must have been started by this client
+    let challenge = match pending_challenge(&state, &tenant, &jar, &login_attempt_id).await {
+        Ok(challenge) => challenge,
+        Err(e) => return (jar, Err(e)),
+    };
+    if let Err(e) = check_code(
+        &state,
+        &tenant,
+        &headers,
+        &login_attempt_id,
+        &challenge,
+        &two_fa_code,
+    )
+    .await
     {
-        let two_fa_code_store = state.two_fa_code_store.read().await;
-        // Validate that the `login_attempt_id` and `two_fa_code`
-        // in the request body matches values in the `code_tuple`.
-        // If not, return a `AuthAPIError::IncorrectCredentials`.
-        match two_fa_code_store.get_code(&email).await {
-            Ok((id, code)) if id == login_attempt_id && code == two_fa_code => (),
-            Ok((id, _)) if id != login_attempt_id => {
-                return (jar, Err(AuthAPIError::InvalidCredentials));
-            }
-            Ok(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-            Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
-        };
+        return (jar, Err(e));
     }
 
-    let auth_cookie = match generate_auth_cookie(&email) {
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
-    };
-    let updated_jar = jar.add(auth_cookie);
+    //  Remove the challenge before signing in, so that its code cannot be used twice
+    if let Err(e) = complete_challenge(&state, &tenant, &login_attempt_id).await {
+        return (jar, Err(e));
+    }
 
-    {
-        //  Remove 2FA code from the code store after successful authentication.
-        let mut two_fa_code_store = state.two_fa_code_store.write().await;
-        match two_fa_code_store.remove_code(&email).await {
-            Ok(_) => (),
-            Err(e) => return (updated_jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    let email = challenge.email;
+    let authentication = Authentication::now(vec![AuthMethod::Pwd, AuthMethod::Otp]);
+    let auth_cookie =
+        match generate_auth_cookie(&state.auth_cookie, &tenant, &email, &authentication) {
+            Ok(cookie) => cookie,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
         };
+    notify_new_device(&state, &tenant, &email, &headers, &auth_cookie).await;
+    let mut updated_jar = jar
+        .add(auth_cookie)
+        .add(generate_csrf_cookie(&state.auth_cookie));
+    if request.remember_me {
+        updated_jar =
+            match remember_device(&state, &tenant, &email, &headers, updated_jar.clone()).await {
+                Ok(jar) => jar,
+                Err(e) => return (updated_jar, Err(e)),
+            };
+    }
+    if request.trust_device {
+        updated_jar =
+            match trust_device(&state, &tenant, &email, &headers, updated_jar.clone()).await {
+                Ok(jar) => jar,
+                Err(e) => return (updated_jar, Err(e)),
+            };
     }
 
     // Return the updated cookie jar and a 200 status code
@@ -68,19 +92,24 @@ pub async fn verify_2fa(
 
 #[derive(Deserialize, Debug)]
 pub struct Verify2FARequest {
-    pub email: SecretBox<String>,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
     #[serde(rename = "2FACode")]
     pub two_fa_code: String,
+    #[serde(rename = "rememberMe", default)]
+    pub remember_me: bool,
+    // Skip 2FA on this device for a while
+    #[serde(rename = "trustDevice", default)]
+    pub trust_device: bool,
 }
 
 impl Verify2FARequest {
-    pub fn new(email: String, login_attempt_id: String, two_fa_code: String) -> Self {
+    pub fn new(login_attempt_id: String, two_fa_code: String) -> Self {
         Self {
-            email: SecretBox::new(Box::new(email)),
             login_attempt_id,
             two_fa_code,
+            remember_me: false,
+            trust_device: false,
         }
     }
 }
@@ -1,11 +1,39 @@
-use color_eyre::eyre::Report;
+use crate::domain::TenantId;
+use color_eyre::eyre::{Report, Result, eyre};
+use std::str::FromStr;
 use thiserror::Error;
 
+// Revoked tokens are identified by their `jti` claim, see `Claims::revocation_id`, and stay
+// revoked for as long as a token can live.
 #[async_trait::async_trait]
 pub trait BannedTokenStore: Send + Sync {
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError>;
-    async fn get_token(&self, token: &str) -> Result<String, BannedTokenStoreError>;
-    async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
+    async fn add_token(&self, tenant: &TenantId, jti: String) -> Result<(), BannedTokenStoreError>;
+    async fn contains_token(
+        &self,
+        tenant: &TenantId,
+        jti: &str,
+    ) -> Result<bool, BannedTokenStoreError>;
+}
+
+// What a token check does when the store cannot be reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevocationFailurePolicy {
+    // Accept the token; a revoked token keeps working until the store is back or it expires
+    Open,
+    // Reject the token; nobody is signed in until the store is back
+    Closed,
+}
+
+impl FromStr for RevocationFailurePolicy {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
+            _ => Err(eyre!("Unknown revocation failure policy: {}", s)),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -0,0 +1,306 @@
+use color_eyre::eyre::{Context, Result, eyre};
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use redis::{AsyncCommands, Client, aio::ConnectionManager};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{
+    domain::TenantId,
+    services::data_stores::{
+        BannedTokenStore, BannedTokenStoreError, RedisBannedTokenStore, RevocationFailurePolicy,
+    },
+    utils::auth::TOKEN_TTL_SECONDS,
+};
+
+// Every instance publishes its revocations here and applies the ones of the others
+const REVOCATION_CHANNEL: &str = "banned_token";
+// How long to wait before subscribing again after the subscription was lost
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+// How often lapsed revocations are dropped from the cache
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+// How often each instance publishes a heartbeat on the revocation channel
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+// A subscription that has not delivered anything, not even a heartbeat, for this long is
+// presumed dead, as a silently dropped connection does not end the message stream
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+const HEARTBEAT_PAYLOAD: &[u8] = b"heartbeat";
+// The most revocations kept in memory. Beyond that, checks go to Redis until the revocations
+// in force fit again.
+pub const MAX_CACHED_REVOCATIONS: usize = 100_000;
+
+// Keeps a copy of every revocation in force in memory, so token checks do not go to Redis.
+// The copy is loaded when `run` subscribes to the revocations of other instances and kept up
+// to date from then on. While it is not known to be complete, e.g. before the first load or
+// after losing the subscription or with more revocations than the cache holds, checks go to
+// Redis and follow `failure_policy` if it fails.
+pub struct CachedBannedTokenStore {
+    store: RedisBannedTokenStore,
+    conn: ConnectionManager,
+    revoked: RevocationCache,
+    synced: AtomicBool,
+    failure_policy: RevocationFailurePolicy,
+}
+
+// The revocations in force, up to a fixed number
+struct RevocationCache {
+    // When each revocation lapses; the token has expired by then
+    entries: DashMap<(TenantId, String), Instant>,
+    capacity: usize,
+}
+
+impl RevocationCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            capacity,
+        }
+    }
+
+    // Returns false if the cache is full, in which case it no longer holds every revocation
+    fn insert(&self, tenant: TenantId, jti: String) -> bool {
+        let key = (tenant, jti);
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.prune();
+            if self.entries.len() >= self.capacity {
+                return false;
+            }
+        }
+        let lapses_at = Instant::now() + Duration::from_secs(TOKEN_TTL_SECONDS as u64);
+        self.entries.insert(key, lapses_at);
+        true
+    }
+
+    fn contains(&self, tenant: &TenantId, jti: &str) -> bool {
+        self.entries
+            .get(&(tenant.clone(), jti.to_owned()))
+            .is_some_and(|lapses_at| *lapses_at > Instant::now())
+    }
+
+    fn prune(&self) {
+        let now = Instant::now();
+        self.entries.retain(|_, lapses_at| *lapses_at > now);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn clear(&self) {
+        self.entries.clear();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Revocation {
+    tenant: String,
+    jti: String,
+}
+
+impl CachedBannedTokenStore {
+    pub fn new(conn: ConnectionManager, failure_policy: RevocationFailurePolicy) -> Self {
+        Self {
+            store: RedisBannedTokenStore::new(conn.clone()),
+            conn,
+            revoked: RevocationCache::new(MAX_CACHED_REVOCATIONS),
+            synced: AtomicBool::new(false),
+            failure_policy,
+        }
+    }
+
+    // Whether checks are answered from memory alone
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Acquire)
+    }
+
+    // Follow the revocations of other instances, subscribing again whenever the subscription is lost
+    pub async fn run(self: Arc<Self>, client: Client) {
+        loop {
+            if let Err(e) = self.follow_revocations(&client).await {
+                tracing::warn!("token revocation cache is out of sync: {:?}", e);
+            }
+            self.synced.store(false, Ordering::Release);
+            tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+        }
+    }
+
+    async fn follow_revocations(&self, client: &Client) -> Result<()> {
+        let mut pubsub = client
+            .get_async_pubsub()
+            .await
+            .wrap_err("failed to connect to Redis")?;
+        pubsub
+            .subscribe(REVOCATION_CHANNEL)
+            .await
+            .wrap_err("failed to subscribe to token revocations")?;
+
+        // Loaded after subscribing, so a revocation made in between is not missed
+        self.revoked.clear();
+        let mut complete = true;
+        for (tenant, jti) in self.store.all_tokens().await? {
+            complete &= self.remember(tenant, jti);
+        }
+        if complete {
+            self.synced.store(true, Ordering::Release);
+            tracing::info!(
+                revocations = self.revoked.len(),
+                "token revocation cache synced"
+            );
+        }
+
+        let mut messages = pubsub.into_on_message();
+        let mut prune =
+            tokio::time::interval_at(tokio::time::Instant::now() + PRUNE_INTERVAL, PRUNE_INTERVAL);
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_message = Instant::now();
+        loop {
+            tokio::select! {
+                message = messages.next() => {
+                    let Some(message) = message else {
+                        return Err(eyre!("the subscription to token revocations was closed"));
+                    };
+                    last_message = Instant::now();
+                    let payload = message.get_payload_bytes();
+                    if payload == HEARTBEAT_PAYLOAD {
+                        continue;
+                    }
+                    match parse_revocation(payload) {
+                        Ok((tenant, jti)) => {
+                            self.remember(tenant, jti);
+                        }
+                        Err(e) => tracing::warn!("ignoring token revocation: {:?}", e),
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_message.elapsed() > HEARTBEAT_TIMEOUT {
+                        return Err(eyre!("the subscription to token revocations went silent"));
+                    }
+                    // Heard by every instance, this one included, so each can tell its
+                    // subscription still delivers
+                    let published: redis::RedisResult<()> = self
+                        .conn
+                        .clone()
+                        .publish(REVOCATION_CHANNEL, HEARTBEAT_PAYLOAD)
+                        .await;
+                    if let Err(e) = published {
+                        tracing::warn!("failed to publish a revocation heartbeat: {:?}", e);
+                    }
+                }
+                _ = prune.tick() => {
+                    self.revoked.prune();
+                    // Revocations were dropped while the cache was full, and only a reload
+                    // brings them back
+                    if !complete {
+                        return Err(eyre!("the token revocation cache overflowed"));
+                    }
+                }
+            }
+            complete &= self.is_synced();
+        }
+    }
+
+    // Cache a revocation. If the cache is full it no longer holds every revocation, so checks
+    // go to Redis until the next reload.
+    fn remember(&self, tenant: TenantId, jti: String) -> bool {
+        if self.revoked.insert(tenant, jti) {
+            return true;
+        }
+        if self.synced.swap(false, Ordering::AcqRel) {
+            tracing::warn!(
+                capacity = self.revoked.capacity,
+                "token revocation cache is full, checking revocations in Redis"
+            );
+        }
+        false
+    }
+
+    fn is_cached(&self, tenant: &TenantId, jti: &str) -> bool {
+        self.revoked.contains(tenant, jti)
+    }
+}
+
+fn parse_revocation(payload: &[u8]) -> Result<(TenantId, String)> {
+    let revocation: Revocation =
+        serde_json::from_slice(payload).wrap_err("failed to parse token revocation")?;
+    Ok((TenantId::parse(&revocation.tenant)?, revocation.jti))
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for CachedBannedTokenStore {
+    #[tracing::instrument(name = "Adding Token To Revocation Cache", skip_all)]
+    async fn add_token(&self, tenant: &TenantId, jti: String) -> Result<(), BannedTokenStoreError> {
+        // Stored first, so an instance that loads the revocations afterwards sees it
+        self.store.add_token(tenant, jti.clone()).await?;
+        self.remember(tenant.clone(), jti.clone());
+
+        let revocation = serde_json::to_string(&Revocation {
+            tenant: tenant.to_string(),
+            jti,
+        })
+        .wrap_err("failed to serialize token revocation")
+        .map_err(BannedTokenStoreError::UnexpectedError)?;
+        let _: () = self
+            .conn
+            .clone()
+            .publish(REVOCATION_CHANNEL, revocation)
+            .await
+            .wrap_err("failed to publish token revocation")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Checking If Token In Revocation Cache", skip_all)]
+    async fn contains_token(
+        &self,
+        tenant: &TenantId,
+        jti: &str,
+    ) -> Result<bool, BannedTokenStoreError> {
+        if self.is_cached(tenant, jti) {
+            return Ok(true);
+        }
+        if self.is_synced() {
+            return Ok(false);
+        }
+
+        match self.store.contains_token(tenant, jti).await {
+            Ok(banned) => Ok(banned),
+            Err(e) if self.failure_policy == RevocationFailurePolicy::Open => {
+                tracing::warn!("accepting a token that could not be checked: {:?}", e);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_revocation() {
+        let (tenant, jti) = parse_revocation(br#"{"tenant":"acme","jti":"abc"}"#).unwrap();
+        assert_eq!(tenant, TenantId::parse("acme").unwrap());
+        assert_eq!(jti, "abc");
+
+        assert!(parse_revocation(br#"{"tenant":"Not A Tenant","jti":"abc"}"#).is_err());
+        assert!(parse_revocation(b"abc").is_err());
+    }
+
+    #[test]
+    fn test_revocation_cache_is_bounded() {
+        let cache = RevocationCache::new(2);
+        let tenant = TenantId::parse("acme").unwrap();
+        assert!(cache.insert(tenant.clone(), "a".to_owned()));
+        assert!(cache.insert(tenant.clone(), "b".to_owned()));
+        // Renewing a revocation already held does not need room
+        assert!(cache.insert(tenant.clone(), "a".to_owned()));
+
+        assert!(!cache.insert(tenant.clone(), "c".to_owned()));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&tenant, "a"));
+        assert!(!cache.contains(&tenant, "c"));
+    }
+}
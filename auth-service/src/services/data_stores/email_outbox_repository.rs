@@ -0,0 +1,98 @@
+use crate::domain::{Email, EmailMessage, TenantId};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use serde::Serialize;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+// This trait represents the interface all concrete email outboxes should implement.
+// Messages are written by request handlers and delivered later by the EmailOutboxWorker.
+#[async_trait::async_trait]
+pub trait EmailOutbox: Send + Sync {
+    // Returns false if a message with the same idempotency key was already enqueued for the tenant
+    async fn enqueue(
+        &self,
+        tenant: &TenantId,
+        idempotency_key: &str,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<bool, EmailOutboxError>;
+    // Claim up to `limit` messages that are due, hiding them from other workers for `lease`
+    async fn claim_due(
+        &self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, EmailOutboxError>;
+    async fn mark_sent(&self, id: Uuid) -> Result<(), EmailOutboxError>;
+    async fn mark_expired(&self, id: Uuid) -> Result<(), EmailOutboxError>;
+    async fn schedule_retry(
+        &self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), EmailOutboxError>;
+    async fn mark_dead(&self, id: Uuid, error: &str) -> Result<(), EmailOutboxError>;
+    async fn stats(&self) -> Result<OutboxStats, EmailOutboxError>;
+    // Dead-lettered messages, plus undelivered messages enqueued more than `older_than` ago
+    async fn stuck_messages(
+        &self,
+        older_than: Duration,
+        limit: u32,
+    ) -> Result<Vec<OutboxEntry>, EmailOutboxError>;
+}
+
+#[derive(Debug, Error)]
+pub enum EmailOutboxError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for EmailOutboxError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// A claimed message, ready to be delivered
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    // Whose sender address the message goes out with
+    pub tenant_id: TenantId,
+    pub recipient: Email,
+    pub message: EmailMessage,
+    // Includes the current attempt
+    pub attempts: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxStats {
+    pub pending: i64,
+    pub sending: i64,
+    pub sent: i64,
+    pub dead: i64,
+    pub expired: i64,
+    // Age of the oldest message that has not been delivered yet
+    pub oldest_undelivered_seconds: Option<i64>,
+}
+
+// An outbox row as shown to administrators. The message payload is left out since it can hold one-time codes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxEntry {
+    pub id: Uuid,
+    pub tenant_id: String,
+    pub idempotency_key: String,
+    pub template_id: String,
+    pub recipient: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,65 @@
+use crate::domain::{Email, TenantId};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use thiserror::Error;
+
+// This trait represents the interface all concrete known device stores should implement.
+// Devices are identified by the fingerprint from ClientInfo::fingerprint.
+#[async_trait::async_trait]
+pub trait KnownDeviceStore: Send + Sync {
+    // Record a sign-in to the account from the device, and tell whether it was seen before
+    async fn record_sign_in(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        fingerprint: &str,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError>;
+    // What `record_sign_in` would say, without recording anything
+    async fn sighting(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        fingerprint: &str,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError>;
+    // Forget the device, e.g. after its owner said the sign-in was not theirs
+    async fn forget_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        fingerprint: &str,
+    ) -> Result<(), KnownDeviceStoreError>;
+    // Mark the new device alert about the session as confirmed by the owner, so its link works
+    // once. False if it was confirmed before. Kept until `expires_at`, when the link expires anyway.
+    async fn use_sign_in_alert(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        session_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, KnownDeviceStoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSighting {
+    // The account signed in from the device before
+    Known,
+    // A device the account never signed in from
+    New,
+    // The first device of the account, which there is nothing to compare with
+    First,
+}
+
+#[derive(Debug, Error)]
+pub enum KnownDeviceStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for KnownDeviceStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
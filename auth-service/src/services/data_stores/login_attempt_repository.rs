@@ -0,0 +1,64 @@
+use crate::domain::{Email, GeoLocation, RiskAssessment, TenantId};
+use crate::utils::client::ClientInfo;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use thiserror::Error;
+
+// How long login attempts are kept for the risk engine
+pub const LOGIN_ATTEMPT_RETENTION_SECONDS: u64 = 90 * 24 * 60 * 60; // 90 days
+
+// This trait represents the interface all concrete login attempt stores should implement
+#[async_trait::async_trait]
+pub trait LoginAttemptStore: Send + Sync {
+    async fn add_attempt(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        attempt: &LoginAttempt,
+    ) -> Result<(), LoginAttemptStoreError>;
+    // Wrong passwords for the account since `since`, from the network `ip_prefix` (see
+    // ClientInfo::ip_prefix), or from clients without an address when it is None
+    async fn count_failures(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        ip_prefix: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<u32, LoginAttemptStoreError>;
+    // The account's latest login that was not denied and could be located
+    async fn last_located_login(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Option<LocatedLogin>, LoginAttemptStoreError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginAttempt {
+    pub attempted_at: DateTime<Utc>,
+    pub client: ClientInfo,
+    pub location: Option<GeoLocation>,
+    // None when the password was wrong
+    pub assessment: Option<RiskAssessment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocatedLogin {
+    pub attempted_at: DateTime<Utc>,
+    pub location: GeoLocation,
+}
+
+#[derive(Debug, Error)]
+pub enum LoginAttemptStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for LoginAttemptStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
@@ -0,0 +1,58 @@
+use crate::domain::{Email, TenantId};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use thiserror::Error;
+
+// This value determines how long a magic link can be used for
+pub const MAGIC_LINK_TTL_SECONDS: u64 = 600; // 10 minutes
+
+// This trait represents the interface all concrete magic link stores should implement.
+// Links expire after MAGIC_LINK_TTL_SECONDS and can be consumed once.
+#[async_trait::async_trait]
+pub trait MagicLinkStore: Send + Sync {
+    async fn add_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+        link: &MagicLink,
+    ) -> Result<(), MagicLinkStoreError>;
+    async fn get_link(&self, tenant: &TenantId, id: &str)
+    -> Result<MagicLink, MagicLinkStoreError>;
+    // Remove the link and return it, so only one caller can sign in with it
+    async fn consume_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+    ) -> Result<MagicLink, MagicLinkStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum MagicLinkStoreError {
+    #[error("Magic link not found")]
+    LinkNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for MagicLinkStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::LinkNotFound, Self::LinkNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// A pending magic link login
+#[derive(Debug, Clone)]
+pub struct MagicLink {
+    pub email: Email,
+    // SHA-256 of the browser binding cookie set on the browser that asked for the link
+    pub browser_binding: String,
+    // SHA-256 of the code shown to that browser, which any other browser must enter to sign in
+    pub confirmation_code_hash: String,
+    pub requested_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
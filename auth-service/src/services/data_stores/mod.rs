@@ -1,17 +1,86 @@
+pub mod store_backend;
+pub use store_backend::StoreBackend;
+
 pub mod user_repository;
-pub use user_repository::{UserStore, UserStoreError};
+pub use user_repository::{MAX_PHONE_VERIFICATION_ATTEMPTS, UserStore, UserStoreError};
 
 pub mod banned_token_repository;
-pub use banned_token_repository::{BannedTokenStore, BannedTokenStoreError};
+pub use banned_token_repository::{
+    BannedTokenStore, BannedTokenStoreError, RevocationFailurePolicy,
+};
 
 pub mod two_factor_repository;
-pub use two_factor_repository::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+pub use two_factor_repository::{
+    LoginAttemptId, MAX_2FA_CODE_ATTEMPTS, MAX_PENDING_2FA_CHALLENGES, TWO_FA_CODE_TTL_SECONDS,
+    TwoFAChallenge, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+};
+
+pub mod magic_link_repository;
+pub use magic_link_repository::{
+    MAGIC_LINK_TTL_SECONDS, MagicLink, MagicLinkStore, MagicLinkStoreError,
+};
+
+pub mod email_outbox_repository;
+pub use email_outbox_repository::{
+    EmailOutbox, EmailOutboxError, OutboxEntry, OutboxMessage, OutboxStats,
+};
+
+pub mod recovery_code_repository;
+pub use recovery_code_repository::{RecoveryCodeStore, RecoveryCodeStoreError, RecoveryCodeUsage};
+
+pub mod remember_me_repository;
+pub use remember_me_repository::{RememberMeStore, RememberMeStoreError};
+
+pub mod trusted_device_repository;
+pub use trusted_device_repository::{TrustedDevice, TrustedDeviceStore, TrustedDeviceStoreError};
+
+pub mod known_device_repository;
+pub use known_device_repository::{DeviceSighting, KnownDeviceStore, KnownDeviceStoreError};
+
+pub mod login_attempt_repository;
+pub use login_attempt_repository::{
+    LOGIN_ATTEMPT_RETENTION_SECONDS, LocatedLogin, LoginAttempt, LoginAttemptStore,
+    LoginAttemptStoreError,
+};
 
 pub mod postgres_user_store;
 pub use postgres_user_store::PostgresUserStore;
 
+pub mod postgres_email_outbox;
+pub use postgres_email_outbox::PostgresEmailOutbox;
+
+pub mod postgres_recovery_code_store;
+pub use postgres_recovery_code_store::PostgresRecoveryCodeStore;
+
+pub mod postgres_remember_me_store;
+pub use postgres_remember_me_store::PostgresRememberMeStore;
+
+pub mod postgres_trusted_device_store;
+pub use postgres_trusted_device_store::PostgresTrustedDeviceStore;
+
+pub mod postgres_known_device_store;
+pub use postgres_known_device_store::PostgresKnownDeviceStore;
+
+pub mod postgres_login_attempt_store;
+pub use postgres_login_attempt_store::PostgresLoginAttemptStore;
+
+pub mod postgres_banned_token_store;
+pub use postgres_banned_token_store::PostgresBannedTokenStore;
+
+pub mod postgres_two_fa_code_store;
+pub use postgres_two_fa_code_store::PostgresTwoFACodeStore;
+
+pub mod postgres_magic_link_store;
+pub use postgres_magic_link_store::PostgresMagicLinkStore;
+
 pub mod redis_banned_token_store;
 pub use redis_banned_token_store::RedisBannedTokenStore;
 
+pub mod cached_banned_token_store;
+pub use cached_banned_token_store::CachedBannedTokenStore;
+
 pub mod redis_two_fa_code_store;
 pub use redis_two_fa_code_store::RedisTwoFACodeStore;
+
+pub mod redis_magic_link_store;
+pub use redis_magic_link_store::RedisMagicLinkStore;
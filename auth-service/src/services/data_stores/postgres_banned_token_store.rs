@@ -0,0 +1,62 @@
+use sqlx::PgPool;
+
+use crate::{
+    domain::TenantId,
+    services::data_stores::{BannedTokenStore, BannedTokenStoreError},
+    utils::auth::TOKEN_TTL_SECONDS,
+};
+
+// Revocations in a table instead of Redis. Every check is a query, so this suits deployments
+// small enough to run without Redis.
+pub struct PostgresBannedTokenStore {
+    pool: PgPool,
+}
+
+impl PostgresBannedTokenStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for PostgresBannedTokenStore {
+    #[tracing::instrument(name = "Adding Token To PostgreSQL", skip_all)]
+    async fn add_token(&self, tenant: &TenantId, jti: String) -> Result<(), BannedTokenStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO banned_tokens (tenant_id, jti, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            ON CONFLICT (tenant_id, jti) DO NOTHING
+            "#,
+            tenant.as_ref(),
+            jti,
+            TOKEN_TTL_SECONDS as f64,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| BannedTokenStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Checking If Token In PostgreSQL", skip_all)]
+    async fn contains_token(
+        &self,
+        tenant: &TenantId,
+        jti: &str,
+    ) -> Result<bool, BannedTokenStoreError> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM banned_tokens
+                WHERE tenant_id = $1 AND jti = $2 AND expires_at > now()
+            ) AS "banned!"
+            "#,
+            tenant.as_ref(),
+            jti,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| BannedTokenStoreError::UnexpectedError(e.into()))
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::domain::{Email, EmailMessage, TenantId};
+use crate::services::data_stores::{
+    EmailOutbox, EmailOutboxError, OutboxEntry, OutboxMessage, OutboxStats,
+};
+
+pub struct PostgresEmailOutbox {
+    pool: PgPool,
+}
+
+impl PostgresEmailOutbox {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for PostgresEmailOutbox {
+    #[tracing::instrument(name = "Enqueuing email in PostgreSQL outbox", skip_all)]
+    async fn enqueue(
+        &self,
+        tenant: &TenantId,
+        idempotency_key: &str,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<bool, EmailOutboxError> {
+        let payload = serde_json::to_value(message)
+            .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO email_outbox (id, tenant_id, idempotency_key, template_id, recipient, message)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (tenant_id, idempotency_key) DO NOTHING
+            "#,
+            Uuid::new_v4(),
+            tenant.as_ref(),
+            idempotency_key,
+            message.template_id(),
+            recipient.as_ref(),
+            payload,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(name = "Claiming due emails from PostgreSQL outbox", skip_all)]
+    async fn claim_due(
+        &self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, EmailOutboxError> {
+        // SKIP LOCKED lets concurrent workers claim disjoint batches without waiting on each other
+        let rows = sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET status = 'sending',
+                attempts = attempts + 1,
+                locked_until = now() + make_interval(secs => $2),
+                updated_at = now()
+            WHERE id IN (
+                SELECT id FROM email_outbox
+                WHERE (status = 'pending' AND next_attempt_at <= now())
+                   OR (status = 'sending' AND locked_until <= now())
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, tenant_id, recipient, message, attempts
+            "#,
+            i64::from(limit),
+            lease.as_secs_f64(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+
+        let mut messages = Vec::with_capacity(rows.len());
+        for row in rows {
+            let parsed = row
+                .message
+                .ok_or_else(|| eyre!("Message payload is missing"))
+                .and_then(|payload| Ok(serde_json::from_value::<EmailMessage>(payload)?))
+                .and_then(|message| {
+                    Ok((
+                        message,
+                        TenantId::parse(&row.tenant_id)?,
+                        Email::parse(SecretBox::new(Box::new(row.recipient)))?,
+                    ))
+                });
+            match parsed {
+                Ok((message, tenant_id, recipient)) => messages.push(OutboxMessage {
+                    id: row.id,
+                    tenant_id,
+                    recipient,
+                    message,
+                    attempts: row.attempts,
+                }),
+                // Retrying cannot fix a message we cannot read, so dead-letter it right away
+                Err(e) => {
+                    tracing::error!(id = %row.id, "Dead-lettering unreadable outbox message: {:?}", e);
+                    self.mark_dead(row.id, &format!("{:#}", e)).await?;
+                }
+            }
+        }
+        Ok(messages)
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as sent", skip_all)]
+    async fn mark_sent(&self, id: Uuid) -> Result<(), EmailOutboxError> {
+        sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET status = 'sent', message = NULL, locked_until = NULL, last_error = NULL, updated_at = now()
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as expired", skip_all)]
+    async fn mark_expired(&self, id: Uuid) -> Result<(), EmailOutboxError> {
+        sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET status = 'expired', message = NULL, locked_until = NULL, updated_at = now()
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Scheduling outbox email retry", skip_all)]
+    async fn schedule_retry(
+        &self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), EmailOutboxError> {
+        sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET status = 'pending', next_attempt_at = $2, last_error = $3, locked_until = NULL, updated_at = now()
+            WHERE id = $1
+            "#,
+            id,
+            next_attempt_at,
+            error,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Dead-lettering outbox email", skip_all)]
+    async fn mark_dead(&self, id: Uuid, error: &str) -> Result<(), EmailOutboxError> {
+        // Like sent and expired messages, keep only the envelope, not the codes and links inside
+        sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET status = 'dead', message = NULL, last_error = $2, locked_until = NULL, updated_at = now()
+            WHERE id = $1
+            "#,
+            id,
+            error,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving PostgreSQL outbox stats", skip_all)]
+    async fn stats(&self) -> Result<OutboxStats, EmailOutboxError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                count(*) FILTER (WHERE status = 'pending') AS "pending!",
+                count(*) FILTER (WHERE status = 'sending') AS "sending!",
+                count(*) FILTER (WHERE status = 'sent') AS "sent!",
+                count(*) FILTER (WHERE status = 'dead') AS "dead!",
+                count(*) FILTER (WHERE status = 'expired') AS "expired!",
+                EXTRACT(EPOCH FROM now() - min(created_at) FILTER (WHERE status IN ('pending', 'sending')))::BIGINT
+                    AS oldest_undelivered_seconds
+            FROM email_outbox
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))?;
+
+        Ok(OutboxStats {
+            pending: row.pending,
+            sending: row.sending,
+            sent: row.sent,
+            dead: row.dead,
+            expired: row.expired,
+            oldest_undelivered_seconds: row.oldest_undelivered_seconds,
+        })
+    }
+
+    #[tracing::instrument(name = "Retrieving stuck emails from PostgreSQL outbox", skip_all)]
+    async fn stuck_messages(
+        &self,
+        older_than: Duration,
+        limit: u32,
+    ) -> Result<Vec<OutboxEntry>, EmailOutboxError> {
+        sqlx::query_as!(
+            OutboxEntry,
+            r#"
+            SELECT id, tenant_id, idempotency_key, template_id, recipient, status, attempts, next_attempt_at, last_error, created_at
+            FROM email_outbox
+            WHERE status = 'dead'
+               OR (status IN ('pending', 'sending') AND created_at <= now() - make_interval(secs => $1))
+            ORDER BY created_at
+            LIMIT $2
+            "#,
+            older_than.as_secs_f64(),
+            i64::from(limit),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| EmailOutboxError::UnexpectedError(e.into()))
+    }
+}
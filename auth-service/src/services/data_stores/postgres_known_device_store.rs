@@ -0,0 +1,132 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::domain::{Email, TenantId};
+use crate::services::data_stores::{DeviceSighting, KnownDeviceStore, KnownDeviceStoreError};
+
+pub struct PostgresKnownDeviceStore {
+    pool: PgPool,
+}
+
+impl PostgresKnownDeviceStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl KnownDeviceStore for PostgresKnownDeviceStore {
+    #[tracing::instrument(name = "Recording sign-in device in PostgreSQL", skip_all)]
+    async fn record_sign_in(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        fingerprint: &str,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError> {
+        // xmax is 0 for a row this statement inserted, and set when it updated an existing one.
+        // The count is taken from the snapshot before the insert.
+        let row = sqlx::query!(
+            r#"
+            WITH known AS (
+                SELECT count(*) AS devices FROM known_devices
+                WHERE tenant_id = $1 AND email_normalized = $2
+            )
+            INSERT INTO known_devices (tenant_id, email_normalized, fingerprint)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (tenant_id, email_normalized, fingerprint)
+            DO UPDATE SET last_seen_at = now()
+            RETURNING (xmax = 0) AS "inserted!", (SELECT devices FROM known) AS "devices!"
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            fingerprint,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| KnownDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(match (row.inserted, row.devices) {
+            (false, _) => DeviceSighting::Known,
+            (true, 0) => DeviceSighting::First,
+            (true, _) => DeviceSighting::New,
+        })
+    }
+
+    #[tracing::instrument(name = "Looking up sign-in device in PostgreSQL", skip_all)]
+    async fn sighting(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        fingerprint: &str,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT count(*) AS "devices!", coalesce(bool_or(fingerprint = $3), false) AS "known!"
+            FROM known_devices
+            WHERE tenant_id = $1 AND email_normalized = $2
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            fingerprint,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| KnownDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(match (row.known, row.devices) {
+            (true, _) => DeviceSighting::Known,
+            (false, 0) => DeviceSighting::First,
+            (false, _) => DeviceSighting::New,
+        })
+    }
+
+    #[tracing::instrument(name = "Forgetting sign-in device in PostgreSQL", skip_all)]
+    async fn forget_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        fingerprint: &str,
+    ) -> Result<(), KnownDeviceStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM known_devices
+            WHERE tenant_id = $1 AND email_normalized = $2 AND fingerprint = $3
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            fingerprint,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| KnownDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Using sign-in alert in PostgreSQL", skip_all)]
+    async fn use_sign_in_alert(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        session_id: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, KnownDeviceStoreError> {
+        // The primary key decides which of two concurrent submissions uses the link
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO used_sign_in_alerts (tenant_id, session_id, email_normalized, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (tenant_id, session_id) DO NOTHING
+            "#,
+            tenant.as_ref(),
+            session_id,
+            email.normalized(),
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| KnownDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
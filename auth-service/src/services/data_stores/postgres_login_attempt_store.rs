@@ -0,0 +1,125 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{Coordinates, Email, GeoLocation, TenantId};
+use crate::services::data_stores::{
+    LOGIN_ATTEMPT_RETENTION_SECONDS, LocatedLogin, LoginAttempt, LoginAttemptStore,
+    LoginAttemptStoreError,
+};
+
+pub struct PostgresLoginAttemptStore {
+    pool: PgPool,
+}
+
+impl PostgresLoginAttemptStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptStore for PostgresLoginAttemptStore {
+    #[tracing::instrument(name = "Adding login attempt to PostgreSQL", skip_all)]
+    async fn add_attempt(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        attempt: &LoginAttempt,
+    ) -> Result<(), LoginAttemptStoreError> {
+        let location = attempt.location.as_ref();
+        let coordinates = location.and_then(|location| location.coordinates);
+        let assessment = attempt.assessment.as_ref();
+        sqlx::query!(
+            r#"
+            INSERT INTO login_attempts
+                (id, tenant_id, email_normalized, attempted_at, expires_at, ip_address, ip_prefix,
+                 user_agent, country, latitude, longitude, decision, score, reasons)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            "#,
+            Uuid::new_v4(),
+            tenant.as_ref(),
+            email.normalized(),
+            attempt.attempted_at,
+            attempt.attempted_at + TimeDelta::seconds(LOGIN_ATTEMPT_RETENTION_SECONDS as i64),
+            attempt.client.ip_address.as_deref(),
+            attempt.client.ip_prefix(),
+            attempt.client.user_agent.as_deref(),
+            location.and_then(|location| location.country.as_deref()),
+            coordinates.map(|coordinates| coordinates.latitude),
+            coordinates.map(|coordinates| coordinates.longitude),
+            assessment.map(|assessment| assessment.decision.as_str()),
+            assessment.map_or(0, |assessment| assessment.score as i32),
+            &assessment
+                .map(|assessment| assessment.reasons())
+                .unwrap_or_default(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| LoginAttemptStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Counting failed logins in PostgreSQL", skip_all)]
+    async fn count_failures(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        ip_prefix: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<u32, LoginAttemptStoreError> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT count(*) AS "count!" FROM login_attempts
+            WHERE tenant_id = $1 AND email_normalized = $2 AND decision IS NULL
+                AND ip_prefix IS NOT DISTINCT FROM $3 AND attempted_at >= $4
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            ip_prefix,
+            since,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| LoginAttemptStoreError::UnexpectedError(e.into()))?;
+
+        Ok(count as u32)
+    }
+
+    #[tracing::instrument(name = "Getting last located login from PostgreSQL", skip_all)]
+    async fn last_located_login(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Option<LocatedLogin>, LoginAttemptStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT attempted_at, country, latitude, longitude FROM login_attempts
+            WHERE tenant_id = $1 AND email_normalized = $2 AND decision IN ('allow', 'challenge')
+                AND (country IS NOT NULL OR latitude IS NOT NULL)
+            ORDER BY attempted_at DESC
+            LIMIT 1
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| LoginAttemptStoreError::UnexpectedError(e.into()))?;
+
+        Ok(row.map(|row| LocatedLogin {
+            attempted_at: row.attempted_at,
+            location: GeoLocation {
+                country: row.country,
+                coordinates: match (row.latitude, row.longitude) {
+                    (Some(latitude), Some(longitude)) => Some(Coordinates {
+                        latitude,
+                        longitude,
+                    }),
+                    _ => None,
+                },
+            },
+        }))
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use secrecy::SecretBox;
+use sqlx::PgPool;
+
+use crate::{
+    domain::{Email, TenantId},
+    services::data_stores::{
+        MAGIC_LINK_TTL_SECONDS, MagicLink, MagicLinkStore, MagicLinkStoreError,
+    },
+};
+
+pub struct PostgresMagicLinkStore {
+    pool: PgPool,
+}
+
+impl PostgresMagicLinkStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl MagicLinkStore for PostgresMagicLinkStore {
+    #[tracing::instrument(name = "Adding magic link to PostgreSQL", skip_all)]
+    async fn add_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+        link: &MagicLink,
+    ) -> Result<(), MagicLinkStoreError> {
+        let expires_at = link.requested_at + TimeDelta::seconds(MAGIC_LINK_TTL_SECONDS as i64);
+        sqlx::query!(
+            r#"
+            INSERT INTO magic_links
+                (tenant_id, id, email, email_normalized, browser_binding, confirmation_code_hash,
+                 requested_at, expires_at, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            tenant.as_ref(),
+            id,
+            link.email.as_ref(),
+            link.email.normalized(),
+            link.browser_binding,
+            link.confirmation_code_hash,
+            link.requested_at,
+            expires_at,
+            link.ip_address.as_deref(),
+            link.user_agent.as_deref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| MagicLinkStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Getting magic link from PostgreSQL", skip_all)]
+    async fn get_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+    ) -> Result<MagicLink, MagicLinkStoreError> {
+        let row = sqlx::query_as!(
+            MagicLinkRow,
+            r#"
+            SELECT email, browser_binding, confirmation_code_hash, requested_at, ip_address, user_agent
+            FROM magic_links
+            WHERE tenant_id = $1 AND id = $2 AND expires_at > now()
+            "#,
+            tenant.as_ref(),
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| MagicLinkStoreError::UnexpectedError(e.into()))?;
+
+        row.ok_or(MagicLinkStoreError::LinkNotFound)?.into_link()
+    }
+
+    #[tracing::instrument(name = "Consuming magic link from PostgreSQL", skip_all)]
+    async fn consume_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+    ) -> Result<MagicLink, MagicLinkStoreError> {
+        // Deleting is atomic, so two requests racing with the same link cannot both get it
+        let row = sqlx::query_as!(
+            MagicLinkRow,
+            r#"
+            DELETE FROM magic_links
+            WHERE tenant_id = $1 AND id = $2 AND expires_at > now()
+            RETURNING email, browser_binding, confirmation_code_hash, requested_at, ip_address, user_agent
+            "#,
+            tenant.as_ref(),
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| MagicLinkStoreError::UnexpectedError(e.into()))?;
+
+        row.ok_or(MagicLinkStoreError::LinkNotFound)?.into_link()
+    }
+}
+
+struct MagicLinkRow {
+    email: String,
+    browser_binding: String,
+    confirmation_code_hash: String,
+    requested_at: DateTime<Utc>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl MagicLinkRow {
+    fn into_link(self) -> Result<MagicLink, MagicLinkStoreError> {
+        let email = Email::parse(SecretBox::new(Box::new(self.email)))
+            .map_err(MagicLinkStoreError::UnexpectedError)?;
+        Ok(MagicLink {
+            email,
+            browser_binding: self.browser_binding,
+            confirmation_code_hash: self.confirmation_code_hash,
+            requested_at: self.requested_at,
+            ip_address: self.ip_address,
+            user_agent: self.user_agent,
+        })
+    }
+}
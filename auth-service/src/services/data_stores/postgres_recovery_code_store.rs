@@ -0,0 +1,117 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{Email, RecoveryCode, TenantId};
+use crate::services::data_stores::{RecoveryCodeStore, RecoveryCodeStoreError, RecoveryCodeUsage};
+
+pub struct PostgresRecoveryCodeStore {
+    pool: PgPool,
+}
+
+impl PostgresRecoveryCodeStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for PostgresRecoveryCodeStore {
+    #[tracing::instrument(name = "Replacing recovery codes in PostgreSQL", skip_all)]
+    async fn replace_codes(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        codes: &[RecoveryCode],
+    ) -> Result<(), RecoveryCodeStoreError> {
+        let ids: Vec<Uuid> = codes.iter().map(|_| Uuid::new_v4()).collect();
+        let hashes: Vec<String> = codes.iter().map(RecoveryCode::hash).collect();
+
+        // Delete and insert together, so a failure never leaves the user without codes
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            "DELETE FROM recovery_codes WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO recovery_codes (id, tenant_id, email_normalized, code_hash)
+            SELECT id, $3, $4, code_hash FROM UNNEST($1::uuid[], $2::text[]) AS codes(id, code_hash)
+            "#,
+            &ids,
+            &hashes,
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Using recovery code in PostgreSQL", skip_all)]
+    async fn use_code(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        code: &RecoveryCode,
+        usage: &RecoveryCodeUsage,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        // The `used_at IS NULL` condition makes concurrent attempts with the same code race safely
+        let result = sqlx::query!(
+            r#"
+            UPDATE recovery_codes
+            SET used_at = $4, used_ip = $5, used_user_agent = $6
+            WHERE tenant_id = $1 AND email_normalized = $2 AND code_hash = $3 AND used_at IS NULL
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            code.hash(),
+            usage.used_at,
+            usage.ip_address.as_deref(),
+            usage.user_agent.as_deref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(RecoveryCodeStoreError::InvalidCode),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Counting remaining recovery codes in PostgreSQL", skip_all)]
+    async fn remaining_codes(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<i64, RecoveryCodeStoreError> {
+        let remaining = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM recovery_codes
+            WHERE tenant_id = $1 AND email_normalized = $2 AND used_at IS NULL
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        Ok(remaining)
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use secrecy::SecretBox;
+use sqlx::PgPool;
+
+use crate::domain::{Email, RememberMeToken, TenantId};
+use crate::services::data_stores::{RememberMeStore, RememberMeStoreError};
+use crate::utils::client::ClientInfo;
+
+pub struct PostgresRememberMeStore {
+    pool: PgPool,
+}
+
+impl PostgresRememberMeStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RememberMeStore for PostgresRememberMeStore {
+    #[tracing::instrument(name = "Adding remember me token to PostgreSQL", skip_all)]
+    async fn add_token(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        token: &RememberMeToken,
+        expires_at: DateTime<Utc>,
+        client: &ClientInfo,
+    ) -> Result<(), RememberMeStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO remember_me_tokens
+                (id, tenant_id, email_normalized, secret_hash, expires_at, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            token.id(),
+            tenant.as_ref(),
+            email.normalized(),
+            token.secret_hash(),
+            expires_at,
+            client.ip_address.as_deref(),
+            client.user_agent.as_deref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Rotating remember me token in PostgreSQL", skip_all)]
+    async fn rotate_token(
+        &self,
+        tenant: &TenantId,
+        token: &RememberMeToken,
+        rotated: &RememberMeToken,
+        client: &ClientInfo,
+    ) -> Result<(Email, DateTime<Utc>), RememberMeStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+
+        // Lock the row so two renewals with the same secret cannot both succeed
+        let stored = sqlx::query!(
+            r#"
+            SELECT users.email, tokens.secret_hash, tokens.previous_secret_hash, tokens.expires_at,
+                tokens.user_agent, tokens.expires_at > now() AS "live!"
+            FROM remember_me_tokens tokens
+            JOIN users USING (tenant_id, email_normalized)
+            WHERE tokens.tenant_id = $1 AND tokens.id = $2
+            FOR UPDATE OF tokens
+            "#,
+            tenant.as_ref(),
+            token.id(),
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?
+        .ok_or(RememberMeStoreError::InvalidToken)?;
+
+        let secret_hash = token.secret_hash();
+        // The cookie is only good in the browser it was set in. Browsers change their user agent
+        // when they update, which signs the device out like any other mismatch.
+        let other_device = stored.user_agent != client.user_agent;
+        if !stored.live || other_device || stored.secret_hash != secret_hash {
+            // An expired token is of no further use, and a replaced secret or another device means
+            // the cookie was copied
+            if !stored.live
+                || other_device
+                || stored.previous_secret_hash.as_deref() == Some(secret_hash.as_str())
+            {
+                sqlx::query!(
+                    "DELETE FROM remember_me_tokens WHERE tenant_id = $1 AND id = $2",
+                    tenant.as_ref(),
+                    token.id(),
+                )
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+                transaction
+                    .commit()
+                    .await
+                    .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+            }
+            return Err(RememberMeStoreError::InvalidToken);
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE remember_me_tokens
+            SET secret_hash = $3, previous_secret_hash = $4, last_used_at = now()
+            WHERE tenant_id = $1 AND id = $2
+            "#,
+            tenant.as_ref(),
+            token.id(),
+            rotated.secret_hash(),
+            secret_hash,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+
+        let email = Email::parse(SecretBox::new(Box::new(stored.email)))
+            .map_err(RememberMeStoreError::UnexpectedError)?;
+        Ok((email, stored.expires_at))
+    }
+
+    #[tracing::instrument(name = "Revoking remember me token in PostgreSQL", skip_all)]
+    async fn revoke_token(
+        &self,
+        tenant: &TenantId,
+        token: &RememberMeToken,
+    ) -> Result<(), RememberMeStoreError> {
+        sqlx::query!(
+            "DELETE FROM remember_me_tokens WHERE tenant_id = $1 AND id = $2",
+            tenant.as_ref(),
+            token.id(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking all remember me tokens in PostgreSQL", skip_all)]
+    async fn revoke_all(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), RememberMeStoreError> {
+        sqlx::query!(
+            "DELETE FROM remember_me_tokens WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| RememberMeStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{Email, TenantId};
+use crate::services::data_stores::{TrustedDevice, TrustedDeviceStore, TrustedDeviceStoreError};
+use crate::utils::client::ClientInfo;
+
+pub struct PostgresTrustedDeviceStore {
+    pool: PgPool,
+}
+
+impl PostgresTrustedDeviceStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl TrustedDeviceStore for PostgresTrustedDeviceStore {
+    #[tracing::instrument(name = "Adding trusted device to PostgreSQL", skip_all)]
+    async fn add_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+        expires_at: DateTime<Utc>,
+        client: &ClientInfo,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO trusted_devices
+                (id, tenant_id, email_normalized, expires_at, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            id,
+            tenant.as_ref(),
+            email.normalized(),
+            expires_at,
+            client.ip_address.as_deref(),
+            client.user_agent.as_deref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Using trusted device in PostgreSQL", skip_all)]
+    async fn use_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE trusted_devices SET last_used_at = now()
+            WHERE tenant_id = $1 AND email_normalized = $2 AND id = $3 AND expires_at > now()
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(TrustedDeviceStoreError::DeviceNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Listing trusted devices in PostgreSQL", skip_all)]
+    async fn list_devices(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, created_at, last_used_at, expires_at, ip_address, user_agent
+            FROM trusted_devices
+            WHERE tenant_id = $1 AND email_normalized = $2 AND expires_at > now()
+            ORDER BY created_at DESC
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TrustedDevice {
+                id: row.id,
+                created_at: row.created_at,
+                last_used_at: row.last_used_at,
+                expires_at: row.expires_at,
+                client: ClientInfo {
+                    ip_address: row.ip_address,
+                    user_agent: row.user_agent,
+                },
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(name = "Revoking trusted device in PostgreSQL", skip_all)]
+    async fn revoke_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        // Scoped to the account, so one user cannot revoke another's devices
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM trusted_devices
+            WHERE tenant_id = $1 AND email_normalized = $2 AND id = $3 AND expires_at > now()
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(TrustedDeviceStoreError::DeviceNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Revoking all trusted devices in PostgreSQL", skip_all)]
+    async fn revoke_all(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        sqlx::query!(
+            "DELETE FROM trusted_devices WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+}
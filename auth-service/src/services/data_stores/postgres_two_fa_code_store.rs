@@ -0,0 +1,250 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::eyre;
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{Email, TenantId},
+    services::data_stores::{
+        LoginAttemptId, MAX_2FA_CODE_ATTEMPTS, MAX_PENDING_2FA_CHALLENGES, TWO_FA_CODE_TTL_SECONDS,
+        TwoFAChallenge, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+    },
+};
+
+// Pending 2FA logins in a table instead of Redis, for deployments that run without it
+pub struct PostgresTwoFACodeStore {
+    pool: PgPool,
+}
+
+impl PostgresTwoFACodeStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn to_uuid(login_attempt_id: &LoginAttemptId) -> Result<Uuid, TwoFACodeStoreError> {
+    Uuid::parse_str(login_attempt_id.as_ref())
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for PostgresTwoFACodeStore {
+    #[tracing::instrument(name = "Adding 2FA code to PostgreSQL", skip_all)]
+    async fn add_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: LoginAttemptId,
+        challenge: TwoFAChallenge,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let login_attempt_id = to_uuid(&login_attempt_id)?;
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO two_fa_codes
+                (tenant_id, email_normalized, login_attempt_id, code, client_binding, created_at,
+                 sent_at, resends, attempts, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now() + make_interval(secs => $10))
+            "#,
+            tenant.as_ref(),
+            challenge.email.normalized(),
+            login_attempt_id,
+            challenge.code.as_ref(),
+            challenge.client_binding,
+            challenge.created_at,
+            challenge.sent_at,
+            challenge.resends as i32,
+            challenge.attempts as i32,
+            TWO_FA_CODE_TTL_SECONDS as f64,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+
+        // Drop the oldest pending challenges of the account beyond the limit
+        sqlx::query!(
+            r#"
+            DELETE FROM two_fa_codes
+            WHERE tenant_id = $1 AND email_normalized = $2 AND login_attempt_id NOT IN (
+                SELECT login_attempt_id FROM two_fa_codes
+                WHERE tenant_id = $1 AND email_normalized = $2 AND expires_at > now()
+                ORDER BY created_at DESC
+                LIMIT $3
+            )
+            "#,
+            tenant.as_ref(),
+            challenge.email.normalized(),
+            MAX_PENDING_2FA_CHALLENGES as i64,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Updating 2FA code in PostgreSQL", skip_all)]
+    async fn update_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+        sent_at: DateTime<Utc>,
+        challenge: TwoFAChallenge,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let login_attempt_id = to_uuid(login_attempt_id)?;
+        // Compare and set: a concurrent update has changed `sent_at` already. The login keeps
+        // its expiry.
+        let result = sqlx::query!(
+            r#"
+            UPDATE two_fa_codes
+            SET code = $3, sent_at = $4, resends = $5
+            WHERE tenant_id = $1 AND login_attempt_id = $2 AND expires_at > now() AND sent_at = $6
+            "#,
+            tenant.as_ref(),
+            login_attempt_id,
+            challenge.code.as_ref(),
+            challenge.sent_at,
+            challenge.resends as i32,
+            sent_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        // Either the login expired or was finished in the meantime, or another update won
+        let pending = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM two_fa_codes
+                WHERE tenant_id = $1 AND login_attempt_id = $2 AND expires_at > now()
+            ) AS "pending!"
+            "#,
+            tenant.as_ref(),
+            login_attempt_id,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+        if pending {
+            Err(TwoFACodeStoreError::ChallengeChanged)
+        } else {
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        }
+    }
+
+    #[tracing::instrument(name = "Recording failed 2FA attempt in PostgreSQL", skip_all)]
+    async fn record_failed_attempt(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let login_attempt_id = to_uuid(login_attempt_id)?;
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+
+        let attempts = sqlx::query_scalar!(
+            r#"
+            UPDATE two_fa_codes SET attempts = attempts + 1
+            WHERE tenant_id = $1 AND login_attempt_id = $2 AND expires_at > now()
+            RETURNING attempts
+            "#,
+            tenant.as_ref(),
+            login_attempt_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?
+        .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        if attempts as u32 >= MAX_2FA_CODE_ATTEMPTS {
+            sqlx::query!(
+                "DELETE FROM two_fa_codes WHERE tenant_id = $1 AND login_attempt_id = $2",
+                tenant.as_ref(),
+                login_attempt_id,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Removing 2FA code from PostgreSQL", skip_all)]
+    async fn remove_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let result = sqlx::query!(
+            "DELETE FROM two_fa_codes WHERE tenant_id = $1 AND login_attempt_id = $2 AND expires_at > now()",
+            tenant.as_ref(),
+            to_uuid(login_attempt_id)?,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?;
+
+        // Another request finished the login first
+        if result.rows_affected() == 0 {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Getting 2FA code from PostgreSQL", skip_all)]
+    async fn get_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAChallenge, TwoFACodeStoreError> {
+        // The address as the user signed up with it, not its canonical form
+        let row = sqlx::query!(
+            r#"
+            SELECT u.email, c.code, c.client_binding, c.created_at, c.sent_at, c.resends,
+                   c.attempts
+            FROM two_fa_codes c
+            JOIN users u ON u.tenant_id = c.tenant_id AND u.email_normalized = c.email_normalized
+            WHERE c.tenant_id = $1 AND c.login_attempt_id = $2 AND c.expires_at > now()
+            "#,
+            tenant.as_ref(),
+            to_uuid(login_attempt_id)?,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| TwoFACodeStoreError::UnexpectedError(e.into()))?
+        .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        let email = Email::parse(SecretBox::new(Box::new(row.email)))
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let code = TwoFACode::parse(row.code)
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
+
+        Ok(TwoFAChallenge {
+            email,
+            code,
+            client_binding: row.client_binding,
+            created_at: row.created_at,
+            sent_at: row.sent_at,
+            resends: row.resends as u32,
+            attempts: row.attempts as u32,
+        })
+    }
+}
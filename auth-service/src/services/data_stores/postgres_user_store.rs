@@ -7,9 +7,12 @@ use argon2::{
 
 use sqlx::PgPool;
 
-use crate::domain::{Email, Password, User};
-use crate::services::data_stores::{UserStore, UserStoreError};
+use crate::domain::{Email, Password, PhoneNumber, TenantId, TwoFAChannel, User};
+use crate::services::data_stores::{
+    MAX_PHONE_VERIFICATION_ATTEMPTS, TwoFACode, UserStore, UserStoreError,
+};
 use argon2::password_hash::rand_core::OsRng;
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{Context, Result, eyre};
 use secrecy::{ExposeSecret, SecretBox};
 
@@ -17,6 +20,8 @@ pub struct DBUser {
     pub email: String,
     pub password_hash: String,
     pub requires_2fa: bool,
+    pub phone_number: Option<String>,
+    pub two_fa_channel: String,
 }
 pub struct PostgresUserStore {
     pool: PgPool,
@@ -31,14 +36,7 @@ impl PostgresUserStore {
 #[async_trait::async_trait]
 impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        // Check if user already exists
-        let _: Result<_, UserStoreError> = match self.get_user(&user.email).await {
-            Ok(_) => return Err(UserStoreError::UserAlreadyExists),
-            Err(UserStoreError::UserNotFound) => Ok(()),
-            Err(other_error) => return Err(other_error),
-        };
-
+    async fn add_user(&self, tenant: &TenantId, user: User) -> Result<(), UserStoreError> {
         // Hash the password before storing
         let password_hash = compute_password_hash(SecretBox::new(Box::new(
             user.password.as_ref().expose_secret().to_owned(),
@@ -46,27 +44,53 @@ impl UserStore for PostgresUserStore {
         .await
         .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?;
 
-        // Insert the new user
+        // Insert the new user. There is no separate existence check: the primary key on
+        // `(tenant_id, email_normalized)` decides atomically which of two concurrent signups wins.
         sqlx::query!(
-            "INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3)",
+            "INSERT INTO users (tenant_id, email, email_normalized, password_hash, requires_2fa) VALUES ($1, $2, $3, $4, $5)",
+            tenant.as_ref(),
             user.email.as_ref(),
+            user.email.normalized(),
             password_hash,
             user.requires_2fa,
         )
         .execute(&self.pool)
         .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Deleting user from PostgreSQL", skip_all)]
+    async fn delete_user(&self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError> {
+        // Whatever else is stored for the account goes with it, by ON DELETE CASCADE
+        let result = sqlx::query!(
+            "DELETE FROM users WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .execute(&self.pool)
+        .await
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
-        //.map_err(UserStoreError::UnexpectedError)?;
 
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
         Ok(())
     }
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError> {
         let user_maybe = sqlx::query_as!(
             DBUser,
-            "SELECT email, password_hash, requires_2fa FROM users WHERE email = $1",
-            email.as_ref()
+            "SELECT email, password_hash, requires_2fa, phone_number, two_fa_channel FROM users WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized()
         )
         .fetch_optional(&self.pool)
         .await
@@ -74,12 +98,22 @@ impl UserStore for PostgresUserStore {
 
         match user_maybe {
             Some(db_user) => {
-                let user = User::new(
-                    email.clone(),
+                let mut user = User::new(
+                    Email::parse(SecretBox::new(Box::new(db_user.email)))
+                        .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
                     Password::parse(SecretBox::new(Box::new(db_user.password_hash)))
                         .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
                     db_user.requires_2fa,
                 );
+                user.phone_number = db_user
+                    .phone_number
+                    .map(|phone_number| PhoneNumber::parse(&phone_number))
+                    .transpose()
+                    .map_err(UserStoreError::UnexpectedError)?;
+                user.two_fa_channel = db_user
+                    .two_fa_channel
+                    .parse()
+                    .map_err(UserStoreError::UnexpectedError)?;
                 Ok(user)
             }
             None => Err(UserStoreError::UserNotFound),
@@ -89,12 +123,14 @@ impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)]
     async fn validate_user(
         &self,
+        tenant: &TenantId,
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
         let user = sqlx::query!(
-            "SELECT password_hash FROM users WHERE email = $1",
-            email.as_ref()
+            "SELECT password_hash FROM users WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized()
         )
         .fetch_optional(&self.pool)
         .await
@@ -113,6 +149,174 @@ impl UserStore for PostgresUserStore {
             None => Err(UserStoreError::UserNotFound),
         }
     }
+
+    #[tracing::instrument(name = "Updating password in PostgreSQL", skip_all)]
+    async fn update_password(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: Password,
+    ) -> Result<(), UserStoreError> {
+        let password_hash = compute_password_hash(SecretBox::new(Box::new(
+            password.as_ref().expose_secret().to_owned(),
+        )))
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?;
+
+        let result = sqlx::query!(
+            "UPDATE users SET password_hash = $3 WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized(),
+            password_hash,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Starting phone verification in PostgreSQL", skip_all)]
+    async fn start_phone_verification(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        phone_number: &PhoneNumber,
+        code: &TwoFACode,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO phone_verifications (tenant_id, email_normalized, phone_number, code, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (tenant_id, email_normalized)
+            DO UPDATE SET phone_number = $3, code = $4, attempts = 0, expires_at = $5
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+            phone_number.as_ref(),
+            code.as_ref(),
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_error) if db_error.is_foreign_key_violation() => {
+                UserStoreError::UserNotFound
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Confirming phone verification in PostgreSQL", skip_all)]
+    async fn confirm_phone_verification(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<PhoneNumber, UserStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        // Lock the row so concurrent guesses are counted one after another
+        let pending = sqlx::query!(
+            r#"
+            SELECT phone_number, code, attempts, expires_at > now() AS "live!"
+            FROM phone_verifications
+            WHERE tenant_id = $1 AND email_normalized = $2
+            FOR UPDATE
+            "#,
+            tenant.as_ref(),
+            email.normalized(),
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::InvalidVerificationCode)?;
+
+        let matches = pending.live && pending.code == code.as_ref();
+        let attempts = pending.attempts + 1;
+
+        if matches {
+            sqlx::query!(
+                "UPDATE users SET phone_number = $3 WHERE tenant_id = $1 AND email_normalized = $2",
+                tenant.as_ref(),
+                email.normalized(),
+                pending.phone_number,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        }
+
+        if matches || !pending.live || attempts >= MAX_PHONE_VERIFICATION_ATTEMPTS {
+            sqlx::query!(
+                "DELETE FROM phone_verifications WHERE tenant_id = $1 AND email_normalized = $2",
+                tenant.as_ref(),
+                email.normalized(),
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        } else {
+            sqlx::query!(
+                "UPDATE phone_verifications SET attempts = $3 WHERE tenant_id = $1 AND email_normalized = $2",
+                tenant.as_ref(),
+                email.normalized(),
+                attempts,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if !matches {
+            return Err(UserStoreError::InvalidVerificationCode);
+        }
+        PhoneNumber::parse(&pending.phone_number).map_err(UserStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Setting 2FA channel in PostgreSQL", skip_all)]
+    async fn set_two_fa_channel(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        // The users_sms_requires_phone_number constraint rejects SMS without a verified number
+        let result = sqlx::query!(
+            "UPDATE users SET two_fa_channel = $3 WHERE tenant_id = $1 AND email_normalized = $2",
+            tenant.as_ref(),
+            email.normalized(),
+            channel.as_str(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_error) if db_error.is_check_violation() => {
+                UserStoreError::PhoneNumberNotVerified
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+        Ok(())
+    }
 }
 
 // Helper function to verify if a given password matches an expected hash
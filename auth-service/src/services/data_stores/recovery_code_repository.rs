@@ -0,0 +1,57 @@
+use crate::domain::{Email, RecoveryCode, TenantId};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use thiserror::Error;
+
+// This trait represents the interface all concrete recovery code stores should implement.
+// A user has at most one set of codes; each code can be used once.
+#[async_trait::async_trait]
+pub trait RecoveryCodeStore: Send + Sync {
+    // Replace the user's codes with `codes`, invalidating the previous set
+    async fn replace_codes(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        codes: &[RecoveryCode],
+    ) -> Result<(), RecoveryCodeStoreError>;
+    // Mark an unused code as used, recording who used it
+    async fn use_code(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        code: &RecoveryCode,
+        usage: &RecoveryCodeUsage,
+    ) -> Result<(), RecoveryCodeStoreError>;
+    // How many codes of the current set are still unused
+    async fn remaining_codes(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<i64, RecoveryCodeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum RecoveryCodeStoreError {
+    #[error("Invalid recovery code")]
+    InvalidCode,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RecoveryCodeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::InvalidCode, Self::InvalidCode)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Where and when a recovery code was used, kept for auditing
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCodeUsage {
+    pub used_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
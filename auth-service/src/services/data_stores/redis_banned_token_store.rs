@@ -1,29 +1,52 @@
-use redis::{Commands, Connection, RedisResult};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use redis::{AsyncCommands, aio::ConnectionManager};
 
 use crate::{
+    domain::TenantId,
     services::data_stores::{BannedTokenStore, BannedTokenStoreError},
     utils::auth::TOKEN_TTL_SECONDS,
 };
 use color_eyre::eyre::{Context, Result};
 
+// `ConnectionManager` is a cheaply cloneable, multiplexed connection that reconnects on its own,
+// so no lock is needed around it.
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+    pub fn new(conn: ConnectionManager) -> Self {
         Self { conn }
     }
+
+    // Every revocation still in force, with the tenant it belongs to
+    #[tracing::instrument(name = "Listing Tokens In Keystore Cache", skip_all)]
+    pub async fn all_tokens(&self) -> Result<Vec<(TenantId, String)>, BannedTokenStoreError> {
+        let mut conn = self.conn.clone();
+        let mut keys = conn
+            .scan_match::<_, String>(format!("{}*", BANNED_TOKEN_KEY_PREFIX))
+            .await
+            .wrap_err("failed to scan banned tokens in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        let mut tokens = Vec::new();
+        while let Some(key) = keys.next_item().await {
+            let key = key
+                .wrap_err("failed to scan banned tokens in Redis")
+                .map_err(BannedTokenStoreError::UnexpectedError)?;
+            if let Some(token) = parse_key(&key) {
+                tokens.push(token);
+            }
+        }
+        Ok(tokens)
+    }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "Adding Token To Keystore Cache", skip_all)]
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
+    async fn add_token(&self, tenant: &TenantId, jti: String) -> Result<(), BannedTokenStoreError> {
         // 1. Create a new key using the get_key helper function.
-        let token_key = get_key(&token);
+        let token_key = get_key(tenant, &jti);
         let value = true;
         // 2. Call the set_ex command on the Redis connection to set a new key/value pair with an expiration time (TTL).
         // The value should simply be a `true` (boolean value).
@@ -37,43 +60,28 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
         let _: () = self
             .conn
-            .write()
-            .await
+            .clone()
             .set_ex(&token_key, value, ttl)
+            .await
             .wrap_err("failed to set banned token in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
         Ok(())
     }
 
-    #[tracing::instrument(name = "Getting Token From Keystore Cache", skip_all)]
-    async fn get_token(&self, token: &str) -> Result<String, BannedTokenStoreError> {
-        // 1. Create a new key using the get_key helper function.
-        let key = get_key(token);
-        // 2. Call the get command on the Redis connection to get the value of the key.
-        let result: RedisResult<Option<String>> = self.conn.write().await.get(key);
-        if let Err(e) = result {
-            return Err(BannedTokenStoreError::UnexpectedError(e.into()));
-        }
-        // 3. Return the value as a &str.
-        // 4. Return BannedTokenStoreError::TokenNotFound if the key does not exist.
-        // 5. Return BannedTokenStoreError::UnexpectedError if the call to get fails.
-        match result {
-            Ok(Some(value)) => Ok(value),
-            Ok(None) => Err(BannedTokenStoreError::TokenNotFound),
-            Err(e) => Err(BannedTokenStoreError::UnexpectedError(e.into())),
-        }
-    }
-
     #[tracing::instrument(name = "Checking If Token In Keystore Cache", skip_all)]
-    async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
+    async fn contains_token(
+        &self,
+        tenant: &TenantId,
+        jti: &str,
+    ) -> Result<bool, BannedTokenStoreError> {
         // Check if the token exists by calling the exists method on the Redis connection
-        let token_key = get_key(token);
+        let token_key = get_key(tenant, jti);
 
         let is_banned: bool = self
             .conn
-            .write()
-            .await
+            .clone()
             .exists(&token_key)
+            .await
             .wrap_err("failed to check if token exists in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
 
@@ -82,8 +90,15 @@ impl BannedTokenStore for RedisBannedTokenStore {
 }
 
 // We are using a key prefix to prevent collisions and organize data!
+// Keys also carry the tenant, so tenants never see each other's entries.
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
 
-fn get_key(token: &str) -> String {
-    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
+fn get_key(tenant: &TenantId, jti: &str) -> String {
+    format!("{}{}:{}", BANNED_TOKEN_KEY_PREFIX, tenant, jti)
+}
+
+// Tenant IDs cannot contain a colon, so the first one ends the tenant
+fn parse_key(key: &str) -> Option<(TenantId, String)> {
+    let (tenant, jti) = key.strip_prefix(BANNED_TOKEN_KEY_PREFIX)?.split_once(':')?;
+    Some((TenantId::parse(tenant).ok()?, jti.to_owned()))
 }
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Context;
+use redis::{AsyncCommands, aio::ConnectionManager};
+use secrecy::SecretBox;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{Email, TenantId},
+    services::data_stores::{
+        MAGIC_LINK_TTL_SECONDS, MagicLink, MagicLinkStore, MagicLinkStoreError,
+    },
+};
+
+pub struct RedisMagicLinkStore {
+    conn: ConnectionManager,
+}
+
+impl RedisMagicLinkStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl MagicLinkStore for RedisMagicLinkStore {
+    #[tracing::instrument(name = "Adding magic link to Redis", skip_all)]
+    async fn add_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+        link: &MagicLink,
+    ) -> Result<(), MagicLinkStoreError> {
+        let record = MagicLinkRecord {
+            email: link.email.as_ref().to_owned(),
+            browser_binding: link.browser_binding.clone(),
+            confirmation_code_hash: link.confirmation_code_hash.clone(),
+            requested_at: link.requested_at,
+            ip_address: link.ip_address.clone(),
+            user_agent: link.user_agent.clone(),
+        };
+        let serialized_record = serde_json::to_string(&record)
+            .wrap_err("failed to serialize magic link")
+            .map_err(MagicLinkStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .clone()
+            .set_ex(
+                get_key(tenant, id),
+                serialized_record,
+                MAGIC_LINK_TTL_SECONDS,
+            )
+            .await
+            .wrap_err("failed to set magic link in Redis")
+            .map_err(MagicLinkStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Getting magic link from Redis", skip_all)]
+    async fn get_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+    ) -> Result<MagicLink, MagicLinkStoreError> {
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get(get_key(tenant, id))
+            .await
+            .wrap_err("failed to get magic link from Redis")
+            .map_err(MagicLinkStoreError::UnexpectedError)?;
+
+        parse_record(value)
+    }
+
+    #[tracing::instrument(name = "Consuming magic link from Redis", skip_all)]
+    async fn consume_link(
+        &self,
+        tenant: &TenantId,
+        id: &str,
+    ) -> Result<MagicLink, MagicLinkStoreError> {
+        // GETDEL is atomic, so two requests racing with the same link cannot both get it
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get_del(get_key(tenant, id))
+            .await
+            .wrap_err("failed to consume magic link in Redis")
+            .map_err(MagicLinkStoreError::UnexpectedError)?;
+
+        parse_record(value)
+    }
+}
+
+fn parse_record(value: Option<String>) -> Result<MagicLink, MagicLinkStoreError> {
+    let value = value.ok_or(MagicLinkStoreError::LinkNotFound)?;
+    let record: MagicLinkRecord = serde_json::from_str(&value)
+        .wrap_err("failed to deserialize magic link")
+        .map_err(MagicLinkStoreError::UnexpectedError)?;
+    let email = Email::parse(SecretBox::new(Box::new(record.email)))
+        .map_err(MagicLinkStoreError::UnexpectedError)?;
+
+    Ok(MagicLink {
+        email,
+        browser_binding: record.browser_binding,
+        confirmation_code_hash: record.confirmation_code_hash,
+        requested_at: record.requested_at,
+        ip_address: record.ip_address,
+        user_agent: record.user_agent,
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct MagicLinkRecord {
+    email: String,
+    browser_binding: String,
+    // Missing from links stored by earlier versions, which then only sign in the requesting browser
+    #[serde(default)]
+    confirmation_code_hash: String,
+    requested_at: DateTime<Utc>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+const MAGIC_LINK_PREFIX: &str = "magic_link:";
+
+fn get_key(tenant: &TenantId, id: &str) -> String {
+    format!("{}{}:{}", MAGIC_LINK_PREFIX, tenant, id)
+}
@@ -1,64 +1,190 @@
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{Context, Result, eyre};
-use redis::{Commands, Connection};
+use redis::{AsyncCommands, Script, aio::ConnectionManager};
+use secrecy::SecretBox;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::RwLock;
 
 use crate::{
-    domain::Email,
-    services::data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    domain::{Email, TenantId},
+    services::data_stores::{
+        LoginAttemptId, MAX_2FA_CODE_ATTEMPTS, MAX_PENDING_2FA_CHALLENGES, TWO_FA_CODE_TTL_SECONDS,
+        TwoFAChallenge, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+    },
 };
 
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+    pub fn new(conn: ConnectionManager) -> Self {
         Self { conn }
     }
+
+    // Drop the oldest pending challenges of the account beyond the limit, and forget the
+    // login attempts whose challenge has expired
+    async fn enforce_limit(&self, tenant: &TenantId, email: &Email) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let index_key = get_index_key(tenant, email);
+        let ids: Vec<String> = conn
+            .smembers(&index_key)
+            .await
+            .wrap_err("failed to list pending 2FA logins in Redis")?;
+
+        let mut pending = Vec::with_capacity(ids.len());
+        for id in ids {
+            let value: Option<String> = conn
+                .get(get_key(tenant, &id))
+                .await
+                .wrap_err("failed to get 2FA code from Redis")?;
+            match value {
+                Some(value) => pending.push((parse_record(&value)?.created_at, id)),
+                None => {
+                    let _: () = conn
+                        .srem(&index_key, &id)
+                        .await
+                        .wrap_err("failed to forget an expired 2FA login in Redis")?;
+                }
+            }
+        }
+
+        pending.sort();
+        let excess = pending.len().saturating_sub(MAX_PENDING_2FA_CHALLENGES);
+        for (_, id) in pending.into_iter().take(excess) {
+            let _: () = conn
+                .del(get_key(tenant, &id))
+                .await
+                .wrap_err("failed to delete 2FA code from Redis")?;
+            let _: () = conn
+                .srem(&index_key, &id)
+                .await
+                .wrap_err("failed to forget a 2FA login in Redis")?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "Adding Code From Code Cache", skip_all)]
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
+        tenant: &TenantId,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        challenge: TwoFAChallenge,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
-
-        let data = TwoFATuple(
-            login_attempt_id.as_ref().to_owned(),
-            code.as_ref().to_owned(),
-        );
-        let serialized_data = serde_json::to_string(&data)
-            .wrap_err("failed to serialize 2FA tuple")
-            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let serialized_record =
+            serialize_record(&challenge).map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        let _: () = self
-            .conn
-            .write()
+        let mut conn = self.conn.clone();
+        let _: () = conn
+            .set_ex(
+                get_key(tenant, login_attempt_id.as_ref()),
+                serialized_record,
+                TWO_FA_CODE_TTL_SECONDS,
+            )
             .await
-            .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
             .wrap_err("failed to set 2FA code in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
+        // The index outlives each of its logins, and expires with the last one
+        let index_key = get_index_key(tenant, &challenge.email);
+        let _: () = conn
+            .sadd(&index_key, login_attempt_id.as_ref())
+            .await
+            .wrap_err("failed to index 2FA login in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(&index_key, TWO_FA_CODE_TTL_SECONDS as i64)
+            .await
+            .wrap_err("failed to set expiry on 2FA login index in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        self.enforce_limit(tenant, &challenge.email)
+            .await
+            .map_err(TwoFACodeStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Updating Code In Code Cache", skip_all)]
+    async fn update_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+        sent_at: DateTime<Utc>,
+        challenge: TwoFAChallenge,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let serialized_record =
+            serialize_record(&challenge).map_err(TwoFACodeStoreError::UnexpectedError)?;
+        // Serialized like the record's field, so the script can compare the strings
+        let expected_sent_at = serde_json::to_value(sent_at)
+            .wrap_err("failed to serialize 2FA challenge")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let expected_sent_at = expected_sent_at.as_str().unwrap_or_default();
+
+        let mut conn = self.conn.clone();
+        let updated: i64 = Script::new(UPDATE_CODE_SCRIPT)
+            .key(get_key(tenant, login_attempt_id.as_ref()))
+            .arg(serialized_record)
+            .arg(expected_sent_at)
+            .invoke_async(&mut conn)
+            .await
+            .wrap_err("failed to update 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        match updated {
+            1 => Ok(()),
+            // A login that expired or was finished in the meantime stays gone
+            0 => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            _ => Err(TwoFACodeStoreError::ChallengeChanged),
+        }
+    }
+
+    #[tracing::instrument(name = "Recording Failed Attempt In Code Cache", skip_all)]
+    async fn record_failed_attempt(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        // A dropped login stays in the account's index until enforce_limit forgets it
+        let mut conn = self.conn.clone();
+        let recorded: i64 = Script::new(RECORD_FAILED_ATTEMPT_SCRIPT)
+            .key(get_key(tenant, login_attempt_id.as_ref()))
+            .arg(MAX_2FA_CODE_ATTEMPTS)
+            .invoke_async(&mut conn)
+            .await
+            .wrap_err("failed to record a failed 2FA attempt in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if recorded == 0 {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
         Ok(())
     }
 
     #[tracing::instrument(name = "Removing Code From Code Cache", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(email);
+    async fn remove_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        // GETDEL is atomic, so only one request can finish the login
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get_del(get_key(tenant, login_attempt_id.as_ref()))
+            .await
+            .wrap_err("failed to delete 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let value = value.ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        let challenge = parse_record(&value).map_err(TwoFACodeStoreError::UnexpectedError)?;
 
         let _: () = self
             .conn
-            .write()
+            .clone()
+            .srem(
+                get_index_key(tenant, &challenge.email),
+                login_attempt_id.as_ref(),
+            )
             .await
-            .del(&key)
-            .wrap_err("failed to delete 2FA code from Redis")
+            .wrap_err("failed to forget a 2FA login in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
         Ok(())
@@ -67,35 +193,115 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "Getting Code From Code Cache", skip_all)]
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = get_key(email);
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAChallenge, TwoFACodeStoreError> {
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get(get_key(tenant, login_attempt_id.as_ref()))
+            .await
+            .wrap_err("failed to get 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        match self.conn.write().await.get::<_, String>(&key) {
-            Ok(value) => {
-                let data: TwoFATuple = serde_json::from_str(&value)
-                    .wrap_err("failed to deserialize 2FA tuple")
-                    .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        match value {
+            Some(value) => parse_record(&value).map_err(TwoFACodeStoreError::UnexpectedError),
+            None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+}
 
-                let login_attempt_id = LoginAttemptId::parse(data.0)
-                    .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
+fn serialize_record(challenge: &TwoFAChallenge) -> Result<String> {
+    let record = TwoFARecord {
+        email: challenge.email.as_ref().to_owned(),
+        code: challenge.code.as_ref().to_owned(),
+        client_binding: challenge.client_binding.clone(),
+        created_at: challenge.created_at,
+        sent_at: Some(challenge.sent_at),
+        resends: challenge.resends,
+        attempts: challenge.attempts,
+    };
+    serde_json::to_string(&record).wrap_err("failed to serialize 2FA challenge")
+}
 
-                let email_code = TwoFACode::parse(data.1)
-                    .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
+fn parse_record(value: &str) -> Result<TwoFAChallenge> {
+    let record: TwoFARecord =
+        serde_json::from_str(value).wrap_err("failed to deserialize 2FA challenge")?;
+    let email = Email::parse(SecretBox::new(Box::new(record.email)))?;
+    let code = TwoFACode::parse(record.code).map_err(|e| eyre!(e))?;
 
-                Ok((login_attempt_id, email_code))
-            }
-            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
-        }
-    }
+    Ok(TwoFAChallenge {
+        email,
+        code,
+        client_binding: record.client_binding,
+        created_at: record.created_at,
+        sent_at: record.sent_at.unwrap_or(record.created_at),
+        resends: record.resends,
+        attempts: record.attempts,
+    })
 }
 
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct TwoFARecord {
+    email: String,
+    code: String,
+    client_binding: String,
+    created_at: DateTime<Utc>,
+    // Missing from challenges stored before codes could be sent again
+    #[serde(default)]
+    sent_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    resends: u32,
+    #[serde(default)]
+    attempts: u32,
+}
+
+// Compare and set for update_code, atomic as Redis runs scripts one at a time. Returns 1 if the
+// challenge was replaced, 0 if the login is gone, and -1 if its code was sent again meanwhile.
+// Wrong codes entered in the meantime still count, and the login keeps its expiry.
+const UPDATE_CODE_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if not value then
+    return 0
+end
+local record = cjson.decode(value)
+local sent_at = record['sent_at']
+if sent_at == nil or sent_at == cjson.null then
+    sent_at = record['created_at']
+end
+if sent_at ~= ARGV[2] then
+    return -1
+end
+local challenge = cjson.decode(ARGV[1])
+challenge['attempts'] = record['attempts'] or 0
+redis.call('SET', KEYS[1], cjson.encode(challenge), 'KEEPTTL')
+return 1
+"#;
+
+// Count a wrong code, and drop the login at ARGV[1] of them. Returns 0 if the login is gone.
+const RECORD_FAILED_ATTEMPT_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if not value then
+    return 0
+end
+local record = cjson.decode(value)
+record['attempts'] = (record['attempts'] or 0) + 1
+if record['attempts'] >= tonumber(ARGV[1]) then
+    redis.call('DEL', KEYS[1])
+else
+    redis.call('SET', KEYS[1], cjson.encode(record), 'KEEPTTL')
+end
+return 1
+"#;
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+// The pending login attempts of an account
+const TWO_FA_LOGINS_PREFIX: &str = "two_fa_logins:";
+
+fn get_key(tenant: &TenantId, login_attempt_id: &str) -> String {
+    format!("{}{}:{}", TWO_FA_CODE_PREFIX, tenant, login_attempt_id)
+}
 
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref())
+fn get_index_key(tenant: &TenantId, email: &Email) -> String {
+    format!("{}{}:{}", TWO_FA_LOGINS_PREFIX, tenant, email.normalized())
 }
@@ -0,0 +1,60 @@
+use crate::domain::{Email, RememberMeToken, TenantId};
+use crate::utils::client::ClientInfo;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use thiserror::Error;
+
+// This trait represents the interface all concrete "remember me" token stores should implement.
+// A token signs its browser back in until it expires, is revoked, or its account's password changes.
+#[async_trait::async_trait]
+pub trait RememberMeStore: Send + Sync {
+    // Store a new token for the account, recording the device it was issued to
+    async fn add_token(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        token: &RememberMeToken,
+        expires_at: DateTime<Utc>,
+        client: &ClientInfo,
+    ) -> Result<(), RememberMeStoreError>;
+    // Replace the token's secret with the one of `rotated`, returning the account and when the
+    // token expires. Presenting a secret that was already replaced revokes the token: the cookie
+    // was copied, and either copy may be the attacker's. So does presenting it from a client with
+    // another user agent than the one it was issued to.
+    async fn rotate_token(
+        &self,
+        tenant: &TenantId,
+        token: &RememberMeToken,
+        rotated: &RememberMeToken,
+        client: &ClientInfo,
+    ) -> Result<(Email, DateTime<Utc>), RememberMeStoreError>;
+    async fn revoke_token(
+        &self,
+        tenant: &TenantId,
+        token: &RememberMeToken,
+    ) -> Result<(), RememberMeStoreError>;
+    // Revoke every token of the account, e.g. after a password change
+    async fn revoke_all(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), RememberMeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum RememberMeStoreError {
+    #[error("Invalid remember me token")]
+    InvalidToken,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RememberMeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::InvalidToken, Self::InvalidToken)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
@@ -0,0 +1,22 @@
+use color_eyre::eyre::{Report, Result, eyre};
+use std::str::FromStr;
+
+// Where revoked tokens and pending 2FA codes are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreBackend {
+    Redis,
+    // For deployments that run without Redis
+    Postgres,
+}
+
+impl FromStr for StoreBackend {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "redis" => Ok(Self::Redis),
+            "postgres" => Ok(Self::Postgres),
+            _ => Err(eyre!("Unknown store backend: {}", s)),
+        }
+    }
+}
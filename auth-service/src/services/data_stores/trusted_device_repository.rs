@@ -0,0 +1,74 @@
+use crate::domain::{Email, TenantId};
+use crate::utils::client::ClientInfo;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use thiserror::Error;
+use uuid::Uuid;
+
+// This trait represents the interface all concrete trusted device stores should implement.
+// A trusted device skips 2FA until it expires, is revoked, or its account's password changes.
+#[async_trait::async_trait]
+pub trait TrustedDeviceStore: Send + Sync {
+    // Trust a new device for the account, recording what it was
+    async fn add_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+        expires_at: DateTime<Utc>,
+        client: &ClientInfo,
+    ) -> Result<(), TrustedDeviceStoreError>;
+    // Record a login from the device. Fails with DeviceNotFound if the device is no longer
+    // trusted for the account.
+    async fn use_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), TrustedDeviceStoreError>;
+    // The account's trusted devices, most recently trusted first
+    async fn list_devices(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError>;
+    async fn revoke_device(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), TrustedDeviceStoreError>;
+    // Revoke every device of the account, e.g. after a password change
+    async fn revoke_all(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), TrustedDeviceStoreError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedDevice {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub client: ClientInfo,
+}
+
+#[derive(Debug, Error)]
+pub enum TrustedDeviceStoreError {
+    #[error("Trusted device not found")]
+    DeviceNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for TrustedDeviceStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::DeviceNotFound, Self::DeviceNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
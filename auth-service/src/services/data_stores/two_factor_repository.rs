@@ -1,29 +1,80 @@
-use crate::domain::Email;
+use crate::domain::{Email, TenantId};
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{Context, Report, Result, eyre};
 use rand::RngExt;
 use thiserror::Error;
 use uuid::Uuid;
 
-// This trait represents the interface all concrete 2FA code stores should implement
+// How long a 2FA code stays valid after it is issued
+pub const TWO_FA_CODE_TTL_SECONDS: u64 = 600;
+// How many logins of an account can wait for their 2FA code at once, e.g. on a laptop and a phone
+pub const MAX_PENDING_2FA_CHALLENGES: usize = 5;
+// How many wrong codes a pending login takes before it is dropped, and has to be started again
+pub const MAX_2FA_CODE_ATTEMPTS: u32 = 5;
+
+// This trait represents the interface all concrete 2FA code stores should implement.
+// Pending logins are keyed by their login attempt, so each login of an account has its own code.
 #[async_trait::async_trait]
 pub trait TwoFACodeStore: Send + Sync {
+    // Store the challenge of a new login. Beyond MAX_PENDING_2FA_CHALLENGES for the account,
+    // the oldest pending challenges are dropped.
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
+        tenant: &TenantId,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        challenge: TwoFAChallenge,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Replace the challenge of a pending login, e.g. with a new code when it is sent again, if
+    // its code was still the one sent at `sent_at`. Of two requests that read the same challenge,
+    // only one can replace it; the other gets ChallengeChanged. The login still expires
+    // TWO_FA_CODE_TTL_SECONDS after it was started, however often its code is sent.
+    async fn update_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+        sent_at: DateTime<Utc>,
+        challenge: TwoFAChallenge,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Count a wrong code against a pending login, and drop the login once it has taken
+    // MAX_2FA_CODE_ATTEMPTS of them. The count survives its code being sent again.
+    async fn record_failed_attempt(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAChallenge, TwoFACodeStoreError>;
+}
+
+// A login waiting for its 2FA code
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoFAChallenge {
+    pub email: Email,
+    pub code: TwoFACode,
+    // SHA-256 of the 2FA cookie of the client that started the login; only it can finish it
+    pub client_binding: String,
+    pub created_at: DateTime<Utc>,
+    // When the current code was sent, and how many times the login's code was sent again
+    pub sent_at: DateTime<Utc>,
+    pub resends: u32,
+    // Wrong codes entered so far
+    pub attempts: u32,
 }
 
 #[derive(Debug, Error)]
 pub enum TwoFACodeStoreError {
     #[error("Login AttemptId Not Found")]
     LoginAttemptIdNotFound,
+    #[error("2FA challenge was changed by another request")]
+    ChallengeChanged,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -33,6 +84,7 @@ impl PartialEq for TwoFACodeStoreError {
         matches!(
             (self, other),
             (Self::LoginAttemptIdNotFound, Self::LoginAttemptIdNotFound)
+                | (Self::ChallengeChanged, Self::ChallengeChanged)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
@@ -1,14 +1,55 @@
-use crate::domain::{Email, Password, User};
+use super::TwoFACode;
+use crate::domain::{Email, Password, PhoneNumber, TenantId, TwoFAChannel, User};
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
+// Wrong codes allowed before a pending phone verification is discarded and has to be restarted
+pub const MAX_PHONE_VERIFICATION_ATTEMPTS: i32 = 5;
+
 #[async_trait::async_trait]
 pub trait UserStore: Send + Sync {
-    // Make sure all methods are async so we can use async user stores in the future
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
-    async fn validate_user(&self, email: &Email, password: &Password)
-    -> Result<(), UserStoreError>;
+    // Make sure all methods are async so we can use async user stores in the future.
+    // Every method is scoped to a tenant; the same email can belong to a different user in another tenant.
+    async fn add_user(&self, tenant: &TenantId, user: User) -> Result<(), UserStoreError>;
+    // Delete the account and everything stored for it, e.g. to undo a signup that failed halfway
+    async fn delete_user(&self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError>;
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError>;
+    async fn validate_user(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError>;
+    async fn update_password(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: Password,
+    ) -> Result<(), UserStoreError>;
+    // Remember `phone_number` as awaiting confirmation with `code`, replacing any earlier attempt
+    async fn start_phone_verification(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        phone_number: &PhoneNumber,
+        code: &TwoFACode,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError>;
+    // Make the pending number the user's phone number if `code` matches
+    async fn confirm_phone_verification(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<PhoneNumber, UserStoreError>;
+    // Fails with `PhoneNumberNotVerified` when choosing SMS without a verified phone number
+    async fn set_two_fa_channel(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -19,6 +60,10 @@ pub enum UserStoreError {
     UserNotFound,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Invalid verification code")]
+    InvalidVerificationCode,
+    #[error("Phone number not verified")]
+    PhoneNumberNotVerified,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -30,6 +75,8 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::InvalidVerificationCode, Self::InvalidVerificationCode)
+                | (Self::PhoneNumberNotVerified, Self::PhoneNumberNotVerified)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::domain::{Email, EmailClient, EmailMessage};
+
+// An email captured by the dev mailbox instead of being delivered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailboxMessage {
+    pub id: Uuid,
+    // Missing from messages captured before tenants had their own senders
+    #[serde(default)]
+    pub sender: String,
+    pub recipient: String,
+    pub template_id: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+enum MailboxStorage {
+    // The most recent messages, oldest first
+    Memory {
+        messages: Mutex<VecDeque<MailboxMessage>>,
+        capacity: usize,
+    },
+    // One JSON file per message, so captured mail survives restarts
+    Directory(PathBuf),
+}
+
+// An email client for local development that keeps outgoing mail instead of sending it.
+// Captured messages are listed at /dev/mailbox.
+pub struct DevMailbox {
+    storage: MailboxStorage,
+}
+
+impl DevMailbox {
+    // Keep up to `capacity` messages in memory, dropping the oldest ones first
+    pub fn in_memory(capacity: usize) -> Self {
+        Self {
+            storage: MailboxStorage::Memory {
+                messages: Mutex::new(VecDeque::with_capacity(capacity)),
+                capacity,
+            },
+        }
+    }
+
+    // Write messages to `dir`, creating it if needed
+    pub fn in_directory(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .wrap_err_with(|| format!("Failed to create mailbox directory {}", dir.display()))?;
+        Ok(Self {
+            storage: MailboxStorage::Directory(dir),
+        })
+    }
+
+    // Captured messages, newest first
+    pub async fn messages(&self) -> Result<Vec<MailboxMessage>> {
+        match &self.storage {
+            MailboxStorage::Memory { messages, .. } => {
+                let messages = messages.lock().expect("mailbox lock poisoned");
+                Ok(messages.iter().rev().cloned().collect())
+            }
+            MailboxStorage::Directory(dir) => {
+                let mut messages = Vec::new();
+                let mut entries = tokio::fs::read_dir(dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path.extension().is_none_or(|extension| extension != "json") {
+                        continue;
+                    }
+                    let contents = tokio::fs::read(&path).await?;
+                    match serde_json::from_slice::<MailboxMessage>(&contents) {
+                        Ok(message) => messages.push(message),
+                        Err(e) => {
+                            tracing::warn!(
+                                "Skipping unreadable mailbox file {}: {}",
+                                path.display(),
+                                e
+                            )
+                        }
+                    }
+                }
+                messages.sort_by_key(|message| std::cmp::Reverse(message.sent_at));
+                Ok(messages)
+            }
+        }
+    }
+
+    async fn store(&self, message: MailboxMessage) -> Result<()> {
+        match &self.storage {
+            MailboxStorage::Memory { messages, capacity } => {
+                let mut messages = messages.lock().expect("mailbox lock poisoned");
+                if messages.len() >= *capacity {
+                    messages.pop_front();
+                }
+                messages.push_back(message);
+            }
+            MailboxStorage::Directory(dir) => {
+                // Prefixing the timestamp keeps the files in delivery order in a directory listing
+                let path = dir.join(format!(
+                    "{}-{}.json",
+                    message.sent_at.format("%Y%m%dT%H%M%S%.6fZ"),
+                    message.id
+                ));
+                tokio::fs::write(&path, serde_json::to_vec_pretty(&message)?)
+                    .await
+                    .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for DevMailbox {
+    #[tracing::instrument(name = "Capturing email in dev mailbox", skip_all)]
+    async fn send_email(
+        &self,
+        sender: &Email,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<()> {
+        let rendered = message.render()?;
+        tracing::info!(
+            "Captured email from {} to {} with subject: {}",
+            sender.as_ref(),
+            recipient.as_ref(),
+            rendered.subject
+        );
+
+        self.store(MailboxMessage {
+            id: Uuid::new_v4(),
+            sender: sender.as_ref().to_owned(),
+            recipient: recipient.as_ref().to_owned(),
+            template_id: message.template_id().to_owned(),
+            subject: rendered.subject,
+            html_body: rendered.html_body,
+            text_body: rendered.text_body,
+            sent_at: Utc::now(),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use secrecy::SecretBox;
+
+    fn recipient(address: &str) -> Email {
+        Email::parse(SecretBox::new(Box::new(address.to_owned()))).unwrap()
+    }
+
+    fn two_fa_code(code: &str) -> EmailMessage {
+        EmailMessage::TwoFACode {
+            code: code.to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_mailbox_keeps_the_newest_messages() {
+        let mailbox = DevMailbox::in_memory(2);
+        for code in ["111111", "222222", "333333"] {
+            mailbox
+                .send_email(
+                    &recipient("no-reply@example.com"),
+                    &recipient("user@example.com"),
+                    &two_fa_code(code),
+                )
+                .await
+                .unwrap();
+        }
+
+        let messages = mailbox.messages().await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].text_body.contains("333333"));
+        assert!(messages[1].text_body.contains("222222"));
+        assert_eq!(messages[0].sender, "no-reply@example.com");
+        assert_eq!(messages[0].recipient, "user@example.com");
+        assert_eq!(messages[0].template_id, "two-fa-code");
+    }
+
+    #[tokio::test]
+    async fn test_directory_mailbox_persists_messages_across_instances() {
+        let dir = std::env::temp_dir().join(format!("dev-mailbox-{}", Uuid::new_v4()));
+
+        let mailbox = DevMailbox::in_directory(&dir).unwrap();
+        for code in ["111111", "222222"] {
+            mailbox
+                .send_email(
+                    &recipient("no-reply@example.com"),
+                    &recipient("user@example.com"),
+                    &two_fa_code(code),
+                )
+                .await
+                .unwrap();
+        }
+
+        let messages = DevMailbox::in_directory(&dir)
+            .unwrap()
+            .messages()
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].text_body.contains("222222"));
+        assert!(messages[1].html_body.contains("111111"));
+    }
+}
@@ -0,0 +1,254 @@
+use chrono::{TimeDelta, Utc};
+use color_eyre::eyre::Result;
+use rand::RngExt;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use crate::app_state::{EmailClientType, EmailOutboxType};
+use crate::services::TenantRegistry;
+use crate::services::data_stores::OutboxMessage;
+use crate::utils::constants::{
+    EMAIL_OUTBOX_BASE_BACKOFF, EMAIL_OUTBOX_BATCH_SIZE, EMAIL_OUTBOX_LEASE,
+    EMAIL_OUTBOX_MAX_ATTEMPTS, EMAIL_OUTBOX_MAX_BACKOFF, EMAIL_OUTBOX_POLL_INTERVAL,
+};
+
+// Counters for deliveries made by this process, reported by the admin outbox endpoint
+pub static OUTBOX_METRICS: OutboxMetrics = OutboxMetrics::new();
+
+pub struct OutboxMetrics {
+    sent: AtomicU64,
+    retried: AtomicU64,
+    dead_lettered: AtomicU64,
+    expired: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMetricsSnapshot {
+    pub sent: u64,
+    pub retried: u64,
+    pub dead_lettered: u64,
+    pub expired: u64,
+}
+
+impl OutboxMetrics {
+    const fn new() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+            dead_lettered: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> OutboxMetricsSnapshot {
+        OutboxMetricsSnapshot {
+            sent: self.sent.load(Ordering::Relaxed),
+            retried: self.retried.load(Ordering::Relaxed),
+            dead_lettered: self.dead_lettered.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailOutboxWorkerConfig {
+    pub poll_interval: Duration,
+    pub batch_size: u32,
+    // Delivery attempts before a message is dead-lettered
+    pub max_attempts: i32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    // How long a claimed message stays hidden from other workers
+    pub lease: Duration,
+}
+
+impl Default for EmailOutboxWorkerConfig {
+    // The settings configured for this deployment
+    fn default() -> Self {
+        Self {
+            poll_interval: *EMAIL_OUTBOX_POLL_INTERVAL,
+            batch_size: *EMAIL_OUTBOX_BATCH_SIZE,
+            max_attempts: *EMAIL_OUTBOX_MAX_ATTEMPTS,
+            base_backoff: *EMAIL_OUTBOX_BASE_BACKOFF,
+            max_backoff: *EMAIL_OUTBOX_MAX_BACKOFF,
+            lease: *EMAIL_OUTBOX_LEASE,
+        }
+    }
+}
+
+// Delivers messages from the email outbox, retrying failures with exponential backoff
+pub struct EmailOutboxWorker {
+    outbox: EmailOutboxType,
+    email_client: EmailClientType,
+    // Each message goes out with the sender address of the tenant that enqueued it
+    tenants: Arc<TenantRegistry>,
+    config: EmailOutboxWorkerConfig,
+}
+
+impl EmailOutboxWorker {
+    pub fn new(
+        outbox: EmailOutboxType,
+        email_client: EmailClientType,
+        tenants: Arc<TenantRegistry>,
+        config: EmailOutboxWorkerConfig,
+    ) -> Self {
+        Self {
+            outbox,
+            email_client,
+            tenants,
+            config,
+        }
+    }
+
+    // Poll the outbox until the task is aborted
+    pub async fn run(self) {
+        tracing::info!("email outbox worker started");
+        loop {
+            match self.process_due().await {
+                // A full batch means more messages are probably waiting, so skip the pause
+                Ok(count) if count == self.config.batch_size as usize => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!("email outbox worker failed to process messages: {:?}", e)
+                }
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    // Claim one batch of due messages and attempt to deliver each of them. Returns the batch size.
+    #[tracing::instrument(name = "Processing email outbox", skip_all)]
+    pub async fn process_due(&self) -> Result<usize> {
+        let messages = self
+            .outbox
+            .claim_due(self.config.batch_size, self.config.lease)
+            .await?;
+        let count = messages.len();
+
+        // Deliver concurrently, so one slow provider call does not hold up the rest of the batch
+        let mut deliveries = JoinSet::new();
+        for message in messages {
+            let outbox = self.outbox.clone();
+            let email_client = self.email_client.clone();
+            let tenants = self.tenants.clone();
+            let config = self.config.clone();
+            deliveries.spawn(async move {
+                deliver(outbox, email_client, &tenants, &config, message).await
+            });
+        }
+        for result in deliveries.join_all().await {
+            if let Err(e) = result {
+                tracing::error!("failed to record email outbox delivery: {:?}", e);
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+async fn deliver(
+    outbox: EmailOutboxType,
+    email_client: EmailClientType,
+    tenants: &TenantRegistry,
+    config: &EmailOutboxWorkerConfig,
+    entry: OutboxMessage,
+) -> Result<()> {
+    let template_id = entry.message.template_id();
+
+    if entry
+        .message
+        .expires_at()
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        tracing::warn!(id = %entry.id, template_id, "email expired before it could be delivered");
+        OUTBOX_METRICS.expired.fetch_add(1, Ordering::Relaxed);
+        outbox.mark_expired(entry.id).await?;
+        return Ok(());
+    }
+
+    if entry.attempts > config.max_attempts {
+        // Only reachable when a worker stopped while holding the message on its final attempt
+        OUTBOX_METRICS.dead_lettered.fetch_add(1, Ordering::Relaxed);
+        outbox
+            .mark_dead(entry.id, "exceeded the maximum number of attempts")
+            .await?;
+        return Ok(());
+    }
+
+    // Retrying cannot bring back a tenant that was removed from the configuration
+    let Some(tenant) = tenants.get(&entry.tenant_id) else {
+        tracing::error!(id = %entry.id, template_id, tenant = %entry.tenant_id, "dead-lettering email for an unknown tenant");
+        OUTBOX_METRICS.dead_lettered.fetch_add(1, Ordering::Relaxed);
+        outbox
+            .mark_dead(entry.id, &format!("unknown tenant {}", entry.tenant_id))
+            .await?;
+        return Ok(());
+    };
+
+    match email_client
+        .send_email(&tenant.email_sender, &entry.recipient, &entry.message)
+        .await
+    {
+        Ok(()) => {
+            tracing::info!(id = %entry.id, template_id, attempts = entry.attempts, "email delivered");
+            OUTBOX_METRICS.sent.fetch_add(1, Ordering::Relaxed);
+            outbox.mark_sent(entry.id).await?;
+        }
+        Err(e) if entry.attempts >= config.max_attempts => {
+            tracing::error!(id = %entry.id, template_id, attempts = entry.attempts, "dead-lettering email: {:?}", e);
+            OUTBOX_METRICS.dead_lettered.fetch_add(1, Ordering::Relaxed);
+            outbox.mark_dead(entry.id, &format!("{:#}", e)).await?;
+        }
+        Err(e) => {
+            let delay = backoff(config, entry.attempts);
+            tracing::warn!(id = %entry.id, template_id, attempts = entry.attempts, ?delay, "email delivery failed, will retry: {:?}", e);
+            OUTBOX_METRICS.retried.fetch_add(1, Ordering::Relaxed);
+            let next_attempt_at = Utc::now() + TimeDelta::from_std(delay)?;
+            outbox
+                .schedule_retry(entry.id, &format!("{:#}", e), next_attempt_at)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// Exponential backoff with full jitter: a random delay up to base * 2^(attempts - 1), capped at max_backoff
+fn backoff(config: &EmailOutboxWorkerConfig, attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    let ceiling = config
+        .base_backoff
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(config.max_backoff);
+    let millis = u64::try_from(ceiling.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rand::rng().random_range(0..=millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> EmailOutboxWorkerConfig {
+        EmailOutboxWorkerConfig {
+            poll_interval: Duration::from_millis(10),
+            batch_size: 10,
+            max_attempts: 5,
+            base_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            lease: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_up_to_the_cap() {
+        let config = config();
+        for (attempts, ceiling) in [(1, 1), (2, 2), (3, 4), (4, 8), (5, 10), (50, 10)] {
+            for _ in 0..100 {
+                assert!(backoff(&config, attempts) <= Duration::from_secs(ceiling));
+            }
+        }
+    }
+}
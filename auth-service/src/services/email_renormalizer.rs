@@ -0,0 +1,123 @@
+use color_eyre::eyre::{Context, Result, bail};
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::domain::{Email, PlusTagPolicy};
+
+// Recomputes `users.email_normalized` from `users.email`, for rows written before the canonical
+// form last changed (e.g. the plain `lower(email)` of earlier versions). Run at startup, after
+// the migrations; rows that are already canonical are left alone.
+pub struct EmailRenormalizer {
+    pool: PgPool,
+    policy: PlusTagPolicy,
+}
+
+impl EmailRenormalizer {
+    pub fn new(pool: PgPool, policy: PlusTagPolicy) -> Self {
+        Self { pool, policy }
+    }
+
+    // Returns the number of users whose canonical email changed. Like the migration that added
+    // the column, refuses to change anything if two accounts of a tenant would end up with the
+    // same canonical email; they must be merged by hand first.
+    #[tracing::instrument(name = "Renormalizing user emails", skip_all)]
+    pub async fn run(&self) -> Result<u64> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .wrap_err("failed to start a transaction")?;
+        // Keep signups, including those of other instances, out until the new values are in
+        sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *transaction)
+            .await
+            .wrap_err("failed to lock the users table")?;
+
+        let users = sqlx::query!("SELECT tenant_id, email, email_normalized FROM users")
+            .fetch_all(&mut *transaction)
+            .await
+            .wrap_err("failed to load users")?;
+
+        let mut accounts: HashMap<(String, String), Vec<String>> = HashMap::new();
+        // The users whose canonical email changes, as columns to bind as arrays
+        let (mut tenant_ids, mut olds, mut news) = (Vec::new(), Vec::new(), Vec::new());
+        for user in users {
+            let normalized = match Email::parse_with_policy(
+                SecretBox::new(Box::new(user.email.clone())),
+                self.policy,
+            ) {
+                Ok(email) => email.normalized().to_owned(),
+                // Such accounts cannot sign in either way, so there is nothing to gain from failing
+                Err(e) => {
+                    tracing::warn!(
+                        tenant_id = %user.tenant_id,
+                        "keeping the stored canonical form of an email that no longer parses: {}",
+                        e
+                    );
+                    user.email_normalized.clone()
+                }
+            };
+            if normalized != user.email_normalized {
+                tenant_ids.push(user.tenant_id.clone());
+                olds.push(user.email_normalized);
+                news.push(normalized.clone());
+            }
+            accounts
+                .entry((user.tenant_id, normalized))
+                .or_default()
+                .push(user.email);
+        }
+
+        let mut duplicates: Vec<String> = accounts
+            .into_iter()
+            .filter(|(_, emails)| emails.len() > 1)
+            .map(|((tenant_id, normalized), emails)| {
+                format!("{} in {} ({})", normalized, tenant_id, emails.join(", "))
+            })
+            .collect();
+        if !duplicates.is_empty() {
+            duplicates.sort();
+            bail!(
+                "users contains accounts whose emails have the same canonical form: {}. \
+                 Merge or delete the duplicate accounts, then restart.",
+                duplicates.join("; ")
+            );
+        }
+
+        // The canonical emails are the primary key, which Postgres checks row by row, so a user
+        // can only take the value another one gives up once that one has moved. Park every changed
+        // row on a placeholder first: without an `@`, it cannot clash with any email.
+        let changed = sqlx::query!(
+            r#"
+            UPDATE users u SET email_normalized = 'renormalizing-' || c.n
+            FROM unnest($1::text[], $2::text[]) WITH ORDINALITY AS c(tenant_id, old, n)
+            WHERE u.tenant_id = c.tenant_id AND u.email_normalized = c.old
+            "#,
+            &tenant_ids,
+            &olds,
+        )
+        .execute(&mut *transaction)
+        .await
+        .wrap_err("failed to park the changed canonical emails")?
+        .rows_affected();
+        sqlx::query!(
+            r#"
+            UPDATE users u SET email_normalized = c.new
+            FROM unnest($1::text[], $2::text[]) WITH ORDINALITY AS c(tenant_id, new, n)
+            WHERE u.tenant_id = c.tenant_id AND u.email_normalized = 'renormalizing-' || c.n
+            "#,
+            &tenant_ids,
+            &news,
+        )
+        .execute(&mut *transaction)
+        .await
+        .wrap_err("failed to update the canonical emails")?;
+
+        transaction
+            .commit()
+            .await
+            .wrap_err("failed to commit the canonical emails")?;
+        Ok(changed)
+    }
+}
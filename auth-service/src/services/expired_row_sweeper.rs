@@ -0,0 +1,72 @@
+use color_eyre::eyre::{Context, Result};
+use sqlx::PgPool;
+use std::time::Duration;
+
+// Deletes rows that are past their `expires_at`. The stores already ignore them, so this only
+// keeps the tables from growing.
+pub struct ExpiredRowSweeper {
+    pool: PgPool,
+    interval: Duration,
+}
+
+impl ExpiredRowSweeper {
+    pub fn new(pool: PgPool, interval: Duration) -> Self {
+        Self { pool, interval }
+    }
+
+    // Sweep every `interval` until the task is aborted
+    pub async fn run(self) {
+        tracing::info!("expired row sweeper started");
+        loop {
+            match self.sweep().await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!(count, "deleted expired rows"),
+                Err(e) => tracing::error!("failed to delete expired rows: {:?}", e),
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    // Returns the number of rows deleted
+    #[tracing::instrument(name = "Sweeping expired rows", skip_all)]
+    pub async fn sweep(&self) -> Result<u64> {
+        let banned_tokens = sqlx::query!("DELETE FROM banned_tokens WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to delete expired banned tokens")?;
+        let two_fa_codes = sqlx::query!("DELETE FROM two_fa_codes WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to delete expired 2FA codes")?;
+        let remember_me_tokens =
+            sqlx::query!("DELETE FROM remember_me_tokens WHERE expires_at <= now()")
+                .execute(&self.pool)
+                .await
+                .wrap_err("failed to delete expired remember me tokens")?;
+        let trusted_devices = sqlx::query!("DELETE FROM trusted_devices WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to delete expired trusted devices")?;
+        let login_attempts = sqlx::query!("DELETE FROM login_attempts WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to delete expired login attempts")?;
+        let magic_links = sqlx::query!("DELETE FROM magic_links WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to delete expired magic links")?;
+        let used_sign_in_alerts =
+            sqlx::query!("DELETE FROM used_sign_in_alerts WHERE expires_at <= now()")
+                .execute(&self.pool)
+                .await
+                .wrap_err("failed to delete expired sign-in alerts")?;
+
+        Ok(banned_tokens.rows_affected()
+            + two_fa_codes.rows_affected()
+            + remember_me_tokens.rows_affected()
+            + trusted_devices.rows_affected()
+            + login_attempts.rows_affected()
+            + magic_links.rows_affected()
+            + used_sign_in_alerts.rows_affected())
+    }
+}
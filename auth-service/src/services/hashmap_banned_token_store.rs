@@ -1,23 +1,23 @@
+use crate::domain::TenantId;
 use crate::services::{BannedTokenStore, BannedTokenStoreError};
-use std::collections::HashSet;
+use dashmap::DashSet;
 
 // Create a new struct called `HashsetBannedTokenStore` containing a `token` field
-// which stores a `HashSet`` of token `String`s.
+// which stores a `DashSet` of token `String`s, each with the tenant it belongs to.
 // Derive the `Default` trait for `HashsetBannedTokenStore`.
 #[derive(Clone, Default)]
 pub struct HashsetBannedTokenStore {
-    tokens: HashSet<String>,
+    tokens: DashSet<(TenantId, String)>,
 }
 
 impl HashsetBannedTokenStore {
     #[tracing::instrument(name = "Adding BannedToken To Local MemoryCache", skip_all)]
-    pub fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
-        // Return `BannedTokenStoreError::TokenAlreadyExists` if the user already exists,
-        // otherwise insert the user into the HashSet and return `Ok(())`.
-        if self.tokens.contains(&token) {
+    pub fn add_token(&self, tenant: &TenantId, token: String) -> Result<(), BannedTokenStoreError> {
+        // Return `BannedTokenStoreError::TokenAlreadyExists` if the token already exists,
+        // otherwise insert the token into the set and return `Ok(())`.
+        if !self.tokens.insert((tenant.clone(), token)) {
             return Err(BannedTokenStoreError::TokenAlreadyExists);
         }
-        self.tokens.insert(token);
         Ok(())
     }
 
@@ -27,26 +27,34 @@ impl HashsetBannedTokenStore {
     // `token` String or a `BannedTokenStoreError`.
     // Return `BannedTokenStoreError::TokenNotFound` if the token can not be found.
     #[tracing::instrument(name = "Getting BannedToken From Local MemoryCache", skip_all)]
-    pub fn get_token(&self, token: &str) -> Result<String, BannedTokenStoreError> {
+    pub fn get_token(
+        &self,
+        tenant: &TenantId,
+        token: &str,
+    ) -> Result<String, BannedTokenStoreError> {
         self.tokens
-            .get(token)
-            .map(|s| s.to_string())
+            .get(&(tenant.clone(), token.to_owned()))
+            .map(|s| s.key().1.clone())
             .ok_or(BannedTokenStoreError::TokenNotFound)
     }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
-        self.add_token(token)
+    async fn add_token(
+        &self,
+        tenant: &TenantId,
+        token: String,
+    ) -> Result<(), BannedTokenStoreError> {
+        self.add_token(tenant, token)
     }
 
-    async fn get_token(&self, token: &str) -> Result<String, BannedTokenStoreError> {
-        self.get_token(token)
-    }
-
-    async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
-        Ok(self.tokens.contains(token))
+    async fn contains_token(
+        &self,
+        tenant: &TenantId,
+        token: &str,
+    ) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.tokens.contains(&(tenant.clone(), token.to_owned())))
     }
 }
 
@@ -55,36 +63,52 @@ impl BannedTokenStore for HashsetBannedTokenStore {
 mod tests {
     use super::*;
 
+    fn tenant() -> TenantId {
+        TenantId::parse("default").unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_token() {
-        let mut token_store = HashsetBannedTokenStore::default();
+        let token_store = HashsetBannedTokenStore::default();
         let token = "test_token".to_string();
-        let result = token_store.add_token(token.clone());
+        let result = token_store.add_token(&tenant(), token.clone());
         assert!(result.is_ok());
-        let result = token_store.add_token(token.clone());
+        let result = token_store.add_token(&tenant(), token.clone());
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_get_token() {
-        let mut token_store = HashsetBannedTokenStore::default();
+        let token_store = HashsetBannedTokenStore::default();
         let token = "test_token".to_string();
-        token_store.add_token(token.clone()).unwrap();
-        let result = token_store.get_token(&token);
+        token_store.add_token(&tenant(), token.clone()).unwrap();
+        let result = token_store.get_token(&tenant(), &token);
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), token);
     }
 
     #[tokio::test]
     async fn test_contains_token() {
-        let mut token_store = HashsetBannedTokenStore::default();
+        let token_store = HashsetBannedTokenStore::default();
         let token = "test_token".to_string();
-        token_store.add_token(token.clone()).unwrap();
-        let result = token_store.contains_token(&token).await;
+        token_store.add_token(&tenant(), token.clone()).unwrap();
+        let result = BannedTokenStore::contains_token(&token_store, &tenant(), &token).await;
         assert!(result.is_ok());
         assert!(result.unwrap());
-        let result = token_store.contains_token(&"not_a_token".to_string()).await;
+        let result = BannedTokenStore::contains_token(&token_store, &tenant(), "not_a_token").await;
         assert!(result.is_ok());
         assert!(!result.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_tokens_are_scoped_by_tenant() {
+        let token_store = HashsetBannedTokenStore::default();
+        let other_tenant = TenantId::parse("other").unwrap();
+        token_store
+            .add_token(&tenant(), "test_token".to_string())
+            .unwrap();
+        let result =
+            BannedTokenStore::contains_token(&token_store, &other_tenant, "test_token").await;
+        assert!(!result.unwrap());
+    }
 }
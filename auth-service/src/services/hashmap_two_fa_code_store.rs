@@ -1,50 +1,120 @@
-use std::collections::HashMap;
-
-use crate::domain::user::Email;
-use crate::services::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+use crate::domain::TenantId;
+use crate::services::{
+    LoginAttemptId, MAX_2FA_CODE_ATTEMPTS, MAX_PENDING_2FA_CHALLENGES, TwoFAChallenge,
+    TwoFACodeStore, TwoFACodeStoreError,
+};
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::eyre;
+use dashmap::{DashMap, mapref::entry::Entry};
 
 #[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: DashMap<(TenantId, String), TwoFAChallenge>,
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     #[tracing::instrument(name = "Adding 2-FA-Code To Local Memery 2FA-Code Cache", skip_all)]
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
+        tenant: &TenantId,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        challenge: TwoFAChallenge,
     ) -> Result<(), TwoFACodeStoreError> {
-        if self.codes.contains_key(&email) {
-            return Err(TwoFACodeStoreError::UnexpectedError(eyre!(
-                "Email already exists in the store"
-            )));
+        let email = challenge.email.clone();
+        match self
+            .codes
+            .entry((tenant.clone(), login_attempt_id.as_ref().to_owned()))
+        {
+            Entry::Occupied(_) => {
+                return Err(TwoFACodeStoreError::UnexpectedError(eyre!(
+                    "Login attempt already exists in the store"
+                )));
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(challenge);
+            }
+        }
+
+        // Drop the oldest pending challenges of the account beyond the limit
+        let mut pending: Vec<_> = self
+            .codes
+            .iter()
+            .filter(|entry| entry.key().0 == *tenant && entry.value().email == email)
+            .map(|entry| (entry.value().created_at, entry.key().clone()))
+            .collect();
+        pending.sort_by_key(|(created_at, _)| *created_at);
+        let excess = pending.len().saturating_sub(MAX_PENDING_2FA_CHALLENGES);
+        for (_, key) in pending.into_iter().take(excess) {
+            self.codes.remove(&key);
         }
-        self.codes.insert(email, (login_attempt_id, code));
         Ok(())
     }
 
-    #[tracing::instrument(name = "Removing 2-FA-Code From Local Memery 2FA-Code Cache", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        if self.codes.contains_key(email) {
-            self.codes.remove(email);
-            Ok(())
-        } else {
-            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    #[tracing::instrument(name = "Updating 2-FA-Code In Local Memery 2FA-Code Cache", skip_all)]
+    async fn update_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+        sent_at: DateTime<Utc>,
+        challenge: TwoFAChallenge,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let mut entry = self
+            .codes
+            .get_mut(&(tenant.clone(), login_attempt_id.as_ref().to_owned()))
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        if entry.sent_at != sent_at {
+            return Err(TwoFACodeStoreError::ChallengeChanged);
         }
+        // Wrong codes entered in the meantime still count
+        *entry = TwoFAChallenge {
+            attempts: entry.attempts,
+            ..challenge
+        };
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Recording Failed 2-FA Attempt In Local Memery 2FA-Code Cache",
+        skip_all
+    )]
+    async fn record_failed_attempt(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let key = (tenant.clone(), login_attempt_id.as_ref().to_owned());
+        let Entry::Occupied(mut entry) = self.codes.entry(key) else {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        };
+        entry.get_mut().attempts += 1;
+        if entry.get().attempts >= MAX_2FA_CODE_ATTEMPTS {
+            entry.remove();
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Removing 2-FA-Code From Local Memery 2FA-Code Cache", skip_all)]
+    async fn remove_code(
+        &self,
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.codes
+            .remove(&(tenant.clone(), login_attempt_id.as_ref().to_owned()))
+            .map(|_| ())
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
     }
 
     #[tracing::instrument(name = "Getting 2-FA-Code From Local Memery 2FA-Code Cache", skip_all)]
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        tenant: &TenantId,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAChallenge, TwoFACodeStoreError> {
         self.codes
-            .get(email)
-            .cloned()
+            .get(&(tenant.clone(), login_attempt_id.as_ref().to_owned()))
+            .map(|entry| entry.value().clone())
             .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
     }
 }
@@ -54,96 +124,258 @@ mod tests {
     use super::*;
     use crate::app_state::TwoFACodeStoreType;
     use crate::domain::user::Email;
+    use crate::services::TwoFACode;
+    use chrono::TimeDelta;
     use fake::{Fake, faker::internet::en::SafeEmail};
     use secrecy::SecretBox;
     use std::sync::Arc;
-    use tokio::sync::RwLock;
+
+    fn tenant() -> TenantId {
+        TenantId::parse("default").unwrap()
+    }
+
+    fn challenge(email: &Email) -> TwoFAChallenge {
+        let now = Utc::now();
+        TwoFAChallenge {
+            email: email.clone(),
+            code: TwoFACode::default(),
+            client_binding: "binding".to_owned(),
+            created_at: now,
+            sent_at: now,
+            resends: 0,
+            attempts: 0,
+        }
+    }
 
     #[tokio::test]
     async fn test_add_code() {
-        //let mut store = HashmapTwoFACodeStore::default();
-        let two_fa_code_store: TwoFACodeStoreType =
-            Arc::new(RwLock::new(Box::new(HashmapTwoFACodeStore::default())));
-        let mut store = two_fa_code_store.write().await;
+        let store: TwoFACodeStoreType = Arc::new(HashmapTwoFACodeStore::default());
         let email_secret: SecretBox<String> = SecretBox::new(Box::new(SafeEmail().fake()));
         let email = Email::parse(email_secret).unwrap();
         let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::default();
 
         // First add should succeed
         assert!(
             store
-                .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+                .add_code(&tenant(), login_attempt_id.clone(), challenge(&email))
                 .await
                 .is_ok()
         );
 
-        // Second add with same email should fail
+        // Second add with same login attempt should fail
         assert_eq!(
-            store.add_code(email, login_attempt_id, code).await,
+            store
+                .add_code(&tenant(), login_attempt_id, challenge(&email))
+                .await,
             Err(TwoFACodeStoreError::UnexpectedError(eyre!(
-                "Email already exists in the store"
+                "Login attempt already exists in the store"
             )))
         );
+
+        // Another login of the same account gets its own challenge
+        assert!(
+            store
+                .add_code(&tenant(), LoginAttemptId::default(), challenge(&email))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_code_drops_the_oldest_challenges() {
+        let store: TwoFACodeStoreType = Arc::new(HashmapTwoFACodeStore::default());
+        let email = Email::parse(SecretBox::new(Box::new(SafeEmail().fake()))).unwrap();
+        let other = Email::parse(SecretBox::new(Box::new(SafeEmail().fake()))).unwrap();
+        let other_login = LoginAttemptId::default();
+        store
+            .add_code(&tenant(), other_login.clone(), challenge(&other))
+            .await
+            .unwrap();
+
+        let started = Utc::now();
+        let mut logins = Vec::new();
+        for i in 0..=MAX_PENDING_2FA_CHALLENGES {
+            let login_attempt_id = LoginAttemptId::default();
+            let challenge = TwoFAChallenge {
+                created_at: started + TimeDelta::seconds(i as i64),
+                ..challenge(&email)
+            };
+            store
+                .add_code(&tenant(), login_attempt_id.clone(), challenge)
+                .await
+                .unwrap();
+            logins.push(login_attempt_id);
+        }
+
+        assert_eq!(
+            store.get_code(&tenant(), &logins[0]).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+        for login_attempt_id in &logins[1..] {
+            assert!(store.get_code(&tenant(), login_attempt_id).await.is_ok());
+        }
+        // Other accounts are not affected
+        assert!(store.get_code(&tenant(), &other_login).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_update_code() {
+        let store: TwoFACodeStoreType = Arc::new(HashmapTwoFACodeStore::default());
+        let email = Email::parse(SecretBox::new(Box::new(SafeEmail().fake()))).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+        let challenge = challenge(&email);
+
+        // Update non-existent code should fail
+        assert_eq!(
+            store
+                .update_code(
+                    &tenant(),
+                    &login_attempt_id,
+                    challenge.sent_at,
+                    challenge.clone()
+                )
+                .await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+
+        store
+            .add_code(&tenant(), login_attempt_id.clone(), challenge.clone())
+            .await
+            .unwrap();
+        let resent = TwoFAChallenge {
+            code: TwoFACode::default(),
+            sent_at: Utc::now(),
+            resends: 1,
+            ..challenge
+        };
+        store
+            .update_code(
+                &tenant(),
+                &login_attempt_id,
+                challenge.sent_at,
+                resent.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_code(&tenant(), &login_attempt_id).await,
+            Ok(resent.clone())
+        );
+
+        // A second update based on the same read loses
+        assert_eq!(
+            store
+                .update_code(&tenant(), &login_attempt_id, challenge.sent_at, resent)
+                .await,
+            Err(TwoFACodeStoreError::ChallengeChanged)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt() {
+        let store: TwoFACodeStoreType = Arc::new(HashmapTwoFACodeStore::default());
+        let email = Email::parse(SecretBox::new(Box::new(SafeEmail().fake()))).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+        let challenge = challenge(&email);
+        store
+            .add_code(&tenant(), login_attempt_id.clone(), challenge.clone())
+            .await
+            .unwrap();
+
+        store
+            .record_failed_attempt(&tenant(), &login_attempt_id)
+            .await
+            .unwrap();
+        // Sending the code again does not reset the count
+        let resent = TwoFAChallenge {
+            sent_at: Utc::now(),
+            resends: 1,
+            ..challenge.clone()
+        };
+        store
+            .update_code(&tenant(), &login_attempt_id, challenge.sent_at, resent)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get_code(&tenant(), &login_attempt_id)
+                .await
+                .map(|challenge| challenge.attempts),
+            Ok(1)
+        );
+
+        for _ in 1..MAX_2FA_CODE_ATTEMPTS {
+            store
+                .record_failed_attempt(&tenant(), &login_attempt_id)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            store.get_code(&tenant(), &login_attempt_id).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+        assert_eq!(
+            store
+                .record_failed_attempt(&tenant(), &login_attempt_id)
+                .await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
     }
 
     #[tokio::test]
     async fn test_remove_code() {
-        let two_fa_code_store: TwoFACodeStoreType =
-            Arc::new(RwLock::new(Box::new(HashmapTwoFACodeStore::default())));
-        let mut store = two_fa_code_store.write().await;
+        let store: TwoFACodeStoreType = Arc::new(HashmapTwoFACodeStore::default());
         let email = Email::parse(SecretBox::new(Box::new(SafeEmail().fake()))).unwrap();
         let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::default();
 
         // Remove non-existent code should fail
         assert_eq!(
-            store.remove_code(&email).await,
+            store.remove_code(&tenant(), &login_attempt_id).await,
             Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
         );
 
         // Add code
         store
-            .add_code(email.clone(), login_attempt_id, code)
+            .add_code(&tenant(), login_attempt_id.clone(), challenge(&email))
             .await
             .unwrap();
 
         // Remove existing code should succeed
-        assert!(store.remove_code(&email).await.is_ok());
+        assert!(
+            store
+                .remove_code(&tenant(), &login_attempt_id)
+                .await
+                .is_ok()
+        );
 
         // Remove again should fail
         assert_eq!(
-            store.remove_code(&email).await,
+            store.remove_code(&tenant(), &login_attempt_id).await,
             Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
         );
     }
 
     #[tokio::test]
     async fn test_get_code() {
-        let two_fa_code_store: TwoFACodeStoreType =
-            Arc::new(RwLock::new(Box::new(HashmapTwoFACodeStore::default())));
-        let mut store = two_fa_code_store.write().await;
+        let store: TwoFACodeStoreType = Arc::new(HashmapTwoFACodeStore::default());
         let email = Email::parse(SecretBox::new(Box::new(SafeEmail().fake()))).unwrap();
         let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::default();
+        let challenge = challenge(&email);
 
         // Get non-existent code should fail
         assert_eq!(
-            store.get_code(&email).await,
+            store.get_code(&tenant(), &login_attempt_id).await,
             Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
         );
 
         // Add code
         store
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .add_code(&tenant(), login_attempt_id.clone(), challenge.clone())
             .await
             .unwrap();
 
         // Get existing code should succeed
-        let result = store.get_code(&email).await;
-        assert!(result.is_ok());
-        let (retrieved_id, retrieved_code) = result.unwrap();
-        assert_eq!(retrieved_id, login_attempt_id);
-        assert_eq!(retrieved_code, code);
+        let result = store.get_code(&tenant(), &login_attempt_id).await;
+        assert_eq!(result, Ok(challenge));
     }
 }
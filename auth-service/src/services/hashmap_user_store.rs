@@ -1,24 +1,39 @@
-use crate::domain::{Email, Password, User};
-use crate::services::{UserStore, UserStoreError};
-use std::collections::HashMap;
+use crate::domain::{Email, Password, PhoneNumber, TenantId, TwoFAChannel, User};
+use crate::services::data_stores::MAX_PHONE_VERIFICATION_ATTEMPTS;
+use crate::services::{TwoFACode, UserStore, UserStoreError};
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, mapref::entry::Entry};
 
 // Create a new struct called `HashmapUserStore` containing a `users` field
-// which stores a `HashMap`` of email `String`s mapped to `User` objects.
+// which stores a `DashMap` of emails mapped to `User` objects, keyed by tenant as well.
+// DashMap shards its locks internally, so the store can be shared without a global lock.
 // Derive the `Default` trait for `HashmapUserStore`.
 #[derive(Clone, Default)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>,
+    users: DashMap<(TenantId, Email), User>,
+    phone_verifications: DashMap<(TenantId, Email), PendingPhoneNumber>,
+}
+
+#[derive(Clone)]
+struct PendingPhoneNumber {
+    phone_number: PhoneNumber,
+    code: TwoFACode,
+    attempts: i32,
+    expires_at: DateTime<Utc>,
 }
 
 impl HashmapUserStore {
     #[tracing::instrument(name = "Adding User To User Local MemoryCache", skip_all)]
-    pub fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+    pub fn add_user(&self, tenant: &TenantId, user: User) -> Result<(), UserStoreError> {
+        // Return `UserStoreError::UserAlreadyExists` if the user already exists,
         // otherwise insert the user into the hashmap and return `Ok(())`.
-        if self.users.contains_key(&user.email) {
-            return Err(UserStoreError::UserAlreadyExists);
+        match self.users.entry((tenant.clone(), user.email.clone())) {
+            Entry::Occupied(_) => Err(UserStoreError::UserAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(user);
+                Ok(())
+            }
         }
-        self.users.insert(user.email.clone(), user);
-        Ok(())
     }
 
     // Implement a public method called `get_user`, which takes an
@@ -27,8 +42,11 @@ impl HashmapUserStore {
     // `User` object or a `UserStoreError`.
     // Return `UserStoreError::UserNotFound` if the user can not be found.
     #[tracing::instrument(name = "Getting User From User Local MemoryCache", skip_all)]
-    pub fn get_user(&self, email: &Email) -> Result<&User, UserStoreError> {
-        self.users.get(email).ok_or(UserStoreError::UserNotFound)
+    pub fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError> {
+        self.users
+            .get(&(tenant.clone(), email.clone()))
+            .map(|user| user.clone())
+            .ok_or(UserStoreError::UserNotFound)
     }
 
     // Implement a public method called `validate_user`, which takes an
@@ -38,8 +56,13 @@ impl HashmapUserStore {
     // Return `UserStoreError::UserNotFound` if the user can not be found.
     // Return `UserStoreError::InvalidCredentials` if the password is incorrect.
     #[tracing::instrument(name = "Validating User using Local MemoryCache", skip_all)]
-    pub fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        let user = self.get_user(email)?;
+    pub fn validate_user(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError> {
+        let user = self.get_user(tenant, email)?;
         if user.password != *password {
             return Err(UserStoreError::InvalidCredentials);
         }
@@ -49,20 +72,112 @@ impl HashmapUserStore {
 
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        self.add_user(user)
+    async fn add_user(&self, tenant: &TenantId, user: User) -> Result<(), UserStoreError> {
+        self.add_user(tenant, user)
+    }
+
+    async fn delete_user(&self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError> {
+        let key = (tenant.clone(), email.clone());
+        self.phone_verifications.remove(&key);
+        self.users
+            .remove(&key)
+            .map(|_| ())
+            .ok_or(UserStoreError::UserNotFound)
     }
 
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        self.get_user(email).cloned()
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError> {
+        self.get_user(tenant, email)
     }
 
     async fn validate_user(
         &self,
+        tenant: &TenantId,
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
-        self.validate_user(email, password)
+        self.validate_user(tenant, email, password)
+    }
+
+    async fn update_password(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: Password,
+    ) -> Result<(), UserStoreError> {
+        let mut user = self
+            .users
+            .get_mut(&(tenant.clone(), email.clone()))
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.password = password;
+        Ok(())
+    }
+
+    async fn start_phone_verification(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        phone_number: &PhoneNumber,
+        code: &TwoFACode,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.get_user(tenant, email)?;
+        self.phone_verifications.insert(
+            (tenant.clone(), email.clone()),
+            PendingPhoneNumber {
+                phone_number: phone_number.clone(),
+                code: code.clone(),
+                attempts: 0,
+                expires_at,
+            },
+        );
+        Ok(())
+    }
+
+    async fn confirm_phone_verification(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<PhoneNumber, UserStoreError> {
+        let key = (tenant.clone(), email.clone());
+        let Entry::Occupied(mut entry) = self.phone_verifications.entry(key.clone()) else {
+            return Err(UserStoreError::InvalidVerificationCode);
+        };
+
+        let pending = entry.get_mut();
+        let live = pending.expires_at > Utc::now();
+        if live && pending.code == *code {
+            let phone_number = entry.remove().phone_number;
+            let mut user = self
+                .users
+                .get_mut(&key)
+                .ok_or(UserStoreError::UserNotFound)?;
+            user.phone_number = Some(phone_number.clone());
+            return Ok(phone_number);
+        }
+
+        pending.attempts += 1;
+        if !live || pending.attempts >= MAX_PHONE_VERIFICATION_ATTEMPTS {
+            entry.remove();
+        }
+        Err(UserStoreError::InvalidVerificationCode)
+    }
+
+    async fn set_two_fa_channel(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let mut user = self
+            .users
+            .get_mut(&(tenant.clone(), email.clone()))
+            .ok_or(UserStoreError::UserNotFound)?;
+        if channel == TwoFAChannel::Sms && user.phone_number.is_none() {
+            return Err(UserStoreError::PhoneNumberNotVerified);
+        }
+        user.two_fa_channel = channel;
+        Ok(())
     }
 }
 
@@ -71,38 +186,178 @@ impl UserStore for HashmapUserStore {
 mod tests {
     use super::*;
     use secrecy::SecretBox;
+
+    fn tenant() -> TenantId {
+        TenantId::parse("default").unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_user() {
-        let mut user_store = HashmapUserStore::default();
+        let user_store = HashmapUserStore::default();
         let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
         let password =
             Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
         let user = User::new(email, password, false);
-        let result = user_store.add_user(user);
+        let result = user_store.add_user(&tenant(), user);
         assert_eq!(result, Ok(()));
     }
 
     #[tokio::test]
     async fn test_get_user() {
-        let mut user_store = HashmapUserStore::default();
+        let user_store = HashmapUserStore::default();
         let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
         let password =
             Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
         let user = User::new(email.clone(), password.clone(), false);
-        user_store.add_user(user.clone()).unwrap();
-        let result = user_store.get_user(&email);
-        assert_eq!(result, Ok(&user));
+        user_store.add_user(&tenant(), user.clone()).unwrap();
+        let result = user_store.get_user(&tenant(), &email);
+        assert_eq!(result, Ok(user));
     }
 
     #[tokio::test]
     async fn test_validate_user() {
-        let mut user_store = HashmapUserStore::default();
+        let user_store = HashmapUserStore::default();
         let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
         let password =
             Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
         let user = User::new(email.clone(), password.clone(), false);
-        user_store.add_user(user.clone()).unwrap();
-        let result = user_store.validate_user(&email, &password);
+        user_store.add_user(&tenant(), user.clone()).unwrap();
+        let result = user_store.validate_user(&tenant(), &email, &password);
         assert_eq!(result, Ok(()));
     }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
+        let password =
+            Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
+        let user = User::new(email.clone(), password, false);
+        user_store.add_user(&tenant(), user.clone()).unwrap();
+
+        assert_eq!(
+            UserStore::delete_user(&user_store, &tenant(), &email).await,
+            Ok(())
+        );
+        assert_eq!(
+            user_store.get_user(&tenant(), &email),
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            UserStore::delete_user(&user_store, &tenant(), &email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        // The email can be used again
+        assert_eq!(user_store.add_user(&tenant(), user), Ok(()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_add_user_only_one_succeeds() {
+        let user_store = std::sync::Arc::new(HashmapUserStore::default());
+        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
+        let password =
+            Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let user_store = user_store.clone();
+                let user = User::new(email.clone(), password.clone(), false);
+                tokio::spawn(async move {
+                    UserStore::add_user(user_store.as_ref(), &tenant(), user).await
+                })
+            })
+            .collect();
+
+        let mut successes = 0;
+        for handle in handles {
+            match handle.await.unwrap() {
+                Ok(()) => successes += 1,
+                Err(e) => assert_eq!(e, UserStoreError::UserAlreadyExists),
+            }
+        }
+        assert_eq!(successes, 1);
+    }
+
+    #[tokio::test]
+    async fn test_phone_verification_and_two_fa_channel() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
+        let password =
+            Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
+        user_store
+            .add_user(&tenant(), User::new(email.clone(), password, true))
+            .unwrap();
+
+        // SMS cannot be chosen before a phone number is verified
+        assert_eq!(
+            user_store
+                .set_two_fa_channel(&tenant(), &email, TwoFAChannel::Sms)
+                .await,
+            Err(UserStoreError::PhoneNumberNotVerified)
+        );
+
+        let phone_number = PhoneNumber::parse("+14155552671").unwrap();
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        user_store
+            .start_phone_verification(
+                &tenant(),
+                &email,
+                &phone_number,
+                &code,
+                Utc::now() + chrono::TimeDelta::minutes(10),
+            )
+            .await
+            .unwrap();
+
+        let wrong_code = TwoFACode::parse("654321".to_owned()).unwrap();
+        assert_eq!(
+            user_store
+                .confirm_phone_verification(&tenant(), &email, &wrong_code)
+                .await,
+            Err(UserStoreError::InvalidVerificationCode)
+        );
+        assert_eq!(
+            user_store
+                .confirm_phone_verification(&tenant(), &email, &code)
+                .await,
+            Ok(phone_number.clone())
+        );
+
+        user_store
+            .set_two_fa_channel(&tenant(), &email, TwoFAChannel::Sms)
+            .await
+            .unwrap();
+        let user = user_store.get_user(&tenant(), &email).unwrap();
+        assert_eq!(user.phone_number, Some(phone_number));
+        assert_eq!(user.two_fa_channel, TwoFAChannel::Sms);
+    }
+
+    #[tokio::test]
+    async fn test_users_are_scoped_by_tenant() {
+        let user_store = HashmapUserStore::default();
+        let other_tenant = TenantId::parse("other").unwrap();
+        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
+        let password =
+            Password::parse(SecretBox::new(Box::new("password123".to_string()))).unwrap();
+        user_store
+            .add_user(&tenant(), User::new(email.clone(), password.clone(), false))
+            .unwrap();
+
+        // The same email is a different user in another tenant
+        assert_eq!(
+            user_store.get_user(&other_tenant, &email),
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.add_user(&other_tenant, User::new(email.clone(), password, true)),
+            Ok(())
+        );
+        assert!(!user_store.get_user(&tenant(), &email).unwrap().requires_2fa);
+        assert!(
+            user_store
+                .get_user(&other_tenant, &email)
+                .unwrap()
+                .requires_2fa
+        );
+    }
 }
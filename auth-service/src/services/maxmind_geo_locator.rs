@@ -0,0 +1,51 @@
+use color_eyre::eyre::{Context, Result};
+use maxminddb::{MaxMindDBError, Reader, geoip2};
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::domain::{Coordinates, GeoLocation, GeoLocator};
+
+// Looks addresses up in a local MaxMind database, e.g. GeoLite2-City.mmdb. The file is read
+// into memory once; restart the service to pick up a new one.
+pub struct MaxMindGeoLocator {
+    reader: Reader<Vec<u8>>,
+}
+
+impl MaxMindGeoLocator {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = Reader::open_readfile(path)
+            .wrap_err_with(|| format!("failed to open GeoIP database {}", path.display()))?;
+        Ok(Self { reader })
+    }
+}
+
+impl GeoLocator for MaxMindGeoLocator {
+    fn locate(&self, ip: IpAddr) -> Option<GeoLocation> {
+        // City records are a superset of Country records, so this reads both kinds of database
+        let city = match self.reader.lookup::<geoip2::City>(ip) {
+            Ok(city) => city,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => return None,
+            Err(e) => {
+                tracing::warn!("GeoIP lookup failed: {}", e);
+                return None;
+            }
+        };
+        let coordinates =
+            city.location
+                .and_then(|location| match (location.latitude, location.longitude) {
+                    (Some(latitude), Some(longitude)) => Some(Coordinates {
+                        latitude,
+                        longitude,
+                    }),
+                    _ => None,
+                });
+        Some(GeoLocation {
+            country: city
+                .country
+                .and_then(|country| country.iso_code)
+                .map(ToOwned::to_owned),
+            coordinates,
+        })
+    }
+}
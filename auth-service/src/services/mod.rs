@@ -9,9 +9,42 @@ pub use hashmap_two_fa_code_store::HashmapTwoFACodeStore;
 
 pub mod data_stores;
 pub use data_stores::{
-    BannedTokenStore, BannedTokenStoreError, LoginAttemptId, TwoFACode, TwoFACodeStore,
-    TwoFACodeStoreError, UserStore, UserStoreError,
+    BannedTokenStore, BannedTokenStoreError, DeviceSighting, KnownDeviceStore,
+    KnownDeviceStoreError, LocatedLogin, LoginAttempt, LoginAttemptId, LoginAttemptStore,
+    LoginAttemptStoreError, MAGIC_LINK_TTL_SECONDS, MAX_2FA_CODE_ATTEMPTS,
+    MAX_PENDING_2FA_CHALLENGES, MAX_PHONE_VERIFICATION_ATTEMPTS, MagicLink, MagicLinkStore,
+    MagicLinkStoreError, RecoveryCodeStore, RecoveryCodeStoreError, RecoveryCodeUsage,
+    RememberMeStore, RememberMeStoreError, RevocationFailurePolicy, StoreBackend,
+    TWO_FA_CODE_TTL_SECONDS, TrustedDevice, TrustedDeviceStore, TrustedDeviceStoreError,
+    TwoFAChallenge, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, UserStore, UserStoreError,
 };
 
+pub mod maxmind_geo_locator;
+pub use maxmind_geo_locator::MaxMindGeoLocator;
+
 pub mod postmark_email_client;
 pub use postmark_email_client::PostmarkEmailClient;
+
+pub mod expired_row_sweeper;
+pub use expired_row_sweeper::ExpiredRowSweeper;
+
+pub mod email_renormalizer;
+pub use email_renormalizer::EmailRenormalizer;
+
+pub mod email_outbox_worker;
+pub use email_outbox_worker::{EmailOutboxWorker, EmailOutboxWorkerConfig, OUTBOX_METRICS};
+
+pub mod dev_mailbox;
+pub use dev_mailbox::{DevMailbox, MailboxMessage};
+
+pub mod twilio_sms_client;
+pub use twilio_sms_client::TwilioSmsClient;
+
+pub mod reloadable_cors_policy;
+pub use reloadable_cors_policy::ReloadableCorsPolicy;
+
+pub mod tenant_registry;
+pub use tenant_registry::TenantRegistry;
+
+pub mod smtp_email_client;
+pub use smtp_email_client::{SmtpCredentials, SmtpEmailClient, SmtpSettings, SmtpTls};
@@ -2,27 +2,24 @@ use color_eyre::eyre::Result;
 use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, SecretBox};
 
-use crate::domain::{Email, EmailClient};
+use crate::domain::{Email, EmailClient, EmailMessage};
 
 // Define the PostmarkEmailClient struct
 pub struct PostmarkEmailClient {
     http_client: Client,
     base_url: String,
-    sender: Email,
     authorization_token: SecretBox<String>,
 }
 
 impl PostmarkEmailClient {
     pub fn new(
         base_url: String,
-        sender: Email,
         authorization_token: SecretBox<String>,
         http_client: Client,
     ) -> Self {
         Self {
             http_client,
             base_url,
-            sender,
             authorization_token,
         }
     }
@@ -31,18 +28,26 @@ impl PostmarkEmailClient {
 #[async_trait::async_trait]
 impl EmailClient for PostmarkEmailClient {
     #[tracing::instrument(name = "Sending email", skip_all)]
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+    async fn send_email(
+        &self,
+        sender: &Email,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<()> {
         // Parse the base URL and join it with the email endpoint
         let base = Url::parse(&self.base_url)?;
         let url = base.join("/email")?;
 
+        let rendered = message.render()?;
+
         // Create the request body for sending the email
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
+            from: sender.as_ref(),
             to: recipient.as_ref(),
-            subject,
-            html_body: content,
-            text_body: content,
+            subject: &rendered.subject,
+            html_body: &rendered.html_body,
+            text_body: &rendered.text_body,
+            tag: message.template_id(),
             message_stream: MESSAGE_STREAM,
         };
 
@@ -77,6 +82,7 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    tag: &'a str,
     message_stream: &'a str,
 }
 
@@ -85,22 +91,20 @@ mod tests {
     use crate::utils::constants::test;
 
     use super::*;
+    use chrono::{TimeDelta, Utc};
     use fake::faker::internet::en::SafeEmail;
-    use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use wiremock::matchers::{any, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     use super::PostmarkEmailClient;
 
-    // Helper function to generate a test subject
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
-
-    // Helper function to generate test content
-    fn content() -> String {
-        Paragraph(1..10).fake()
+    // Helper function to generate a test message
+    fn message() -> EmailMessage {
+        EmailMessage::TwoFACode {
+            code: "123456".to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        }
     }
 
     // Helper function to generate a test email
@@ -116,7 +120,6 @@ mod tests {
             .unwrap();
         PostmarkEmailClient::new(
             base_url,
-            email(),
             SecretBox::new(Box::new(Faker.fake())),
             http_client,
         )
@@ -134,6 +137,8 @@ mod tests {
                     && body.get("Subject").is_some()
                     && body.get("HtmlBody").is_some()
                     && body.get("TextBody").is_some()
+                    && body.get("HtmlBody") != body.get("TextBody")
+                    && body.get("Tag") == Some(&serde_json::json!("two-fa-code"))
                     && body.get("MessageStream").is_some()
             } else {
                 false
@@ -160,7 +165,7 @@ mod tests {
 
         // Execute the send_email function and check the outcome
         let outcome = email_client
-            .send_email(&email(), &subject(), &content())
+            .send_email(&email(), &email(), &message())
             .await;
 
         assert!(outcome.is_ok());
@@ -181,7 +186,7 @@ mod tests {
 
         // Execute the send_email function and check the outcome
         let outcome = email_client
-            .send_email(&email(), &subject(), &content())
+            .send_email(&email(), &email(), &message())
             .await;
 
         assert!(outcome.is_err());
@@ -203,7 +208,7 @@ mod tests {
 
         // Execute the send_email function and check the outcome
         let outcome = email_client
-            .send_email(&email(), &subject(), &content())
+            .send_email(&email(), &email(), &message())
             .await;
 
         assert!(outcome.is_err());
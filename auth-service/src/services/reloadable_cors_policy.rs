@@ -0,0 +1,188 @@
+use color_eyre::eyre::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::domain::{CorsPolicy, OriginPattern};
+
+// The CORS policy in force. When it comes from a file, the file is read again whenever it changes,
+// so origins can be added or removed without restarting the service.
+pub struct ReloadableCorsPolicy {
+    // Settings from the environment; the file overrides the ones it sets
+    base: CorsPolicy,
+    file: Option<PathBuf>,
+    current: RwLock<Arc<CorsPolicy>>,
+    // When the file was last modified, as of the last load
+    loaded_version: Mutex<Option<SystemTime>>,
+}
+
+impl ReloadableCorsPolicy {
+    pub fn fixed(policy: CorsPolicy) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(policy.clone())),
+            base: policy,
+            file: None,
+            loaded_version: Mutex::new(None),
+        }
+    }
+
+    // Fails when the file cannot be loaded, so a broken policy is noticed at startup
+    pub fn from_file(base: CorsPolicy, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let version = modified(&path)?;
+        let policy = load(&base, &path)?;
+        Ok(Self {
+            base,
+            file: Some(path),
+            current: RwLock::new(Arc::new(policy)),
+            loaded_version: Mutex::new(Some(version)),
+        })
+    }
+
+    pub fn current(&self) -> Arc<CorsPolicy> {
+        self.current
+            .read()
+            .expect("CORS policy lock poisoned")
+            .clone()
+    }
+
+    // Load the file again if it changed since the last load. Returns whether the policy was replaced.
+    // An invalid file leaves the current policy in place.
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let Some(path) = &self.file else {
+            return Ok(false);
+        };
+        let version = modified(path)?;
+        let mut loaded_version = self
+            .loaded_version
+            .lock()
+            .expect("CORS policy lock poisoned");
+        if *loaded_version == Some(version) {
+            return Ok(false);
+        }
+
+        let policy = load(&self.base, path)?;
+        *self.current.write().expect("CORS policy lock poisoned") = Arc::new(policy);
+        *loaded_version = Some(version);
+        Ok(true)
+    }
+
+    // Check the file for changes every `interval`
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        if self.file.is_none() {
+            return;
+        }
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.reload_if_changed() {
+                Ok(true) => tracing::info!(policy = ?self.current(), "CORS policy reloaded"),
+                Ok(false) => {}
+                Err(e) => tracing::error!("failed to reload the CORS policy: {:?}", e),
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct CorsPolicyFile {
+    allowed_origins: Option<Vec<OriginPattern>>,
+    allowed_methods: Option<Vec<String>>,
+    allowed_headers: Option<Vec<String>>,
+    max_age_seconds: Option<u64>,
+}
+
+fn load(base: &CorsPolicy, path: &Path) -> Result<CorsPolicy> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read CORS policy file {}", path.display()))?;
+    let file: CorsPolicyFile = serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("Failed to parse CORS policy file {}", path.display()))?;
+
+    let mut policy = base.clone();
+    if let Some(origins) = file.allowed_origins {
+        policy.allowed_origins = origins;
+    }
+    if let Some(methods) = file.allowed_methods {
+        policy.allowed_methods = CorsPolicy::parse_methods(&methods.join(","))?;
+    }
+    if let Some(headers) = file.allowed_headers {
+        policy.allowed_headers = CorsPolicy::parse_headers(&headers.join(","))?;
+    }
+    if let Some(max_age) = file.max_age_seconds {
+        policy.max_age = Duration::from_secs(max_age);
+    }
+    Ok(policy)
+}
+
+fn modified(path: &Path) -> Result<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .wrap_err_with(|| format!("Failed to read CORS policy file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+
+    fn policy_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cors-policy-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn base() -> CorsPolicy {
+        CorsPolicy {
+            allowed_origins: CorsPolicy::parse_origins("http://localhost:8000").unwrap(),
+            ..CorsPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_file_overrides_the_settings_it_contains() {
+        let path = policy_file(
+            r#"{"allowedOrigins": ["https://*.example.com"], "allowedMethods": ["get", "put"]}"#,
+        );
+        let policy = ReloadableCorsPolicy::from_file(base(), &path)
+            .unwrap()
+            .current();
+
+        assert!(policy.allows_origin("https://app.example.com"));
+        assert!(!policy.allows_origin("http://localhost:8000"));
+        assert_eq!(policy.allowed_methods, vec![Method::GET, Method::PUT]);
+        assert_eq!(policy.max_age, base().max_age);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reloads_when_the_file_changes() {
+        let path = policy_file(r#"{"allowedOrigins": ["https://one.example.com"]}"#);
+        let policy = ReloadableCorsPolicy::from_file(base(), &path).unwrap();
+        assert!(!policy.reload_if_changed().unwrap());
+
+        std::fs::write(&path, r#"{"allowedOrigins": ["https://two.example.com"]}"#).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(policy.reload_if_changed().unwrap());
+        assert!(policy.current().allows_origin("https://two.example.com"));
+        assert!(!policy.current().allows_origin("https://one.example.com"));
+
+        // A broken file keeps the last good policy
+        std::fs::write(&path, r#"{"allowedOrigins": ["*"]}"#).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later + Duration::from_secs(1))
+            .unwrap();
+        assert!(policy.reload_if_changed().is_err());
+        assert!(policy.current().allows_origin("https://two.example.com"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use color_eyre::eyre::{Report, Result, eyre};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, MultiPart},
+    transport::smtp::{PoolConfig, authentication::Credentials},
+};
+use secrecy::{ExposeSecret, SecretBox};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::domain::{Email, EmailClient, EmailMessage};
+
+// How the connection to the SMTP server is encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    // Plain-text connection, only suitable for a relay on a trusted network
+    None,
+    // Connect in plain text and upgrade with STARTTLS. Sending fails if the server does not offer it.
+    StartTls,
+    // TLS from the first byte (implicit TLS, RFC 8314)
+    Tls,
+}
+
+impl SmtpTls {
+    // The port conventionally used for each mode
+    pub fn default_port(&self) -> u16 {
+        match self {
+            SmtpTls::None => 25,
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            _ => Err(eyre!("Unknown SMTP TLS mode: {}", s)),
+        }
+    }
+}
+
+pub struct SmtpCredentials {
+    pub username: String,
+    pub password: SecretBox<String>,
+}
+
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub credentials: Option<SmtpCredentials>,
+    // Maximum number of connections kept open to the server
+    pub pool_max_size: u32,
+    pub timeout: Duration,
+}
+
+// Define the SmtpEmailClient struct
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpEmailClient {
+    pub fn new(settings: SmtpSettings) -> Result<Self> {
+        let builder = match settings.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(settings.host),
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        };
+
+        let mut builder = builder
+            .port(settings.port)
+            .timeout(Some(settings.timeout))
+            .pool_config(PoolConfig::new().max_size(settings.pool_max_size));
+        if let Some(credentials) = settings.credentials {
+            builder = builder.credentials(Credentials::new(
+                credentials.username,
+                credentials.password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Sending email over SMTP", skip_all)]
+    async fn send_email(
+        &self,
+        sender: &Email,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<()> {
+        let rendered = message.render()?;
+
+        let email = Message::builder()
+            .from(sender.as_ref().parse::<Mailbox>()?)
+            .to(recipient.as_ref().parse::<Mailbox>()?)
+            .subject(rendered.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                rendered.text_body,
+                rendered.html_body,
+            ))?;
+
+        // The transport checks a connection out of its pool, or opens a new one if none is idle
+        self.transport.send(email).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeDelta, Utc};
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // base64 of "\0user\0secret", the AUTH PLAIN response for the test credentials
+    const PLAIN_AUTH_RESPONSE: &str = "AHVzZXIAc2VjcmV0";
+
+    // A minimal in-process SMTP server that records the messages it receives
+    #[derive(Clone, Default)]
+    struct SmtpStandIn {
+        require_auth: bool,
+        messages: Arc<Mutex<Vec<String>>>,
+        connections: Arc<AtomicUsize>,
+    }
+
+    impl SmtpStandIn {
+        async fn start(self) -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    self.connections.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(self.clone().handle(stream));
+                }
+            });
+            port
+        }
+
+        async fn handle(self, stream: tokio::net::TcpStream) {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut authenticated = !self.require_auth;
+
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let command = line.to_ascii_uppercase();
+                let reply = if command.starts_with("EHLO") {
+                    "250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("AUTH PLAIN") {
+                    authenticated = line.ends_with(PLAIN_AUTH_RESPONSE);
+                    if authenticated {
+                        "235 Authentication succeeded\r\n"
+                    } else {
+                        "535 Authentication failed\r\n"
+                    }
+                } else if command.starts_with("MAIL FROM") && !authenticated {
+                    "530 Authentication required\r\n"
+                } else if command.starts_with("DATA") {
+                    writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                    let mut data = String::new();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    self.messages.lock().unwrap().push(data);
+                    "250 Queued\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    return;
+                } else {
+                    "250 OK\r\n"
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+        }
+    }
+
+    fn settings(port: u16, tls: SmtpTls, password: Option<&str>) -> SmtpSettings {
+        SmtpSettings {
+            host: "127.0.0.1".to_owned(),
+            port,
+            tls,
+            credentials: password.map(|password| SmtpCredentials {
+                username: "user".to_owned(),
+                password: SecretBox::new(Box::new(password.to_owned())),
+            }),
+            pool_max_size: 2,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn email(s: &str) -> Email {
+        Email::parse(SecretBox::new(Box::new(s.to_owned()))).unwrap()
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage::TwoFACode {
+            code: "123456".to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        }
+    }
+
+    fn email_client(settings: SmtpSettings) -> SmtpEmailClient {
+        SmtpEmailClient::new(settings).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_multipart_message() {
+        let server = SmtpStandIn::default();
+        let port = server.clone().start().await;
+        let client = email_client(settings(port, SmtpTls::None, None));
+
+        let outcome = client
+            .send_email(
+                &email("sender@example.com"),
+                &email("recipient@example.com"),
+                &message(),
+            )
+            .await;
+        assert!(outcome.is_ok(), "{outcome:?}");
+
+        let messages = server.messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        let data = &messages[0];
+        assert!(data.contains("From: sender@example.com"));
+        assert!(data.contains("To: recipient@example.com"));
+        assert!(data.contains("Subject: Your "));
+        assert!(data.contains("multipart/alternative"));
+        assert!(data.contains("text/plain"));
+        assert!(data.contains("text/html"));
+        assert!(data.contains("123456"));
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_with_credentials() {
+        let server = SmtpStandIn {
+            require_auth: true,
+            ..Default::default()
+        };
+        let port = server.clone().start().await;
+
+        let client = email_client(settings(port, SmtpTls::None, Some("secret")));
+        let outcome = client
+            .send_email(
+                &email("sender@example.com"),
+                &email("recipient@example.com"),
+                &message(),
+            )
+            .await;
+        assert!(outcome.is_ok(), "{outcome:?}");
+
+        let client = email_client(settings(port, SmtpTls::None, Some("wrong")));
+        let outcome = client
+            .send_email(
+                &email("sender@example.com"),
+                &email("recipient@example.com"),
+                &message(),
+            )
+            .await;
+        assert!(outcome.is_err());
+
+        assert_eq!(server.messages.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn send_email_reuses_pooled_connections() {
+        let server = SmtpStandIn::default();
+        let port = server.clone().start().await;
+        let client = email_client(settings(port, SmtpTls::None, None));
+
+        for _ in 0..3 {
+            client
+                .send_email(
+                    &email("sender@example.com"),
+                    &email("recipient@example.com"),
+                    &message(),
+                )
+                .await
+                .unwrap();
+            // Connections go back to the pool on a spawned task, so let it run before the next send
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert_eq!(server.messages.lock().unwrap().len(), 3);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_does_not_offer_starttls() {
+        let server = SmtpStandIn::default();
+        let port = server.clone().start().await;
+        let client = email_client(settings(port, SmtpTls::StartTls, Some("secret")));
+
+        let outcome = client
+            .send_email(
+                &email("sender@example.com"),
+                &email("recipient@example.com"),
+                &message(),
+            )
+            .await;
+
+        // Credentials and mail must never be sent over an unencrypted connection
+        assert!(outcome.is_err());
+        assert!(server.messages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_smtp_tls_from_str() {
+        assert_eq!("STARTTLS".parse::<SmtpTls>().unwrap(), SmtpTls::StartTls);
+        assert_eq!("tls".parse::<SmtpTls>().unwrap(), SmtpTls::Tls);
+        assert_eq!("none".parse::<SmtpTls>().unwrap(), SmtpTls::None);
+        assert!("ssl".parse::<SmtpTls>().is_err());
+    }
+}
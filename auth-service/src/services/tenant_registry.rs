@@ -0,0 +1,354 @@
+use color_eyre::eyre::{Context, Result, bail, eyre};
+use secrecy::SecretBox;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::domain::{Email, OriginPattern, SigningKey, Tenant, TenantId, TwoFAPolicy};
+use crate::utils::constants::AUTH_SERVICE_URL;
+
+// Requests under this prefix name their tenant in the path, e.g. `/realms/acme/login`
+pub const REALM_PATH_PREFIX: &str = "/realms/";
+
+// The tenants served by this deployment, looked up by ID or by the host name a request was sent to
+#[derive(Debug)]
+pub struct TenantRegistry {
+    tenants: HashMap<TenantId, Arc<Tenant>>,
+    hosts: HashMap<String, TenantId>,
+    // Serves requests that match neither a realm path nor a tenant host
+    default_tenant: Option<TenantId>,
+}
+
+impl TenantRegistry {
+    // Fails if IDs, hosts or issuers are shared between tenants, or a tenant has no signing key
+    pub fn new(tenants: Vec<Tenant>, default_tenant: Option<TenantId>) -> Result<Self> {
+        let mut by_id = HashMap::new();
+        let mut hosts = HashMap::new();
+        let mut issuers = HashSet::new();
+
+        for tenant in tenants {
+            if tenant.signing_keys.is_empty() {
+                bail!("Tenant {} has no JWT signing keys", tenant.id);
+            }
+            // Tokens are only told apart by their issuer and key, so each tenant needs its own
+            if !issuers.insert(tenant.jwt_issuer.clone()) {
+                bail!(
+                    "JWT issuer {} is used by more than one tenant",
+                    tenant.jwt_issuer
+                );
+            }
+            for host in &tenant.hosts {
+                if hosts
+                    .insert(normalize_host(host), tenant.id.clone())
+                    .is_some()
+                {
+                    bail!("Host {} is assigned to more than one tenant", host);
+                }
+            }
+            if by_id.contains_key(&tenant.id) {
+                bail!("Tenant {} is configured more than once", tenant.id);
+            }
+            by_id.insert(tenant.id.clone(), Arc::new(tenant));
+        }
+
+        if let Some(id) = &default_tenant
+            && !by_id.contains_key(id)
+        {
+            bail!("The default tenant {} is not configured", id);
+        }
+
+        Ok(Self {
+            tenants: by_id,
+            hosts,
+            default_tenant,
+        })
+    }
+
+    // A deployment with one tenant that serves every request
+    pub fn single(tenant: Tenant) -> Result<Self> {
+        let id = tenant.id.clone();
+        Self::new(vec![tenant], Some(id))
+    }
+
+    // Load tenants from a JSON file. Signing secrets are read from the environment variables it names.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read tenants file {}", path.display()))?;
+        let config: TenantsConfig = serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse tenants file {}", path.display()))?;
+
+        let tenants = config
+            .tenants
+            .into_iter()
+            .map(TenantConfig::into_tenant)
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(tenants, config.default)
+    }
+
+    pub fn get(&self, id: &TenantId) -> Option<Arc<Tenant>> {
+        self.tenants.get(id).cloned()
+    }
+
+    pub fn by_host(&self, host: &str) -> Option<Arc<Tenant>> {
+        self.hosts
+            .get(&normalize_host(host))
+            .and_then(|id| self.get(id))
+    }
+
+    // The tenant a request is for. A realm in the path wins over the host name, which wins over
+    // the default tenant. An unknown realm in the path never falls back to another tenant.
+    pub fn resolve(&self, host: Option<&str>, path: &str) -> Option<Arc<Tenant>> {
+        if let Some(realm) = realm_from_path(path) {
+            return TenantId::parse(realm).ok().and_then(|id| self.get(&id));
+        }
+        host.and_then(|host| self.by_host(host))
+            .or_else(|| self.default_tenant.as_ref().and_then(|id| self.get(id)))
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        self.tenants.values()
+    }
+}
+
+// The realm named by a `/realms/{tenant}/...` path
+fn realm_from_path(path: &str) -> Option<&str> {
+    path.strip_prefix(REALM_PATH_PREFIX)
+        .map(|rest| rest.split('/').next().unwrap_or_default())
+}
+
+// Host headers may carry a port and differ in case
+fn normalize_host(host: &str) -> String {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct TenantsConfig {
+    #[serde(default)]
+    default: Option<TenantId>,
+    tenants: Vec<TenantConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct TenantConfig {
+    id: TenantId,
+    name: String,
+    #[serde(default)]
+    hosts: Vec<String>,
+    // Defaults to the tenant's realm path under AUTH_SERVICE_URL
+    #[serde(default)]
+    public_url: Option<String>,
+    jwt: JwtConfig,
+    #[serde(default)]
+    cors_origins: Vec<OriginPattern>,
+    #[serde(default, rename = "twoFAPolicy")]
+    two_fa_policy: TwoFAPolicy,
+    email_sender: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct JwtConfig {
+    issuer: String,
+    // The first key signs new tokens
+    keys: Vec<KeyConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct KeyConfig {
+    kid: String,
+    secret_env: String,
+}
+
+impl TenantConfig {
+    fn into_tenant(self) -> Result<Tenant> {
+        let signing_keys = self
+            .jwt
+            .keys
+            .into_iter()
+            .map(|key| {
+                let secret = std::env::var(&key.secret_env)
+                    .ok()
+                    .filter(|secret| !secret.is_empty())
+                    .ok_or_else(|| {
+                        eyre!("{} must be set for tenant {}", key.secret_env, self.id)
+                    })?;
+                Ok(SigningKey {
+                    kid: key.kid,
+                    secret: SecretBox::new(Box::new(secret)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Tenant {
+            email_sender: Email::parse(SecretBox::new(Box::new(self.email_sender)))
+                .wrap_err_with(|| format!("Invalid email sender for tenant {}", self.id))?,
+            public_url: self.public_url.unwrap_or_else(|| {
+                format!(
+                    "{}{}{}",
+                    AUTH_SERVICE_URL.trim_end_matches('/'),
+                    REALM_PATH_PREFIX,
+                    self.id
+                )
+            }),
+            id: self.id,
+            name: self.name,
+            hosts: self.hosts,
+            jwt_issuer: self.jwt.issuer,
+            signing_keys,
+            cors_origins: self.cors_origins,
+            two_fa_policy: self.two_fa_policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(id: &str, hosts: &[&str]) -> Tenant {
+        Tenant {
+            id: TenantId::parse(id).unwrap(),
+            name: id.to_owned(),
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            public_url: format!("https://{}.example.com", id),
+            jwt_issuer: format!("https://{}.example.com", id),
+            signing_keys: vec![SigningKey {
+                kid: "1".to_owned(),
+                secret: SecretBox::new(Box::new(format!("{}-secret", id))),
+            }],
+            cors_origins: Vec::new(),
+            two_fa_policy: TwoFAPolicy::Optional,
+            email_sender: Email::parse(SecretBox::new(Box::new(format!("no-reply@{}.com", id))))
+                .unwrap(),
+        }
+    }
+
+    fn registry() -> TenantRegistry {
+        TenantRegistry::new(
+            vec![
+                tenant("main", &["auth.example.com"]),
+                tenant("acme", &["auth.acme.com"]),
+            ],
+            Some(TenantId::parse("main").unwrap()),
+        )
+        .unwrap()
+    }
+
+    fn resolved(registry: &TenantRegistry, host: Option<&str>, path: &str) -> Option<String> {
+        registry
+            .resolve(host, path)
+            .map(|tenant| tenant.id.to_string())
+    }
+
+    #[test]
+    fn test_resolve_by_host() {
+        let registry = registry();
+        assert_eq!(
+            resolved(&registry, Some("auth.acme.com"), "/login").as_deref(),
+            Some("acme")
+        );
+        assert_eq!(
+            resolved(&registry, Some("Auth.ACME.com:443"), "/login").as_deref(),
+            Some("acme")
+        );
+        // Unknown hosts fall back to the default tenant
+        assert_eq!(
+            resolved(&registry, Some("localhost:3000"), "/login").as_deref(),
+            Some("main")
+        );
+        assert_eq!(resolved(&registry, None, "/login").as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_resolve_by_path_prefix() {
+        let registry = registry();
+        assert_eq!(
+            resolved(&registry, Some("auth.example.com"), "/realms/acme/login").as_deref(),
+            Some("acme")
+        );
+        // An unknown realm is not served by the default tenant
+        assert_eq!(resolved(&registry, None, "/realms/other/login"), None);
+        assert_eq!(resolved(&registry, None, "/realms/Not Valid/login"), None);
+    }
+
+    #[test]
+    fn test_resolve_without_default_tenant() {
+        let registry = TenantRegistry::new(vec![tenant("acme", &["auth.acme.com"])], None).unwrap();
+        assert_eq!(resolved(&registry, Some("localhost"), "/login"), None);
+        assert_eq!(
+            resolved(&registry, Some("auth.acme.com"), "/login").as_deref(),
+            Some("acme")
+        );
+    }
+
+    #[test]
+    fn test_new_rejects_conflicting_tenants() {
+        let shared_host = vec![
+            tenant("a", &["auth.example.com"]),
+            tenant("b", &["auth.example.com"]),
+        ];
+        assert!(TenantRegistry::new(shared_host, None).is_err());
+
+        let mut same_issuer = tenant("b", &[]);
+        same_issuer.jwt_issuer = tenant("a", &[]).jwt_issuer;
+        assert!(TenantRegistry::new(vec![tenant("a", &[]), same_issuer], None).is_err());
+
+        let mut without_keys = tenant("a", &[]);
+        without_keys.signing_keys.clear();
+        assert!(TenantRegistry::new(vec![without_keys], None).is_err());
+
+        let unknown_default = Some(TenantId::parse("missing").unwrap());
+        assert!(TenantRegistry::new(vec![tenant("a", &[])], unknown_default).is_err());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = std::env::temp_dir().join(format!("tenants-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tenants.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "default": "main",
+                "tenants": [{
+                    "id": "main",
+                    "name": "Main",
+                    "hosts": ["auth.example.com"],
+                    "jwt": {"issuer": "https://auth.example.com", "keys": [{"kid": "2026-10", "secretEnv": "TENANT_REGISTRY_TEST_SECRET"}]},
+                    "corsOrigins": ["https://app.example.com"],
+                    "twoFAPolicy": "required",
+                    "emailSender": "no-reply@example.com"
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        // The secret has to be in the environment
+        assert!(TenantRegistry::from_file(&path).is_err());
+
+        // SAFETY: no other test reads this variable
+        unsafe { std::env::set_var("TENANT_REGISTRY_TEST_SECRET", "main-secret") };
+        let registry = TenantRegistry::from_file(&path).unwrap();
+        let tenant = registry.by_host("auth.example.com").unwrap();
+        assert_eq!(tenant.name, "Main");
+        assert!(tenant.public_url.ends_with("/realms/main"));
+        assert_eq!(tenant.active_signing_key().kid, "2026-10");
+        assert_eq!(
+            tenant.cors_origins,
+            vec![OriginPattern::parse("https://app.example.com").unwrap()]
+        );
+        assert_eq!(tenant.two_fa_policy, TwoFAPolicy::Required);
+        assert_eq!(tenant.email_sender.as_ref(), "no-reply@example.com");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use color_eyre::eyre::Result;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, SecretBox};
+
+use crate::domain::{PhoneNumber, SmsClient, SmsMessage};
+
+// Sends SMS through the Twilio Messages API, or any service that implements the same endpoint
+pub struct TwilioSmsClient {
+    http_client: Client,
+    base_url: String,
+    account_sid: String,
+    auth_token: SecretBox<String>,
+    sender: PhoneNumber,
+}
+
+impl TwilioSmsClient {
+    pub fn new(
+        base_url: String,
+        account_sid: String,
+        auth_token: SecretBox<String>,
+        sender: PhoneNumber,
+        http_client: Client,
+    ) -> Self {
+        Self {
+            http_client,
+            base_url,
+            account_sid,
+            auth_token,
+            sender,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for TwilioSmsClient {
+    #[tracing::instrument(name = "Sending SMS", skip_all)]
+    async fn send_sms(&self, recipient: &PhoneNumber, message: &SmsMessage) -> Result<()> {
+        // See https://www.twilio.com/docs/messaging/api/message-resource#create-a-message-resource
+        let url = Url::parse(&self.base_url)?.join(&format!(
+            "/2010-04-01/Accounts/{}/Messages.json",
+            self.account_sid
+        ))?;
+
+        let body = message.body();
+        let request_body = SendSmsRequest {
+            to: recipient.as_ref(),
+            from: self.sender.as_ref(),
+            body: &body,
+        };
+
+        self.http_client
+            .post(url)
+            .basic_auth(&self.account_sid, Some(self.auth_token.expose_secret()))
+            .form(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct SendSmsRequest<'a> {
+    to: &'a str,
+    from: &'a str,
+    body: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeDelta, Utc};
+    use wiremock::matchers::{any, body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ACCOUNT_SID: &str = "AC00000000000000000000000000000000";
+
+    fn message() -> SmsMessage {
+        SmsMessage::TwoFACode {
+            code: "123456".to_owned(),
+            expires_at: Utc::now() + TimeDelta::minutes(10),
+        }
+    }
+
+    fn phone_number() -> PhoneNumber {
+        PhoneNumber::parse("+14155552671").unwrap()
+    }
+
+    fn sms_client(base_url: String) -> TwilioSmsClient {
+        let http_client = Client::builder()
+            .timeout(std::time::Duration::from_millis(200))
+            .build()
+            .unwrap();
+        TwilioSmsClient::new(
+            base_url,
+            ACCOUNT_SID.to_owned(),
+            SecretBox::new(Box::new("auth-token".to_owned())),
+            PhoneNumber::parse("+15005550006").unwrap(),
+            http_client,
+        )
+    }
+
+    #[tokio::test]
+    async fn send_sms_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/2010-04-01/Accounts/{}/Messages.json",
+                ACCOUNT_SID
+            )))
+            // Basic auth with the account SID and auth token
+            .and(header(
+                "Authorization",
+                "Basic QUMwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDphdXRoLXRva2Vu",
+            ))
+            .and(header("Content-Type", "application/x-www-form-urlencoded"))
+            .and(body_string_contains("To=%2B14155552671"))
+            .and(body_string_contains("From=%2B15005550006"))
+            .and(body_string_contains("Body=123456+is+your"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &message()).await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_sms_fails_if_the_server_returns_400() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &message()).await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_sms_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(201).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &message()).await;
+
+        assert!(outcome.is_err());
+    }
+}
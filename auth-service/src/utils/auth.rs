@@ -1,38 +1,86 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
-use chrono::Utc;
-use color_eyre::eyre::{Context, ContextCompat, Result, eyre};
-use jsonwebtoken::{DecodingKey, EncodingKey, Validation, decode, encode};
-use serde::{Deserialize, Serialize};
+use axum::http::{HeaderMap, header::AUTHORIZATION};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::Cookie;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, ContextCompat, Result, bail, eyre};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
-use crate::app_state::BannedTokenStoreType;
+use crate::app_state::{AppState, BannedTokenStoreType};
 use crate::domain::user::Email;
+use crate::domain::{
+    AuthAPIError, AuthCookieSettings, AuthLevel, AuthMethod, Authentication, Password, Tenant,
+};
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+// Create cookie with a new JWT auth token, for a session authenticated as given
+#[tracing::instrument(skip_all)]
+pub fn generate_auth_cookie(
+    settings: &AuthCookieSettings,
+    tenant: &Tenant,
+    email: &Email,
+    authentication: &Authentication,
+) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(tenant, email, Some(authentication))?;
+    Ok(create_auth_cookie(settings, token))
+}
 
-// Create cookie with a new JWT auth token
+// Create cookie with a new JWT auth token for a session that was resumed without authenticating,
+// e.g. with a "remember me" cookie. Routes guarded by `require_step_up` need /reauthenticate.
 #[tracing::instrument(skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
-    Ok(create_auth_cookie(token))
+pub fn generate_resumed_auth_cookie(
+    settings: &AuthCookieSettings,
+    tenant: &Tenant,
+    email: &Email,
+) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(tenant, email, None)?;
+    Ok(create_auth_cookie(settings, token))
 }
 
-// Create cookie and set the value to the passed-in token string
-fn create_auth_cookie(token: String) -> Cookie<'static> {
-    let cookie = Cookie::build((JWT_COOKIE_NAME, token))
-        .path("/") // apple cookie to all URLs on the server
-        .http_only(true) // prevent JavaScript from accessing the cookie
-        .same_site(SameSite::Lax) // send cookie with "same-site" requests, and with "cross-site" top-level navigations.
-        .build();
+// Create cookie with a new JWT auth token that continues the session of `claims`, which the user
+// just authenticated again. The session keeps its ID, so revoking it covers both tokens.
+#[tracing::instrument(skip_all)]
+pub fn generate_reauthenticated_auth_cookie(
+    settings: &AuthCookieSettings,
+    tenant: &Tenant,
+    claims: &Claims,
+    authentication: &Authentication,
+) -> Result<Cookie<'static>> {
+    let jti = match claims.jti.is_empty() {
+        true => Uuid::new_v4().to_string(),
+        false => claims.jti.clone(),
+    };
+    let token = sign_session(tenant, claims.sub.clone(), jti, Some(authentication))?;
+    Ok(create_auth_cookie(settings, token))
+}
 
-    cookie
+// Create cookie and set the value to the passed-in token string
+fn create_auth_cookie(settings: &AuthCookieSettings, token: String) -> Cookie<'static> {
+    settings.auth_cookie(token)
 }
 
 // This value determines how long the JWT auth token is valid for
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
-// Create JWT auth token
+// Create JWT auth token for a new session
 #[tracing::instrument(skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
+fn generate_auth_token(
+    tenant: &Tenant,
+    email: &Email,
+    authentication: Option<&Authentication>,
+) -> Result<String> {
+    let sub = email.normalized().to_owned();
+    sign_session(tenant, sub, Uuid::new_v4().to_string(), authentication)
+}
+
+fn sign_session(
+    tenant: &Tenant,
+    sub: String,
+    jti: String,
+    authentication: Option<&Authentication>,
+) -> Result<String> {
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
         .wrap_err("failed to create 10 minute time delta")?;
 
@@ -48,20 +96,35 @@ fn generate_auth_token(email: &Email) -> Result<String> {
         exp
     ))?;
 
-    let sub = email.as_ref().to_owned();
-
-    let claims = Claims { sub, exp };
+    let claims = Claims {
+        sub,
+        exp,
+        iss: tenant.jwt_issuer.clone(),
+        jti,
+        auth_time: authentication.map(|authentication| authentication.time.timestamp()),
+        acr: authentication.map(Authentication::level),
+        amr: authentication
+            .map(|authentication| authentication.methods.clone())
+            .unwrap_or_default(),
+    };
 
-    create_token(&claims)
+    create_token(tenant, &claims)
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// Check if JWT auth token is valid by decoding it with the tenant's keys, then make sure
+// it was not revoked. Tokens issued by another tenant are rejected.
 #[tracing::instrument(skip_all)]
 pub async fn validate_token(
+    tenant: &Tenant,
     token: &str,
     banned_token_store: BannedTokenStoreType,
 ) -> Result<Claims> {
-    match banned_token_store.read().await.contains_token(token).await {
+    let claims = decode_claims(tenant, token)?;
+
+    match banned_token_store
+        .contains_token(&tenant.id, &claims.revocation_id(token))
+        .await
+    {
         Ok(value) => {
             if value {
                 return Err(eyre!("token is banned"));
@@ -70,56 +133,490 @@ pub async fn validate_token(
         Err(e) => return Err(e.into()),
     }
 
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    Ok(claims)
+}
+
+// Check the signature, issuer and expiry of a JWT auth token, without looking for revocations
+pub fn decode_claims(tenant: &Tenant, token: &str) -> Result<Claims> {
+    decode_token::<Claims>(tenant, token, validation(tenant)).wrap_err("failed to decode token")
+}
+
+// The account signed in with the JWT cookie, for routes that act on the caller's own account
+#[tracing::instrument(skip_all)]
+pub async fn authenticated_email(
+    state: &AppState,
+    tenant: &Tenant,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<Email, AuthAPIError> {
+    let claims = authenticated_claims(state, tenant, headers, jar).await?;
+    claims.email()
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
+// The claims of the session the request is authenticated with
 #[tracing::instrument(skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
+pub async fn authenticated_claims(
+    state: &AppState,
+    tenant: &Tenant,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<Claims, AuthAPIError> {
+    let token =
+        session_token(&state.auth_cookie, headers, jar).ok_or(AuthAPIError::MissingToken)?;
+    validate_token(tenant, token, state.banned_token_store.clone())
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)
+}
+
+// The token a request is authenticated with. A bearer token wins over the auth cookie, so a
+// request that sends one is never acting on the cookie's authority.
+pub fn session_token<'a>(
+    settings: &AuthCookieSettings,
+    headers: &'a HeaderMap,
+    jar: &'a CookieJar,
+) -> Option<&'a str> {
+    bearer_token(headers).or_else(|| {
+        jar.get(&settings.cookie_name())
+            .map(|cookie| cookie.value())
+    })
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// Compares without returning early, so response timing does not reveal how much of a secret matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Magic link tokens carry this audience, so they can never be mistaken for auth tokens
+const MAGIC_LINK_AUDIENCE: &str = "magic-link";
+
+// Sign a magic link ID so that links can be checked before touching the store.
+// The token expires with the link; single use is enforced by the store.
+#[tracing::instrument(skip_all)]
+pub fn generate_magic_link_token(tenant: &Tenant, id: &str, ttl_seconds: u64) -> Result<String> {
+    let exp = Utc::now().timestamp() as u64 + ttl_seconds;
+    let claims = MagicLinkClaims {
+        jti: id.to_owned(),
+        aud: MAGIC_LINK_AUDIENCE.to_owned(),
+        iss: tenant.jwt_issuer.clone(),
+        exp: exp
+            .try_into()
+            .wrap_err("failed to cast exp time to usize")?,
+    };
+    create_token(tenant, &claims).wrap_err("failed to create magic link token")
+}
+
+// Check the signature and expiry of a magic link token and return the link ID
+#[tracing::instrument(skip_all)]
+pub fn validate_magic_link_token(tenant: &Tenant, token: &str) -> Result<String> {
+    let mut validation = validation(tenant);
+    validation.set_audience(&[MAGIC_LINK_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud", "iss"]);
+    // No clock skew allowance; the link should stop working when the email says it does
+    validation.leeway = 0;
+
+    decode_token::<MagicLinkClaims>(tenant, token, validation)
+        .map(|claims| claims.jti)
+        .wrap_err("failed to decode magic link token")
+}
+
+// Tokens of the magic link confirmation form carry this audience
+const MAGIC_LINK_CONFIRMATION_AUDIENCE: &str = "magic-link-confirmation";
+
+// Sign a magic link ID for the browser a confirmation form is rendered for, identified by the
+// digest of its binding cookie. Another site cannot obtain one for the user's browser, so it
+// cannot submit the form on their behalf.
+#[tracing::instrument(skip_all)]
+pub fn generate_magic_link_confirmation_token(
+    tenant: &Tenant,
+    id: &str,
+    browser_binding: &str,
+    ttl_seconds: u64,
+) -> Result<String> {
+    let exp = Utc::now().timestamp() as u64 + ttl_seconds;
+    let claims = MagicLinkConfirmationClaims {
+        jti: id.to_owned(),
+        bnd: browser_binding.to_owned(),
+        aud: MAGIC_LINK_CONFIRMATION_AUDIENCE.to_owned(),
+        iss: tenant.jwt_issuer.clone(),
+        exp: exp
+            .try_into()
+            .wrap_err("failed to cast exp time to usize")?,
+    };
+    create_token(tenant, &claims).wrap_err("failed to create magic link confirmation token")
+}
+
+// Check the signature and expiry of a confirmation form token, and that it was rendered for the
+// link and the browser given
+#[tracing::instrument(skip_all)]
+pub fn validate_magic_link_confirmation_token(
+    tenant: &Tenant,
+    token: &str,
+    id: &str,
+    browser_binding: &str,
+) -> Result<()> {
+    let mut validation = validation(tenant);
+    validation.set_audience(&[MAGIC_LINK_CONFIRMATION_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud", "iss"]);
+    validation.leeway = 0;
+
+    let claims = decode_token::<MagicLinkConfirmationClaims>(tenant, token, validation)
+        .wrap_err("failed to decode magic link confirmation token")?;
+    if claims.jti != id || !constant_time_eq(claims.bnd.as_bytes(), browser_binding.as_bytes()) {
+        bail!("magic link confirmation token was rendered for another link or browser");
+    }
+    Ok(())
+}
+
+// Trusted device tokens carry this audience, so they can never be mistaken for auth tokens
+const TRUSTED_DEVICE_AUDIENCE: &str = "trusted-device";
+
+// Sign a trusted device ID for the account, so that a cookie can be checked before touching
+// the store, and cannot be moved to another account. Revocation is enforced by the store.
+#[tracing::instrument(skip_all)]
+pub fn generate_trusted_device_token(
+    tenant: &Tenant,
+    id: &str,
+    email: &Email,
+    ttl_seconds: u64,
+) -> Result<String> {
+    let exp = Utc::now().timestamp() as u64 + ttl_seconds;
+    let claims = TrustedDeviceClaims {
+        jti: id.to_owned(),
+        sub: email.normalized().to_owned(),
+        aud: TRUSTED_DEVICE_AUDIENCE.to_owned(),
+        iss: tenant.jwt_issuer.clone(),
+        exp: exp
+            .try_into()
+            .wrap_err("failed to cast exp time to usize")?,
+    };
+    create_token(tenant, &claims).wrap_err("failed to create trusted device token")
+}
+
+// Check the signature and expiry of a trusted device token and return the device ID,
+// if the token was issued to the account
+#[tracing::instrument(skip_all)]
+pub fn validate_trusted_device_token(
+    tenant: &Tenant,
+    token: &str,
+    email: &Email,
+) -> Result<String> {
+    let mut validation = validation(tenant);
+    validation.set_audience(&[TRUSTED_DEVICE_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+    validation.sub = Some(email.normalized().to_owned());
+    validation.leeway = 0;
+
+    decode_token::<TrustedDeviceClaims>(tenant, token, validation)
+        .map(|claims| claims.jti)
+        .wrap_err("failed to decode trusted device token")
+}
+
+// Sign-in alert tokens carry this audience, so they can never be mistaken for auth tokens
+const SIGN_IN_ALERT_AUDIENCE: &str = "sign-in-alert";
+
+// Sign the session and device of a sign-in, for the "this wasn't me" link of a new device
+// email. The link outlives the session, so that it can still revoke what came with it.
+#[tracing::instrument(skip_all)]
+pub fn generate_sign_in_alert_token(
+    tenant: &Tenant,
+    session_id: &str,
+    email: &Email,
+    fingerprint: &str,
+    ttl_seconds: u64,
+) -> Result<String> {
+    let exp = Utc::now().timestamp() as u64 + ttl_seconds;
+    let claims = SignInAlertClaims {
+        jti: session_id.to_owned(),
+        sub: email.normalized().to_owned(),
+        dev: fingerprint.to_owned(),
+        aud: SIGN_IN_ALERT_AUDIENCE.to_owned(),
+        iss: tenant.jwt_issuer.clone(),
+        exp: exp
+            .try_into()
+            .wrap_err("failed to cast exp time to usize")?,
+    };
+    create_token(tenant, &claims).wrap_err("failed to create sign-in alert token")
+}
+
+// Check the signature and expiry of a sign-in alert token and return its claims
+#[tracing::instrument(skip_all)]
+pub fn validate_sign_in_alert_token(tenant: &Tenant, token: &str) -> Result<SignInAlertClaims> {
+    let mut validation = validation(tenant);
+    validation.set_audience(&[SIGN_IN_ALERT_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+    validation.leeway = 0;
+
+    decode_token::<SignInAlertClaims>(tenant, token, validation)
+        .wrap_err("failed to decode sign-in alert token")
+}
+
+// Password reset tokens carry this audience, so they can never be mistaken for auth tokens
+const PASSWORD_RESET_AUDIENCE: &str = "password-reset";
+
+// Sign a password reset for the account. The token carries a digest of the current password
+// hash, so it stops working once the password changes and can be used only once.
+#[tracing::instrument(skip_all)]
+pub fn generate_password_reset_token(
+    tenant: &Tenant,
+    email: &Email,
+    password_hash: &Password,
+    ttl_seconds: u64,
+) -> Result<String> {
+    let exp = Utc::now().timestamp() as u64 + ttl_seconds;
+    let claims = PasswordResetClaims {
+        sub: email.normalized().to_owned(),
+        pwd: password_digest(password_hash),
+        aud: PASSWORD_RESET_AUDIENCE.to_owned(),
+        iss: tenant.jwt_issuer.clone(),
+        exp: exp
+            .try_into()
+            .wrap_err("failed to cast exp time to usize")?,
+    };
+    create_token(tenant, &claims).wrap_err("failed to create password reset token")
+}
+
+// Check the signature and expiry of a password reset token and return its claims. Whether the
+// password changed since is up to the caller, see `PasswordResetClaims::is_current`.
+#[tracing::instrument(skip_all)]
+pub fn validate_password_reset_token(tenant: &Tenant, token: &str) -> Result<PasswordResetClaims> {
+    let mut validation = validation(tenant);
+    validation.set_audience(&[PASSWORD_RESET_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+    validation.leeway = 0;
+
+    decode_token::<PasswordResetClaims>(tenant, token, validation)
+        .wrap_err("failed to decode password reset token")
+}
+
+fn password_digest(password_hash: &Password) -> String {
+    hex::encode(Sha256::digest(
+        password_hash.as_ref().expose_secret().as_bytes(),
+    ))
+}
+
+// Sign claims with the tenant's active key, naming the key in the header so it can be rotated
+#[tracing::instrument(skip_all)]
+fn create_token<T: Serialize>(tenant: &Tenant, claims: &T) -> Result<String> {
+    let key = tenant.active_signing_key();
+    let header = Header {
+        kid: Some(key.kid.clone()),
+        ..Header::default()
+    };
     encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &header,
+        claims,
+        &EncodingKey::from_secret(key.secret.expose_secret().as_bytes()),
     )
     .wrap_err("failed to create token")
 }
 
+// Only tokens carrying the tenant's issuer are accepted
+fn validation(tenant: &Tenant) -> Validation {
+    let mut validation = Validation::default();
+    validation.set_issuer(&[&tenant.jwt_issuer]);
+    validation.set_required_spec_claims(&["exp", "iss"]);
+    validation
+}
+
+// Decode a token with the tenant key named in its header. Tokens without a `kid` are checked
+// against the active key, which covers tokens issued before keys had IDs.
+fn decode_token<T: DeserializeOwned>(
+    tenant: &Tenant,
+    token: &str,
+    validation: Validation,
+) -> Result<T> {
+    let header = decode_header(token).wrap_err("failed to decode token header")?;
+    let key = match header.kid.as_deref() {
+        Some(kid) => tenant
+            .signing_key(kid)
+            .ok_or_else(|| eyre!("unknown signing key {}", kid))?,
+        None => tenant.active_signing_key(),
+    };
+    decode::<T>(
+        token,
+        &DecodingKey::from_secret(key.secret.expose_secret().as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .wrap_err("failed to verify token")
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    // The issuer of the tenant the token belongs to
+    pub iss: String,
+    // Identifies the token when it is revoked. Empty in tokens issued before tokens had IDs.
+    #[serde(default)]
+    pub jti: String,
+    // When and how the user authenticated, as in OpenID Connect. Absent from tokens of resumed
+    // sessions and tokens issued before tokens recorded it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<AuthLevel>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<AuthMethod>,
+}
+
+impl Claims {
+    pub fn email(&self) -> Result<Email, AuthAPIError> {
+        Email::parse(SecretBox::new(Box::new(self.sub.clone())))
+            .map_err(AuthAPIError::UnexpectedError)
+    }
+
+    // When and how the session was authenticated, if the token says
+    pub fn authentication(&self) -> Option<Authentication> {
+        let time = DateTime::from_timestamp(self.auth_time?, 0)?;
+        Some(Authentication {
+            time,
+            methods: self.amr.clone(),
+        })
+    }
+
+    // The ID the token is revoked under. Tokens without a `jti` are revoked under their digest.
+    pub fn revocation_id(&self, token: &str) -> String {
+        if self.jti.is_empty() {
+            hex::encode(Sha256::digest(token.as_bytes()))
+        } else {
+            self.jti.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MagicLinkClaims {
+    jti: String,
+    aud: String,
+    iss: String,
+    exp: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MagicLinkConfirmationClaims {
+    // The magic link ID
+    jti: String,
+    // The browser binding the form was rendered for, see `MagicLink::browser_binding`
+    bnd: String,
+    aud: String,
+    iss: String,
+    exp: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrustedDeviceClaims {
+    jti: String,
+    // The account the device is trusted for
+    sub: String,
+    aud: String,
+    iss: String,
+    exp: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignInAlertClaims {
+    // The session the sign-in started, see `Claims::jti`
+    pub jti: String,
+    // The account that was signed in to
+    pub sub: String,
+    // The fingerprint of the device, see `ClientInfo::fingerprint`
+    pub dev: String,
+    aud: String,
+    iss: String,
+    exp: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordResetClaims {
+    // The account whose password can be reset
+    pub sub: String,
+    // Digest of the password hash the token was issued against
+    pwd: String,
+    aud: String,
+    iss: String,
+    exp: usize,
+}
+
+impl PasswordResetClaims {
+    // Whether the password is still the one the token was issued against
+    pub fn is_current(&self, password_hash: &Password) -> bool {
+        constant_time_eq(
+            self.pwd.as_bytes(),
+            password_digest(password_hash).as_bytes(),
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{SigningKey, TenantId, TwoFAPolicy};
     use crate::services::HashsetBannedTokenStore;
+    use crate::utils::constants::JWT_COOKIE_NAME;
+    use axum_extra::extract::cookie::SameSite;
     use chrono::Utc;
     use secrecy::SecretBox;
     use std::sync::Arc;
-    use tokio::sync::RwLock;
+
+    fn tenant(id: &str) -> Tenant {
+        Tenant {
+            id: TenantId::parse(id).unwrap(),
+            name: id.to_owned(),
+            hosts: Vec::new(),
+            public_url: format!("https://{}.example.com", id),
+            jwt_issuer: format!("https://{}.example.com", id),
+            signing_keys: vec![SigningKey {
+                kid: "1".to_owned(),
+                secret: SecretBox::new(Box::new(format!("{}-secret", id))),
+            }],
+            cors_origins: Vec::new(),
+            two_fa_policy: TwoFAPolicy::Optional,
+            email_sender: Email::parse(SecretBox::new(Box::new("no-reply@example.com".to_owned())))
+                .unwrap(),
+        }
+    }
+
+    fn email() -> Email {
+        Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap()
+    }
+
+    fn banned_token_store() -> BannedTokenStoreType {
+        Arc::new(HashsetBannedTokenStore::default())
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let cookie = generate_auth_cookie(
+            &AuthCookieSettings::default(),
+            &tenant("main"),
+            &email(),
+            &Authentication::now(vec![AuthMethod::Pwd]),
+        )
+        .unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.http_only(), Some(true));
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        // The cookie expires with the token
+        assert_eq!(
+            cookie.max_age().map(|age| age.whole_seconds()),
+            Some(TOKEN_TTL_SECONDS)
+        );
     }
 
     #[tokio::test]
     async fn test_create_auth_cookie() {
         let token = "test_token".to_owned();
-        let cookie = create_auth_cookie(token.clone());
+        let cookie = create_auth_cookie(&AuthCookieSettings::default(), token.clone());
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value(), token);
         assert_eq!(cookie.path(), Some("/"));
@@ -129,19 +626,20 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(&tenant("main"), &email(), None).unwrap();
         assert_eq!(result.split('.').count(), 3);
+        assert_eq!(decode_header(&result).unwrap().kid.as_deref(), Some("1"));
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse(SecretBox::new(Box::new("test@example.com".to_string()))).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let banned_token_store: BannedTokenStoreType =
-            Arc::new(RwLock::new(Box::new(HashsetBannedTokenStore::default())));
-        let result = validate_token(&token, banned_token_store).await.unwrap();
+        let tenant = tenant("main");
+        let token = generate_auth_token(&tenant, &email(), None).unwrap();
+        let result = validate_token(&tenant, &token, banned_token_store())
+            .await
+            .unwrap();
         assert_eq!(result.sub, "test@example.com");
+        assert_eq!(result.iss, "https://main.example.com");
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -151,12 +649,245 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_revoked_tokens_are_rejected_by_id() {
+        let tenant = tenant("main");
+        let store = banned_token_store();
+        let token = generate_auth_token(&tenant, &email(), None).unwrap();
+        let other_token = generate_auth_token(&tenant, &email(), None).unwrap();
+
+        let claims = decode_claims(&tenant, &token).unwrap();
+        assert!(Uuid::parse_str(&claims.jti).is_ok());
+        store
+            .add_token(&tenant.id, claims.revocation_id(&token))
+            .await
+            .unwrap();
+
+        assert!(
+            validate_token(&tenant, &token, store.clone())
+                .await
+                .is_err()
+        );
+        assert!(validate_token(&tenant, &other_token, store).await.is_ok());
+
+        // Tokens issued without an ID are revoked under their digest
+        let legacy = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: claims.exp,
+            iss: tenant.jwt_issuer.clone(),
+            jti: String::new(),
+            auth_time: None,
+            acr: None,
+            amr: Vec::new(),
+        };
+        let legacy_token = create_token(&tenant, &legacy).unwrap();
+        assert_eq!(legacy.revocation_id(&legacy_token).len(), 64);
+        assert_ne!(
+            legacy.revocation_id(&legacy_token),
+            legacy.revocation_id(&token)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_tokens_record_how_the_user_authenticated() {
+        let tenant = tenant("main");
+        let authentication = Authentication::now(vec![AuthMethod::Pwd, AuthMethod::Otp]);
+        let token = generate_auth_token(&tenant, &email(), Some(&authentication)).unwrap();
+
+        let claims = decode_claims(&tenant, &token).unwrap();
+        assert_eq!(claims.auth_time, Some(authentication.time.timestamp()));
+        assert_eq!(claims.acr, Some(AuthLevel::Aal2));
+        assert_eq!(claims.amr, vec![AuthMethod::Pwd, AuthMethod::Otp]);
+
+        // Resumed sessions carry none of it
+        let token = generate_auth_token(&tenant, &email(), None).unwrap();
+        let claims = decode_claims(&tenant, &token).unwrap();
+        assert_eq!((claims.auth_time, claims.acr), (None, None));
+        assert!(claims.amr.is_empty());
+        assert!(claims.authentication().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reauthenticated_tokens_continue_the_session() {
+        let tenant = tenant("main");
+        let settings = AuthCookieSettings::default();
+        let token = generate_auth_token(&tenant, &email(), None).unwrap();
+        let claims = decode_claims(&tenant, &token).unwrap();
+
+        let authentication = Authentication::now(vec![AuthMethod::Pwd]);
+        let cookie =
+            generate_reauthenticated_auth_cookie(&settings, &tenant, &claims, &authentication)
+                .unwrap();
+        let upgraded = decode_claims(&tenant, cookie.value()).unwrap();
+        assert_eq!(upgraded.jti, claims.jti);
+        assert_eq!(upgraded.sub, claims.sub);
+        assert_eq!(
+            upgraded.authentication(),
+            Some(Authentication {
+                time: DateTime::from_timestamp(authentication.time.timestamp(), 0).unwrap(),
+                methods: vec![AuthMethod::Pwd],
+            })
+        );
+        assert_eq!(upgraded.acr, Some(AuthLevel::Aal1));
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_only_valid_for_their_tenant() {
+        let token = generate_auth_token(&tenant("main"), &email(), None).unwrap();
+        assert!(
+            validate_token(&tenant("acme"), &token, banned_token_store())
+                .await
+                .is_err()
+        );
+
+        // Even with the same key, the issuer has to match
+        let mut same_key = tenant("acme");
+        same_key.signing_keys = tenant("main").signing_keys;
+        assert!(
+            validate_token(&same_key, &token, banned_token_store())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tokens_signed_with_a_previous_key_stay_valid() {
+        let old = tenant("main");
+        let token = generate_auth_token(&old, &email(), None).unwrap();
+
+        let mut rotated = tenant("main");
+        rotated.signing_keys.insert(
+            0,
+            SigningKey {
+                kid: "2".to_owned(),
+                secret: SecretBox::new(Box::new("new-secret".to_owned())),
+            },
+        );
+        assert!(
+            validate_token(&rotated, &token, banned_token_store())
+                .await
+                .is_ok()
+        );
+        let new_token = generate_auth_token(&rotated, &email(), None).unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid.as_deref(), Some("2"));
+
+        // Once the old key is removed, its tokens are rejected
+        rotated.signing_keys.truncate(1);
+        assert!(
+            validate_token(&rotated, &token, banned_token_store())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_magic_link_token_round_trip() {
+        let tenant = tenant("main");
+        let token = generate_magic_link_token(&tenant, "link-id", 600).unwrap();
+        assert_eq!(
+            validate_magic_link_token(&tenant, &token).unwrap(),
+            "link-id"
+        );
+
+        // A tampered signature is rejected
+        let mut tampered = token.clone();
+        tampered.pop();
+        tampered.push(if token.ends_with('A') { 'B' } else { 'A' });
+        assert!(validate_magic_link_token(&tenant, &tampered).is_err());
+
+        // An expired token is rejected
+        let expired = create_token(
+            &tenant,
+            &MagicLinkClaims {
+                jti: "link-id".to_owned(),
+                aud: MAGIC_LINK_AUDIENCE.to_owned(),
+                iss: tenant.jwt_issuer.clone(),
+                exp: (Utc::now().timestamp() - 1) as usize,
+            },
+        )
+        .unwrap();
+        assert!(validate_magic_link_token(&tenant, &expired).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_magic_link_and_auth_tokens_are_not_interchangeable() {
+        let tenant = tenant("main");
+        let auth_token = generate_auth_token(&tenant, &email(), None).unwrap();
+        assert!(validate_magic_link_token(&tenant, &auth_token).is_err());
+
+        let magic_link_token = generate_magic_link_token(&tenant, "link-id", 600).unwrap();
+        assert!(
+            validate_token(&tenant, &magic_link_token, banned_token_store())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trusted_device_tokens_are_bound_to_their_account() {
+        let main = tenant("main");
+        let token = generate_trusted_device_token(&main, "device-id", &email(), 600).unwrap();
+        assert_eq!(
+            validate_trusted_device_token(&main, &token, &email()).unwrap(),
+            "device-id"
+        );
+
+        let other = Email::parse(SecretBox::new(Box::new("bob@example.com".to_owned()))).unwrap();
+        assert!(validate_trusted_device_token(&main, &token, &other).is_err());
+        assert!(validate_trusted_device_token(&tenant("other"), &token, &email()).is_err());
+
+        // Neither an auth token nor a magic link token
+        assert!(
+            validate_token(&main, &token, banned_token_store())
+                .await
+                .is_err()
+        );
+        assert!(validate_magic_link_token(&main, &token).is_err());
+        let auth_token = generate_auth_token(&main, &email(), None).unwrap();
+        assert!(validate_trusted_device_token(&main, &auth_token, &email()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_alert_token_round_trip() {
+        let main = tenant("main");
+        let token = generate_sign_in_alert_token(&main, "session-id", &email(), "fingerprint", 600)
+            .unwrap();
+        let claims = validate_sign_in_alert_token(&main, &token).unwrap();
+        assert_eq!(claims.jti, "session-id");
+        assert_eq!(claims.sub, email().normalized());
+        assert_eq!(claims.dev, "fingerprint");
+
+        assert!(validate_sign_in_alert_token(&tenant("other"), &token).is_err());
+        assert!(
+            validate_token(&main, &token, banned_token_store())
+                .await
+                .is_err()
+        );
+        let auth_token = generate_auth_token(&main, &email(), None).unwrap();
+        assert!(validate_sign_in_alert_token(&main, &auth_token).is_err());
+    }
+
+    #[test]
+    fn test_password_reset_tokens_stop_working_once_the_password_changes() {
+        let main = tenant("main");
+        let hash = |s: &str| Password::parse(SecretBox::new(Box::new(s.to_owned()))).unwrap();
+        let token =
+            generate_password_reset_token(&main, &email(), &hash("old-hash-1"), 600).unwrap();
+
+        let claims = validate_password_reset_token(&main, &token).unwrap();
+        assert_eq!(claims.sub, email().normalized());
+        assert!(claims.is_current(&hash("old-hash-1")));
+        assert!(!claims.is_current(&hash("new-hash-2")));
+
+        assert!(validate_password_reset_token(&tenant("other"), &token).is_err());
+        assert!(validate_sign_in_alert_token(&main, &token).is_err());
+        assert!(validate_magic_link_token(&main, &token).is_err());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
-        let banned_token_store: BannedTokenStoreType =
-            Arc::new(RwLock::new(Box::new(HashsetBannedTokenStore::default())));
-        let result = validate_token(&token, banned_token_store).await;
+        let result = validate_token(&tenant("main"), &token, banned_token_store()).await;
         assert!(result.is_err());
     }
 }
@@ -0,0 +1,181 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, header::USER_AGENT},
+    middleware::Next,
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::app_state::AppState;
+
+const X_REAL_IP: &str = "x-real-ip";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+// Who made a request. The address is the one resolve_client_address found; the user agent is
+// whatever the client sent. Used for audit records, notifications and risk scoring, and to refuse
+// requests that do not match, never to grant access.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            ip_address: header_value(headers, X_REAL_IP),
+            user_agent: header_value(headers, USER_AGENT.as_str()),
+        }
+    }
+
+    // The network the request came from: the /24 of an IPv4 address or the /48 of an IPv6 one,
+    // so that a home router renewing its address does not make a new device
+    pub fn ip_prefix(&self) -> Option<String> {
+        let ip_address = self.ip_address.as_deref()?;
+        Some(match ip_address.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                let [a, b, c, _] = ip.octets();
+                format!("{}.{}.{}.0/24", a, b, c)
+            }
+            Ok(IpAddr::V6(ip)) => {
+                let [a, b, c, ..] = ip.segments();
+                format!("{:x}:{:x}:{:x}::/48", a, b, c)
+            }
+            // Not an address we understand; compare it as it is
+            Err(_) => ip_address.to_owned(),
+        })
+    }
+
+    // Tells the devices of an account apart by browser and network. Like the rest of the
+    // struct, it is only fit for notifications: any client can send the same headers.
+    pub fn fingerprint(&self) -> String {
+        let device = format!(
+            "{}\n{}",
+            self.user_agent.as_deref().unwrap_or_default(),
+            self.ip_prefix().unwrap_or_default()
+        );
+        hex::encode(Sha256::digest(device.as_bytes()))
+    }
+}
+
+// Replace the X-Real-IP and X-Forwarded-For headers a request came with by X-Real-IP set to the
+// address of the client: the peer address, or the one a trusted proxy forwarded, see
+// TrustedProxies. Without a known address, X-Real-IP is left out.
+pub async fn resolve_client_address(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip());
+    let address = state
+        .trusted_proxies
+        .client_address(peer, request.headers())
+        .and_then(|ip| HeaderValue::from_str(&ip.to_string()).ok());
+
+    let headers = request.headers_mut();
+    headers.remove(X_FORWARDED_FOR);
+    match address {
+        Some(address) => headers.insert(X_REAL_IP, address),
+        None => headers.remove(X_REAL_IP),
+    };
+    next.run(request).await
+}
+
+// Where the request came from, if the proxy looked the address up, e.g. with nginx's geoip2 module
+// setting X-Geo-City and X-Geo-Country, or behind Cloudflare, which sets CF-IPCountry
+pub fn approximate_location(headers: &HeaderMap) -> Option<String> {
+    let country =
+        header_value(headers, "x-geo-country").or_else(|| header_value(headers, "cf-ipcountry"));
+    match (header_value(headers, "x-geo-city"), country) {
+        (Some(city), Some(country)) => Some(format!("{}, {}", city, country)),
+        (city, country) => city.or(country),
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_REAL_IP, HeaderValue::from_static("203.0.113.7"));
+        headers.insert(USER_AGENT, HeaderValue::from_static("curl/8.0"));
+
+        let client = ClientInfo::from_headers(&headers);
+        assert_eq!(client.ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(client.user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!(
+            ClientInfo::from_headers(&HeaderMap::new()),
+            ClientInfo::default()
+        );
+    }
+
+    fn client(ip_address: &str, user_agent: &str) -> ClientInfo {
+        ClientInfo {
+            ip_address: Some(ip_address.to_owned()),
+            user_agent: Some(user_agent.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_ip_prefix() {
+        assert_eq!(
+            client("203.0.113.7", "curl").ip_prefix().as_deref(),
+            Some("203.0.113.0/24")
+        );
+        assert_eq!(
+            client("2001:db8:85a3:8d3:1319:8a2e:370:7348", "curl")
+                .ip_prefix()
+                .as_deref(),
+            Some("2001:db8:85a3::/48")
+        );
+        assert_eq!(ClientInfo::default().ip_prefix(), None);
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_the_host_part_of_the_address() {
+        let laptop = client("203.0.113.7", "Firefox");
+        assert_eq!(
+            laptop.fingerprint(),
+            client("203.0.113.200", "Firefox").fingerprint()
+        );
+        assert_ne!(
+            laptop.fingerprint(),
+            client("198.51.100.7", "Firefox").fingerprint()
+        );
+        assert_ne!(
+            laptop.fingerprint(),
+            client("203.0.113.7", "Chrome").fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_approximate_location() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(approximate_location(&headers), None);
+
+        headers.insert("cf-ipcountry", HeaderValue::from_static("DE"));
+        assert_eq!(approximate_location(&headers).as_deref(), Some("DE"));
+
+        headers.insert("x-geo-city", HeaderValue::from_static("Berlin"));
+        headers.insert("x-geo-country", HeaderValue::from_static("Germany"));
+        assert_eq!(
+            approximate_location(&headers).as_deref(),
+            Some("Berlin, Germany")
+        );
+    }
+}
@@ -1,9 +1,70 @@
+use crate::domain::{CookiePrefix, EmailProvider};
+use crate::services::{RevocationFailurePolicy, StoreBackend};
 use dotenvy::dotenv;
 use lazy_static::lazy_static;
 use std::env as std_env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::auth::TOKEN_TTL_SECONDS;
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const DEFAULT_AUTH_COOKIE_PREFIX: CookiePrefix = CookiePrefix::None;
+pub const DEFAULT_AUTH_COOKIE_SECURE: bool = false;
+pub const DEFAULT_AUTH_COOKIE_SAME_SITE: &str = "lax";
+// Holds the long-lived "remember me" token
+pub const REMEMBER_ME_COOKIE_NAME: &str = "remember_me";
+pub const DEFAULT_REMEMBER_ME_MAX_AGE_SECONDS: u64 = 30 * 24 * 60 * 60; // 30 days
+// Lets a device that passed 2FA skip it on later logins
+pub const TRUSTED_DEVICE_COOKIE_NAME: &str = "trusted_device";
+pub const DEFAULT_TRUSTED_DEVICE_MAX_AGE_SECONDS: u64 = 30 * 24 * 60 * 60; // 30 days
+// The double-submit CSRF token, sent back by scripts in the header
+// How long the links in new device emails work
+pub const SIGN_IN_ALERT_TTL_SECONDS: u64 = 7 * 24 * 60 * 60; // 7 days
+// The reset form is shown right after the owner confirms the alert, and only needs to last while
+// they pick a new password
+pub const PASSWORD_RESET_TTL_SECONDS: u64 = 10 * 60; // 10 minutes
+
+pub const CSRF_COOKIE_NAME: &str = "csrf_token";
+pub const CSRF_HEADER_NAME: &str = "x-csrf-token";
+// Ties a magic link to the browser that asked for it
+pub const MAGIC_LINK_COOKIE_NAME: &str = "magic_link_browser";
+// Ties pending 2FA logins to the browser that started them
+pub const TWO_FA_COOKIE_NAME: &str = "two_fa_browser";
 pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
+pub const DEFAULT_REDIS_CONNECTION_TIMEOUT_MILLIS: u64 = 1_000;
+pub const DEFAULT_REDIS_RESPONSE_TIMEOUT_MILLIS: u64 = 500;
+pub const DEFAULT_REDIS_NUMBER_OF_RETRIES: usize = 3;
+pub const DEFAULT_STORE_BACKEND: StoreBackend = StoreBackend::Redis;
+pub const DEFAULT_EXPIRED_ROW_SWEEP_INTERVAL_SECONDS: u64 = 300;
+pub const DEFAULT_TWO_FA_RESEND_COOLDOWN_SECONDS: u64 = 30;
+pub const DEFAULT_TWO_FA_MAX_RESENDS: u32 = 3;
+pub const DEFAULT_RISK_CHALLENGE_THRESHOLD: u32 = 50;
+pub const DEFAULT_RISK_DENY_THRESHOLD: u32 = 100;
+pub const DEFAULT_STEP_UP_MAX_AGE_SECONDS: u64 = 300;
+// Reject tokens whose revocation cannot be checked
+pub const DEFAULT_TOKEN_REVOCATION_FAILURE_POLICY: RevocationFailurePolicy =
+    RevocationFailurePolicy::Closed;
+pub const DEFAULT_EMAIL_PLUS_TAG_DEDUPLICATION: bool = false;
+pub const DEFAULT_EMAIL_BRAND_NAME: &str = "Auth Service";
+pub const DEFAULT_EMAIL_PROVIDER: EmailProvider = EmailProvider::Postmark;
+pub const DEFAULT_EMAIL_OUTBOX_POLL_INTERVAL_MILLIS: u64 = 1_000;
+pub const DEFAULT_EMAIL_OUTBOX_BATCH_SIZE: u32 = 10;
+pub const DEFAULT_EMAIL_OUTBOX_MAX_ATTEMPTS: i32 = 8;
+pub const DEFAULT_EMAIL_OUTBOX_BASE_BACKOFF_MILLIS: u64 = 1_000;
+pub const DEFAULT_EMAIL_OUTBOX_MAX_BACKOFF_MILLIS: u64 = 300_000;
+pub const DEFAULT_EMAIL_OUTBOX_LEASE_MILLIS: u64 = 60_000;
+pub const DEFAULT_DEV_MAILBOX_CAPACITY: usize = 100;
+pub const DEFAULT_DEV_MODE: bool = false;
+pub const DEFAULT_AUTH_SERVICE_URL: &str = "http://localhost:3000";
+pub const DEFAULT_LOGIN_REDIRECT_URL: &str = "https://app-service.billkunyiha.com";
+pub const DEFAULT_CORS_ALLOWED_METHODS: &str = "GET,POST";
+pub const DEFAULT_CORS_ALLOWED_HEADERS: &str = "content-type,x-csrf-token";
+pub const DEFAULT_CORS_MAX_AGE_SECONDS: u64 = 600;
+pub const DEFAULT_CORS_RELOAD_INTERVAL_MILLIS: u64 = 5_000;
+// The tenant configured from JWT_SECRET and friends when TENANTS_FILE is unset
+pub const DEFAULT_TENANT_ID: &str = "default";
+pub const DEFAULT_JWT_ISSUER: &str = "auth-service";
 
 pub mod prod {
     use super::dotenv;
@@ -11,6 +72,59 @@ pub mod prod {
     use super::std_env;
     pub const APP_ADDRESS: &str = "0.0.0.0:3000";
 
+    pub mod smtp {
+        use super::super::{env, set_env_or_default};
+        use crate::services::SmtpTls;
+        use lazy_static::lazy_static;
+        use std::time::Duration;
+
+        pub const DEFAULT_TLS: SmtpTls = SmtpTls::StartTls;
+        pub const DEFAULT_POOL_MAX_SIZE: u32 = 10;
+        pub const DEFAULT_TIMEOUT_MILLIS: u64 = 10_000;
+
+        lazy_static! {
+            pub static ref HOST: String = super::set_smtp_host();
+            pub static ref TLS: SmtpTls = set_env_or_default(env::SMTP_TLS_ENV_VAR, DEFAULT_TLS);
+            pub static ref PORT: u16 =
+                set_env_or_default(env::SMTP_PORT_ENV_VAR, TLS.default_port());
+            pub static ref USERNAME: Option<String> =
+                super::set_optional(env::SMTP_USERNAME_ENV_VAR);
+            pub static ref PASSWORD: Option<String> =
+                super::set_optional(env::SMTP_PASSWORD_ENV_VAR);
+            pub static ref POOL_MAX_SIZE: u32 =
+                set_env_or_default(env::SMTP_POOL_MAX_SIZE_ENV_VAR, DEFAULT_POOL_MAX_SIZE);
+            pub static ref TIMEOUT: Duration = Duration::from_millis(set_env_or_default(
+                env::SMTP_TIMEOUT_MILLIS_ENV_VAR,
+                DEFAULT_TIMEOUT_MILLIS
+            ));
+        }
+    }
+
+    // SMS is enabled when TWILIO_ACCOUNT_SID is set
+    pub mod twilio {
+        use super::super::{env, set_env_or_default};
+        use lazy_static::lazy_static;
+        use std::time::Duration;
+
+        pub const DEFAULT_BASE_URL: &str = "https://api.twilio.com";
+        pub const DEFAULT_TIMEOUT_MILLIS: u64 = 10_000;
+
+        lazy_static! {
+            pub static ref ACCOUNT_SID: Option<String> =
+                super::set_optional(env::TWILIO_ACCOUNT_SID_ENV_VAR);
+            pub static ref AUTH_TOKEN: Option<String> =
+                super::set_optional(env::TWILIO_AUTH_TOKEN_ENV_VAR);
+            pub static ref FROM_NUMBER: Option<String> =
+                super::set_optional(env::TWILIO_FROM_NUMBER_ENV_VAR);
+            pub static ref BASE_URL: String =
+                set_env_or_default(env::TWILIO_BASE_URL_ENV_VAR, DEFAULT_BASE_URL.to_owned());
+            pub static ref TIMEOUT: Duration = Duration::from_millis(set_env_or_default(
+                env::SMS_TIMEOUT_MILLIS_ENV_VAR,
+                DEFAULT_TIMEOUT_MILLIS
+            ));
+        }
+    }
+
     pub mod email_client {
         use lazy_static::lazy_static;
         use std::time::Duration;
@@ -34,6 +148,16 @@ pub mod prod {
         std_env::var(env::EMAIL_FROM_USER_ENV_VAR).expect("EMAIL_FROM_USER_ENV_VAR must be set.")
     }
 
+    fn set_smtp_host() -> String {
+        dotenv().ok();
+        std_env::var(env::SMTP_HOST_ENV_VAR).expect("SMTP_HOST must be set.")
+    }
+
+    fn set_optional(env_var: &str) -> Option<String> {
+        dotenv().ok();
+        std_env::var(env_var).ok().filter(|value| !value.is_empty())
+    }
+
     fn set_email_timeout_millis() -> i32 {
         dotenv().ok();
         std_env::var(env::EMAIL_TIMEOUT_MILLIS_ENV_VAR)
@@ -52,6 +176,10 @@ pub mod test {
         pub const SENDER: &str = "test@email.com";
         pub const TIMEOUT: Duration = std::time::Duration::from_millis(200);
     }
+    pub mod sms_client {
+        pub const ACCOUNT_SID: &str = "AC00000000000000000000000000000000";
+        pub const SENDER: &str = "+15005550006";
+    }
 }
 
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
@@ -60,6 +188,179 @@ lazy_static! {
     pub static ref APP_SERVICE_HOST: String = set_app_service_host();
     pub static ref DATABASE_URL: String = set_db_url();
     pub static ref REDIS_HOST_NAME: String = set_redis_host();
+    pub static ref REDIS_CONNECTION_TIMEOUT: Duration = Duration::from_millis(set_env_or_default(
+        env::REDIS_CONNECTION_TIMEOUT_MILLIS_ENV_VAR,
+        DEFAULT_REDIS_CONNECTION_TIMEOUT_MILLIS
+    ));
+    pub static ref REDIS_RESPONSE_TIMEOUT: Duration = Duration::from_millis(set_env_or_default(
+        env::REDIS_RESPONSE_TIMEOUT_MILLIS_ENV_VAR,
+        DEFAULT_REDIS_RESPONSE_TIMEOUT_MILLIS
+    ));
+    pub static ref REDIS_NUMBER_OF_RETRIES: usize = set_env_or_default(
+        env::REDIS_NUMBER_OF_RETRIES_ENV_VAR,
+        DEFAULT_REDIS_NUMBER_OF_RETRIES
+    );
+    pub static ref STORE_BACKEND: StoreBackend =
+        set_env_or_default(env::STORE_BACKEND_ENV_VAR, DEFAULT_STORE_BACKEND);
+    pub static ref EXPIRED_ROW_SWEEP_INTERVAL: Duration = Duration::from_secs(set_env_or_default(
+        env::EXPIRED_ROW_SWEEP_INTERVAL_SECONDS_ENV_VAR,
+        DEFAULT_EXPIRED_ROW_SWEEP_INTERVAL_SECONDS
+    ));
+    pub static ref TWO_FA_RESEND_COOLDOWN: Duration = Duration::from_secs(set_env_or_default(
+        env::TWO_FA_RESEND_COOLDOWN_SECONDS_ENV_VAR,
+        DEFAULT_TWO_FA_RESEND_COOLDOWN_SECONDS
+    ));
+    pub static ref TWO_FA_MAX_RESENDS: u32 = set_env_or_default(
+        env::TWO_FA_MAX_RESENDS_ENV_VAR,
+        DEFAULT_TWO_FA_MAX_RESENDS
+    );
+    // Logins scoring at least this much need an email code, even without 2FA
+    pub static ref RISK_CHALLENGE_THRESHOLD: u32 = set_env_or_default(
+        env::RISK_CHALLENGE_THRESHOLD_ENV_VAR,
+        DEFAULT_RISK_CHALLENGE_THRESHOLD
+    );
+    // Logins scoring at least this much are refused
+    pub static ref RISK_DENY_THRESHOLD: u32 =
+        set_env_or_default(env::RISK_DENY_THRESHOLD_ENV_VAR, DEFAULT_RISK_DENY_THRESHOLD);
+    // Addresses and networks that logins are not accepted from, one per line
+    pub static ref IP_BLOCKLIST_FILE: Option<String> = set_optional_file(env::IP_BLOCKLIST_FILE_ENV_VAR);
+    // A MaxMind (GeoIP2 or GeoLite2) City or Country database; no location signals when unset
+    pub static ref GEOIP_DATABASE_FILE: Option<String> =
+        set_optional_file(env::GEOIP_DATABASE_FILE_ENV_VAR);
+    // Comma-separated addresses or networks of the reverse proxies whose X-Real-IP and
+    // X-Forwarded-For are believed, e.g. nginx's; none by default
+    pub static ref TRUSTED_PROXIES: String =
+        set_env_or_default(env::TRUSTED_PROXIES_ENV_VAR, String::new());
+    // How long after authenticating a session may use sensitive routes without /reauthenticate
+    pub static ref STEP_UP_MAX_AGE: Duration = Duration::from_secs(set_env_or_default(
+        env::STEP_UP_MAX_AGE_SECONDS_ENV_VAR,
+        DEFAULT_STEP_UP_MAX_AGE_SECONDS
+    ));
+    // The same for single routes; each falls back to STEP_UP_MAX_AGE
+    pub static ref CHANGE_PASSWORD_STEP_UP_MAX_AGE: Duration = Duration::from_secs(
+        set_env_or_default(
+            env::CHANGE_PASSWORD_STEP_UP_MAX_AGE_SECONDS_ENV_VAR,
+            STEP_UP_MAX_AGE.as_secs()
+        )
+    );
+    pub static ref TWO_FA_CHANNEL_STEP_UP_MAX_AGE: Duration = Duration::from_secs(
+        set_env_or_default(
+            env::TWO_FA_CHANNEL_STEP_UP_MAX_AGE_SECONDS_ENV_VAR,
+            STEP_UP_MAX_AGE.as_secs()
+        )
+    );
+    pub static ref RECOVERY_CODES_STEP_UP_MAX_AGE: Duration = Duration::from_secs(
+        set_env_or_default(
+            env::RECOVERY_CODES_STEP_UP_MAX_AGE_SECONDS_ENV_VAR,
+            STEP_UP_MAX_AGE.as_secs()
+        )
+    );
+    pub static ref TOKEN_REVOCATION_FAILURE_POLICY: RevocationFailurePolicy = set_env_or_default(
+        env::TOKEN_REVOCATION_FAILURE_POLICY_ENV_VAR,
+        DEFAULT_TOKEN_REVOCATION_FAILURE_POLICY
+    );
+    pub static ref EMAIL_PLUS_TAG_DEDUPLICATION: bool = set_env_or_default(
+        env::EMAIL_PLUS_TAG_DEDUPLICATION_ENV_VAR,
+        DEFAULT_EMAIL_PLUS_TAG_DEDUPLICATION
+    );
+    pub static ref EMAIL_BRAND_NAME: String = set_env_or_default(
+        env::EMAIL_BRAND_NAME_ENV_VAR,
+        DEFAULT_EMAIL_BRAND_NAME.to_owned()
+    );
+    pub static ref EMAIL_BRAND_URL: Option<String> = set_email_brand_url();
+    pub static ref EMAIL_PROVIDER: EmailProvider =
+        set_env_or_default(env::EMAIL_PROVIDER_ENV_VAR, DEFAULT_EMAIL_PROVIDER);
+    pub static ref EMAIL_OUTBOX_POLL_INTERVAL: Duration =
+        Duration::from_millis(set_env_or_default(
+            env::EMAIL_OUTBOX_POLL_INTERVAL_MILLIS_ENV_VAR,
+            DEFAULT_EMAIL_OUTBOX_POLL_INTERVAL_MILLIS
+        ));
+    pub static ref EMAIL_OUTBOX_BATCH_SIZE: u32 = set_env_or_default(
+        env::EMAIL_OUTBOX_BATCH_SIZE_ENV_VAR,
+        DEFAULT_EMAIL_OUTBOX_BATCH_SIZE
+    );
+    pub static ref EMAIL_OUTBOX_MAX_ATTEMPTS: i32 = set_env_or_default(
+        env::EMAIL_OUTBOX_MAX_ATTEMPTS_ENV_VAR,
+        DEFAULT_EMAIL_OUTBOX_MAX_ATTEMPTS
+    );
+    pub static ref EMAIL_OUTBOX_BASE_BACKOFF: Duration = Duration::from_millis(set_env_or_default(
+        env::EMAIL_OUTBOX_BASE_BACKOFF_MILLIS_ENV_VAR,
+        DEFAULT_EMAIL_OUTBOX_BASE_BACKOFF_MILLIS
+    ));
+    pub static ref EMAIL_OUTBOX_MAX_BACKOFF: Duration = Duration::from_millis(set_env_or_default(
+        env::EMAIL_OUTBOX_MAX_BACKOFF_MILLIS_ENV_VAR,
+        DEFAULT_EMAIL_OUTBOX_MAX_BACKOFF_MILLIS
+    ));
+    pub static ref EMAIL_OUTBOX_LEASE: Duration = Duration::from_millis(set_env_or_default(
+        env::EMAIL_OUTBOX_LEASE_MILLIS_ENV_VAR,
+        DEFAULT_EMAIL_OUTBOX_LEASE_MILLIS
+    ));
+    pub static ref ADMIN_API_TOKEN: Option<String> = set_admin_api_token();
+    pub static ref DEV_MAILBOX_DIR: Option<String> = set_dev_mailbox_dir();
+    // Marks a development or test machine, where unauthenticated debugging aids may run
+    pub static ref DEV_MODE: bool = set_env_or_default(env::DEV_MODE_ENV_VAR, DEFAULT_DEV_MODE);
+    // Where users reach this service; links in emails point here
+    pub static ref AUTH_SERVICE_URL: String = set_env_or_default(
+        env::AUTH_SERVICE_URL_ENV_VAR,
+        DEFAULT_AUTH_SERVICE_URL.to_owned()
+    );
+    // Where browsers are sent after signing in through a link
+    pub static ref LOGIN_REDIRECT_URL: String = set_env_or_default(
+        env::LOGIN_REDIRECT_URL_ENV_VAR,
+        DEFAULT_LOGIN_REDIRECT_URL.to_owned()
+    );
+    // The tenants (realms) to serve; a single default tenant when unset
+    pub static ref TENANTS_FILE: Option<String> = set_tenants_file();
+    // Browser origins allowed to call the API, exact or `scheme://*.domain`, comma-separated
+    pub static ref CORS_ALLOWED_ORIGINS: String = set_cors_allowed_origins();
+    pub static ref CORS_ALLOWED_METHODS: String = set_env_or_default(
+        env::CORS_ALLOWED_METHODS_ENV_VAR,
+        DEFAULT_CORS_ALLOWED_METHODS.to_owned()
+    );
+    pub static ref CORS_ALLOWED_HEADERS: String = set_env_or_default(
+        env::CORS_ALLOWED_HEADERS_ENV_VAR,
+        DEFAULT_CORS_ALLOWED_HEADERS.to_owned()
+    );
+    pub static ref CORS_MAX_AGE: Duration = Duration::from_secs(set_env_or_default(
+        env::CORS_MAX_AGE_SECONDS_ENV_VAR,
+        DEFAULT_CORS_MAX_AGE_SECONDS
+    ));
+    // A JSON file that overrides the CORS settings above and is reloaded when it changes
+    pub static ref CORS_POLICY_FILE: Option<String> = set_cors_policy_file();
+    pub static ref CORS_RELOAD_INTERVAL: Duration = Duration::from_millis(set_env_or_default(
+        env::CORS_RELOAD_INTERVAL_MILLIS_ENV_VAR,
+        DEFAULT_CORS_RELOAD_INTERVAL_MILLIS
+    ));
+    // The auth cookie's name, without the prefix
+    pub static ref AUTH_COOKIE_NAME: String = set_env_or_default(
+        env::AUTH_COOKIE_NAME_ENV_VAR,
+        JWT_COOKIE_NAME.to_owned()
+    );
+    pub static ref AUTH_COOKIE_PREFIX: CookiePrefix =
+        set_env_or_default(env::AUTH_COOKIE_PREFIX_ENV_VAR, DEFAULT_AUTH_COOKIE_PREFIX);
+    pub static ref AUTH_COOKIE_SECURE: bool =
+        set_env_or_default(env::AUTH_COOKIE_SECURE_ENV_VAR, DEFAULT_AUTH_COOKIE_SECURE);
+    pub static ref AUTH_COOKIE_DOMAIN: Option<String> = set_auth_cookie_domain();
+    pub static ref AUTH_COOKIE_SAME_SITE: String = set_env_or_default(
+        env::AUTH_COOKIE_SAME_SITE_ENV_VAR,
+        DEFAULT_AUTH_COOKIE_SAME_SITE.to_owned()
+    );
+    // Defaults to the token lifetime; 0 makes it a session cookie
+    pub static ref AUTH_COOKIE_MAX_AGE: Option<Duration> = Some(Duration::from_secs(
+        set_env_or_default(
+            env::AUTH_COOKIE_MAX_AGE_SECONDS_ENV_VAR,
+            TOKEN_TTL_SECONDS as u64
+        )
+    ))
+    .filter(|max_age| !max_age.is_zero());
+    pub static ref REMEMBER_ME_MAX_AGE: Duration = Duration::from_secs(set_env_or_default(
+        env::REMEMBER_ME_MAX_AGE_SECONDS_ENV_VAR,
+        DEFAULT_REMEMBER_ME_MAX_AGE_SECONDS
+    ));
+    pub static ref TRUSTED_DEVICE_MAX_AGE: Duration = Duration::from_secs(set_env_or_default(
+        env::TRUSTED_DEVICE_MAX_AGE_SECONDS_ENV_VAR,
+        DEFAULT_TRUSTED_DEVICE_MAX_AGE_SECONDS
+    ));
 }
 
 pub mod env {
@@ -67,9 +368,73 @@ pub mod env {
     pub const APP_SERVICE_HOST_ENV_VAR: &str = "APP_SERVICE_HOST";
     pub const DB_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
+    pub const REDIS_CONNECTION_TIMEOUT_MILLIS_ENV_VAR: &str = "REDIS_CONNECTION_TIMEOUT_MILLIS";
+    pub const REDIS_RESPONSE_TIMEOUT_MILLIS_ENV_VAR: &str = "REDIS_RESPONSE_TIMEOUT_MILLIS";
+    pub const REDIS_NUMBER_OF_RETRIES_ENV_VAR: &str = "REDIS_NUMBER_OF_RETRIES";
+    pub const STORE_BACKEND_ENV_VAR: &str = "STORE_BACKEND";
+    pub const EXPIRED_ROW_SWEEP_INTERVAL_SECONDS_ENV_VAR: &str =
+        "EXPIRED_ROW_SWEEP_INTERVAL_SECONDS";
+    pub const TWO_FA_RESEND_COOLDOWN_SECONDS_ENV_VAR: &str = "TWO_FA_RESEND_COOLDOWN_SECONDS";
+    pub const TWO_FA_MAX_RESENDS_ENV_VAR: &str = "TWO_FA_MAX_RESENDS";
+    pub const RISK_CHALLENGE_THRESHOLD_ENV_VAR: &str = "RISK_CHALLENGE_THRESHOLD";
+    pub const RISK_DENY_THRESHOLD_ENV_VAR: &str = "RISK_DENY_THRESHOLD";
+    pub const IP_BLOCKLIST_FILE_ENV_VAR: &str = "IP_BLOCKLIST_FILE";
+    pub const GEOIP_DATABASE_FILE_ENV_VAR: &str = "GEOIP_DATABASE_FILE";
+    pub const TRUSTED_PROXIES_ENV_VAR: &str = "TRUSTED_PROXIES";
+    pub const STEP_UP_MAX_AGE_SECONDS_ENV_VAR: &str = "STEP_UP_MAX_AGE_SECONDS";
+    pub const CHANGE_PASSWORD_STEP_UP_MAX_AGE_SECONDS_ENV_VAR: &str =
+        "CHANGE_PASSWORD_STEP_UP_MAX_AGE_SECONDS";
+    pub const TWO_FA_CHANNEL_STEP_UP_MAX_AGE_SECONDS_ENV_VAR: &str =
+        "TWO_FA_CHANNEL_STEP_UP_MAX_AGE_SECONDS";
+    pub const RECOVERY_CODES_STEP_UP_MAX_AGE_SECONDS_ENV_VAR: &str =
+        "RECOVERY_CODES_STEP_UP_MAX_AGE_SECONDS";
+    pub const TOKEN_REVOCATION_FAILURE_POLICY_ENV_VAR: &str = "TOKEN_REVOCATION_FAILURE_POLICY";
     pub const EMAIL_SERVICE_HOST_ENV_VAR: &str = "EMAIL_SERVICE_HOST";
     pub const EMAIL_FROM_USER_ENV_VAR: &str = "EMAIL_FROM_USER";
     pub const EMAIL_TIMEOUT_MILLIS_ENV_VAR: &str = "EMAIL_TIMEOUT_MILLIS";
+    pub const EMAIL_PLUS_TAG_DEDUPLICATION_ENV_VAR: &str = "EMAIL_PLUS_TAG_DEDUPLICATION";
+    pub const EMAIL_BRAND_NAME_ENV_VAR: &str = "EMAIL_BRAND_NAME";
+    pub const EMAIL_BRAND_URL_ENV_VAR: &str = "EMAIL_BRAND_URL";
+    pub const EMAIL_PROVIDER_ENV_VAR: &str = "EMAIL_PROVIDER";
+    pub const EMAIL_OUTBOX_POLL_INTERVAL_MILLIS_ENV_VAR: &str = "EMAIL_OUTBOX_POLL_INTERVAL_MILLIS";
+    pub const EMAIL_OUTBOX_BATCH_SIZE_ENV_VAR: &str = "EMAIL_OUTBOX_BATCH_SIZE";
+    pub const EMAIL_OUTBOX_MAX_ATTEMPTS_ENV_VAR: &str = "EMAIL_OUTBOX_MAX_ATTEMPTS";
+    pub const EMAIL_OUTBOX_BASE_BACKOFF_MILLIS_ENV_VAR: &str = "EMAIL_OUTBOX_BASE_BACKOFF_MILLIS";
+    pub const EMAIL_OUTBOX_MAX_BACKOFF_MILLIS_ENV_VAR: &str = "EMAIL_OUTBOX_MAX_BACKOFF_MILLIS";
+    pub const EMAIL_OUTBOX_LEASE_MILLIS_ENV_VAR: &str = "EMAIL_OUTBOX_LEASE_MILLIS";
+    pub const ADMIN_API_TOKEN_ENV_VAR: &str = "ADMIN_API_TOKEN";
+    pub const DEV_MAILBOX_DIR_ENV_VAR: &str = "DEV_MAILBOX_DIR";
+    pub const DEV_MODE_ENV_VAR: &str = "DEV_MODE";
+    pub const AUTH_SERVICE_URL_ENV_VAR: &str = "AUTH_SERVICE_URL";
+    pub const LOGIN_REDIRECT_URL_ENV_VAR: &str = "LOGIN_REDIRECT_URL";
+    pub const TENANTS_FILE_ENV_VAR: &str = "TENANTS_FILE";
+    pub const CORS_ALLOWED_ORIGINS_ENV_VAR: &str = "CORS_ALLOWED_ORIGINS";
+    pub const CORS_ALLOWED_METHODS_ENV_VAR: &str = "CORS_ALLOWED_METHODS";
+    pub const CORS_ALLOWED_HEADERS_ENV_VAR: &str = "CORS_ALLOWED_HEADERS";
+    pub const CORS_MAX_AGE_SECONDS_ENV_VAR: &str = "CORS_MAX_AGE_SECONDS";
+    pub const CORS_POLICY_FILE_ENV_VAR: &str = "CORS_POLICY_FILE";
+    pub const CORS_RELOAD_INTERVAL_MILLIS_ENV_VAR: &str = "CORS_RELOAD_INTERVAL_MILLIS";
+    pub const AUTH_COOKIE_NAME_ENV_VAR: &str = "AUTH_COOKIE_NAME";
+    pub const AUTH_COOKIE_PREFIX_ENV_VAR: &str = "AUTH_COOKIE_PREFIX";
+    pub const AUTH_COOKIE_SECURE_ENV_VAR: &str = "AUTH_COOKIE_SECURE";
+    pub const AUTH_COOKIE_DOMAIN_ENV_VAR: &str = "AUTH_COOKIE_DOMAIN";
+    pub const AUTH_COOKIE_SAME_SITE_ENV_VAR: &str = "AUTH_COOKIE_SAME_SITE";
+    pub const AUTH_COOKIE_MAX_AGE_SECONDS_ENV_VAR: &str = "AUTH_COOKIE_MAX_AGE_SECONDS";
+    pub const REMEMBER_ME_MAX_AGE_SECONDS_ENV_VAR: &str = "REMEMBER_ME_MAX_AGE_SECONDS";
+    pub const TRUSTED_DEVICE_MAX_AGE_SECONDS_ENV_VAR: &str = "TRUSTED_DEVICE_MAX_AGE_SECONDS";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    pub const SMTP_TLS_ENV_VAR: &str = "SMTP_TLS";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const SMTP_POOL_MAX_SIZE_ENV_VAR: &str = "SMTP_POOL_MAX_SIZE";
+    pub const SMTP_TIMEOUT_MILLIS_ENV_VAR: &str = "SMTP_TIMEOUT_MILLIS";
+    pub const TWILIO_ACCOUNT_SID_ENV_VAR: &str = "TWILIO_ACCOUNT_SID";
+    pub const TWILIO_AUTH_TOKEN_ENV_VAR: &str = "TWILIO_AUTH_TOKEN";
+    pub const TWILIO_FROM_NUMBER_ENV_VAR: &str = "TWILIO_FROM_NUMBER";
+    pub const TWILIO_BASE_URL_ENV_VAR: &str = "TWILIO_BASE_URL";
+    pub const SMS_TIMEOUT_MILLIS_ENV_VAR: &str = "SMS_TIMEOUT_MILLIS";
+    pub const OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
 }
 
 // Set the JWT secret from the environment variable
@@ -107,3 +472,75 @@ fn set_redis_host() -> String {
     dotenv().ok();
     std_env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_owned())
 }
+
+// The link shown in email footers, omitted when unset
+fn set_email_brand_url() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::EMAIL_BRAND_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+}
+
+// The bearer token for the /admin endpoints, which are disabled when it is unset
+fn set_admin_api_token() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::ADMIN_API_TOKEN_ENV_VAR)
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+// Where the dev mailbox writes captured mail; it keeps them in memory when unset
+fn set_dev_mailbox_dir() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::DEV_MAILBOX_DIR_ENV_VAR)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+}
+
+// A JSON file describing the tenants, see TenantRegistry::from_file
+fn set_tenants_file() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::TENANTS_FILE_ENV_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+fn set_optional_file(env_var: &str) -> Option<String> {
+    dotenv().ok();
+    std_env::var(env_var).ok().filter(|path| !path.is_empty())
+}
+
+// Defaults to the app service, locally and at APP_SERVICE_HOST
+fn set_cors_allowed_origins() -> String {
+    dotenv().ok();
+    std_env::var(env::CORS_ALLOWED_ORIGINS_ENV_VAR)
+        .ok()
+        .filter(|origins| !origins.is_empty())
+        .unwrap_or_else(|| format!("http://localhost:8000,http://{}", *APP_SERVICE_HOST))
+}
+
+fn set_cors_policy_file() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::CORS_POLICY_FILE_ENV_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+// Share the auth cookie with the subdomains of this domain; host-only when unset
+fn set_auth_cookie_domain() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::AUTH_COOKIE_DOMAIN_ENV_VAR)
+        .ok()
+        .filter(|domain| !domain.is_empty())
+}
+
+// Parse an optional setting from the environment, falling back to the default when it is unset
+fn set_env_or_default<T: FromStr>(env_var: &str, default: T) -> T {
+    dotenv().ok();
+    match std_env::var(env_var) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a valid value.", env_var)),
+        Err(_) => default,
+    }
+}
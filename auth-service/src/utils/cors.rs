@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderValue, Method, StatusCode,
+        header::{
+            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+            ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+        },
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::tenant::tenant_for_request;
+use crate::app_state::AppState;
+
+const VARY_HEADERS: &str = "origin, access-control-request-method, access-control-request-headers";
+
+// Answers CORS preflights and marks responses readable by allowed origins. The policy is read on
+// every request, so changes take effect without a restart. Origins are allowed by the service-wide
+// policy or by the tenant the request is for.
+pub async fn cors(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let policy = state.cors_policy.current();
+    let allowed_origin = request
+        .headers()
+        .get(ORIGIN)
+        .filter(|origin| {
+            origin.to_str().is_ok_and(|origin| {
+                policy.allows_origin(origin)
+                    || tenant_for_request(&state.tenants, request.headers(), request.uri())
+                        .is_some_and(|tenant| {
+                            tenant
+                                .cors_origins
+                                .iter()
+                                .any(|pattern| pattern.matches(origin))
+                        })
+            })
+        })
+        .cloned();
+    let preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut response = if preflight {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.append(VARY, HeaderValue::from_static(VARY_HEADERS));
+    let Some(origin) = allowed_origin else {
+        return response;
+    };
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        HeaderValue::from_static("true"),
+    );
+    if preflight {
+        if let Some(methods) = join_header(policy.allowed_methods.iter().map(Method::as_str)) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if let Some(names) = join_header(policy.allowed_headers.iter().map(|name| name.as_str())) {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, names);
+        }
+        if !policy.max_age.is_zero() {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, policy.max_age.as_secs().into());
+        }
+    }
+    response
+}
+
+fn join_header<'a>(values: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let joined = values.collect::<Vec<_>>().join(", ");
+    if joined.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&joined).ok()
+}
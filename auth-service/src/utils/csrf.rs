@@ -0,0 +1,144 @@
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderMap, Method,
+        header::{HOST, ORIGIN, REFERER},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::Cookie;
+use rand::RngExt;
+use std::sync::Arc;
+
+use super::auth::{bearer_token, constant_time_eq};
+use super::constants::{CSRF_COOKIE_NAME, CSRF_HEADER_NAME};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, AuthCookieSettings, Tenant},
+};
+
+// The double-submit token. It is issued with the auth cookie and, unlike it, readable by scripts,
+// which send it back in the X-CSRF-Token header. Another site can make the browser send the
+// cookie, but cannot read it to set the header.
+pub fn generate_csrf_cookie(settings: &AuthCookieSettings) -> Cookie<'static> {
+    settings.csrf_cookie(
+        CSRF_COOKIE_NAME,
+        hex::encode(rand::rng().random::<[u8; 32]>()),
+    )
+}
+
+// Guards endpoints authenticated by the auth cookie. A state-changing request that carries the
+// cookie must come from the service itself or an origin allowed by the CORS policy, and must
+// echo the CSRF cookie in the X-CSRF-Token header. Requests with a bearer token are exempt:
+// browsers never attach one on their own, and the endpoints then ignore the cookie.
+pub async fn csrf_protection(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let jar = CookieJar::from_headers(headers);
+    let exempt = is_safe(request.method())
+        || bearer_token(headers).is_some()
+        || jar.get(&state.auth_cookie.cookie_name()).is_none();
+    if exempt {
+        return next.run(request).await;
+    }
+
+    let tenant = request.extensions().get::<Arc<Tenant>>();
+    if !origin_is_trusted(&state, tenant, headers) || !token_matches(headers, &jar) {
+        return AuthAPIError::CsrfCheckFailed.into_response();
+    }
+    next.run(request).await
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+// Browsers send Origin with state-changing requests, and older ones at least a Referer.
+// Clients that send neither are not browsers, which cannot be tricked into a forged request.
+fn origin_is_trusted(state: &AppState, tenant: Option<&Arc<Tenant>>, headers: &HeaderMap) -> bool {
+    let origin = match headers.get(ORIGIN) {
+        Some(origin) => origin.to_str().ok().map(ToOwned::to_owned),
+        None => match headers.get(REFERER) {
+            Some(referer) => referer.to_str().ok().and_then(origin_of),
+            None => return true,
+        },
+    };
+    let Some(origin) = origin else {
+        return false;
+    };
+
+    let same_origin = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .zip(origin.split_once("://"))
+        .is_some_and(|(host, (_, authority))| authority.eq_ignore_ascii_case(host));
+    same_origin
+        || state.cors_policy.current().allows_origin(&origin)
+        || tenant.is_some_and(|tenant| {
+            tenant
+                .cors_origins
+                .iter()
+                .any(|pattern| pattern.matches(&origin))
+        })
+}
+
+// `https://app.example.com/some/page?x=1` -> `https://app.example.com`
+fn origin_of(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    (!authority.is_empty()).then(|| format!("{}://{}", scheme, authority))
+}
+
+fn token_matches(headers: &HeaderMap, jar: &CookieJar) -> bool {
+    let Some(expected) = jar.get(CSRF_COOKIE_NAME).map(|cookie| cookie.value()) else {
+        return false;
+    };
+    let provided = headers
+        .get(CSRF_HEADER_NAME)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    !expected.is_empty() && constant_time_eq(provided.as_bytes(), expected.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origin_of_referer() {
+        assert_eq!(
+            origin_of("https://app.example.com/some/page?x=1").as_deref(),
+            Some("https://app.example.com")
+        );
+        assert_eq!(
+            origin_of("http://localhost:8000").as_deref(),
+            Some("http://localhost:8000")
+        );
+        assert_eq!(origin_of("not a url"), None);
+        assert_eq!(origin_of("https:///path"), None);
+    }
+
+    #[test]
+    fn test_token_must_match_the_cookie() {
+        let cookie = generate_csrf_cookie(&AuthCookieSettings::default());
+        let token = cookie.value().to_owned();
+        assert_eq!(token.len(), 64);
+        let jar = CookieJar::new().add(cookie);
+
+        let mut headers = HeaderMap::new();
+        assert!(!token_matches(&headers, &jar));
+        headers.insert(CSRF_HEADER_NAME, "forged".parse().unwrap());
+        assert!(!token_matches(&headers, &jar));
+        headers.insert(CSRF_HEADER_NAME, token.parse().unwrap());
+        assert!(token_matches(&headers, &jar));
+        assert!(!token_matches(&headers, &CookieJar::new()));
+    }
+}
@@ -0,0 +1,131 @@
+use axum::http::HeaderMap;
+use chrono::{TimeDelta, Utc};
+use color_eyre::eyre::Result;
+use std::net::IpAddr;
+
+use super::client::ClientInfo;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, RiskAssessment, RiskDecision, RiskSignal, Tenant},
+    services::{DeviceSighting, LoginAttempt},
+};
+
+// How far back wrong passwords count towards the risk of a login
+const RECENT_FAILURE_WINDOW_SECONDS: i64 = 15 * 60; // 15 minutes
+
+// Score a login whose password or magic link was right, and record the decision with its reasons
+#[tracing::instrument(skip_all)]
+pub async fn assess_login(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    headers: &HeaderMap,
+) -> Result<RiskAssessment, AuthAPIError> {
+    let engine = &state.risk_engine;
+    let client = ClientInfo::from_headers(headers);
+    let now = Utc::now();
+    let ip_address = client
+        .ip_address
+        .as_deref()
+        .and_then(|ip| ip.parse::<IpAddr>().ok());
+    let location = match (&engine.geo_locator, ip_address) {
+        (Some(geo_locator), Some(ip)) => geo_locator.locate(ip),
+        _ => None,
+    };
+
+    let mut signals = Vec::new();
+    match ip_address {
+        Some(ip) if engine.blocklist.contains(ip) => signals.push(RiskSignal::BlocklistedIp),
+        Some(_) => (),
+        None => signals.push(RiskSignal::UnknownIp),
+    }
+    if let Some(location) = &location {
+        let last = state
+            .login_attempt_store
+            .last_located_login(&tenant.id, email)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        if let Some(last) = last {
+            if let (Some(from), Some(to)) = (&last.location.coordinates, &location.coordinates) {
+                signals.extend(RiskSignal::travel((last.attempted_at, from), (now, to)));
+            }
+            if let (Some(from), Some(to)) = (&last.location.country, &location.country)
+                && from != to
+            {
+                signals.push(RiskSignal::NewCountry {
+                    country: to.clone(),
+                });
+            }
+        }
+    }
+    let sighting = state
+        .known_device_store
+        .sighting(&tenant.id, email, &client.fingerprint())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if sighting == DeviceSighting::New {
+        signals.push(RiskSignal::NewDevice);
+    }
+    // Only those from the client's network, so that guessing at an account from elsewhere does
+    // not make its owner's logins riskier
+    let failures = state
+        .login_attempt_store
+        .count_failures(
+            &tenant.id,
+            email,
+            client.ip_prefix().as_deref(),
+            now - TimeDelta::seconds(RECENT_FAILURE_WINDOW_SECONDS),
+        )
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if failures > 0 {
+        signals.push(RiskSignal::RecentFailures { count: failures });
+    }
+
+    let assessment = engine.policy.assess(signals);
+    let attempt = LoginAttempt {
+        attempted_at: now,
+        client,
+        location,
+        assessment: Some(assessment.clone()),
+    };
+    state
+        .login_attempt_store
+        .add_attempt(&tenant.id, email, &attempt)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if assessment.decision != RiskDecision::Allow {
+        tracing::warn!(
+            decision = assessment.decision.as_str(),
+            score = assessment.score,
+            reasons = ?assessment.reasons(),
+            "Risky login"
+        );
+    }
+
+    Ok(assessment)
+}
+
+// Record a wrong password, or a wrong magic link confirmation code, for an existing account.
+// The login fails either way, so errors are only logged.
+#[tracing::instrument(skip_all)]
+pub async fn record_failed_login(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    headers: &HeaderMap,
+) {
+    let attempt = LoginAttempt {
+        attempted_at: Utc::now(),
+        client: ClientInfo::from_headers(headers),
+        location: None,
+        assessment: None,
+    };
+    if let Err(e) = state
+        .login_attempt_store
+        .add_attempt(&tenant.id, email, &attempt)
+        .await
+    {
+        tracing::error!("failed to record a failed login: {:?}", e);
+    }
+}
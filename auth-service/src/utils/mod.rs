@@ -1,6 +1,16 @@
 pub mod auth;
+pub mod client;
 pub mod constants;
+pub mod cors;
+pub mod csrf;
+pub mod login_risk;
+pub mod remember_me;
+pub mod sign_in_alert;
+pub mod step_up;
+pub mod tenant;
 pub mod tracing;
+pub mod trusted_device;
+pub mod two_fa;
 
 // re-export items from sub-modules
 pub use constants::*;
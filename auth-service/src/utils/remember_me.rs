@@ -0,0 +1,55 @@
+use axum::http::HeaderMap;
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+
+use super::client::ClientInfo;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, RememberMeToken, Tenant},
+};
+
+// Issue a "remember me" token for the account and keep it in a cookie next to the auth cookie.
+// It expires after the configured maximum lifetime, however often it is renewed.
+#[tracing::instrument(skip_all)]
+pub async fn remember_device(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    headers: &HeaderMap,
+    jar: CookieJar,
+) -> Result<CookieJar, AuthAPIError> {
+    let max_age = state.auth_cookie.remember_me_max_age;
+    let expires_at = Utc::now()
+        + chrono::Duration::from_std(max_age)
+            .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
+    let token = RememberMeToken::generate();
+    state
+        .remember_me_store
+        .add_token(
+            &tenant.id,
+            email,
+            &token,
+            expires_at,
+            &ClientInfo::from_headers(headers),
+        )
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(jar.add(state.auth_cookie.remember_me_cookie(token.value(), max_age)))
+}
+
+// The "remember me" token the browser sent, if any
+pub fn remembered_token(state: &AppState, jar: &CookieJar) -> Option<RememberMeToken> {
+    jar.get(&state.auth_cookie.remember_me_cookie_name())
+        .and_then(|cookie| RememberMeToken::parse(cookie.value()).ok())
+}
+
+// Drop the "remember me" cookie, with the attributes it was set with
+pub fn forget_device(state: &AppState, jar: CookieJar) -> CookieJar {
+    jar.remove(
+        state
+            .auth_cookie
+            .removal(state.auth_cookie.remember_me_cookie_name()),
+    )
+}
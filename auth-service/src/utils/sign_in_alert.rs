@@ -0,0 +1,78 @@
+use axum::http::HeaderMap;
+use axum_extra::extract::cookie::Cookie;
+use chrono::Utc;
+use color_eyre::eyre::{Result, eyre};
+
+use super::{
+    auth::{decode_claims, generate_sign_in_alert_token},
+    client::{ClientInfo, approximate_location},
+    constants::SIGN_IN_ALERT_TTL_SECONDS,
+};
+use crate::{
+    app_state::AppState,
+    domain::{Email, EmailMessage, Tenant},
+    services::DeviceSighting,
+};
+
+// Email the account owner if the sign-in that created `auth_cookie` came from a device the
+// account was not used from before. The sign-in goes ahead either way, so errors are only logged.
+#[tracing::instrument(skip_all)]
+pub async fn notify_new_device(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    headers: &HeaderMap,
+    auth_cookie: &Cookie<'_>,
+) {
+    if let Err(e) = try_notify_new_device(state, tenant, email, headers, auth_cookie).await {
+        tracing::error!("failed to check for a new sign-in device: {:?}", e);
+    }
+}
+
+async fn try_notify_new_device(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    headers: &HeaderMap,
+    auth_cookie: &Cookie<'_>,
+) -> Result<()> {
+    let client = ClientInfo::from_headers(headers);
+    let fingerprint = client.fingerprint();
+    let sighting = state
+        .known_device_store
+        .record_sign_in(&tenant.id, email, &fingerprint)
+        .await?;
+    if sighting != DeviceSighting::New {
+        return Ok(());
+    }
+
+    // The link revokes the session the sign-in started
+    let session_id = decode_claims(tenant, auth_cookie.value())?.jti;
+    let token = generate_sign_in_alert_token(
+        tenant,
+        &session_id,
+        email,
+        &fingerprint,
+        SIGN_IN_ALERT_TTL_SECONDS,
+    )?;
+    let message = EmailMessage::NewDeviceSignIn {
+        signed_in_at: Utc::now(),
+        location: approximate_location(headers),
+        ip_address: client.ip_address,
+        user_agent: client.user_agent,
+        url: format!(
+            "{}/sign-in-alert?token={}",
+            tenant.public_url.trim_end_matches('/'),
+            token
+        ),
+    };
+    let idempotency_key = format!("sign-in-alert:{}", session_id);
+    state
+        .email_outbox
+        .enqueue(&tenant.id, &idempotency_key, email, &message)
+        .await
+        .map_err(|e| eyre!(e))?;
+    tracing::info!("Signed in from a new device");
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use axum::{
+    Extension,
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use std::sync::Arc;
+
+use super::auth::authenticated_claims;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, StepUpPolicy, Tenant},
+};
+
+// Guards sensitive routes. The session must have authenticated recently enough for the route,
+// with its second factor if the account uses 2FA, or the request fails with 401 and the client
+// has the user go through /reauthenticate. Requests without a valid session fail as usual.
+pub async fn require_step_up(
+    State((state, policy)): State<(AppState, StepUpPolicy)>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Response {
+    let jar = CookieJar::from_headers(&headers);
+    match step_up_check(&state, &tenant, &policy, &headers, &jar).await {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn step_up_check(
+    state: &AppState,
+    tenant: &Tenant,
+    policy: &StepUpPolicy,
+    headers: &HeaderMap,
+    jar: &CookieJar,
+) -> Result<(), AuthAPIError> {
+    let claims = authenticated_claims(state, tenant, headers, jar).await?;
+    let uses_2fa = match policy.second_factor {
+        true => {
+            let user = state
+                .user_store
+                .get_user(&tenant.id, &claims.email()?)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+            tenant.two_fa_policy.applies(user.requires_2fa)
+        }
+        false => false,
+    };
+
+    if !policy.allows(claims.authentication().as_ref(), uses_2fa, Utc::now()) {
+        tracing::info!("Step-up authentication required");
+        return Err(AuthAPIError::ReauthenticationRequired);
+    }
+    Ok(())
+}
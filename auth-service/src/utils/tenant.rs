@@ -0,0 +1,55 @@
+use axum::{
+    extract::{OriginalUri, Request, State},
+    http::{HeaderMap, Uri, header::HOST},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Tenant},
+    services::TenantRegistry,
+};
+
+// Resolves the tenant a request is for and hands it to the handlers as an `Extension<Arc<Tenant>>`.
+// Requests that match no tenant are rejected before they reach a handler.
+pub async fn resolve_tenant(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // Routers nested under /realms/{tenant} only see the rest of the path
+    let path = request.extensions().get::<OriginalUri>().map_or_else(
+        || request.uri().path().to_owned(),
+        |uri| uri.path().to_owned(),
+    );
+    let host = request_host(request.headers(), request.uri());
+
+    match state.tenants.resolve(host.as_deref(), &path) {
+        Some(tenant) => {
+            request.extensions_mut().insert(tenant);
+            next.run(request).await
+        }
+        None => AuthAPIError::UnknownTenant.into_response(),
+    }
+}
+
+// The tenant of a request that has not been routed yet, e.g. a CORS preflight
+pub fn tenant_for_request(
+    tenants: &TenantRegistry,
+    headers: &HeaderMap,
+    uri: &Uri,
+) -> Option<Arc<Tenant>> {
+    let host = request_host(headers, uri);
+    tenants.resolve(host.as_deref(), uri.path())
+}
+
+// HTTP/2 requests carry the host in the URI rather than a Host header
+fn request_host(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    headers
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned)
+        .or_else(|| uri.authority().map(|authority| authority.to_string()))
+}
@@ -1,12 +1,40 @@
-use axum::{body::Body, extract::Request, response::Response};
+use axum::{body::Body, extract::Request, http::HeaderMap, response::Response};
 use color_eyre::eyre::Result;
+use opentelemetry::{global, propagation::Extractor, trace::TracerProvider};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use std::env as std_env;
 use std::time::Duration;
 use tracing::{Level, Span};
 use tracing_error::ErrorLayer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{EnvFilter, fmt};
 
-pub fn init_tracing() -> Result<()> {
+use super::constants::env::OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+const SERVICE_NAME: &str = "auth-service";
+
+// Keeps the OTLP exporter, if any, so that spans still in its batch are sent before the
+// process exits. Call `shutdown` once the server has stopped.
+#[must_use]
+pub struct TracingGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl TracingGuard {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider
+            && let Err(e) = provider.shutdown()
+        {
+            tracing::error!("failed to flush exported spans: {:?}", e);
+        }
+    }
+}
+
+pub fn init_tracing() -> Result<TracingGuard> {
     // Create a formatting layer for tracing output with a compact format
     let fmt_layer = fmt::layer().compact();
 
@@ -15,29 +43,108 @@ pub fn init_tracing() -> Result<()> {
     // If it fails, default to the "info" log level
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
 
+    // Use the W3C trace context format so incoming `traceparent` headers are honored
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    // Only export spans when an OTLP collector has been configured.
+    // The exporter reads the endpoint (and OTEL_EXPORTER_OTLP_* settings) from the environment itself.
+    let mut provider = None;
+    let otel_layer = match std_env::var(OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR) {
+        Ok(endpoint) if !endpoint.is_empty() => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .build()?;
+            let sdk_provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                .build();
+            let tracer = sdk_provider.tracer(SERVICE_NAME);
+            global::set_tracer_provider(sdk_provider.clone());
+            provider = Some(sdk_provider);
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        _ => None,
+    };
+
     // Build the tracing subscriber registry with the formatting layer,
     // the filter layer, and the error layer for enhanced error reporting
     tracing_subscriber::registry()
         .with(filter_layer) // Add the filter layer to control log verbosity
         .with(fmt_layer) // Add the formatting layer for compact log output
+        .with(otel_layer) // Add the OTLP export layer when enabled
         .with(ErrorLayer::default()) // Add the error layer to capture error contexts
         .init(); // Initialize the tracing subscriber
 
-    Ok(())
+    Ok(TracingGuard { provider })
 }
 
-// Creates a new tracing span with a unique request ID for each incoming request.
-// This helps in tracking and correlating logs for individual requests.
+// Creates a new tracing span for each incoming request.
+// The request ID is taken from the `X-Request-Id` header (set by `SetRequestIdLayer` when the caller
+// did not send one) so that logs can be correlated across services. If the caller sent a W3C
+// `traceparent` header, the span joins the caller's trace.
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = uuid::Uuid::new_v4();
-    tracing::span!(
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let trace_id = request
+        .headers()
+        .get(TRACEPARENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(trace_id_from_traceparent)
+        .unwrap_or_default();
+
+    let span = tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
         uri = tracing::field::display(request.uri()),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
-    )
+        trace_id = tracing::field::display(trace_id),
+    );
+
+    let parent_context = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    // Fails only when no OpenTelemetry layer is installed, in which case there is nothing to link
+    let _ = span.set_parent(parent_context);
+
+    span
+}
+
+// Returns the trace ID of a W3C `traceparent` header (`{version}-{trace-id}-{parent-id}-{flags}`),
+// or `None` if the header is malformed.
+fn trace_id_from_traceparent(traceparent: &str) -> Option<&str> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, parent_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+
+    let is_hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit());
+    let is_valid = is_hex(version, 2)
+        && version != "ff"
+        && is_hex(trace_id, 32)
+        && trace_id.chars().any(|c| c != '0')
+        && is_hex(parent_id, 16)
+        && parent_id.chars().any(|c| c != '0')
+        && is_hex(flags, 2);
+
+    is_valid.then_some(trace_id)
+}
+
+// Lets the OpenTelemetry propagator read trace context from HTTP request headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
 }
 
 // Logs an event indicating the start of a request.
@@ -71,3 +178,37 @@ pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_id_from_valid_traceparent() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        assert_eq!(
+            trace_id_from_traceparent(traceparent),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+    }
+
+    #[test]
+    fn test_trace_id_from_invalid_traceparent() {
+        let invalid = [
+            "",
+            "garbage",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473z-00f067aa0ba902b7-01",
+        ];
+        for traceparent in invalid {
+            assert_eq!(
+                trace_id_from_traceparent(traceparent),
+                None,
+                "{traceparent}"
+            );
+        }
+    }
+}
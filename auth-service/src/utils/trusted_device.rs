@@ -0,0 +1,95 @@
+use axum::http::HeaderMap;
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use color_eyre::eyre::eyre;
+use uuid::Uuid;
+
+use super::{
+    auth::{generate_trusted_device_token, validate_trusted_device_token},
+    client::ClientInfo,
+};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Tenant},
+    services::TrustedDeviceStoreError,
+};
+
+// Trust the browser to skip 2FA for the account, until the configured lifetime runs out
+#[tracing::instrument(skip_all)]
+pub async fn trust_device(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    headers: &HeaderMap,
+    jar: CookieJar,
+) -> Result<CookieJar, AuthAPIError> {
+    let max_age = state.auth_cookie.trusted_device_max_age;
+    let expires_at = Utc::now()
+        + chrono::Duration::from_std(max_age)
+            .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
+    let id = Uuid::new_v4();
+    let token = generate_trusted_device_token(tenant, &id.to_string(), email, max_age.as_secs())
+        .map_err(AuthAPIError::UnexpectedError)?;
+    state
+        .trusted_device_store
+        .add_device(
+            &tenant.id,
+            email,
+            id,
+            expires_at,
+            &ClientInfo::from_headers(headers),
+        )
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(jar.add(state.auth_cookie.trusted_device_cookie(token)))
+}
+
+// The ID of the device the browser is trusted as for the account, if its cookie is valid.
+// Says nothing about revocation; see `is_trusted_device`.
+pub fn trusted_device_id(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    jar: &CookieJar,
+) -> Option<Uuid> {
+    let cookie = jar.get(&state.auth_cookie.trusted_device_cookie_name())?;
+    validate_trusted_device_token(tenant, cookie.value(), email)
+        .ok()
+        .and_then(|id| Uuid::parse_str(&id).ok())
+}
+
+// Whether the browser can skip 2FA for the account. When the store cannot be reached, the
+// device is not trusted and the user is asked for a code as usual.
+#[tracing::instrument(skip_all)]
+pub async fn is_trusted_device(
+    state: &AppState,
+    tenant: &Tenant,
+    email: &Email,
+    jar: &CookieJar,
+) -> bool {
+    let Some(id) = trusted_device_id(state, tenant, email, jar) else {
+        return false;
+    };
+    match state
+        .trusted_device_store
+        .use_device(&tenant.id, email, id)
+        .await
+    {
+        Ok(()) => true,
+        Err(TrustedDeviceStoreError::DeviceNotFound) => false,
+        Err(e) => {
+            tracing::error!("failed to check a trusted device: {:?}", e);
+            false
+        }
+    }
+}
+
+// Drop the trusted device cookie, with the attributes it was set with
+pub fn forget_trusted_device(state: &AppState, jar: CookieJar) -> CookieJar {
+    jar.remove(
+        state
+            .auth_cookie
+            .removal(state.auth_cookie.trusted_device_cookie_name()),
+    )
+}
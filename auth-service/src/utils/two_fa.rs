@@ -0,0 +1,106 @@
+use axum::http::HeaderMap;
+use axum_extra::extract::CookieJar;
+use rand::RngExt;
+use sha2::{Digest, Sha256};
+
+use super::{auth::constant_time_eq, login_risk::record_failed_login};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Tenant},
+    services::{LoginAttemptId, TwoFAChallenge, TwoFACode, TwoFACodeStoreError},
+};
+
+// Set the 2FA cookie on the browser unless it has one, returning the binding to store with a
+// new login. Every login from the browser shares the cookie, so each of its tabs can finish its own.
+pub fn bind_client(state: &AppState, jar: CookieJar) -> (CookieJar, String) {
+    let settings = &state.auth_cookie;
+    let nonce = match jar.get(&settings.two_fa_cookie_name()) {
+        Some(cookie) if !cookie.value().is_empty() => cookie.value().to_owned(),
+        _ => hex::encode(rand::rng().random::<[u8; 32]>()),
+    };
+    let binding = client_binding(&nonce);
+    (jar.add(settings.two_fa_cookie(nonce)), binding)
+}
+
+// The pending login, if it was started by the client that sent the request. Logins that are
+// unknown, expired or bound to another client are all reported as InvalidToken.
+#[tracing::instrument(skip_all)]
+pub async fn pending_challenge(
+    state: &AppState,
+    tenant: &Tenant,
+    jar: &CookieJar,
+    login_attempt_id: &LoginAttemptId,
+) -> Result<TwoFAChallenge, AuthAPIError> {
+    let challenge = match state
+        .two_fa_code_store
+        .get_code(&tenant.id, login_attempt_id)
+        .await
+    {
+        Ok(challenge) => challenge,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let same_client = jar
+        .get(&state.auth_cookie.two_fa_cookie_name())
+        .is_some_and(|cookie| {
+            constant_time_eq(
+                client_binding(cookie.value()).as_bytes(),
+                challenge.client_binding.as_bytes(),
+            )
+        });
+    if !same_client {
+        tracing::warn!("Rejected a 2FA login from another client than the one that started it");
+        return Err(AuthAPIError::InvalidToken);
+    }
+    Ok(challenge)
+}
+
+// Check the code entered for the pending login. A wrong code counts against the login, which is
+// dropped after MAX_2FA_CODE_ATTEMPTS of them, and against the account like a wrong password.
+#[tracing::instrument(skip_all)]
+pub async fn check_code(
+    state: &AppState,
+    tenant: &Tenant,
+    headers: &HeaderMap,
+    login_attempt_id: &LoginAttemptId,
+    challenge: &TwoFAChallenge,
+    code: &TwoFACode,
+) -> Result<(), AuthAPIError> {
+    if constant_time_eq(challenge.code.as_ref().as_bytes(), code.as_ref().as_bytes()) {
+        return Ok(());
+    }
+
+    match state
+        .two_fa_code_store
+        .record_failed_attempt(&tenant.id, login_attempt_id)
+        .await
+    {
+        // Finished or dropped by another request in the meantime
+        Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+    record_failed_login(state, tenant, &challenge.email, headers).await;
+    Err(AuthAPIError::IncorrectCredentials)
+}
+
+// Finish the login, so that its code cannot be used again. Fails if another request finished it first.
+pub async fn complete_challenge(
+    state: &AppState,
+    tenant: &Tenant,
+    login_attempt_id: &LoginAttemptId,
+) -> Result<(), AuthAPIError> {
+    match state
+        .two_fa_code_store
+        .remove_code(&tenant.id, login_attempt_id)
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+fn client_binding(nonce: &str) -> String {
+    hex::encode(Sha256::digest(nonce.as_bytes()))
+}
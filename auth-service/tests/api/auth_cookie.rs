@@ -0,0 +1,135 @@
+use crate::helpers::TestApp;
+use auth_service::domain::{AuthCookieSettings, CookiePrefix};
+use auth_service::utils::auth::TOKEN_TTL_SECONDS;
+use auth_service::utils::constants::{CSRF_COOKIE_NAME, JWT_COOKIE_NAME};
+use axum_extra::extract::cookie::SameSite;
+use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
+use reqwest::header::COOKIE;
+use std::time::Duration;
+
+fn production_settings() -> AuthCookieSettings {
+    AuthCookieSettings {
+        name: "session".to_owned(),
+        prefix: CookiePrefix::Secure,
+        secure: true,
+        domain: Some("example.test".to_owned()),
+        same_site: SameSite::Strict,
+        max_age: Some(Duration::from_secs(300)),
+        ..AuthCookieSettings::default()
+    }
+}
+
+// Sign up and log in a user without 2FA, returning the login response
+async fn sign_in(app: &TestApp) -> reqwest::Response {
+    let email: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+
+    let response = app.signup(&email, &password).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+}
+
+#[tokio::test]
+async fn auth_cookie_expires_with_the_token_by_default() {
+    let app = TestApp::new().await;
+    let response = sign_in(&app).await;
+
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(cookie.http_only());
+    assert!(!cookie.secure());
+    assert_eq!(cookie.domain(), None);
+    assert_eq!(
+        cookie.max_age(),
+        Some(Duration::from_secs(TOKEN_TTL_SECONDS as u64))
+    );
+}
+
+#[tokio::test]
+async fn auth_cookie_uses_the_configured_attributes() {
+    let app = TestApp::with_auth_cookie(production_settings()).await;
+    let response = sign_in(&app).await;
+
+    for name in ["__Secure-session", CSRF_COOKIE_NAME] {
+        let cookie = response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .unwrap_or_else(|| panic!("No {} cookie found", name));
+        assert_eq!(cookie.http_only(), name != CSRF_COOKIE_NAME);
+        assert!(cookie.secure());
+        assert!(cookie.same_site_strict());
+        assert_eq!(cookie.domain(), Some("example.test"));
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.max_age(), Some(Duration::from_secs(300)));
+    }
+}
+
+#[tokio::test]
+async fn magic_link_cookie_uses_the_configured_attributes() {
+    let app = TestApp::with_auth_cookie(production_settings()).await;
+    let email: String = SafeEmail().fake();
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "__Secure-magic_link_browser")
+        .expect("No magic link cookie found");
+    assert!(cookie.http_only());
+    assert!(cookie.secure());
+    assert!(cookie.same_site_lax());
+    assert_eq!(cookie.domain(), Some("example.test"));
+    assert_eq!(cookie.path(), Some("/login/magic-link"));
+}
+
+#[tokio::test]
+async fn logout_clears_the_cookies_with_the_same_attributes() {
+    let app = TestApp::with_auth_cookie(production_settings()).await;
+    let response = sign_in(&app).await;
+    let value = |name: &str| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+            .unwrap()
+    };
+    let (token, csrf_token) = (value("__Secure-session"), value(CSRF_COOKIE_NAME));
+
+    // The browser would only send the Secure cookies to https://*.example.test, so send them by hand
+    let response = reqwest::Client::new()
+        .post(format!("{}/logout", &app.address))
+        .header(
+            COOKIE,
+            format!(
+                "__Secure-session={}; {}={}",
+                token, CSRF_COOKIE_NAME, csrf_token
+            ),
+        )
+        .header("x-csrf-token", &csrf_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    for name in ["__Secure-session", CSRF_COOKIE_NAME] {
+        let removal = response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .unwrap_or_else(|| panic!("{} cookie was not cleared", name));
+        assert!(removal.value().is_empty());
+        assert_eq!(removal.max_age(), Some(Duration::ZERO));
+        assert!(removal.secure());
+        assert!(removal.same_site_strict());
+        assert_eq!(removal.domain(), Some("example.test"));
+        assert_eq!(removal.path(), Some("/"));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::helpers::TestApp;
+use reqwest::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ORIGIN,
+};
+use std::time::{Duration, SystemTime};
+
+async fn preflight(app: &TestApp, origin: &str) -> reqwest::Response {
+    app.http_client
+        .request(reqwest::Method::OPTIONS, format!("{}/login", app.address))
+        .header(ORIGIN, origin)
+        .header("access-control-request-method", "POST")
+        .header("access-control-request-headers", "content-type")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn allowed_origin(response: &reqwest::Response) -> Option<&str> {
+    response
+        .headers()
+        .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+        .map(|origin| origin.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn preflight_from_an_allowed_origin_lists_the_policy() {
+    let app = TestApp::new().await;
+
+    let response = preflight(&app, "http://localhost:8000").await;
+    assert!(response.status().is_success());
+    let headers = response.headers();
+    assert_eq!(
+        headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+        "http://localhost:8000"
+    );
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+    assert_eq!(
+        headers[ACCESS_CONTROL_ALLOW_HEADERS],
+        "content-type, x-csrf-token"
+    );
+    assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+}
+
+#[tokio::test]
+async fn wildcard_origins_match_subdomains_with_the_same_scheme() {
+    let app = TestApp::new().await;
+
+    let response = preflight(&app, "https://app.example.test").await;
+    assert_eq!(allowed_origin(&response), Some("https://app.example.test"));
+
+    for origin in [
+        "http://app.example.test",
+        "https://example.test",
+        "https://app.example.test.evil.com",
+    ] {
+        let response = preflight(&app, origin).await;
+        assert_eq!(allowed_origin(&response), None, "{}", origin);
+    }
+}
+
+#[tokio::test]
+async fn responses_are_only_shared_with_allowed_origins() {
+    let app = TestApp::new().await;
+    let login = |origin: &'static str| {
+        app.http_client
+            .post(format!("{}/login", app.address))
+            .header(ORIGIN, origin)
+            .json(&serde_json::json!({}))
+            .send()
+    };
+
+    let response = login("https://app.example.test").await.unwrap();
+    assert_eq!(allowed_origin(&response), Some("https://app.example.test"));
+
+    let response = login("https://evil.test").await.unwrap();
+    assert_eq!(allowed_origin(&response), None);
+}
+
+#[tokio::test]
+async fn policy_changes_apply_without_a_restart() {
+    let app = TestApp::new().await;
+    assert_eq!(
+        allowed_origin(&preflight(&app, "https://new.test").await),
+        None
+    );
+
+    std::fs::write(
+        &app.cors_policy_file,
+        r#"{"allowedOrigins": ["https://new.test"], "allowedMethods": ["GET", "POST", "PUT"]}"#,
+    )
+    .unwrap();
+    // Make sure the change is visible even on file systems with coarse timestamps
+    std::fs::File::options()
+        .write(true)
+        .open(&app.cors_policy_file)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(1))
+        .unwrap();
+    assert!(app.cors_policy.reload_if_changed().unwrap());
+
+    let response = preflight(&app, "https://new.test").await;
+    assert_eq!(allowed_origin(&response), Some("https://new.test"));
+    assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_METHODS],
+        "GET, POST, PUT"
+    );
+    assert_eq!(
+        allowed_origin(&preflight(&app, "http://localhost:8000").await),
+        None
+    );
+}
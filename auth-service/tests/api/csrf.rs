@@ -0,0 +1,139 @@
+use crate::helpers::TestApp;
+use auth_service::utils::constants::{CSRF_COOKIE_NAME, JWT_COOKIE_NAME};
+use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
+use reqwest::header::{ORIGIN, REFERER};
+
+// Sign up and log in a user without 2FA, returning the JWT
+async fn sign_in(app: &TestApp) -> String {
+    let email: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+
+    let response = app.signup(&email, &password).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let csrf_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == CSRF_COOKIE_NAME)
+        .expect("No CSRF cookie found");
+    assert!(!csrf_cookie.http_only());
+    assert!(!csrf_cookie.value().is_empty());
+
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
+
+fn logout_request(app: &TestApp) -> reqwest::RequestBuilder {
+    app.http_client.post(format!("{}/logout", &app.address))
+}
+
+#[tokio::test]
+async fn should_return_403_without_the_csrf_token() {
+    let app = TestApp::new().await;
+    sign_in(&app).await;
+
+    let response = logout_request(&app).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = logout_request(&app)
+        .header("x-csrf-token", "forged")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    // The session is still active
+    assert_eq!(app.logout().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_403_from_an_untrusted_origin() {
+    let app = TestApp::new().await;
+    sign_in(&app).await;
+
+    let response = logout_request(&app)
+        .header("x-csrf-token", app.csrf_token())
+        .header(ORIGIN, "https://evil.test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Without an Origin, the Referer is checked
+    let response = logout_request(&app)
+        .header("x-csrf-token", app.csrf_token())
+        .header(REFERER, "https://evil.test/page")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn should_accept_the_token_from_a_trusted_origin() {
+    let app = TestApp::new().await;
+    sign_in(&app).await;
+
+    let response = logout_request(&app)
+        .header("x-csrf-token", app.csrf_token())
+        .header(ORIGIN, "http://localhost:8000")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_accept_a_referer_from_the_service_itself() {
+    let app = TestApp::new().await;
+    sign_in(&app).await;
+
+    let response = logout_request(&app)
+        .header("x-csrf-token", app.csrf_token())
+        .header(REFERER, format!("{}/", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn bearer_tokens_are_not_checked() {
+    let app = TestApp::new().await;
+    let token = sign_in(&app).await;
+
+    // A client that is not a browser, with no cookies
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/logout", &app.address))
+        .bearer_auth(&token)
+        .header(ORIGIN, "https://evil.test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn csrf_token_endpoint_returns_the_cookie_value() {
+    let app = TestApp::new().await;
+    sign_in(&app).await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/csrf-token", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["csrfToken"], app.csrf_token());
+}
@@ -0,0 +1,62 @@
+use crate::helpers::TestApp;
+use auth_service::routes::TwoFactorAuthResponse;
+
+#[tokio::test]
+async fn dev_mailbox_lists_the_2fa_email() {
+    let app = TestApp::with_dev_mailbox().await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": "dana@example.com",
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": "dana@example.com",
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    assert_eq!(app.deliver_emails().await, 1);
+
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    let code = app.two_fa_code(&body.login_attempt_id).await;
+
+    let response = app.get_dev_mailbox().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .headers()
+            .get("content-type")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+
+    let page = response.text().await.unwrap();
+    assert!(page.contains("dana@example.com"));
+    assert!(page.contains("two-fa-code"));
+    assert!(page.contains(code.as_ref()));
+
+    // Nothing was sent to the real provider
+    assert!(
+        app.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn dev_mailbox_is_not_found_when_another_provider_is_configured() {
+    let app = TestApp::new().await;
+
+    let response = app.get_dev_mailbox().await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,196 @@
+use crate::helpers::{ADMIN_API_TOKEN, TestApp, default_tenant};
+use auth_service::domain::{Email, EmailMessage};
+use chrono::{TimeDelta, Utc};
+use secrecy::SecretBox;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn email(s: &str) -> Email {
+    Email::parse(SecretBox::new(Box::new(s.to_owned()))).unwrap()
+}
+
+fn two_fa_code(expires_in: TimeDelta) -> EmailMessage {
+    EmailMessage::TwoFACode {
+        code: "123456".to_owned(),
+        expires_at: Utc::now() + expires_in,
+    }
+}
+
+async fn signup_with_2fa_and_login(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+}
+
+async fn outbox_status(app: &TestApp) -> serde_json::Value {
+    let response = app.get_email_outbox_status(Some(ADMIN_API_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn login_returns_206_without_waiting_for_the_email_provider() {
+    let app = TestApp::new().await;
+
+    // The provider is down, but the code is queued and login still succeeds
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    signup_with_2fa_and_login(&app, "erin@example.com").await;
+    assert!(
+        app.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let status = outbox_status(&app).await;
+    assert_eq!(status["stats"]["pending"], 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_until_they_succeed() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    signup_with_2fa_and_login(&app, "frank@example.com").await;
+    assert_eq!(app.deliver_emails().await, 2);
+
+    let status = outbox_status(&app).await;
+    assert_eq!(status["stats"]["sent"], 1);
+    assert_eq!(status["stats"]["pending"], 0);
+    assert!(status["stuck"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn messages_are_dead_lettered_after_the_maximum_number_of_attempts() {
+    let app = TestApp::new().await;
+
+    // TestApp allows three attempts
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    signup_with_2fa_and_login(&app, "grace@example.com").await;
+    assert_eq!(app.deliver_emails().await, 3);
+
+    let status = outbox_status(&app).await;
+    assert_eq!(status["stats"]["dead"], 1);
+    let stuck = status["stuck"].as_array().unwrap();
+    assert_eq!(stuck.len(), 1);
+    assert_eq!(stuck[0]["status"], "dead");
+    assert_eq!(stuck[0]["attempts"], 3);
+    assert_eq!(stuck[0]["templateId"], "two-fa-code");
+    assert!(stuck[0]["lastError"].as_str().unwrap().contains("500"));
+
+    // The code inside is not kept
+    let message: Option<serde_json::Value> =
+        sqlx::query_scalar("SELECT message FROM email_outbox WHERE status = 'dead'")
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap();
+    assert!(message.is_none());
+}
+
+#[tokio::test]
+async fn enqueuing_the_same_idempotency_key_twice_sends_one_email() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let recipient = email("heidi@example.com");
+    let message = two_fa_code(TimeDelta::minutes(10));
+    assert!(
+        app.email_outbox
+            .enqueue(&default_tenant(), "test:key", &recipient, &message)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !app.email_outbox
+            .enqueue(&default_tenant(), "test:key", &recipient, &message)
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(app.deliver_emails().await, 1);
+}
+
+#[tokio::test]
+async fn expired_messages_are_not_sent() {
+    let app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.email_outbox
+        .enqueue(
+            &default_tenant(),
+            "test:expired",
+            &email("ivan@example.com"),
+            &two_fa_code(TimeDelta::minutes(-1)),
+        )
+        .await
+        .unwrap();
+    app.deliver_emails().await;
+
+    let status = outbox_status(&app).await;
+    assert_eq!(status["stats"]["expired"], 1);
+    assert!(status["stuck"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn email_outbox_status_requires_the_admin_token() {
+    let app = TestApp::new().await;
+
+    let response = app.get_email_outbox_status(None).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.get_email_outbox_status(Some("wrong-token")).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.get_email_outbox_status(Some(ADMIN_API_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,29 +1,61 @@
-use auth_service::domain::Email;
+#![allow(clippy::needless_borrows_for_generic_args)]
+
+use auth_service::domain::{
+    AuthCookieSettings, CorsPolicy, Email, OriginPattern, PhoneNumber, RiskEngine, SigningKey,
+    StepUpMaxAges, Tenant, TenantId, TrustedProxies, TwoFAPolicy, TwoFAResendPolicy,
+};
 use auth_service::{
     Application,
     app_state::{
-        AppState, BannedTokenStoreType, EmailClientType, TwoFACodeStoreType, UserStoreType,
+        AppState, BannedTokenStoreType, EmailClientType, EmailOutboxType, KnownDeviceStoreType,
+        LoginAttemptStoreType, MagicLinkStoreType, RecoveryCodeStoreType, RememberMeStoreType,
+        SmsClientType, TrustedDeviceStoreType, TwoFACodeStoreType, UserStoreType,
+    },
+    get_postgres_pool, get_redis_client, get_redis_connection_manager,
+    services::data_stores::{
+        CachedBannedTokenStore, PostgresBannedTokenStore, PostgresEmailOutbox,
+        PostgresKnownDeviceStore, PostgresLoginAttemptStore, PostgresMagicLinkStore,
+        PostgresRecoveryCodeStore, PostgresRememberMeStore, PostgresTrustedDeviceStore,
+        PostgresTwoFACodeStore, PostgresUserStore, RedisMagicLinkStore, RedisTwoFACodeStore,
+        RevocationFailurePolicy, StoreBackend,
     },
-    get_postgres_pool, get_redis_client,
-    services::data_stores::{PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore},
+    services::dev_mailbox::DevMailbox,
+    services::email_outbox_worker::{EmailOutboxWorker, EmailOutboxWorkerConfig},
     services::postmark_email_client::PostmarkEmailClient,
-    utils::constants::{DATABASE_URL, REDIS_HOST_NAME, test},
+    services::reloadable_cors_policy::ReloadableCorsPolicy,
+    services::tenant_registry::TenantRegistry,
+    services::twilio_sms_client::TwilioSmsClient,
+    services::{LoginAttemptId, TwoFACode},
+    utils::constants::{
+        AUTH_SERVICE_URL, DATABASE_URL, DEFAULT_JWT_ISSUER, DEFAULT_TENANT_ID, JWT_SECRET,
+        REDIS_HOST_NAME, test,
+    },
 };
 use reqwest::Client;
 use secrecy::SecretBox;
+use std::path::PathBuf;
 use std::str::FromStr;
 use wiremock::MockServer;
 
-use reqwest::cookie::Jar;
+use reqwest::cookie::{CookieStore, Jar};
 use serde_json::json;
 use sqlx::{
     Connection, Executor, PgConnection, PgPool,
     postgres::{PgConnectOptions, PgPoolOptions},
 };
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
 use uuid::Uuid;
 
+pub const ADMIN_API_TOKEN: &str = "admin-token-for-tests";
+// A second tenant, served under /realms/acme and on its own host, which requires 2FA
+pub const ACME_TENANT_ID: &str = "acme";
+pub const ACME_HOST: &str = "auth.acme.test";
+pub const ACME_ORIGIN: &str = "https://app.acme.test";
+pub const ACME_SENDER: &str = "no-reply@acme.test";
+// Origins allowed by the service-wide CORS policy, which tests can change through `cors_policy_file`
+pub const CORS_ALLOWED_ORIGINS: &str = "http://localhost:8000,https://*.example.test";
+
 pub struct DBName(String);
 
 pub struct TestApp {
@@ -31,35 +63,200 @@ pub struct TestApp {
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
     pub banned_token_store: BannedTokenStoreType,
+    pub tenants: Arc<TenantRegistry>,
     pub two_fa_code_store: TwoFACodeStoreType,
     pub email_server: MockServer,
+    pub sms_server: MockServer,
+    pub email_outbox: EmailOutboxType,
+    // Not spawned in the background; tests call `deliver_emails` to run it deterministically
+    pub email_worker: EmailOutboxWorker,
+    pub cors_policy: Arc<ReloadableCorsPolicy>,
+    pub cors_policy_file: PathBuf,
+    pub pg_pool: PgPool,
     pub db_name: DBName,
 }
 
 impl TestApp {
     pub async fn new() -> Self {
+        Self::build(
+            None,
+            AuthCookieSettings::default(),
+            StoreBackend::Redis,
+            TwoFAResendPolicy::default(),
+            RiskEngine::default(),
+            StepUpMaxAges::default(),
+        )
+        .await
+    }
+
+    // Capture emails in an in-memory dev mailbox instead of sending them to the mock email server
+    pub async fn with_dev_mailbox() -> Self {
+        Self::build(
+            Some(Arc::new(DevMailbox::in_memory(10))),
+            AuthCookieSettings::default(),
+            StoreBackend::Redis,
+            TwoFAResendPolicy::default(),
+            RiskEngine::default(),
+            StepUpMaxAges::default(),
+        )
+        .await
+    }
+
+    pub async fn with_auth_cookie(auth_cookie: AuthCookieSettings) -> Self {
+        Self::build(
+            None,
+            auth_cookie,
+            StoreBackend::Redis,
+            TwoFAResendPolicy::default(),
+            RiskEngine::default(),
+            StepUpMaxAges::default(),
+        )
+        .await
+    }
+
+    // Keep revoked tokens and 2FA codes in Postgres, as deployments without Redis do
+    pub async fn with_store_backend(store_backend: StoreBackend) -> Self {
+        Self::build(
+            None,
+            AuthCookieSettings::default(),
+            store_backend,
+            TwoFAResendPolicy::default(),
+            RiskEngine::default(),
+            StepUpMaxAges::default(),
+        )
+        .await
+    }
+
+    pub async fn with_two_fa_resend(two_fa_resend: TwoFAResendPolicy) -> Self {
+        Self::build(
+            None,
+            AuthCookieSettings::default(),
+            StoreBackend::Redis,
+            two_fa_resend,
+            RiskEngine::default(),
+            StepUpMaxAges::default(),
+        )
+        .await
+    }
+
+    pub async fn with_risk_engine(risk_engine: RiskEngine) -> Self {
+        Self::build(
+            None,
+            AuthCookieSettings::default(),
+            StoreBackend::Redis,
+            TwoFAResendPolicy::default(),
+            risk_engine,
+            StepUpMaxAges::default(),
+        )
+        .await
+    }
+
+    pub async fn with_step_up_max_ages(step_up_max_ages: StepUpMaxAges) -> Self {
+        Self::build(
+            None,
+            AuthCookieSettings::default(),
+            StoreBackend::Redis,
+            TwoFAResendPolicy::default(),
+            RiskEngine::default(),
+            step_up_max_ages,
+        )
+        .await
+    }
+
+    async fn build(
+        dev_mailbox: Option<Arc<DevMailbox>>,
+        auth_cookie: AuthCookieSettings,
+        store_backend: StoreBackend,
+        two_fa_resend: TwoFAResendPolicy,
+        risk_engine: RiskEngine,
+        step_up_max_ages: StepUpMaxAges,
+    ) -> Self {
         let (pg_pool, db_name) = configure_postgresql().await;
-        let user_store: UserStoreType =
-            Arc::new(RwLock::new(Box::new(PostgresUserStore::new(pg_pool))));
-        let banned_token_store: BannedTokenStoreType = Arc::new(RwLock::new(Box::new(
-            RedisBannedTokenStore::new(Arc::new(RwLock::new(configure_redis()))),
-        )));
-        let two_fa_code_store: TwoFACodeStoreType = Arc::new(RwLock::new(Box::new(
-            RedisTwoFACodeStore::new(Arc::new(RwLock::new(configure_redis()))),
-        )));
+        let user_store: UserStoreType = Arc::new(PostgresUserStore::new(pg_pool.clone()));
+        let email_outbox: EmailOutboxType = Arc::new(PostgresEmailOutbox::new(pg_pool.clone()));
+        let recovery_code_store: RecoveryCodeStoreType =
+            Arc::new(PostgresRecoveryCodeStore::new(pg_pool.clone()));
+        let remember_me_store: RememberMeStoreType =
+            Arc::new(PostgresRememberMeStore::new(pg_pool.clone()));
+        let trusted_device_store: TrustedDeviceStoreType =
+            Arc::new(PostgresTrustedDeviceStore::new(pg_pool.clone()));
+        let known_device_store: KnownDeviceStoreType =
+            Arc::new(PostgresKnownDeviceStore::new(pg_pool.clone()));
+        let login_attempt_store: LoginAttemptStoreType =
+            Arc::new(PostgresLoginAttemptStore::new(pg_pool.clone()));
+        // The Postgres backend does not connect to Redis, as deployments without it do not
+        let (banned_token_store, two_fa_code_store, magic_link_store): (
+            BannedTokenStoreType,
+            TwoFACodeStoreType,
+            MagicLinkStoreType,
+        ) = match store_backend {
+            StoreBackend::Redis => {
+                let redis_conn = configure_redis().await;
+                (
+                    revocation_cache(redis_conn.clone(), RevocationFailurePolicy::Closed),
+                    Arc::new(RedisTwoFACodeStore::new(redis_conn.clone())),
+                    Arc::new(RedisMagicLinkStore::new(redis_conn)),
+                )
+            }
+            StoreBackend::Postgres => (
+                Arc::new(PostgresBannedTokenStore::new(pg_pool.clone())),
+                Arc::new(PostgresTwoFACodeStore::new(pg_pool.clone())),
+                Arc::new(PostgresMagicLinkStore::new(pg_pool.clone())),
+            ),
+        };
+        let tenants = Arc::new(configure_tenants());
+        let (cors_policy, cors_policy_file) = configure_cors_policy();
 
         // Set up a mock email server
         let email_server = MockServer::start().await; // New!
         let base_url = email_server.uri(); // New!
-        let email_client: EmailClientType = Arc::new(RwLock::new(Box::new(
-            configure_postmark_email_client(base_url),
-        ))); //
+        // Set up a mock SMS server
+        let sms_server = MockServer::start().await;
+        let sms_client: SmsClientType = Arc::new(configure_twilio_sms_client(sms_server.uri()));
+
+        let email_client: EmailClientType = match &dev_mailbox {
+            Some(mailbox) => mailbox.clone(),
+            None => Arc::new(configure_postmark_email_client(base_url)),
+        };
 
         let app_state: AppState = AppState::new(
             user_store,
             banned_token_store.clone(),
             two_fa_code_store.clone(),
             email_client.clone(),
+            email_outbox.clone(),
+            recovery_code_store,
+            magic_link_store,
+            remember_me_store,
+            trusted_device_store,
+            known_device_store,
+            login_attempt_store,
+            tenants.clone(),
+        )
+        .with_cors_policy(cors_policy.clone())
+        .with_auth_cookie(auth_cookie)
+        .with_admin_api_token(Some(SecretBox::new(Box::new(ADMIN_API_TOKEN.to_owned()))))
+        .with_dev_mailbox(dev_mailbox)
+        .with_sms_client(Some(sms_client))
+        .with_two_fa_resend(two_fa_resend)
+        .with_risk_engine(risk_engine)
+        .with_step_up_max_ages(step_up_max_ages)
+        // The tests stand in for nginx, setting X-Real-IP to play other clients
+        .with_trusted_proxies(TrustedProxies::parse("127.0.0.1, ::1").unwrap());
+
+        // Retry immediately so tests do not wait on backoff
+        let email_worker = EmailOutboxWorker::new(
+            email_outbox.clone(),
+            email_client,
+            tenants.clone(),
+            EmailOutboxWorkerConfig {
+                poll_interval: Duration::from_millis(10),
+                batch_size: 10,
+                max_attempts: 3,
+                base_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+                lease: Duration::from_secs(30),
+            },
         );
 
         let app = Application::build(app_state, test::APP_SERVICE_HOST)
@@ -75,10 +272,11 @@ impl TestApp {
         #[allow(clippy::let_underscore_future)]
         let _ = tokio::spawn(app.run());
 
-        // Create a new cookie jar and HTTP client
+        // Create a new cookie jar and HTTP client. Redirects are not followed, so tests can check them.
         let cookie_jar = Arc::new(Jar::default());
         let http_client = reqwest::Client::builder()
             .cookie_provider(cookie_jar.clone())
+            .redirect(reqwest::redirect::Policy::none())
             .build()
             .unwrap();
         // Create new `TestApp` instance and return it
@@ -87,12 +285,246 @@ impl TestApp {
             cookie_jar,
             http_client,
             banned_token_store,
+            tenants,
             two_fa_code_store,
             email_server,
+            sms_server,
+            email_outbox,
+            email_worker,
+            cors_policy,
+            cors_policy_file,
+            pg_pool,
             db_name,
         }
     }
 
+    // Run the outbox worker until no message is due. Returns the number of delivery attempts.
+    pub async fn deliver_emails(&self) -> usize {
+        let mut attempts = 0;
+        loop {
+            let count = self
+                .email_worker
+                .process_due()
+                .await
+                .expect("Failed to process the email outbox");
+            if count == 0 {
+                return attempts;
+            }
+            attempts += count;
+        }
+    }
+
+    // The code of a pending 2FA login of the default tenant, as it was sent to the user
+    pub async fn two_fa_code(&self, login_attempt_id: &str) -> TwoFACode {
+        let login_attempt_id = LoginAttemptId::parse(login_attempt_id.to_owned())
+            .expect("Failed to parse the login attempt id");
+        self.two_fa_code_store
+            .get_code(&default_tenant(), &login_attempt_id)
+            .await
+            .expect("No pending 2FA login")
+            .code
+    }
+
+    // The address of a tenant's realm, e.g. `http://127.0.0.1:1234/realms/acme`
+    pub fn realm_address(&self, tenant: &str) -> String {
+        format!("{}/realms/{}", &self.address, tenant)
+    }
+
+    pub async fn get_email_outbox_status(&self, token: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .http_client
+            .get(format!("{}/admin/email-outbox", &self.address));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_dev_mailbox(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/dev/mailbox", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_magic_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/login/magic-link", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_magic_link(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!(
+                "{}/login/magic-link/verify?token={}",
+                &self.address, token
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_recovery_codes(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/recovery-codes", &self.address))
+            .header("x-csrf-token", self.csrf_token())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_recovery_code<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-recovery-code", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/phone-number", &self.address))
+            .header("x-csrf-token", self.csrf_token())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/phone-number/verify", &self.address))
+            .header("x-csrf-token", self.csrf_token())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_two_fa_channel<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .put(format!("{}/2fa-channel", &self.address))
+            .header("x-csrf-token", self.csrf_token())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_refresh_token(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/refresh-token", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-password", &self.address))
+            .header("x-csrf-token", self.csrf_token())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reauthenticate<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/reauthenticate", &self.address))
+            .header("x-csrf-token", self.csrf_token())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_trusted_devices(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/trusted-devices", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_trusted_device(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/trusted-devices/{}", &self.address, id))
+            .header("x-csrf-token", self.csrf_token())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sign_in_alert(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sign-in-alert?token={}", &self.address, token))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_sign_in_alert(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/sign-in-alert", &self.address))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_password(&self, token: &str, new_password: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/reset-password", &self.address))
+            .form(&[("token", token), ("new_password", new_password)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // The CSRF token issued with the auth cookie, which cookie-authenticated endpoints expect
+    // in the X-CSRF-Token header. Empty when not signed in.
+    pub fn csrf_token(&self) -> String {
+        let url = reqwest::Url::parse(&self.address).expect("Failed to parse the app address");
+        self.cookie_jar
+            .cookies(&url)
+            .and_then(|cookies| {
+                cookies.to_str().ok().and_then(|cookies| {
+                    cookies
+                        .split("; ")
+                        .find_map(|cookie| cookie.strip_prefix("csrf_token="))
+                        .map(ToOwned::to_owned)
+                })
+            })
+            .unwrap_or_default()
+    }
+
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
             .get(&format!("{}/", &self.address))
@@ -138,6 +570,7 @@ impl TestApp {
     pub async fn logout(&self) -> reqwest::Response {
         self.http_client
             .post(&format!("{}/logout", &self.address))
+            .header("x-csrf-token", self.csrf_token())
             .send()
             .await
             .expect("Failed to execute request.")
@@ -149,6 +582,7 @@ impl TestApp {
     {
         self.http_client
             .post(&format!("{}/logout", &self.address))
+            .header("x-csrf-token", self.csrf_token())
             .json(body)
             .send()
             .await
@@ -178,10 +612,23 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+
+    pub async fn post_resend_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/resend-2fa", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
 
 impl Drop for TestApp {
     fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.cors_policy_file);
         println!("Dropping test db {}!", self.db_name.0);
         let db_name = self.db_name.0.clone();
         tokio::task::spawn_blocking(move || {
@@ -239,20 +686,86 @@ async fn configure_database(db_conn_string: &str, db_name: &str) {
         .expect("Failed to migrate the database");
 }
 
+pub fn default_tenant() -> TenantId {
+    TenantId::parse(DEFAULT_TENANT_ID).unwrap()
+}
+
+fn configure_tenants() -> TenantRegistry {
+    let default = Tenant {
+        id: default_tenant(),
+        name: DEFAULT_TENANT_ID.to_owned(),
+        hosts: Vec::new(),
+        public_url: AUTH_SERVICE_URL.to_owned(),
+        jwt_issuer: DEFAULT_JWT_ISSUER.to_owned(),
+        signing_keys: vec![SigningKey {
+            kid: DEFAULT_TENANT_ID.to_owned(),
+            secret: SecretBox::new(Box::new(JWT_SECRET.to_owned())),
+        }],
+        cors_origins: Vec::new(),
+        two_fa_policy: TwoFAPolicy::Optional,
+        email_sender: Email::parse(SecretBox::new(Box::new(
+            test::email_client::SENDER.to_owned(),
+        )))
+        .unwrap(),
+    };
+    let acme = Tenant {
+        id: TenantId::parse(ACME_TENANT_ID).unwrap(),
+        name: "Acme".to_owned(),
+        hosts: vec![ACME_HOST.to_owned()],
+        public_url: format!("{}/realms/{}", *AUTH_SERVICE_URL, ACME_TENANT_ID),
+        jwt_issuer: "https://auth.acme.test".to_owned(),
+        signing_keys: vec![SigningKey {
+            kid: "acme-1".to_owned(),
+            secret: SecretBox::new(Box::new("acme-secret-for-tests".to_owned())),
+        }],
+        cors_origins: vec![OriginPattern::parse(ACME_ORIGIN).unwrap()],
+        two_fa_policy: TwoFAPolicy::Required,
+        email_sender: Email::parse(SecretBox::new(Box::new(ACME_SENDER.to_owned()))).unwrap(),
+    };
+
+    TenantRegistry::new(vec![default, acme], Some(default_tenant()))
+        .expect("Failed to configure tenants")
+}
+
+// A policy file that starts out empty, so the settings below apply until a test writes to it
+fn configure_cors_policy() -> (Arc<ReloadableCorsPolicy>, PathBuf) {
+    let policy = CorsPolicy {
+        allowed_origins: CorsPolicy::parse_origins(CORS_ALLOWED_ORIGINS).unwrap(),
+        allowed_methods: CorsPolicy::parse_methods("GET,POST").unwrap(),
+        allowed_headers: CorsPolicy::parse_headers("content-type,x-csrf-token").unwrap(),
+        max_age: Duration::from_secs(600),
+    };
+    let path = std::env::temp_dir().join(format!("cors-policy-{}.json", Uuid::new_v4()));
+    std::fs::write(&path, "{}").expect("Failed to write the CORS policy file");
+    let policy =
+        ReloadableCorsPolicy::from_file(policy, &path).expect("Failed to load the CORS policy");
+    (Arc::new(policy), path)
+}
+
 fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
     let postmark_auth_token = SecretBox::new(Box::new("auth_token".to_owned()));
 
-    let sender = Email::parse(SecretBox::new(Box::new(
-        test::email_client::SENDER.to_owned(),
-    )))
-    .unwrap();
+    let http_client = Client::builder()
+        .timeout(test::email_client::TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
 
+    PostmarkEmailClient::new(base_url, postmark_auth_token, http_client)
+}
+
+fn configure_twilio_sms_client(base_url: String) -> TwilioSmsClient {
     let http_client = Client::builder()
         .timeout(test::email_client::TIMEOUT)
         .build()
         .expect("Failed to build HTTP client");
 
-    PostmarkEmailClient::new(base_url, sender, postmark_auth_token, http_client)
+    TwilioSmsClient::new(
+        base_url,
+        test::sms_client::ACCOUNT_SID.to_owned(),
+        SecretBox::new(Box::new("auth_token".to_owned())),
+        PhoneNumber::parse(test::sms_client::SENDER).unwrap(),
+        http_client,
+    )
 }
 
 async fn delete_database(db_name: &str) {
@@ -288,9 +801,20 @@ async fn delete_database(db_name: &str) {
         .expect("Failed to drop the database.");
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+// A revocation cache that follows the revocations of other instances in the background, like
+// the one every instance of the service runs
+pub fn revocation_cache(
+    conn: redis::aio::ConnectionManager,
+    failure_policy: RevocationFailurePolicy,
+) -> Arc<CachedBannedTokenStore> {
+    let cache = Arc::new(CachedBannedTokenStore::new(conn, failure_policy));
+    let client = get_redis_client(REDIS_HOST_NAME.to_owned()).expect("Failed to get Redis client");
+    tokio::spawn(cache.clone().run(client));
+    cache
+}
+
+pub async fn configure_redis() -> redis::aio::ConnectionManager {
+    get_redis_connection_manager(REDIS_HOST_NAME.to_owned())
+        .await
+        .expect("Failed to get Redis connection manager")
 }
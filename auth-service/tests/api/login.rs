@@ -1,6 +1,6 @@
-use crate::helpers::TestApp;
+use crate::helpers::{TestApp, default_tenant};
 use auth_service::utils::constants::JWT_COOKIE_NAME;
-use auth_service::{domain::Email, routes::TwoFactorAuthResponse};
+use auth_service::{domain::Email, routes::TwoFactorAuthResponse, services::LoginAttemptId};
 use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
 use secrecy::SecretBox;
 use wiremock::matchers::{method, path};
@@ -136,6 +136,7 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         "password": password_str,
     });
     let response = app.post_login(&login_request).await;
+    app.deliver_emails().await;
     assert_eq!(response.status().as_u16(), 206);
 }
 
@@ -169,6 +170,7 @@ async fn should_return_2fa_required_message_when_2fa_enabled() {
         "password": password_str,
     });
     let response = app.post_login(&login_request).await;
+    app.deliver_emails().await;
 
     let json_body = response
         .json::<TwoFactorAuthResponse>()
@@ -178,8 +180,71 @@ async fn should_return_2fa_required_message_when_2fa_enabled() {
     assert_eq!(json_body.message, "2FA required".to_owned());
 
     // assert that `json_body.login_attempt_id` is stored inside `app.two_fa_code_store`
-    let two_fa_store = app.two_fa_code_store.read().await;
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id).unwrap();
+    let challenge = app
+        .two_fa_code_store
+        .get_code(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
     let email = Email::parse(SecretBox::new(Box::new(email_str))).unwrap();
-    let (stored_login_attempt_id, _) = two_fa_store.get_code(&email).await.unwrap();
-    assert_eq!(json_body.login_attempt_id, stored_login_attempt_id.as_ref());
+    assert_eq!(challenge.email, email);
+}
+
+#[tokio::test]
+async fn should_email_the_stored_2fa_code_as_html_and_text() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": "dave@example.com",
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": "dave@example.com",
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    let code = app.two_fa_code(&body.login_attempt_id).await;
+
+    assert_eq!(app.deliver_emails().await, 1);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.contains(code.as_ref()));
+    assert!(html_body.contains("<html"));
+    assert!(text_body.contains(code.as_ref()));
+    assert!(text_body.contains("expires in 10 minutes"));
+    assert!(!text_body.contains('<'));
+    assert_eq!(body["Tag"], "two-fa-code");
+}
+
+#[tokio::test]
+async fn should_return_200_if_email_case_differs_from_signup() {
+    let app = TestApp::new().await;
+
+    let response = app.signup("Alice@Example.com", "password123").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": "alice@example.COM",
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
 }
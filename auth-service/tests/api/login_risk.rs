@@ -0,0 +1,237 @@
+use crate::helpers::TestApp;
+use auth_service::domain::{
+    Coordinates, GeoLocation, GeoLocator, IpBlocklist, RiskEngine, RiskPolicy,
+};
+use auth_service::routes::TwoFactorAuthResponse;
+use fake::{Fake, faker::internet::en::SafeEmail};
+use reqwest::header::USER_AGENT;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+const PASSWORD: &str = "password123";
+// One network, so the device stays known wherever the test locator places it
+const BERLIN: &str = "203.0.113.7";
+const POTSDAM: &str = "203.0.113.8";
+const SYDNEY: &str = "203.0.113.9";
+const BLOCKLISTED: &str = "198.51.100.7";
+
+// Places a few test addresses, instead of a GeoIP database
+struct StaticGeoLocator(HashMap<IpAddr, GeoLocation>);
+
+impl GeoLocator for StaticGeoLocator {
+    fn locate(&self, ip: IpAddr) -> Option<GeoLocation> {
+        self.0.get(&ip).cloned()
+    }
+}
+
+fn geo_locator() -> Arc<dyn GeoLocator> {
+    let location = |country: &str, latitude, longitude| GeoLocation {
+        country: Some(country.to_owned()),
+        coordinates: Some(Coordinates {
+            latitude,
+            longitude,
+        }),
+    };
+    Arc::new(StaticGeoLocator(HashMap::from([
+        (BERLIN.parse().unwrap(), location("DE", 52.52, 13.40)),
+        (POTSDAM.parse().unwrap(), location("DE", 52.40, 13.06)),
+        (SYDNEY.parse().unwrap(), location("AU", -33.87, 151.21)),
+    ])))
+}
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let email: String = SafeEmail().fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+async fn login_from(
+    client: &reqwest::Client,
+    app: &TestApp,
+    email: &str,
+    password: &str,
+    ip_address: &str,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/login", &app.address))
+        .header("x-real-ip", ip_address)
+        .header(USER_AGENT, "Firefox")
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// The decisions recorded for the account, oldest first
+async fn decisions(app: &TestApp, email: &str) -> Vec<(Option<String>, Vec<String>)> {
+    sqlx::query_as(
+        "SELECT decision, reasons FROM login_attempts WHERE email_normalized = $1 ORDER BY attempted_at",
+    )
+    .bind(email.to_lowercase())
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn logins_from_a_blocklisted_address_are_denied() {
+    let app = TestApp::with_risk_engine(RiskEngine {
+        blocklist: IpBlocklist::parse("# Known bad\n198.51.100.0/24\n").unwrap(),
+        ..RiskEngine::default()
+    })
+    .await;
+    let email = signup(&app, false).await;
+    let client = reqwest::Client::new();
+
+    // Like a wrong password, so the client cannot tell that it had the right one
+    let response = login_from(&client, &app, &email, PASSWORD, BLOCKLISTED).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let denied = response.json::<serde_json::Value>().await.unwrap();
+    let response = login_from(&client, &app, &email, "wrong-password", BLOCKLISTED).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.json::<serde_json::Value>().await.unwrap(), denied);
+
+    let response = login_from(&client, &app, &email, PASSWORD, BERLIN).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        decisions(&app, &email).await,
+        vec![
+            (Some("deny".to_owned()), vec!["blocklisted-ip".to_owned()]),
+            (None, vec![]),
+            (Some("allow".to_owned()), vec![]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn repeated_failures_require_an_email_code_without_2fa() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    let browser = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    for _ in 0..5 {
+        let response = login_from(&browser, &app, &email, "wrong-password", BERLIN).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = login_from(&browser, &app, &email, PASSWORD, BERLIN).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    assert_eq!(serde_json::json!(login.channel), "email");
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+
+    let response = browser
+        .post(format!("{}/verify-2fa", &app.address))
+        .json(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let recorded = decisions(&app, &email).await;
+    assert_eq!(recorded.len(), 6);
+    assert!(recorded[..5].iter().all(|(decision, _)| decision.is_none()));
+    assert_eq!(
+        recorded[5],
+        (
+            Some("challenge".to_owned()),
+            vec!["recent-failures:5".to_owned()]
+        )
+    );
+}
+
+#[tokio::test]
+async fn impossible_travel_is_challenged() {
+    let app = TestApp::with_risk_engine(RiskEngine {
+        geo_locator: Some(geo_locator()),
+        ..RiskEngine::default()
+    })
+    .await;
+    let email = signup(&app, false).await;
+    let client = reqwest::Client::new();
+
+    let response = login_from(&client, &app, &email, PASSWORD, BERLIN).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // Nearby, so not a journey
+    let response = login_from(&client, &app, &email, PASSWORD, POTSDAM).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login_from(&client, &app, &email, PASSWORD, SYDNEY).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (decision, reasons) = decisions(&app, &email).await.pop().unwrap();
+    assert_eq!(decision.as_deref(), Some("challenge"));
+    assert!(reasons[0].starts_with("impossible-travel:"));
+    assert_eq!(reasons[1], "new-country:AU");
+}
+
+#[tokio::test]
+async fn thresholds_are_configurable() {
+    let app = TestApp::with_risk_engine(RiskEngine {
+        policy: RiskPolicy {
+            challenge_threshold: 10,
+            deny_threshold: 30,
+        },
+        ..RiskEngine::default()
+    })
+    .await;
+    let email = signup(&app, false).await;
+    let client = reqwest::Client::new();
+
+    login_from(&client, &app, &email, "wrong-password", BERLIN).await;
+    let response = login_from(&client, &app, &email, PASSWORD, BERLIN).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    login_from(&client, &app, &email, "wrong-password", BERLIN).await;
+    login_from(&client, &app, &email, "wrong-password", BERLIN).await;
+    let response = login_from(&client, &app, &email, PASSWORD, BERLIN).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn logins_without_a_client_address_are_challenged() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    let client = reqwest::Client::new();
+
+    // The proxy could not tell who the client is
+    let response = login_from(&client, &app, &email, PASSWORD, "unknown").await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    assert_eq!(
+        decisions(&app, &email).await,
+        vec![(Some("challenge".to_owned()), vec!["unknown-ip".to_owned()])]
+    );
+}
+
+#[tokio::test]
+async fn failures_from_another_network_do_not_affect_the_owner() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    let client = reqwest::Client::new();
+
+    for _ in 0..5 {
+        let response = login_from(&client, &app, &email, "wrong-password", "192.0.2.1").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = login_from(&client, &app, &email, PASSWORD, BERLIN).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let (decision, reasons) = decisions(&app, &email).await.pop().unwrap();
+    assert_eq!(decision.as_deref(), Some("allow"));
+    assert!(reasons.is_empty());
+}
@@ -1,5 +1,5 @@
-use crate::helpers::TestApp;
-use auth_service::utils::constants::JWT_COOKIE_NAME;
+use crate::helpers::{TestApp, default_tenant};
+use auth_service::utils::{auth::decode_claims, constants::JWT_COOKIE_NAME};
 use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
 use reqwest::Url;
 
@@ -158,12 +158,13 @@ async fn verify_token_added_to_banned_token_store_after_logout() {
     let response = app.post_logout(&logout_request).await;
     assert_eq!(response.status().as_u16(), 200);
 
-    // Verify the token added to banned token store after logout
-    let banned_token_store = app.banned_token_store.read().await;
+    // Verify the token's ID added to banned token store after logout
+    let tenant = app.tenants.get(&default_tenant()).unwrap();
+    let claims = decode_claims(&tenant, login_token).unwrap();
     assert!(
-        banned_token_store
-            .get_token(&login_token.to_string())
+        app.banned_token_store
+            .contains_token(&default_tenant(), &claims.jti)
             .await
-            .is_ok()
+            .unwrap()
     );
 }
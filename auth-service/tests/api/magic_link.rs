@@ -0,0 +1,332 @@
+use crate::helpers::TestApp;
+use auth_service::domain::{IpBlocklist, RiskEngine};
+use auth_service::routes::MagicLinkResponse;
+use auth_service::utils::constants::{JWT_COOKIE_NAME, LOGIN_REDIRECT_URL};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Magic links live in Redis, which is shared between test apps, so each test needs its own address
+fn unique_email() -> String {
+    format!("{}@example.com", Uuid::new_v4())
+}
+
+async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Returns the confirmation code shown to the requesting browser
+async fn request_magic_link(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    response
+        .json::<MagicLinkResponse>()
+        .await
+        .unwrap()
+        .confirmation_code
+}
+
+// Deliver the queued email and pull the token out of the link in its plain-text body
+async fn emailed_token(app: &TestApp) -> String {
+    assert_eq!(app.deliver_emails().await, 1);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests.last().expect("no email sent").body_json().unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+    let start = text.find("token=").expect("no link in email") + "token=".len();
+    text[start..].split_whitespace().next().unwrap().to_owned()
+}
+
+// A browser other than the one that asked for the link
+fn other_browser() -> reqwest::Client {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+// Open the link and pull the CSRF token out of the confirmation form
+async fn open_confirmation_page(app: &TestApp, browser: &reqwest::Client, token: &str) -> String {
+    let response = browser
+        .get(format!(
+            "{}/login/magic-link/verify?token={}",
+            app.address, token
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!has_auth_cookie(&response));
+    let page = response.text().await.unwrap();
+    let start = page
+        .find(r#"name="csrf_token" value=""#)
+        .expect("no CSRF token in form")
+        + r#"name="csrf_token" value=""#.len();
+    page[start..].split('"').next().unwrap().to_owned()
+}
+
+async fn confirm(
+    app: &TestApp,
+    browser: &reqwest::Client,
+    token: &str,
+    code: &str,
+    csrf_token: &str,
+) -> reqwest::Response {
+    browser
+        .post(format!("{}/login/magic-link/confirm", app.address))
+        .form(&[("token", token), ("code", code), ("csrf_token", csrf_token)])
+        .send()
+        .await
+        .unwrap()
+}
+
+// Open the link in the requesting browser, from another address
+async fn open_magic_link_from(app: &TestApp, token: &str, ip_address: &str) -> reqwest::Response {
+    app.http_client
+        .get(format!(
+            "{}/login/magic-link/verify?token={}",
+            app.address, token
+        ))
+        .header("x-real-ip", ip_address)
+        .send()
+        .await
+        .unwrap()
+}
+
+// The risk decisions recorded for the account, oldest first
+async fn decisions(app: &TestApp, email: &str) -> Vec<Option<String>> {
+    sqlx::query_scalar(
+        "SELECT decision FROM login_attempts WHERE email_normalized = $1 ORDER BY attempted_at",
+    )
+    .bind(email)
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap()
+}
+
+fn has_auth_cookie(response: &reqwest::Response) -> bool {
+    response
+        .cookies()
+        .any(|cookie| cookie.name() == JWT_COOKIE_NAME && !cookie.value().is_empty())
+}
+
+#[tokio::test]
+async fn magic_link_signs_in_the_requesting_browser_once() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+
+    let response = app.get_magic_link(&token).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers()["location"], LOGIN_REDIRECT_URL.as_str());
+    assert!(has_auth_cookie(&response));
+
+    // The link works once
+    let response = app.get_magic_link(&token).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!has_auth_cookie(&response));
+}
+
+#[tokio::test]
+async fn magic_link_asks_for_confirmation_in_another_browser() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    let code = request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+
+    // A second browser, without the cookie set by the request
+    let browser = other_browser();
+    let response = browser
+        .get(format!(
+            "{}/login/magic-link/verify?token={}",
+            app.address, token
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!has_auth_cookie(&response));
+    let page = response.text().await.unwrap();
+    assert!(page.contains("different browser"));
+    assert!(page.contains(&email));
+
+    // Viewing the confirmation page does not use up the link
+    let csrf_token = open_confirmation_page(&app, &browser, &token).await;
+    let response = confirm(&app, &browser, &token, &code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(has_auth_cookie(&response));
+
+    let response = confirm(&app, &browser, &token, &code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn magic_link_cannot_be_confirmed_without_the_code() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    let code = request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+    let browser = other_browser();
+    let csrf_token = open_confirmation_page(&app, &browser, &token).await;
+
+    let wrong_code = if code == "000000" { "000001" } else { "000000" };
+    let response = confirm(&app, &browser, &token, wrong_code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!has_auth_cookie(&response));
+
+    // A wrong guess uses up the link
+    let response = confirm(&app, &browser, &token, &code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!has_auth_cookie(&response));
+
+    // and counts like a wrong password
+    assert_eq!(decisions(&app, &email).await, vec![None]);
+}
+
+#[tokio::test]
+async fn magic_link_sign_ins_from_blocklisted_addresses_are_denied() {
+    let app = TestApp::with_risk_engine(RiskEngine {
+        blocklist: IpBlocklist::parse("198.51.100.0/24").unwrap(),
+        ..RiskEngine::default()
+    })
+    .await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+
+    let response = open_magic_link_from(&app, &token, "198.51.100.7").await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(!has_auth_cookie(&response));
+    assert_eq!(decisions(&app, &email).await, vec![Some("deny".to_owned())]);
+}
+
+#[tokio::test]
+async fn risky_magic_link_sign_ins_need_the_password() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+
+    // The proxy could not tell who the client is
+    let response = open_magic_link_from(&app, &token, "unknown").await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(!has_auth_cookie(&response));
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("Sign in with your password")
+    );
+    assert_eq!(
+        decisions(&app, &email).await,
+        vec![Some("challenge".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn magic_link_confirmation_is_protected_against_csrf() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    let code = request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+    // The attacker renders the form in their own browser...
+    let csrf_token = open_confirmation_page(&app, &other_browser(), &token).await;
+
+    // ...but it is not valid in the victim's, with or without a binding cookie of its own
+    let victim = other_browser();
+    let response = confirm(&app, &victim, &token, &code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    open_confirmation_page(&app, &victim, &token).await;
+    let response = confirm(&app, &victim, &token, &code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(!has_auth_cookie(&response));
+
+    // The link is still usable
+    let csrf_token = open_confirmation_page(&app, &victim, &token).await;
+    let response = confirm(&app, &victim, &token, &code, &csrf_token).await;
+    assert_eq!(response.status().as_u16(), 303);
+}
+
+#[tokio::test]
+async fn magic_link_rejects_tampered_tokens() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+    let email = unique_email();
+    signup(&app, &email, false).await;
+
+    request_magic_link(&app, &email).await;
+    let token = emailed_token(&app).await;
+
+    let (header_and_claims, signature) = token.rsplit_once('.').unwrap();
+    let forged = format!(
+        "{}.{}",
+        header_and_claims,
+        signature.chars().rev().collect::<String>()
+    );
+    for token in [forged.as_str(), "not-a-token", ""] {
+        let response = app.get_magic_link(token).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", token);
+        assert!(!has_auth_cookie(&response));
+    }
+}
+
+#[tokio::test]
+async fn magic_link_is_not_sent_to_unknown_or_2fa_accounts() {
+    let app = TestApp::new().await;
+    mount_email_server(&app).await;
+
+    request_magic_link(&app, &unique_email()).await;
+
+    let email = unique_email();
+    signup(&app, &email, true).await;
+    request_magic_link(&app, &email).await;
+
+    assert_eq!(app.deliver_emails().await, 0);
+}
+
+#[tokio::test]
+async fn magic_link_rejects_malformed_emails() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": "not-an-email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
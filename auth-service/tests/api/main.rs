@@ -1,7 +1,24 @@
+mod auth_cookie;
+mod cors;
+mod csrf;
+mod dev_mailbox;
+mod email_outbox;
 mod helpers;
 mod login;
+mod login_risk;
 mod logout;
+mod magic_link;
+mod phone_number;
+mod postgres_stores;
+mod recovery_codes;
+mod remember_me;
+mod resend_2fa;
 mod root;
+mod sign_in_alerts;
 mod signup;
+mod step_up;
+mod tenants;
+mod token_revocation;
+mod trusted_devices;
 mod verify_2fa;
 mod verify_token;
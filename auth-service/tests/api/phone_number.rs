@@ -0,0 +1,198 @@
+use crate::helpers::TestApp;
+use auth_service::domain::TwoFAChannel;
+use auth_service::routes::TwoFactorAuthResponse;
+use auth_service::services::MAX_PHONE_VERIFICATION_ATTEMPTS;
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, ResponseTemplate};
+
+const PASSWORD: &str = "password123";
+const PHONE_NUMBER: &str = "+1 (415) 555-2671";
+
+async fn signup_and_login(app: &TestApp, email: &str, requires_2fa: bool) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    if !requires_2fa {
+        assert_eq!(response.status().as_u16(), 200);
+        return;
+    }
+
+    // Complete the emailed 2FA challenge to get a session
+    assert_eq!(response.status().as_u16(), 206);
+    let body: TwoFactorAuthResponse = response.json().await.unwrap();
+    assert_eq!(body.channel, TwoFAChannel::Email);
+    verify_2fa(app, &body.login_attempt_id).await;
+}
+
+async fn verify_2fa(app: &TestApp, login_attempt_id: &str) {
+    let code = app.two_fa_code(login_attempt_id).await;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn mount_sms_server(app: &TestApp) {
+    Mock::given(path_regex(r"^/2010-04-01/Accounts/[^/]+/Messages\.json$"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&app.sms_server)
+        .await;
+}
+
+// The code from the most recent text message; message bodies start with it
+async fn last_sms_code(app: &TestApp) -> String {
+    let requests = app.sms_server.received_requests().await.unwrap();
+    let body = String::from_utf8(requests.last().expect("no SMS sent").body.clone()).unwrap();
+    let start = body.find("Body=").expect("no Body field") + "Body=".len();
+    body[start..start + 6].to_owned()
+}
+
+async fn verify_phone_number(app: &TestApp) {
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let code = last_sms_code(app).await;
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn phone_number_routes_require_a_session() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .put_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn add_phone_number_rejects_numbers_that_are_not_e164() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, "frank@example.com", false).await;
+
+    for phone_number in ["(415) 555-2671", "+1 415 CALL NOW", "+12"] {
+        let response = app
+            .post_phone_number(&serde_json::json!({ "phoneNumber": phone_number }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "{}", phone_number);
+    }
+    assert!(app.sms_server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn verified_phone_number_is_returned_in_e164_form() {
+    let app = TestApp::new().await;
+    mount_sms_server(&app).await;
+    signup_and_login(&app, "grace@example.com", false).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["phoneNumber"], "+*******2671");
+
+    let code = last_sms_code(&app).await;
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["phoneNumber"], "+14155552671");
+}
+
+#[tokio::test]
+async fn phone_verification_is_discarded_after_too_many_wrong_codes() {
+    let app = TestApp::new().await;
+    mount_sms_server(&app).await;
+    signup_and_login(&app, "heidi@example.com", false).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let code = last_sms_code(&app).await;
+    let wrong_code = if code == "100000" { "111111" } else { "100000" };
+
+    for _ in 0..MAX_PHONE_VERIFICATION_ATTEMPTS {
+        let response = app
+            .post_verify_phone_number(&serde_json::json!({ "code": wrong_code }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    // The right code no longer works; the user has to request a new one
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn choosing_sms_requires_a_verified_phone_number() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, "ivan@example.com", false).await;
+
+    let response = app
+        .put_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn login_sends_the_2fa_code_by_sms_when_chosen() {
+    let app = TestApp::new().await;
+    mount_sms_server(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    signup_and_login(&app, "judy@example.com", true).await;
+    assert_eq!(app.deliver_emails().await, 1);
+    verify_phone_number(&app).await;
+
+    let response = app
+        .put_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": "judy@example.com", "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body: TwoFactorAuthResponse = response.json().await.unwrap();
+    assert_eq!(body.channel, TwoFAChannel::Sms);
+
+    // The code went out by SMS, and no second email was queued
+    let code = app.two_fa_code(&body.login_attempt_id).await;
+    assert_eq!(last_sms_code(&app).await, code.as_ref());
+    assert_eq!(app.deliver_emails().await, 0);
+
+    verify_2fa(&app, &body.login_attempt_id).await;
+}
@@ -0,0 +1,484 @@
+use crate::helpers::{TestApp, default_tenant};
+use auth_service::domain::{Email, PlusTagPolicy};
+use auth_service::routes::TwoFactorAuthResponse;
+use auth_service::services::data_stores::StoreBackend;
+use auth_service::services::email_renormalizer::EmailRenormalizer;
+use auth_service::services::expired_row_sweeper::ExpiredRowSweeper;
+use auth_service::services::{
+    LoginAttemptId, MAX_2FA_CODE_ATTEMPTS, MAX_PENDING_2FA_CHALLENGES, TwoFAChallenge, TwoFACode,
+    TwoFACodeStoreError,
+};
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use chrono::{DateTime, TimeDelta, Utc};
+use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
+use secrecy::SecretBox;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn two_fa_login_and_logout_work_without_redis() {
+    let app = TestApp::with_store_backend(StoreBackend::Postgres).await;
+
+    let email_str: String = SafeEmail().fake();
+    let email = Email::parse(SecretBox::new(Box::new(email_str.clone()))).unwrap();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email_str,
+            "password": password,
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email_str, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    let login_attempt_id = LoginAttemptId::parse(login.login_attempt_id).unwrap();
+
+    let challenge = app
+        .two_fa_code_store
+        .get_code(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
+    assert_eq!(challenge.email, email);
+    let code = challenge.code;
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login_attempt_id.as_ref(),
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // The code is used up
+    assert!(
+        app.two_fa_code_store
+            .get_code(&default_tenant(), &login_attempt_id)
+            .await
+            .is_err()
+    );
+
+    assert_eq!(app.logout().await.status().as_u16(), 200);
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn magic_links_work_without_redis() {
+    let app = TestApp::with_store_backend(StoreBackend::Postgres).await;
+    let email: String = SafeEmail().fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_magic_link(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let pending: i64 = sqlx::query_scalar("SELECT count(*) FROM magic_links")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending, 1);
+
+    assert_eq!(app.deliver_emails().await, 1);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests.last().unwrap().body_json().unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+    let start = text.find("token=").expect("no link in email") + "token=".len();
+    let token = text[start..].split_whitespace().next().unwrap();
+
+    let response = app.get_magic_link(token).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(
+        response
+            .cookies()
+            .any(|cookie| cookie.name() == JWT_COOKIE_NAME && !cookie.value().is_empty())
+    );
+    // The link works once
+    assert_eq!(app.get_magic_link(token).await.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn only_the_latest_2fa_logins_stay_pending() {
+    let app = TestApp::with_store_backend(StoreBackend::Postgres).await;
+    let email_str: String = SafeEmail().fake();
+    let response = app.signup(&email_str, "password123").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let email = Email::parse(SecretBox::new(Box::new(email_str))).unwrap();
+
+    let started = Utc::now();
+    let mut logins = Vec::new();
+    for i in 0..=MAX_PENDING_2FA_CHALLENGES {
+        let login_attempt_id = LoginAttemptId::default();
+        let challenge = TwoFAChallenge {
+            email: email.clone(),
+            code: TwoFACode::default(),
+            client_binding: "binding".to_owned(),
+            created_at: started + TimeDelta::seconds(i as i64),
+            sent_at: started,
+            resends: 0,
+            attempts: 0,
+        };
+        app.two_fa_code_store
+            .add_code(&default_tenant(), login_attempt_id.clone(), challenge)
+            .await
+            .unwrap();
+        logins.push(login_attempt_id);
+    }
+
+    assert!(
+        app.two_fa_code_store
+            .get_code(&default_tenant(), &logins[0])
+            .await
+            .is_err()
+    );
+    for login_attempt_id in &logins[1..] {
+        let challenge = app
+            .two_fa_code_store
+            .get_code(&default_tenant(), login_attempt_id)
+            .await
+            .unwrap();
+        assert_eq!(challenge.email, email);
+    }
+}
+
+#[tokio::test]
+async fn a_resent_2fa_code_replaces_the_pending_one() {
+    let app = TestApp::with_store_backend(StoreBackend::Postgres).await;
+    let email_str: String = SafeEmail().fake();
+    let response = app.signup(&email_str, "password123").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let email = Email::parse(SecretBox::new(Box::new(email_str))).unwrap();
+
+    let login_attempt_id = LoginAttemptId::default();
+    let now = Utc::now();
+    let challenge = TwoFAChallenge {
+        email,
+        code: TwoFACode::default(),
+        client_binding: "binding".to_owned(),
+        created_at: now,
+        sent_at: now,
+        resends: 0,
+        attempts: 0,
+    };
+    // Nothing to resend before the login is started
+    assert!(
+        app.two_fa_code_store
+            .update_code(
+                &default_tenant(),
+                &login_attempt_id,
+                challenge.sent_at,
+                challenge.clone()
+            )
+            .await
+            .is_err()
+    );
+    app.two_fa_code_store
+        .add_code(
+            &default_tenant(),
+            login_attempt_id.clone(),
+            challenge.clone(),
+        )
+        .await
+        .unwrap();
+    // Compare against the stored value, as Postgres keeps microseconds only
+    let pending = app
+        .two_fa_code_store
+        .get_code(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
+    let expires_at = || async {
+        sqlx::query_scalar::<_, DateTime<Utc>>(
+            "SELECT expires_at FROM two_fa_codes WHERE login_attempt_id = $1",
+        )
+        .bind(Uuid::parse_str(login_attempt_id.as_ref()).unwrap())
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+    };
+    let started_expires_at = expires_at().await;
+
+    let resent = TwoFAChallenge {
+        code: TwoFACode::default(),
+        sent_at: now + TimeDelta::seconds(30),
+        resends: 1,
+        ..challenge
+    };
+    app.two_fa_code_store
+        .update_code(
+            &default_tenant(),
+            &login_attempt_id,
+            pending.sent_at,
+            resent.clone(),
+        )
+        .await
+        .unwrap();
+    // A concurrent resend that read the same challenge loses
+    assert_eq!(
+        app.two_fa_code_store
+            .update_code(
+                &default_tenant(),
+                &login_attempt_id,
+                pending.sent_at,
+                resent.clone()
+            )
+            .await,
+        Err(TwoFACodeStoreError::ChallengeChanged)
+    );
+    let stored = app
+        .two_fa_code_store
+        .get_code(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
+    assert_eq!(stored.code, resent.code);
+    assert_eq!(stored.resends, 1);
+    // Postgres keeps microseconds
+    assert!((stored.sent_at - resent.sent_at).abs() < TimeDelta::milliseconds(1));
+    // Resending does not extend the login
+    assert_eq!(expires_at().await, started_expires_at);
+}
+
+#[tokio::test]
+async fn a_2fa_login_is_dropped_after_too_many_wrong_codes() {
+    let app = TestApp::with_store_backend(StoreBackend::Postgres).await;
+    let email_str: String = SafeEmail().fake();
+    let response = app.signup(&email_str, "password123").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let email = Email::parse(SecretBox::new(Box::new(email_str))).unwrap();
+
+    let login_attempt_id = LoginAttemptId::default();
+    let now = Utc::now();
+    let challenge = TwoFAChallenge {
+        email,
+        code: TwoFACode::default(),
+        client_binding: "binding".to_owned(),
+        created_at: now,
+        sent_at: now,
+        resends: 0,
+        attempts: 0,
+    };
+    app.two_fa_code_store
+        .add_code(&default_tenant(), login_attempt_id.clone(), challenge)
+        .await
+        .unwrap();
+
+    app.two_fa_code_store
+        .record_failed_attempt(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
+    // Sending the code again does not reset the count
+    let pending = app
+        .two_fa_code_store
+        .get_code(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
+    assert_eq!(pending.attempts, 1);
+    let resent = TwoFAChallenge {
+        code: TwoFACode::default(),
+        sent_at: now + TimeDelta::seconds(30),
+        resends: 1,
+        attempts: 0,
+        ..pending.clone()
+    };
+    app.two_fa_code_store
+        .update_code(
+            &default_tenant(),
+            &login_attempt_id,
+            pending.sent_at,
+            resent,
+        )
+        .await
+        .unwrap();
+
+    for _ in 1..MAX_2FA_CODE_ATTEMPTS {
+        app.two_fa_code_store
+            .record_failed_attempt(&default_tenant(), &login_attempt_id)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        app.two_fa_code_store
+            .get_code(&default_tenant(), &login_attempt_id)
+            .await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+#[tokio::test]
+async fn the_sweeper_deletes_expired_rows() {
+    let app = TestApp::with_store_backend(StoreBackend::Postgres).await;
+    let live = Uuid::new_v4().to_string();
+    app.banned_token_store
+        .add_token(&default_tenant(), live.clone())
+        .await
+        .unwrap();
+
+    // Revoked longer ago than any token lives
+    let expired = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO banned_tokens (tenant_id, jti, expires_at) VALUES ($1, $2, now() - interval '1 second')",
+    )
+    .bind(default_tenant().as_ref())
+    .bind(&expired)
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+    assert!(
+        !app.banned_token_store
+            .contains_token(&default_tenant(), &expired)
+            .await
+            .unwrap()
+    );
+
+    let sweeper = ExpiredRowSweeper::new(app.pg_pool.clone(), Duration::from_secs(60));
+    assert_eq!(sweeper.sweep().await.unwrap(), 1);
+    assert_eq!(sweeper.sweep().await.unwrap(), 0);
+
+    let remaining: i64 = sqlx::query_scalar("SELECT count(*) FROM banned_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 1);
+    assert!(
+        app.banned_token_store
+            .contains_token(&default_tenant(), &live)
+            .await
+            .unwrap()
+    );
+}
+
+async fn canonical_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query_scalar("SELECT email_normalized FROM users ORDER BY email_normalized")
+        .fetch_all(&app.pg_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn emails_canonicalized_by_earlier_versions_are_renormalized() {
+    let app = TestApp::new().await;
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": "Bob@Bücher.de",
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    // What the plain `lower(email)` of earlier versions stored
+    sqlx::query("UPDATE users SET email_normalized = lower(email)")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post_login(&serde_json::json!({ "email": "bob@bücher.de", "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let renormalizer = EmailRenormalizer::new(app.pg_pool.clone(), PlusTagPolicy::Keep);
+    assert_eq!(renormalizer.run().await.unwrap(), 1);
+    assert_eq!(renormalizer.run().await.unwrap(), 0);
+    assert_eq!(canonical_emails(&app).await, vec!["bob@xn--bcher-kva.de"]);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": "bob@bücher.de", "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn renormalizing_swaps_canonical_emails() {
+    let app = TestApp::new().await;
+    for email in ["erin@example.com", "frank@example.com"] {
+        let response = app.signup(email, "password123").await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+    // Each account holds the canonical email that belongs to the other
+    for (from, to) in [
+        ("erin@example.com", "swap"),
+        ("frank@example.com", "erin@example.com"),
+        ("swap", "frank@example.com"),
+    ] {
+        sqlx::query("UPDATE users SET email_normalized = $2 WHERE email_normalized = $1")
+            .bind(from)
+            .bind(to)
+            .execute(&app.pg_pool)
+            .await
+            .unwrap();
+    }
+
+    let renormalizer = EmailRenormalizer::new(app.pg_pool.clone(), PlusTagPolicy::Keep);
+    assert_eq!(renormalizer.run().await.unwrap(), 2);
+    assert_eq!(renormalizer.run().await.unwrap(), 0);
+
+    for email in ["erin@example.com", "frank@example.com"] {
+        let email_normalized: String =
+            sqlx::query_scalar("SELECT email_normalized FROM users WHERE email = $1")
+                .bind(email)
+                .fetch_one(&app.pg_pool)
+                .await
+                .unwrap();
+        assert_eq!(email_normalized, email);
+        let response = app
+            .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn renormalizing_refuses_to_merge_accounts() {
+    let app = TestApp::new().await;
+    for email in ["carol@example.com", "carol+news@example.com"] {
+        let response = app
+            .post_signup(&serde_json::json!({
+                "email": email,
+                "password": "password123",
+                "requires2FA": false
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    // Turning on plus-tag deduplication would give both accounts the same canonical email
+    let error = EmailRenormalizer::new(app.pg_pool.clone(), PlusTagPolicy::Strip)
+        .run()
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("carol@example.com in default"));
+    assert_eq!(
+        canonical_emails(&app).await,
+        vec!["carol+news@example.com", "carol@example.com"]
+    );
+}
@@ -0,0 +1,191 @@
+use crate::helpers::TestApp;
+use auth_service::routes::{
+    RecoveryCodesResponse, SignupResponse, TwoFactorAuthResponse, VerifyRecoveryCodeResponse,
+};
+use uuid::Uuid;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const PASSWORD: &str = "password123";
+
+// 2FA codes live in Redis, which is shared between test apps, so each test needs its own address
+fn unique_email() -> String {
+    format!("{}@example.com", Uuid::new_v4())
+}
+
+async fn signup_with_2fa(app: &TestApp, email: &str) -> Vec<String> {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: SignupResponse = response.json().await.unwrap();
+    body.recovery_codes.expect("no recovery codes returned")
+}
+
+// Log in with the password and return the login attempt ID of the pending 2FA challenge
+async fn start_login(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body: TwoFactorAuthResponse = response.json().await.unwrap();
+    body.login_attempt_id
+}
+
+async fn verify_recovery_code(
+    app: &TestApp,
+    login_attempt_id: &str,
+    code: &str,
+) -> reqwest::Response {
+    app.post_verify_recovery_code(&serde_json::json!({
+        "loginAttemptId": login_attempt_id,
+        "recoveryCode": code,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn signup_returns_recovery_codes_only_for_2fa_accounts() {
+    let app = TestApp::new().await;
+    let alice = unique_email();
+    let bob = unique_email();
+
+    let codes = signup_with_2fa(&app, &alice).await;
+    assert_eq!(codes.len(), 10);
+
+    let response = app.signup(&bob, PASSWORD).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body.get("recoveryCodes").is_none());
+}
+
+#[tokio::test]
+async fn recovery_code_completes_login_once() {
+    let app = TestApp::new().await;
+    let carol = unique_email();
+    let codes = signup_with_2fa(&app, &carol).await;
+
+    let login_attempt_id = start_login(&app, &carol).await;
+    // Codes are accepted regardless of case and dashes
+    let code = codes[0].to_uppercase().replace('-', "");
+    let response = verify_recovery_code(&app, &login_attempt_id, &code).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|cookie| cookie.name() == "jwt"));
+    let body: VerifyRecoveryCodeResponse = response.json().await.unwrap();
+    assert_eq!(body.remaining_recovery_codes, 9);
+
+    let login_attempt_id = start_login(&app, &carol).await;
+    let response = verify_recovery_code(&app, &login_attempt_id, &codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = verify_recovery_code(&app, &login_attempt_id, &codes[1]).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn recovery_code_requires_a_pending_login() {
+    let app = TestApp::new().await;
+    let dave = unique_email();
+    let codes = signup_with_2fa(&app, &dave).await;
+
+    let response =
+        verify_recovery_code(&app, "4ac4f6c3-0a2b-4d5e-9f3c-0a1b2c3d4e5f", &codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // A different login attempt ID is rejected without using up the code
+    let login_attempt_id = start_login(&app, &dave).await;
+    let response =
+        verify_recovery_code(&app, "4ac4f6c3-0a2b-4d5e-9f3c-0a1b2c3d4e5f", &codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = verify_recovery_code(&app, &login_attempt_id, &codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn regenerating_recovery_codes_invalidates_the_old_set() {
+    let app = TestApp::new().await;
+    let erin = unique_email();
+    let old_codes = signup_with_2fa(&app, &erin).await;
+
+    // Regenerating needs a session
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let login_attempt_id = start_login(&app, &erin).await;
+    let response = verify_recovery_code(&app, &login_attempt_id, &old_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .unwrap()
+        .recovery_codes;
+    assert_eq!(new_codes.len(), 10);
+    assert!(new_codes.iter().all(|code| !old_codes.contains(code)));
+
+    let login_attempt_id = start_login(&app, &erin).await;
+    let response = verify_recovery_code(&app, &login_attempt_id, &old_codes[1]).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = verify_recovery_code(&app, &login_attempt_id, &new_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: VerifyRecoveryCodeResponse = response.json().await.unwrap();
+    assert_eq!(body.remaining_recovery_codes, 9);
+}
+
+#[tokio::test]
+async fn regenerating_recovery_codes_requires_2fa() {
+    let app = TestApp::new().await;
+    let frank = unique_email();
+    let response = app.signup(&frank, PASSWORD).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app
+        .post_login(&serde_json::json!({ "email": &frank, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn using_a_recovery_code_sends_a_notification() {
+    let app = TestApp::new().await;
+    let grace = unique_email();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_string_contains("recovery code was used"))
+        .and(body_string_contains("203.0.113.7"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let codes = signup_with_2fa(&app, &grace).await;
+    let login_attempt_id = start_login(&app, &grace).await;
+    let response = app
+        .http_client
+        .post(format!("{}/verify-recovery-code", &app.address))
+        .header("X-Real-IP", "203.0.113.7")
+        .json(&serde_json::json!({
+            "loginAttemptId": login_attempt_id,
+            "recoveryCode": codes[0],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The 2FA code email and the notification
+    assert_eq!(app.deliver_emails().await, 2);
+}
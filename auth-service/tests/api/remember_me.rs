@@ -0,0 +1,274 @@
+use crate::helpers::TestApp;
+use auth_service::routes::TwoFactorAuthResponse;
+use auth_service::utils::constants::{JWT_COOKIE_NAME, REMEMBER_ME_COOKIE_NAME};
+use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
+use reqwest::header::{COOKIE, USER_AGENT};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn cookie_value(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == name && !cookie.value().is_empty())
+        .map(|cookie| cookie.value().to_owned())
+}
+
+// Sign up a user without 2FA and log in with "remember me", returning the password
+// and the remember me token
+async fn sign_in_remembered(app: &TestApp, email: &str) -> (String, String) {
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+
+    let response = app.signup(email, &password).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "rememberMe": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let remember_me_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REMEMBER_ME_COOKIE_NAME)
+        .expect("No remember me cookie found");
+    assert!(remember_me_cookie.http_only());
+    assert!(remember_me_cookie.max_age().is_some());
+
+    (password, remember_me_cookie.value().to_owned())
+}
+
+// Exchange a remember me token from a browser that only has that cookie
+async fn refresh_with(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/refresh-token", &app.address))
+        .header(COOKIE, format!("{}={}", REMEMBER_ME_COOKIE_NAME, token))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn should_not_remember_the_device_by_default() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+    app.signup(&email, &password).await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(cookie_value(&response, REMEMBER_ME_COOKIE_NAME).is_none());
+}
+
+#[tokio::test]
+async fn should_issue_a_new_session_and_rotate_the_token() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (_, token) = sign_in_remembered(&app, &email).await;
+
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let jwt = cookie_value(&response, JWT_COOKIE_NAME).expect("No auth cookie found");
+    let rotated = cookie_value(&response, REMEMBER_ME_COOKIE_NAME).expect("No token found");
+    assert_ne!(rotated, token);
+    assert!(!app.csrf_token().is_empty());
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": jwt }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The rotated token keeps working
+    assert_eq!(refresh_with(&app, &rotated).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_revoke_the_token_when_an_old_secret_is_reused() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (_, token) = sign_in_remembered(&app, &email).await;
+
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // A copy of the cookie taken before the rotation
+    let response = refresh_with(&app, &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Which ends the login for the legitimate browser too
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_revoke_the_token_when_used_by_another_browser() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (_, token) = sign_in_remembered(&app, &email).await;
+
+    // The cookie copied into a browser with another user agent
+    let response = reqwest::Client::new()
+        .post(format!("{}/refresh-token", &app.address))
+        .header(COOKIE, format!("{}={}", REMEMBER_ME_COOKIE_NAME, token))
+        .header(USER_AGENT, "Mozilla/5.0 (X11; Linux x86_64) Firefox/131.0")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Which ends the login for the legitimate browser too
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_400_without_the_cookie() {
+    let app = TestApp::new().await;
+
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = refresh_with(&app, "not-a-token").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_for_an_unknown_token() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (_, token) = sign_in_remembered(&app, &email).await;
+
+    // Same ID, different secret
+    let (id, _) = token.split_once('.').unwrap();
+    let forged = format!("{}.{}", id, "0".repeat(64));
+    assert_eq!(refresh_with(&app, &forged).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn logout_should_revoke_the_token() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (_, token) = sign_in_remembered(&app, &email).await;
+
+    assert_eq!(app.logout().await.status().as_u16(), 200);
+
+    assert_eq!(app.post_refresh_token().await.status().as_u16(), 400);
+    assert_eq!(refresh_with(&app, &token).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn changing_the_password_should_revoke_every_token() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (password, first_device) = sign_in_remembered(&app, &email).await;
+
+    // A second device
+    let response = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "rememberMe": true,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let second_device = cookie_value(&response, REMEMBER_ME_COOKIE_NAME).unwrap();
+
+    let new_password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": password,
+            "newPassword": new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        refresh_with(&app, &first_device).await.status().as_u16(),
+        401
+    );
+    assert_eq!(
+        refresh_with(&app, &second_device).await.status().as_u16(),
+        401
+    );
+
+    // Only the new password works
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": new_password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn change_password_should_require_the_current_password() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+    let (_, token) = sign_in_remembered(&app, &email).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "wrong-password",
+            "newPassword": "new-password-123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Nothing was revoked
+    assert_eq!(refresh_with(&app, &token).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn verify_2fa_should_remember_the_device() {
+    let app = TestApp::new().await;
+    let email_str: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email_str,
+            "password": password,
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Remembering is decided when the second factor is checked
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email_str,
+            "password": password,
+            "rememberMe": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    assert!(cookie_value(&response, REMEMBER_ME_COOKIE_NAME).is_none());
+    let body = response.json::<TwoFactorAuthResponse>().await.unwrap();
+
+    let code = app.two_fa_code(&body.login_attempt_id).await;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": body.login_attempt_id,
+            "2FACode": code.as_ref(),
+            "rememberMe": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(cookie_value(&response, REMEMBER_ME_COOKIE_NAME).is_some());
+
+    assert_eq!(app.post_refresh_token().await.status().as_u16(), 200);
+}
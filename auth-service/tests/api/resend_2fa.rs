@@ -0,0 +1,184 @@
+use crate::helpers::TestApp;
+use auth_service::domain::{TwoFAChannel, TwoFAResendPolicy};
+use auth_service::routes::{Resend2FAResponse, TwoFactorAuthResponse};
+use chrono::{TimeDelta, Utc};
+use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// No cooldown, so that tests can resend right away
+fn no_cooldown(max_resends: u32) -> TwoFAResendPolicy {
+    TwoFAResendPolicy {
+        cooldown: Duration::ZERO,
+        max_resends,
+    }
+}
+
+// Sign up a user with 2FA and log in, returning the pending login
+async fn start_login(app: &TestApp) -> TwoFactorAuthResponse {
+    let email: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    response.json::<TwoFactorAuthResponse>().await.unwrap()
+}
+
+#[tokio::test]
+async fn login_returns_when_the_code_can_be_resent() {
+    let app = TestApp::new().await;
+    let started = Utc::now();
+    let login = start_login(&app).await;
+
+    let next_resend_at = login.next_resend_at.expect("No resend time");
+    assert!(next_resend_at >= started + TimeDelta::seconds(30));
+    assert!(next_resend_at <= Utc::now() + TimeDelta::seconds(30));
+}
+
+#[tokio::test]
+async fn resend_rotates_the_code() {
+    let app = TestApp::with_two_fa_resend(no_cooldown(3)).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let login = start_login(&app).await;
+    let old_code = app.two_fa_code(&login.login_attempt_id).await;
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({ "loginAttemptId": login.login_attempt_id }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<Resend2FAResponse>().await.unwrap();
+    assert_eq!(body.channel, TwoFAChannel::Email);
+    assert_eq!(body.resends_remaining, 2);
+    assert!(body.next_resend_at.is_some());
+    // Both the first code and the new one are emailed
+    app.deliver_emails().await;
+
+    let new_code = app.two_fa_code(&login.login_attempt_id).await;
+    assert_ne!(old_code, new_code);
+
+    // A code that arrives late no longer works
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": old_code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": new_code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_429_if_resent_during_the_cooldown() {
+    let app = TestApp::new().await;
+    let login = start_login(&app).await;
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({ "loginAttemptId": login.login_attempt_id }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // The code sent with the login still works
+    assert_eq!(app.two_fa_code(&login.login_attempt_id).await, code);
+}
+
+#[tokio::test]
+async fn should_return_429_once_no_resends_are_left() {
+    let app = TestApp::with_two_fa_resend(no_cooldown(2)).await;
+    let login = start_login(&app).await;
+
+    let request = serde_json::json!({ "loginAttemptId": login.login_attempt_id });
+    let response = app.post_resend_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_resend_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<Resend2FAResponse>().await.unwrap();
+    assert_eq!(body.resends_remaining, 0);
+    assert_eq!(body.next_resend_at, None);
+
+    let response = app.post_resend_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // The last code sent can still finish the login
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_resent_from_another_client() {
+    let app = TestApp::with_two_fa_resend(no_cooldown(3)).await;
+    let login = start_login(&app).await;
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/resend-2fa", &app.address))
+        .json(&serde_json::json!({ "loginAttemptId": login.login_attempt_id }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(app.two_fa_code(&login.login_attempt_id).await, code);
+}
+
+#[tokio::test]
+async fn should_return_401_if_the_login_is_unknown_or_finished() {
+    let app = TestApp::with_two_fa_resend(no_cooldown(3)).await;
+    let response = app
+        .post_resend_2fa(&serde_json::json!({ "loginAttemptId": uuid::Uuid::new_v4() }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login = start_login(&app).await;
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_resend_2fa(&serde_json::json!({ "loginAttemptId": login.login_attempt_id }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_400_if_the_login_attempt_id_is_invalid() {
+    let app = TestApp::new().await;
+    let response = app
+        .post_resend_2fa(&serde_json::json!({ "loginAttemptId": "not-a-login-attempt" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -9,3 +9,34 @@ async fn root_returns_auth_ui() {
     assert_eq!(response.status().as_u16(), 200);
     assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
 }
+
+#[tokio::test]
+async fn root_echoes_incoming_request_id() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("x-request-id", "app-service-request-id")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(
+        response.headers().get("x-request-id").unwrap(),
+        "app-service-request-id"
+    );
+}
+
+#[tokio::test]
+async fn root_generates_request_id_when_missing() {
+    let app = TestApp::new().await;
+
+    let response = app.get_root().await;
+
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .expect("No request id header found");
+    assert!(uuid::Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
@@ -0,0 +1,269 @@
+use crate::helpers::TestApp;
+use auth_service::routes::TwoFactorAuthResponse;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use fake::{Fake, faker::internet::en::SafeEmail};
+use reqwest::header::USER_AGENT;
+
+const PASSWORD: &str = "password123";
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let email: String = SafeEmail().fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+// Log in from the given network and browser, in a browser of its own
+async fn login_from(
+    app: &TestApp,
+    email: &str,
+    password: &str,
+    ip_address: &str,
+    user_agent: &str,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .header("x-real-ip", ip_address)
+        .header(USER_AGENT, user_agent)
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// The tokens of the "this wasn't me" links queued for delivery, oldest first
+async fn sign_in_alert_tokens(app: &TestApp) -> Vec<String> {
+    let urls: Vec<String> = sqlx::query_scalar(
+        "SELECT message->>'url' FROM email_outbox WHERE template_id = 'new-device-sign-in' ORDER BY created_at",
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap();
+    urls.iter()
+        .map(|url| {
+            url.split_once("token=")
+                .expect("no token in link")
+                .1
+                .to_owned()
+        })
+        .collect()
+}
+
+fn auth_token(response: &reqwest::Response) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn the_first_sign_in_is_not_reported() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+
+    let response = login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert!(sign_in_alert_tokens(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn sign_ins_from_a_new_browser_or_network_are_reported() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+
+    // The same browser on the same network, with another address
+    login_from(&app, &email, PASSWORD, "203.0.113.200", "Firefox").await;
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 0);
+
+    login_from(&app, &email, PASSWORD, "203.0.113.7", "Chrome").await;
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 1);
+    login_from(&app, &email, PASSWORD, "198.51.100.7", "Firefox").await;
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 2);
+
+    // Once known, the devices are not reported again
+    login_from(&app, &email, PASSWORD, "198.51.100.7", "Firefox").await;
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 2);
+
+    // Failed logins are not reported
+    let response = login_from(&app, &email, "wrong-password", "192.0.2.1", "Safari").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn the_alert_email_describes_the_sign_in() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+
+    reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .header("x-real-ip", "198.51.100.7")
+        .header(USER_AGENT, "Chrome")
+        .header("x-geo-city", "Berlin")
+        .header("x-geo-country", "Germany")
+        .json(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+
+    let message: serde_json::Value = sqlx::query_scalar(
+        "SELECT message FROM email_outbox WHERE template_id = 'new-device-sign-in'",
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(message["ip_address"], "198.51.100.7");
+    assert_eq!(message["location"], "Berlin, Germany");
+    assert_eq!(message["user_agent"], "Chrome");
+    assert!(
+        message["url"]
+            .as_str()
+            .unwrap()
+            .contains("/sign-in-alert?token=")
+    );
+}
+
+#[tokio::test]
+async fn sign_ins_completed_with_2fa_are_reported() {
+    let app = TestApp::new().await;
+    let email = signup(&app, true).await;
+    for user_agent in ["Firefox", "Chrome"] {
+        // The code is checked in the browser the login was started from
+        let browser = reqwest::Client::builder()
+            .cookie_store(true)
+            .build()
+            .unwrap();
+        let response = browser
+            .post(format!("{}/login", &app.address))
+            .header(USER_AGENT, user_agent)
+            .json(&serde_json::json!({ "email": email, "password": PASSWORD }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 206);
+        let login = response.json::<TwoFactorAuthResponse>().await.unwrap();
+        let code = app.two_fa_code(&login.login_attempt_id).await;
+
+        let response = browser
+            .post(format!("{}/verify-2fa", &app.address))
+            .header(USER_AGENT, user_agent)
+            .json(&serde_json::json!({
+                "loginAttemptId": login.login_attempt_id,
+                "2FACode": code.as_ref(),
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn reporting_a_sign_in_revokes_it_and_resets_the_password() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+    let intruder = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = intruder
+        .post(format!("{}/login", &app.address))
+        .header("x-real-ip", "198.51.100.7")
+        .json(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    let intruder_token = auth_token(&response);
+    let verify_intruder_session = || {
+        intruder
+            .post(format!("{}/verify-token", &app.address))
+            .json(&serde_json::json!({ "token": intruder_token }))
+            .send()
+    };
+    let token = sign_in_alert_tokens(&app).await.pop().unwrap();
+
+    // Opening the link only asks for confirmation
+    let response = app.get_sign_in_alert(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(&email));
+    let response = verify_intruder_session().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_sign_in_alert(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    let response = verify_intruder_session().await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let start = page.find(r#"name="token" value=""#).expect("no reset form") + 20;
+    let reset_token = &page[start..page[start..].find('"').unwrap() + start];
+
+    // Too short a password is rejected with the form
+    let response = app.post_reset_password(reset_token, "short").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let new_password = "new-password-123";
+    let response = app.post_reset_password(reset_token, new_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = login_from(&app, &email, new_password, "203.0.113.7", "Firefox").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The reset works once
+    let response = app
+        .post_reset_password(reset_token, "another-password")
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The reported device counts as new again. The intruder's browser sent no user agent.
+    login_from(&app, &email, new_password, "198.51.100.7", "").await;
+    assert_eq!(sign_in_alert_tokens(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn a_sign_in_alert_can_be_confirmed_once() {
+    let app = TestApp::new().await;
+    let email = signup(&app, false).await;
+    login_from(&app, &email, PASSWORD, "203.0.113.7", "Firefox").await;
+    login_from(&app, &email, PASSWORD, "198.51.100.7", "Chrome").await;
+    let token = sign_in_alert_tokens(&app).await.pop().unwrap();
+
+    let response = app.post_sign_in_alert(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("new_password"));
+
+    // Whoever else has the email cannot get another password reset from it
+    let response = app.post_sign_in_alert(&token).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!response.text().await.unwrap().contains("new_password"));
+}
+
+#[tokio::test]
+async fn invalid_sign_in_alert_links_are_rejected() {
+    let app = TestApp::new().await;
+    let response = app.get_sign_in_alert("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.post_sign_in_alert("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app
+        .post_reset_password("not-a-token", "new-password-123")
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -132,6 +132,7 @@ async fn should_return_201_if_valid_input() {
 
     let expected_response = SignupResponse {
         message: "User created successfully!".to_owned(),
+        recovery_codes: None,
     };
     // Assert that we are getting the correct response body!
     assert_eq!(
@@ -142,3 +143,89 @@ async fn should_return_201_if_valid_input() {
         expected_response
     );
 }
+
+#[tokio::test]
+async fn should_return_409_if_email_differs_only_by_case() {
+    let app = TestApp::new().await;
+    let response = app.signup("Bob@Example.com", "password123").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.signup("bob@example.com", "password123").await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn should_return_409_if_idn_domain_matches_its_punycode_form() {
+    let app = TestApp::new().await;
+    let response = app.signup("josé@Bücher.de", "password123").await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.signup("JOSÉ@xn--bcher-kva.de", "password123").await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn concurrent_signups_for_the_same_email_create_one_user() {
+    let app = TestApp::new().await;
+    let email: String = SafeEmail().fake();
+
+    // Fire all requests at once, varying the case so both unique constraints are exercised
+    let mut requests = tokio::task::JoinSet::new();
+    for i in 0..8 {
+        let email = if i % 2 == 0 {
+            email.clone()
+        } else {
+            email.to_uppercase()
+        };
+        let request = app
+            .http_client
+            .post(format!("{}/signup", &app.address))
+            .json(&serde_json::json!({ "email": email, "password": "password123", "requires2FA": false }));
+        requests.spawn(async move { request.send().await.expect("Failed to execute request.") });
+    }
+
+    let statuses: Vec<u16> = requests
+        .join_all()
+        .await
+        .iter()
+        .map(|r| r.status().as_u16())
+        .collect();
+    assert_eq!(
+        statuses.iter().filter(|s| **s == 201).count(),
+        1,
+        "{statuses:?}"
+    );
+    assert_eq!(
+        statuses.iter().filter(|s| **s == 409).count(),
+        7,
+        "{statuses:?}"
+    );
+}
+
+#[tokio::test]
+async fn a_signup_that_cannot_issue_recovery_codes_can_be_retried() {
+    let app = TestApp::new().await;
+    let signup_request = serde_json::json!({
+        "email": "grace@example.com",
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    // The recovery codes cannot be stored
+    sqlx::query("ALTER TABLE recovery_codes RENAME TO recovery_codes_offline")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    let response = app.post_signup(&signup_request).await;
+    assert_eq!(response.status().as_u16(), 500);
+    sqlx::query("ALTER TABLE recovery_codes_offline RENAME TO recovery_codes")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // No account was left behind without its codes
+    let response = app.post_signup(&signup_request).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let body = response.json::<SignupResponse>().await.unwrap();
+    assert_eq!(body.recovery_codes.map(|codes| codes.len()), Some(10));
+}
@@ -0,0 +1,212 @@
+use crate::helpers::TestApp;
+use auth_service::domain::StepUpMaxAges;
+use auth_service::routes::TwoFactorAuthResponse;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use std::time::Duration;
+use uuid::Uuid;
+
+const PASSWORD: &str = "password123";
+
+// 2FA codes live in Redis, which is shared between test apps, so each test needs its own address
+fn unique_email() -> String {
+    format!("{}@example.com", Uuid::new_v4())
+}
+
+// Sign up and sign in with "remember me", completing 2FA if the account uses it
+async fn sign_in_remembered(app: &TestApp, email: &str, requires_2fa: bool) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "rememberMe": true,
+        }))
+        .await;
+    if requires_2fa {
+        assert_eq!(response.status().as_u16(), 206);
+        let login: TwoFactorAuthResponse = response.json().await.unwrap();
+        let code = app.two_fa_code(&login.login_attempt_id).await;
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "loginAttemptId": login.login_attempt_id,
+                "2FACode": code.as_ref(),
+                "rememberMe": true,
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    } else {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+fn auth_token(response: &reqwest::Response) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
+
+async fn change_password(app: &TestApp, new_password: &str) -> reqwest::Response {
+    app.post_change_password(&serde_json::json!({
+        "currentPassword": PASSWORD,
+        "newPassword": new_password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn resumed_sessions_need_the_password_again() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+    sign_in_remembered(&app, &email, false).await;
+
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = change_password(&app, "new-password123").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap()["error"],
+        "Reauthentication required"
+    );
+
+    let response = app
+        .post_reauthenticate(&serde_json::json!({ "password": "wrong-password" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app
+        .post_reauthenticate(&serde_json::json!({ "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = change_password(&app, "new-password123").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn accounts_with_2fa_need_the_second_factor_again() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+    sign_in_remembered(&app, &email, true).await;
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.post_refresh_token().await;
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The password alone is not enough
+    let response = app
+        .post_reauthenticate(&serde_json::json!({ "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login: TwoFactorAuthResponse = response.json().await.unwrap();
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+    let wrong_code: String = code
+        .as_ref()
+        .chars()
+        .map(|digit| {
+            // Not '0', as codes do not start with one
+            if digit == '9' {
+                '1'
+            } else {
+                (digit as u8 + 1) as char
+            }
+        })
+        .collect();
+    let response = app
+        .post_reauthenticate(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": wrong_code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app
+        .post_reauthenticate(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn reauthenticating_keeps_the_session() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+    sign_in_remembered(&app, &email, false).await;
+    let response = app.post_refresh_token().await;
+    let old_token = auth_token(&response);
+
+    let response = app
+        .post_reauthenticate(&serde_json::json!({ "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_token = auth_token(&response);
+    assert_ne!(old_token, new_token);
+
+    // Logging out ends the session, whichever of its tokens is presented
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+    for token in [old_token, new_token] {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn reauthenticating_requires_a_session() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .post_reauthenticate(&serde_json::json!({ "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn fresh_sign_ins_pass_the_guard() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+    sign_in_remembered(&app, &email, false).await;
+
+    let response = change_password(&app, "new-password123").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn each_route_has_its_own_max_age() {
+    let app = TestApp::with_step_up_max_ages(StepUpMaxAges {
+        recovery_codes: Duration::from_secs(1),
+        ..StepUpMaxAges::default()
+    })
+    .await;
+    let email = unique_email();
+    sign_in_remembered(&app, &email, true).await;
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let response = app.post_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 401);
+    // /change-password keeps its own max age
+    let response = change_password(&app, "new-password123").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,196 @@
+use crate::helpers::{ACME_HOST, ACME_ORIGIN, ACME_SENDER, ACME_TENANT_ID, TestApp};
+use auth_service::utils::constants::{JWT_COOKIE_NAME, test};
+use reqwest::header::{ACCESS_CONTROL_ALLOW_ORIGIN, HOST, ORIGIN};
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// 2FA codes live in Redis, which is shared between test apps, so each test needs its own address
+fn unique_email() -> String {
+    format!("{}@example.com", Uuid::new_v4())
+}
+
+fn credentials(email: &str) -> serde_json::Value {
+    json!({ "email": email, "password": "password123", "requires2FA": false })
+}
+
+async fn post_realm(app: &TestApp, realm: &str, route: &str, body: &serde_json::Value) -> u16 {
+    app.http_client
+        .post(format!("{}{}", app.realm_address(realm), route))
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn the_same_email_can_sign_up_in_each_tenant() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+
+    assert_eq!(app.post_signup(&credentials(&email)).await.status(), 201);
+    assert_eq!(app.post_signup(&credentials(&email)).await.status(), 409);
+
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/signup", &credentials(&email)).await,
+        201
+    );
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/signup", &credentials(&email)).await,
+        409
+    );
+}
+
+#[tokio::test]
+async fn users_of_one_tenant_cannot_log_in_to_another() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+    assert_eq!(app.post_signup(&credentials(&email)).await.status(), 201);
+
+    let login = json!({ "email": email, "password": "password123" });
+    assert_eq!(app.post_login(&login).await.status(), 200);
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/login", &login).await,
+        401
+    );
+}
+
+#[tokio::test]
+async fn tokens_are_only_valid_in_the_tenant_that_issued_them() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+    assert_eq!(app.post_signup(&credentials(&email)).await.status(), 201);
+
+    let response = app
+        .post_login(&json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let body = json!({ "token": token });
+    assert_eq!(app.post_verify_token(&body).await.status(), 200);
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/verify-token", &body).await,
+        401
+    );
+}
+
+#[tokio::test]
+async fn tenant_is_resolved_from_the_host_name() {
+    let app = TestApp::new().await;
+    let email = unique_email();
+
+    let response = app
+        .http_client
+        .post(format!("{}/signup", app.address))
+        .header(HOST, ACME_HOST)
+        .json(&credentials(&email))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    // The account exists in the acme realm and not in the default tenant
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/signup", &credentials(&email)).await,
+        409
+    );
+    assert_eq!(app.post_signup(&credentials(&email)).await.status(), 201);
+}
+
+#[tokio::test]
+async fn unknown_realms_are_not_found() {
+    let app = TestApp::new().await;
+
+    let status = post_realm(&app, "unknown", "/signup", &credentials(&unique_email())).await;
+    assert_eq!(status, 404);
+
+    let response = app
+        .http_client
+        .get(format!("{}/", app.realm_address("unknown")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn realms_serve_the_login_page() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/", app.realm_address(ACME_TENANT_ID)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("<html"));
+}
+
+#[tokio::test]
+async fn required_2fa_policy_applies_to_users_who_opted_out() {
+    let app = TestApp::new().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let email = unique_email();
+
+    // The user asked for no 2FA, but the acme tenant requires it
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/signup", &credentials(&email)).await,
+        201
+    );
+    let login = json!({ "email": email, "password": "password123" });
+    assert_eq!(
+        post_realm(&app, ACME_TENANT_ID, "/login", &login).await,
+        206
+    );
+
+    // The code is sent from the tenant's own address
+    assert_eq!(app.deliver_emails().await, 1);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    assert_eq!(body["From"], ACME_SENDER);
+    assert_ne!(body["From"], test::email_client::SENDER);
+}
+
+#[tokio::test]
+async fn cors_origins_are_allowed_per_tenant() {
+    let app = TestApp::new().await;
+
+    let preflight = |url: String| {
+        app.http_client
+            .request(reqwest::Method::OPTIONS, url)
+            .header(ORIGIN, ACME_ORIGIN)
+            .header("access-control-request-method", "POST")
+            .send()
+    };
+
+    let response = preflight(format!("{}/login", app.realm_address(ACME_TENANT_ID)))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+        ACME_ORIGIN
+    );
+
+    let response = preflight(format!("{}/login", app.address)).await.unwrap();
+    assert!(
+        response
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none()
+    );
+}
@@ -0,0 +1,82 @@
+use crate::helpers::{configure_redis, default_tenant, revocation_cache};
+use auth_service::services::data_stores::{
+    BannedTokenStore, CachedBannedTokenStore, RevocationFailurePolicy,
+};
+use std::time::Duration;
+use uuid::Uuid;
+
+async fn synced_cache() -> std::sync::Arc<CachedBannedTokenStore> {
+    let cache = revocation_cache(configure_redis().await, RevocationFailurePolicy::Closed);
+    for _ in 0..100 {
+        if cache.is_synced() {
+            return cache;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("The revocation cache did not sync");
+}
+
+#[tokio::test]
+async fn revocations_reach_other_instances() {
+    let first = synced_cache().await;
+    let second = synced_cache().await;
+    let jti = Uuid::new_v4().to_string();
+
+    first
+        .add_token(&default_tenant(), jti.clone())
+        .await
+        .unwrap();
+    assert!(first.contains_token(&default_tenant(), &jti).await.unwrap());
+
+    // Answered from memory alone, so it only turns true once the revocation is published
+    let mut revoked = false;
+    for _ in 0..100 {
+        revoked = second
+            .contains_token(&default_tenant(), &jti)
+            .await
+            .unwrap();
+        if revoked {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(second.is_synced());
+    assert!(revoked);
+}
+
+#[tokio::test]
+async fn new_instances_load_earlier_revocations() {
+    let first = synced_cache().await;
+    let jti = Uuid::new_v4().to_string();
+    first
+        .add_token(&default_tenant(), jti.clone())
+        .await
+        .unwrap();
+
+    let second = synced_cache().await;
+    assert!(
+        second
+            .contains_token(&default_tenant(), &jti)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !second
+            .contains_token(&default_tenant(), &Uuid::new_v4().to_string())
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn revocations_are_scoped_by_tenant() {
+    let cache = synced_cache().await;
+    let jti = Uuid::new_v4().to_string();
+    cache
+        .add_token(&default_tenant(), jti.clone())
+        .await
+        .unwrap();
+
+    let other = auth_service::domain::TenantId::parse("other").unwrap();
+    assert!(!cache.contains_token(&other, &jti).await.unwrap());
+}
@@ -0,0 +1,241 @@
+use crate::helpers::TestApp;
+use auth_service::routes::{TrustedDevicesResponse, TwoFactorAuthResponse};
+use auth_service::utils::constants::TRUSTED_DEVICE_COOKIE_NAME;
+use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
+use reqwest::header::COOKIE;
+
+// Sign up a user with 2FA, returning the email and password
+async fn signup_with_2fa(app: &TestApp) -> (String, String) {
+    let email: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    (email, password)
+}
+
+// Log in with 2FA, asking to trust the device or not, and return the verify response
+async fn login_with_2fa(
+    app: &TestApp,
+    email: &str,
+    password: &str,
+    trust_device: bool,
+) -> reqwest::Response {
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login = response.json::<TwoFactorAuthResponse>().await.unwrap();
+    let code = app.two_fa_code(&login.login_attempt_id).await;
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login.login_attempt_id,
+            "2FACode": code.as_ref(),
+            "trustDevice": trust_device,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+}
+
+fn trusted_device_cookie(response: &reqwest::Response) -> Option<String> {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == TRUSTED_DEVICE_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+}
+
+// Log in from a browser that only has the given trusted device cookie
+async fn login_with_cookie(
+    app: &TestApp,
+    email: &str,
+    password: &str,
+    token: &str,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .header(COOKIE, format!("{}={}", TRUSTED_DEVICE_COOKIE_NAME, token))
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn a_trusted_device_skips_2fa() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+
+    let response = login_with_2fa(&app, &email, &password, true).await;
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == TRUSTED_DEVICE_COOKIE_NAME)
+        .expect("No trusted device cookie found");
+    assert!(cookie.http_only());
+    assert_eq!(
+        cookie.max_age().map(|age| age.as_secs()),
+        Some(30 * 24 * 60 * 60)
+    );
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Other browsers still need a code
+    let response = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 206);
+}
+
+#[tokio::test]
+async fn devices_are_not_trusted_by_default() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+
+    let response = login_with_2fa(&app, &email, &password, false).await;
+    assert_eq!(trusted_device_cookie(&response), None);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+}
+
+#[tokio::test]
+async fn a_trusted_device_is_only_trusted_for_its_account() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    let response = login_with_2fa(&app, &email, &password, true).await;
+    let token = trusted_device_cookie(&response).unwrap();
+
+    let (other_email, other_password) = signup_with_2fa(&app).await;
+    let response = login_with_cookie(&app, &other_email, &other_password, &token).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    // A cookie that was tampered with is not trusted either
+    let response = login_with_cookie(&app, &email, &password, &format!("{}x", token)).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let response = login_with_cookie(&app, &email, &password, &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn trusted_devices_can_be_listed_and_revoked() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    let response = login_with_2fa(&app, &email, &password, true).await;
+    let token = trusted_device_cookie(&response).unwrap();
+
+    let response = app.get_trusted_devices().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<TrustedDevicesResponse>().await.unwrap();
+    assert_eq!(body.devices.len(), 1);
+    let device = &body.devices[0];
+    assert!(device.current);
+    assert!(device.last_used_at.is_none());
+
+    let response = app.delete_trusted_device(&device.id.to_string()).await;
+    assert_eq!(response.status().as_u16(), 204);
+    // The browser's cookie is dropped with it
+    assert_eq!(trusted_device_cookie(&response).as_deref(), Some(""));
+
+    let response = app.get_trusted_devices().await;
+    let body = response.json::<TrustedDevicesResponse>().await.unwrap();
+    assert!(body.devices.is_empty());
+
+    let response = login_with_cookie(&app, &email, &password, &token).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let response = app.delete_trusted_device(&device.id.to_string()).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn users_cannot_revoke_the_devices_of_others() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    let response = login_with_2fa(&app, &email, &password, true).await;
+    let token = trusted_device_cookie(&response).unwrap();
+    let body = app
+        .get_trusted_devices()
+        .await
+        .json::<TrustedDevicesResponse>()
+        .await
+        .unwrap();
+    let device_id = body.devices[0].id.to_string();
+
+    // Sign in as someone else in the same browser
+    let (other_email, other_password) = signup_with_2fa(&app).await;
+    login_with_2fa(&app, &other_email, &other_password, false).await;
+    let body = app
+        .get_trusted_devices()
+        .await
+        .json::<TrustedDevicesResponse>()
+        .await
+        .unwrap();
+    assert!(body.devices.is_empty());
+    let response = app.delete_trusted_device(&device_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = login_with_cookie(&app, &email, &password, &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn using_a_trusted_device_is_recorded() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    login_with_2fa(&app, &email, &password, true).await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = app
+        .get_trusted_devices()
+        .await
+        .json::<TrustedDevicesResponse>()
+        .await
+        .unwrap();
+    assert!(body.devices[0].last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn a_password_change_revokes_all_trusted_devices() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    let response = login_with_2fa(&app, &email, &password, true).await;
+    let token = trusted_device_cookie(&response).unwrap();
+
+    let new_password = "new-password-123";
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": password,
+            "newPassword": new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(trusted_device_cookie(&response).as_deref(), Some(""));
+
+    let response = login_with_cookie(&app, &email, new_password, &token).await;
+    assert_eq!(response.status().as_u16(), 206);
+}
+
+#[tokio::test]
+async fn listing_trusted_devices_requires_a_session() {
+    let app = TestApp::new().await;
+    let response = app.get_trusted_devices().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
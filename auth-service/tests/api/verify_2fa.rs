@@ -1,10 +1,9 @@
-use crate::helpers::TestApp;
+use crate::helpers::{TestApp, default_tenant};
 use auth_service::domain::Email;
 use auth_service::routes::TwoFactorAuthResponse;
-use auth_service::services::{LoginAttemptId, TwoFACode};
+use auth_service::services::{LoginAttemptId, MAX_2FA_CODE_ATTEMPTS, MAX_PENDING_2FA_CHALLENGES};
 use fake::{Fake, faker::internet::en::Password as FakerPassword, faker::internet::en::SafeEmail};
 use secrecy::SecretBox;
-use serde_json;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -39,6 +38,7 @@ async fn verify_2fa_returns_200() {
         "password": password_str,
     });
     let response = app.post_login(&login_request).await;
+    app.deliver_emails().await;
 
     let json_body = response
         .json::<TwoFactorAuthResponse>()
@@ -47,25 +47,19 @@ async fn verify_2fa_returns_200() {
 
     assert_eq!(json_body.message, "2FA required".to_owned());
 
-    // Since you can only have one mutable RWLock then, wrap the code in a block to drop lock
-    #[allow(unused_assignments)]
-    let mut stored_login_attempt_id: LoginAttemptId = LoginAttemptId::default();
-    #[allow(unused_assignments)]
-    let mut two_factor_code: TwoFACode = TwoFACode::default();
-    {
-        // assert that `json_body.login_attempt_id` is stored inside `app.two_fa_code_store`
-        let two_fa_store = app.two_fa_code_store.read().await;
-        let (id, code) = two_fa_store.get_code(&email).await.unwrap();
-        stored_login_attempt_id = id;
-        two_factor_code = code;
-        assert_eq!(json_body.login_attempt_id, stored_login_attempt_id.as_ref());
-    }
+    // assert that `json_body.login_attempt_id` is stored inside `app.two_fa_code_store`
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id).unwrap();
+    let challenge = app
+        .two_fa_code_store
+        .get_code(&default_tenant(), &login_attempt_id)
+        .await
+        .unwrap();
+    assert_eq!(challenge.email, email);
 
-    // Verify 2 FA Auth
+    // Verify 2 FA Auth; the login attempt identifies the account
     let request = serde_json::json!({
-        "email": email_str.clone(),
-        "loginAttemptId": stored_login_attempt_id.as_ref(),
-        "2FACode": two_factor_code.as_ref()
+        "loginAttemptId": login_attempt_id.as_ref(),
+        "2FACode": challenge.code.as_ref()
     });
     let response = app.post_verify_2fa(&request).await;
     assert_eq!(response.status().as_u16(), 200);
@@ -112,21 +106,30 @@ async fn should_return_400_if_invalid_input() {
         "password": password_str,
     });
     let response = app.post_login(&login_request).await;
+    app.deliver_emails().await;
 
     response
         .json::<TwoFactorAuthResponse>()
         .await
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
 
-    // Try to verify with incorrect login attempt ID
+    // Try to verify with a login attempt ID that is not one
     let request = serde_json::json!({
-        "email": email_str,
-        "loginAttemptId": "00000000-0000-0000-0000-000000000000",  // Incorrect login attempt ID
+        "loginAttemptId": "not-a-login-attempt",
         "2FACode": "000000"  // Incorrect code
     });
 
     let response = app.post_verify_2fa(&request).await;
     assert_eq!(response.status().as_u16(), 400);
+
+    // An unknown login attempt is treated like an expired one
+    let request = serde_json::json!({
+        "loginAttemptId": "00000000-0000-0000-0000-000000000000",
+        "2FACode": "123456"
+    });
+
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 401);
 }
 
 #[tokio::test]
@@ -158,6 +161,7 @@ async fn should_return_401_if_incorrect_credentials() {
         "password": password_str,
     });
     let response = app.post_login(&login_request).await;
+    app.deliver_emails().await;
 
     let json_body = response
         .json::<TwoFactorAuthResponse>()
@@ -166,7 +170,6 @@ async fn should_return_401_if_incorrect_credentials() {
 
     // Try to verify with incorrect 2FA code
     let request = serde_json::json!({
-        "email": email_str,
         "loginAttemptId": json_body.login_attempt_id,
         "2FACode": "111111"  // Valid But Incorrect code
     });
@@ -204,29 +207,19 @@ async fn should_return_401_if_old_code() {
         "password": password_str,
     });
     let response = app.post_login(&login_request).await;
+    app.deliver_emails().await;
     assert_eq!(response.status().as_u16(), 206);
     let login_json_body = response
         .json::<TwoFactorAuthResponse>()
         .await
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
 
-    // *************************
-    // two_fa_code_store.read()
-    // *************************
     // 3) Get the first 2FA code from store
-    let email = Email::parse(SecretBox::new(Box::new(email_str.clone()))).unwrap();
-    let (_, two_factor_code) = app
-        .two_fa_code_store
-        .read()
-        .await
-        .get_code(&email)
-        .await
-        .unwrap();
-    //let (_, two_factor_code) = two_fa_store.get_code(&email).await.unwrap();
+    let two_factor_code = app.two_fa_code(&login_json_body.login_attempt_id).await;
+
     // 4) Verify with the 2FA code
 
     let request = serde_json::json!({
-        "email": email_str,
         "loginAttemptId": login_json_body.login_attempt_id,
         "2FACode": two_factor_code.as_ref()
     });
@@ -236,7 +229,6 @@ async fn should_return_401_if_old_code() {
 
     // 5) Try to verify with the old 2FA code
     let request = serde_json::json!({
-        "email": email_str,
         "loginAttemptId": login_json_body.login_attempt_id,
         "2FACode": two_factor_code.as_ref()
     });
@@ -244,3 +236,154 @@ async fn should_return_401_if_old_code() {
     let response = app.post_verify_2fa(&request).await;
     assert_eq!(response.status().as_u16(), 401);
 }
+
+async fn signup_with_2fa(app: &TestApp) -> (String, String) {
+    let email: String = SafeEmail().fake();
+    let password: String = FakerPassword(std::ops::Range { start: 8, end: 30 }).fake();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    (email, password)
+}
+
+// Log in from `client` and return the login attempt ID of the pending 2FA challenge
+async fn start_login(
+    app: &TestApp,
+    client: &reqwest::Client,
+    email: &str,
+    password: &str,
+) -> String {
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 206);
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id
+}
+
+async fn verify_from(
+    app: &TestApp,
+    client: &reqwest::Client,
+    login_attempt_id: &str,
+) -> reqwest::Response {
+    let code = app.two_fa_code(login_attempt_id).await;
+    client
+        .post(format!("{}/verify-2fa", &app.address))
+        .json(&serde_json::json!({
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn another_browser() -> reqwest::Client {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn concurrent_logins_can_each_be_verified() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+
+    // A laptop and then a phone
+    let phone = another_browser();
+    let laptop_login = start_login(&app, &app.http_client, &email, &password).await;
+    let phone_login = start_login(&app, &phone, &email, &password).await;
+    assert_ne!(laptop_login, phone_login);
+
+    let response = verify_from(&app, &phone, &phone_login).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = verify_from(&app, &app.http_client, &laptop_login).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_verified_from_another_client() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    let login_attempt_id = start_login(&app, &app.http_client, &email, &password).await;
+
+    // Someone who learned the login attempt ID and the code, but not the browser's cookie
+    let response = verify_from(&app, &reqwest::Client::new(), &login_attempt_id).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = verify_from(&app, &another_browser(), &login_attempt_id).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The login is still pending for the browser that started it
+    let response = verify_from(&app, &app.http_client, &login_attempt_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn only_the_latest_logins_stay_pending() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+
+    let mut logins = Vec::new();
+    for _ in 0..=MAX_PENDING_2FA_CHALLENGES {
+        logins.push(start_login(&app, &app.http_client, &email, &password).await);
+    }
+
+    let oldest = LoginAttemptId::parse(logins[0].clone()).unwrap();
+    assert!(
+        app.two_fa_code_store
+            .get_code(&default_tenant(), &oldest)
+            .await
+            .is_err()
+    );
+    let response = verify_from(&app, &app.http_client, &logins[1]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = verify_from(&app, &app.http_client, logins.last().unwrap()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_right_code_is_rejected_after_too_many_wrong_ones() {
+    let app = TestApp::new().await;
+    let (email, password) = signup_with_2fa(&app).await;
+    let login_attempt_id = start_login(&app, &app.http_client, &email, &password).await;
+    let code = app.two_fa_code(&login_attempt_id).await;
+    let wrong_code = if code.as_ref() == "111111" {
+        "222222"
+    } else {
+        "111111"
+    };
+
+    for _ in 0..MAX_2FA_CODE_ATTEMPTS {
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "loginAttemptId": login_attempt_id,
+                "2FACode": wrong_code,
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The login was dropped, and has to be started again
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let login_attempt_id = start_login(&app, &app.http_client, &email, &password).await;
+    let response = verify_from(&app, &app.http_client, &login_attempt_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+}